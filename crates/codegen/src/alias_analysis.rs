@@ -0,0 +1,653 @@
+//! This module contains an alias analysis of memory locations.
+//!
+//! A location is represented as `base + offset` with a constant `offset`,
+//! where the offset is accumulated through `gep` with constant indices, which
+//! is computed with the type layout of the target, `add`/`sub` with a
+//! constant and casts. Two locations with the same base are compared by their
+//! byte ranges.
+//!
+//! The results of `alloca` and `evm_malloc` are regarded as allocation sites,
+//! which never overlap each other. An allocation site escapes if its address
+//! may flow into a value that the analysis can't decompose, e.g., when it's
+//! stored to memory, passed to a call, or merged by a phi. A location based
+//! on an allocation site that doesn't escape never overlaps a location with a
+//! different base, since no other pointer can be derived from the address.
+//!
+//! Note that the results are about a single execution of the instructions
+//! defining the bases, i.e., an allocation site in a loop is regarded as the
+//! same location in all iterations.
+//!
+//! Storage and transient storage slots are represented in the same way by
+//! [`Slot`], with the key decomposed into `base + offset`. Slots with the same
+//! base and different offsets never overlap, since keys are not byte
+//! addresses.
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use sonatina_ir::{
+    inst::{arith, cast, control_flow, data, evm, SideEffect},
+    prelude::*,
+    types::CompoundType,
+    DataFlowGraph, Function, Immediate, InstId, Type, ValueId, I256,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alias {
+    Must,
+    May,
+    No,
+}
+
+#[derive(Debug, Default)]
+pub struct AliasAnalysis {
+    /// Maps allocation sites to `true` if they escape.
+    alloc_sites: FxHashMap<ValueId, bool>,
+}
+
+impl AliasAnalysis {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.alloc_sites.clear();
+    }
+
+    /// Collects allocation sites of the function and computes whether they
+    /// escape.
+    pub fn compute(&mut self, func: &Function) {
+        self.clear();
+
+        let is = func.dfg.inst_set();
+        for block in func.layout.iter_block() {
+            for inst_id in func.layout.iter_inst(block) {
+                let inst = func.dfg.inst(inst_id);
+                if <&data::Alloca as InstDowncast>::downcast(is, inst).is_none()
+                    && <&evm::EvmMalloc as InstDowncast>::downcast(is, inst).is_none()
+                {
+                    continue;
+                }
+
+                if let Some(site) = func.dfg.inst_result(inst_id) {
+                    self.alloc_sites.insert(site, escapes(&func.dfg, site));
+                }
+            }
+        }
+    }
+
+    pub fn alias(&self, lhs: &MemoryLocation, rhs: &MemoryLocation) -> Alias {
+        if lhs.size == Some(0) || rhs.size == Some(0) {
+            return Alias::No;
+        }
+
+        if lhs.base != rhs.base {
+            let escaped = |loc: &MemoryLocation| {
+                loc.base
+                    .and_then(|base| self.alloc_sites.get(&base).copied())
+            };
+            return match (escaped(lhs), escaped(rhs)) {
+                (Some(_), Some(_)) | (Some(false), None) | (None, Some(false)) => Alias::No,
+                _ => Alias::May,
+            };
+        }
+
+        if lhs.offset == rhs.offset && lhs.size.is_some() && lhs.size == rhs.size {
+            Alias::Must
+        } else if lhs.end().is_some_and(|end| end <= rhs.offset as i128)
+            || rhs.end().is_some_and(|end| end <= lhs.offset as i128)
+        {
+            Alias::No
+        } else {
+            Alias::May
+        }
+    }
+
+    /// Returns `true` if `value` is an allocation site that escapes, or
+    /// `None` if it's not an allocation site.
+    pub fn is_escaped(&self, value: ValueId) -> Option<bool> {
+        self.alloc_sites.get(&value).copied()
+    }
+}
+
+/// Returns `true` if the address of `site` may flow into a value that can't be
+/// decomposed into `site + offset`.
+fn escapes(dfg: &DataFlowGraph, site: ValueId) -> bool {
+    let mut visited = FxHashSet::default();
+    let mut worklist = vec![site];
+
+    while let Some(ptr) = worklist.pop() {
+        if !visited.insert(ptr) {
+            continue;
+        }
+
+        for &user in dfg.users(ptr) {
+            if let Some(derived) = dfg.inst_result(user) {
+                if step(dfg, derived).is_some_and(|(base, _)| base == ptr) {
+                    worklist.push(derived);
+                    continue;
+                }
+            }
+
+            let inst = dfg.inst(user);
+            let is = dfg.inst_set();
+            let is_captured = match MemoryAccess::of(dfg, user) {
+                MemoryAccess::Store(_, Some((_, value))) => value == ptr,
+                MemoryAccess::Store(_, None) => {
+                    <&evm::EvmMstore8 as InstDowncast>::downcast(is, inst)
+                        .is_some_and(|store| *store.val() == ptr)
+                }
+                MemoryAccess::Load(..) | MemoryAccess::Copy { .. } | MemoryAccess::Read(_) => false,
+                MemoryAccess::ReadAll | MemoryAccess::ReadWriteAll | MemoryAccess::None => true,
+            };
+            if is_captured {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+/// A memory location of `size` bytes starting at `base + offset`. `base` is
+/// `None` if the address is a constant, and `size` is `None` if it's unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLocation {
+    pub base: Option<ValueId>,
+    pub offset: i64,
+    pub size: Option<u64>,
+}
+
+impl MemoryLocation {
+    /// Returns the location of `size` bytes at `addr`.
+    pub fn new(dfg: &DataFlowGraph, addr: ValueId, size: Option<u64>) -> Self {
+        let (base, offset) = base_and_offset(dfg, addr);
+        Self { base, offset, size }
+    }
+
+    /// Returns the location at `addr` whose size is given by the value `len`.
+    pub fn with_len(dfg: &DataFlowGraph, addr: ValueId, len: ValueId) -> Self {
+        let size = dfg
+            .value_imm(len)
+            .and_then(|len| to_i64(len.as_i256()))
+            .and_then(|len| u64::try_from(len).ok());
+        Self::new(dfg, addr, size)
+    }
+
+    /// Returns the location of a value of `ty` at `addr`.
+    pub fn of_ty(dfg: &DataFlowGraph, addr: ValueId, ty: Type) -> Self {
+        let size = dfg.ctx.size_of(ty).ok().map(|size| size as u64);
+        Self::new(dfg, addr, size)
+    }
+
+    fn end(&self) -> Option<i128> {
+        self.size.map(|size| self.offset as i128 + size as i128)
+    }
+
+    /// Returns `true` if `self` contains all bytes of `rhs`.
+    pub fn covers(&self, rhs: &Self) -> bool {
+        match (self.end(), rhs.end()) {
+            (Some(end), Some(rhs_end)) => {
+                self.base == rhs.base && self.offset <= rhs.offset && rhs_end <= end
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Decomposes `addr` into `(base, offset)` by walking through `gep` with
+/// constant indices, `add`/`sub` with a constant and casts.
+fn base_and_offset(dfg: &DataFlowGraph, addr: ValueId) -> (Option<ValueId>, i64) {
+    let mut base = addr;
+    let mut offset = 0i64;
+
+    loop {
+        if let Some(imm) = dfg.value_imm(base) {
+            if let Some(addr) = to_i64(imm.as_i256()).and_then(|imm| imm.checked_add(offset)) {
+                return (None, addr);
+            }
+            return (Some(base), offset);
+        }
+
+        let Some((next, delta)) = step(dfg, base) else {
+            return (Some(base), offset);
+        };
+        let Some(next_offset) = offset.checked_add(delta) else {
+            return (Some(base), offset);
+        };
+        base = next;
+        offset = next_offset;
+    }
+}
+
+/// Returns `(ptr, delta)` if `value` is computed as `ptr + delta` with a
+/// constant `delta`, where casts between pointers and integers are regarded
+/// as adding zero.
+fn step(dfg: &DataFlowGraph, value: ValueId) -> Option<(ValueId, i64)> {
+    let inst = dfg.inst(dfg.value_inst(value)?);
+    let is = dfg.inst_set();
+
+    if let Some(add) = <&arith::Add as InstDowncast>::downcast(is, inst) {
+        match (dfg.value_imm(*add.lhs()), dfg.value_imm(*add.rhs())) {
+            (None, Some(delta)) => Some((*add.lhs(), to_i64(delta.as_i256())?)),
+            (Some(delta), None) => Some((*add.rhs(), to_i64(delta.as_i256())?)),
+            _ => None,
+        }
+    } else if let Some(sub) = <&arith::Sub as InstDowncast>::downcast(is, inst) {
+        match (dfg.value_imm(*sub.lhs()), dfg.value_imm(*sub.rhs())) {
+            (None, Some(delta)) => Some((*sub.lhs(), to_i64(delta.as_i256())?.checked_neg()?)),
+            _ => None,
+        }
+    } else if let Some(cast) = <&cast::PtrToInt as InstDowncast>::downcast(is, inst) {
+        Some((*cast.from(), 0))
+    } else if let Some(cast) = <&cast::IntToPtr as InstDowncast>::downcast(is, inst) {
+        Some((*cast.from(), 0))
+    } else if let Some(cast) = <&cast::Bitcast as InstDowncast>::downcast(is, inst) {
+        Some((*cast.from(), 0))
+    } else if let Some(gep) = <&data::Gep as InstDowncast>::downcast(is, inst) {
+        Some((gep.values()[0], gep_offset(dfg, gep)?))
+    } else {
+        None
+    }
+}
+
+/// Returns the byte offset that `gep` adds to its base pointer if all indices
+/// are constants.
+fn gep_offset(dfg: &DataFlowGraph, gep: &data::Gep) -> Option<i64> {
+    let mut ty = dfg.value_ty(gep.values()[0]);
+    let mut offset = 0i64;
+
+    for &idx in &gep.values()[1..] {
+        let idx = to_i64(dfg.value_imm(idx)?.as_i256())?;
+        let delta = match ty.resolve_compound(&dfg.ctx)? {
+            CompoundType::Ptr(elem) | CompoundType::Array { elem, .. } => {
+                ty = elem;
+                idx.checked_mul(dfg.ctx.size_of(elem).ok()? as i64)?
+            }
+
+            CompoundType::Struct(s) => {
+                let idx = usize::try_from(idx).ok()?;
+                ty = *s.fields.get(idx)?;
+                let mut delta = 0;
+                for &field in &s.fields[..idx] {
+                    delta += dfg.ctx.size_of(field).ok()? as i64;
+                }
+                delta
+            }
+
+            CompoundType::Func { .. } => return None,
+        };
+        offset = offset.checked_add(delta)?;
+    }
+
+    Some(offset)
+}
+
+fn to_i64(value: I256) -> Option<i64> {
+    let truncated = value.trunc_to_i64();
+    (I256::from(truncated) == value).then_some(truncated)
+}
+
+/// The effect of an instruction on memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAccess {
+    Load(MemoryLocation, Type),
+    /// Writes the entire location. The stored type and value are `None` if
+    /// the store is not a plain `mstore`.
+    Store(MemoryLocation, Option<(Type, ValueId)>),
+    /// Reads `src` if any, then writes `dest`, which may not be written
+    /// entirely if `partial` is `true`.
+    Copy {
+        src: Option<MemoryLocation>,
+        dest: MemoryLocation,
+        partial: bool,
+    },
+    Read(MemoryLocation),
+    ReadAll,
+    ReadWriteAll,
+    None,
+}
+
+impl MemoryAccess {
+    pub fn of(dfg: &DataFlowGraph, inst_id: InstId) -> Self {
+        let is = dfg.inst_set();
+        let inst = dfg.inst(inst_id);
+
+        macro_rules! downcast {
+            ($ty:ty) => {
+                <&$ty as InstDowncast>::downcast(is, inst)
+            };
+        }
+
+        if let Some(load) = downcast!(data::Mload) {
+            Self::Load(
+                MemoryLocation::of_ty(dfg, *load.addr(), *load.ty()),
+                *load.ty(),
+            )
+        } else if let Some(store) = downcast!(data::Mstore) {
+            let loc = MemoryLocation::of_ty(dfg, *store.addr(), *store.ty());
+            Self::Store(loc, Some((*store.ty(), *store.value())))
+        } else if let Some(store) = downcast!(evm::EvmMstore8) {
+            Self::Store(MemoryLocation::new(dfg, *store.addr(), Some(1)), None)
+        } else if let Some(copy) = downcast!(evm::EvmMcopy) {
+            Self::Copy {
+                src: Some(MemoryLocation::with_len(dfg, *copy.addr(), *copy.len())),
+                dest: MemoryLocation::with_len(dfg, *copy.dest(), *copy.len()),
+                partial: false,
+            }
+        } else if let Some(copy) = downcast!(evm::EvmCalldataCopy) {
+            Self::copy_in(dfg, *copy.dst_addr(), *copy.len())
+        } else if let Some(copy) = downcast!(evm::EvmReturnDataCopy) {
+            Self::copy_in(dfg, *copy.dst_addr(), *copy.len())
+        } else if let Some(copy) = downcast!(evm::EvmCodeCopy) {
+            Self::copy_in(dfg, *copy.dst_addr(), *copy.len())
+        } else if let Some(copy) = downcast!(evm::EvmExtCodeCopy) {
+            Self::copy_in(dfg, *copy.dst_addr(), *copy.len())
+        } else if let Some(call) = downcast!(evm::EvmCall) {
+            Self::call(
+                dfg,
+                (*call.arg_addr(), *call.arg_len()),
+                (*call.ret_addr(), *call.ret_offset()),
+            )
+        } else if let Some(call) = downcast!(evm::EvmCallCode) {
+            Self::call(
+                dfg,
+                (*call.arg_addr(), *call.arg_len()),
+                (*call.ret_addr(), *call.ret_offset()),
+            )
+        } else if let Some(call) = downcast!(evm::EvmDelegateCall) {
+            Self::call(
+                dfg,
+                (*call.arg_addr(), *call.arg_len()),
+                (*call.ret_addr(), *call.ret_len()),
+            )
+        } else if let Some(call) = downcast!(evm::EvmStaticCall) {
+            Self::call(
+                dfg,
+                (*call.arg_addr(), *call.arg_len()),
+                (*call.ret_addr(), *call.ret_len()),
+            )
+        } else if let Some(hash) = downcast!(evm::EvmKeccak256) {
+            Self::Read(MemoryLocation::with_len(dfg, *hash.addr(), *hash.len()))
+        } else if let Some(log) = downcast!(evm::EvmLog0) {
+            Self::Read(MemoryLocation::with_len(dfg, *log.addr(), *log.len()))
+        } else if let Some(log) = downcast!(evm::EvmLog1) {
+            Self::Read(MemoryLocation::with_len(dfg, *log.addr(), *log.len()))
+        } else if let Some(log) = downcast!(evm::EvmLog2) {
+            Self::Read(MemoryLocation::with_len(dfg, *log.addr(), *log.len()))
+        } else if let Some(log) = downcast!(evm::EvmLog3) {
+            Self::Read(MemoryLocation::with_len(dfg, *log.addr(), *log.len()))
+        } else if let Some(log) = downcast!(evm::EvmLog4) {
+            Self::Read(MemoryLocation::with_len(dfg, *log.addr(), *log.len()))
+        } else if let Some(create) = downcast!(evm::EvmCreate) {
+            Self::Read(MemoryLocation::with_len(dfg, *create.addr(), *create.len()))
+        } else if let Some(create) = downcast!(evm::EvmCreate2) {
+            Self::Read(MemoryLocation::with_len(dfg, *create.addr(), *create.len()))
+        } else if let Some(ret) = downcast!(evm::EvmReturn) {
+            Self::Read(MemoryLocation::with_len(dfg, *ret.addr(), *ret.len()))
+        } else if let Some(revert) = downcast!(evm::EvmRevert) {
+            Self::Read(MemoryLocation::with_len(dfg, *revert.addr(), *revert.len()))
+        } else if downcast!(data::Alloca).is_some() {
+            Self::None
+        } else if downcast!(evm::EvmMsize).is_some() || downcast!(control_flow::Return).is_some() {
+            Self::ReadAll
+        } else if inst.side_effect() == SideEffect::Write {
+            // Internal calls, `evm_malloc` that may maintain the free memory
+            // pointer, and anything else unknown.
+            Self::ReadWriteAll
+        } else {
+            Self::None
+        }
+    }
+
+    fn copy_in(dfg: &DataFlowGraph, dest: ValueId, len: ValueId) -> Self {
+        Self::Copy {
+            src: None,
+            dest: MemoryLocation::with_len(dfg, dest, len),
+            partial: false,
+        }
+    }
+
+    /// An external call reads the arguments and writes the return data, which
+    /// may be shorter than the region.
+    fn call(dfg: &DataFlowGraph, args: (ValueId, ValueId), ret: (ValueId, ValueId)) -> Self {
+        Self::Copy {
+            src: Some(MemoryLocation::with_len(dfg, args.0, args.1)),
+            dest: MemoryLocation::with_len(dfg, ret.0, ret.1),
+            partial: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Space {
+    Storage,
+    Transient,
+}
+
+/// A storage slot represented as `base + offset`. `base` is `None` if the key
+/// is a constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    space: Space,
+    base: Option<ValueId>,
+    offset: Immediate,
+}
+
+impl Slot {
+    pub fn new(dfg: &DataFlowGraph, space: Space, key: ValueId) -> Self {
+        let (base, offset) = if let Some(imm) = dfg.value_imm(key) {
+            (None, imm)
+        } else if let Some((base, offset)) = slot_base_and_offset(dfg, key) {
+            (Some(base), offset)
+        } else {
+            (Some(key), Immediate::zero(dfg.value_ty(key)))
+        };
+
+        Self {
+            space,
+            base,
+            offset,
+        }
+    }
+
+    pub fn alias(&self, rhs: &Self) -> Alias {
+        if self.space != rhs.space {
+            return Alias::No;
+        }
+        if self == rhs {
+            return Alias::Must;
+        }
+
+        if self.base == rhs.base && self.offset.ty() == rhs.offset.ty() {
+            Alias::No
+        } else {
+            Alias::May
+        }
+    }
+}
+
+/// Returns `(base, offset)` if `key` is computed as `base + offset` or
+/// `base - offset` with a constant `offset`.
+fn slot_base_and_offset(dfg: &DataFlowGraph, key: ValueId) -> Option<(ValueId, Immediate)> {
+    let inst = dfg.inst(dfg.value_inst(key)?);
+    let is = dfg.inst_set();
+
+    if let Some(add) = <&arith::Add as InstDowncast>::downcast(is, inst) {
+        match (dfg.value_imm(*add.lhs()), dfg.value_imm(*add.rhs())) {
+            (None, Some(offset)) => Some((*add.lhs(), offset)),
+            (Some(offset), None) => Some((*add.rhs(), offset)),
+            _ => None,
+        }
+    } else if let Some(sub) = <&arith::Sub as InstDowncast>::downcast(is, inst) {
+        match (dfg.value_imm(*sub.lhs()), dfg.value_imm(*sub.rhs())) {
+            (None, Some(offset)) => Some((*sub.lhs(), -offset)),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+/// The effect of an instruction on storage.
+pub enum StorageAccess {
+    Load(Slot),
+    Store(Slot, ValueId),
+    ReadAll,
+    ReadWriteAll,
+    None,
+}
+
+impl StorageAccess {
+    pub fn of(dfg: &DataFlowGraph, inst_id: InstId) -> Self {
+        let is = dfg.inst_set();
+        let inst = dfg.inst(inst_id);
+
+        if let Some(load) = <&evm::EvmSload as InstDowncast>::downcast(is, inst) {
+            Self::Load(Slot::new(dfg, Space::Storage, *load.key()))
+        } else if let Some(load) = <&evm::EvmTload as InstDowncast>::downcast(is, inst) {
+            Self::Load(Slot::new(dfg, Space::Transient, *load.key()))
+        } else if let Some(store) = <&evm::EvmSstore as InstDowncast>::downcast(is, inst) {
+            Self::Store(Slot::new(dfg, Space::Storage, *store.key()), *store.val())
+        } else if let Some(store) = <&evm::EvmTstore as InstDowncast>::downcast(is, inst) {
+            Self::Store(Slot::new(dfg, Space::Transient, *store.key()), *store.val())
+        } else if <&evm::EvmStaticCall as InstDowncast>::downcast(is, inst).is_some() {
+            Self::ReadAll
+        } else if inst.side_effect() == SideEffect::Write && !is_storage_transparent(dfg, inst_id) {
+            Self::ReadWriteAll
+        } else {
+            Self::None
+        }
+    }
+}
+
+/// Returns `true` if the instruction writes something but never accesses
+/// storage.
+fn is_storage_transparent(dfg: &DataFlowGraph, inst: InstId) -> bool {
+    let is = dfg.inst_set();
+    let inst = dfg.inst(inst);
+
+    macro_rules! is_any {
+        ($($ty:ty),*) => {
+            $(<&$ty as InstDowncast>::downcast(is, inst).is_some())||*
+        };
+    }
+
+    is_any!(
+        data::Mstore,
+        data::Alloca,
+        evm::EvmMstore8,
+        evm::EvmMcopy,
+        evm::EvmMalloc,
+        evm::EvmCalldataCopy,
+        evm::EvmCodeCopy,
+        evm::EvmExtCodeCopy,
+        evm::EvmReturnDataCopy,
+        evm::EvmLog0,
+        evm::EvmLog1,
+        evm::EvmLog2,
+        evm::EvmLog3,
+        evm::EvmLog4
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use smallvec::smallvec;
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            cast::PtrToInt,
+            control_flow::Return,
+            data::{Alloca, Gep, Mstore},
+        },
+        isa::Isa,
+        Type,
+    };
+
+    use super::*;
+
+    #[test]
+    fn constant_gep_offset() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::Unit);
+        let is = evm.inst_set();
+
+        let pair = builder.declare_struct_type("pair", &[Type::I32, Type::I64], false);
+        let pair_ptr = builder.ptr_type(pair);
+        let i32_ptr = builder.ptr_type(Type::I32);
+        let i64_ptr = builder.ptr_type(Type::I64);
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst_with(|| Alloca::new(is, pair), pair_ptr);
+        let zero = builder.make_imm_value(I256::zero());
+        let one = builder.make_imm_value(I256::one());
+        let v1 = builder.insert_inst_with(|| Gep::new(is, smallvec![v0, zero, zero]), i32_ptr);
+        let v2 = builder.insert_inst_with(|| Gep::new(is, smallvec![v0, zero, one]), i64_ptr);
+        builder.insert_inst_no_result_with(|| Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut aa = AliasAnalysis::new();
+            aa.compute(func);
+            assert_eq!(aa.is_escaped(v0), Some(false));
+
+            let dfg = &func.dfg;
+            let first = MemoryLocation::of_ty(dfg, v1, Type::I32);
+            let second = MemoryLocation::of_ty(dfg, v2, Type::I64);
+            let whole = MemoryLocation::of_ty(dfg, v0, pair);
+            assert_eq!(second.base, Some(v0));
+            assert_eq!(second.offset, 4);
+
+            assert_eq!(aa.alias(&first, &second), Alias::No);
+            assert_eq!(
+                aa.alias(&first, &MemoryLocation::new(dfg, v0, Some(4))),
+                Alias::Must
+            );
+            assert_eq!(aa.alias(&second, &whole), Alias::May);
+            assert!(whole.covers(&second));
+        });
+    }
+
+    #[test]
+    fn escaped_alloc_site() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I256], Type::Unit);
+        let is = evm.inst_set();
+
+        let ptr_ty = builder.ptr_type(Type::I256);
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let arg = builder.args()[0];
+        let v1 = builder.insert_inst_with(|| Alloca::new(is, Type::I256), ptr_ty);
+        let v2 = builder.insert_inst_with(|| Alloca::new(is, Type::I256), ptr_ty);
+        let v3 = builder.insert_inst_with(|| PtrToInt::new(is, v1, Type::I256), Type::I256);
+        builder.insert_inst_no_result_with(|| Mstore::new(is, v2, v3, Type::I256));
+        builder.insert_inst_no_result_with(|| Return::new(is, None));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut aa = AliasAnalysis::new();
+            aa.compute(func);
+            assert_eq!(aa.is_escaped(v1), Some(true));
+            assert_eq!(aa.is_escaped(v2), Some(false));
+            assert_eq!(aa.is_escaped(arg), None);
+
+            let dfg = &func.dfg;
+            let escaped = MemoryLocation::of_ty(dfg, v1, Type::I256);
+            let local = MemoryLocation::of_ty(dfg, v2, Type::I256);
+            let unknown = MemoryLocation::of_ty(dfg, arg, Type::I256);
+            assert_eq!(aa.alias(&escaped, &local), Alias::No);
+            assert_eq!(aa.alias(&escaped, &unknown), Alias::May);
+            assert_eq!(aa.alias(&local, &unknown), Alias::No);
+        });
+    }
+}
@@ -0,0 +1,555 @@
+use std::collections::VecDeque;
+
+use cranelift_entity::SecondaryMap;
+use sonatina_ir::{inst::control_flow::BranchKind, BlockId, ControlFlowGraph, Function, InstId};
+
+use super::Lattice;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+/// An analysis that computes a fact at each point of the function.
+pub trait DenseAnalysis {
+    type Fact: Lattice;
+
+    const DIRECTION: Direction;
+
+    /// Returns the fact at the entry of the function for a forward analysis,
+    /// or at the exits of the function for a backward one.
+    fn boundary(&self, func: &Function) -> Self::Fact;
+
+    /// Transfers `fact` across `inst` in the direction of the analysis.
+    fn transfer(&self, func: &Function, inst: InstId, fact: &mut Self::Fact);
+
+    /// Transfers `fact` along the edge from `from` to `to` made by `branch`,
+    /// i.e., from the exit of `from` to the entry of `to` for a forward
+    /// analysis, and the other way around for a backward one.
+    ///
+    /// Returns `None` if the edge is never taken.
+    fn transfer_edge(
+        &self,
+        _func: &Function,
+        _branch: BranchKind,
+        _from: BlockId,
+        _to: BlockId,
+        fact: &Self::Fact,
+    ) -> Option<Self::Fact> {
+        Some(fact.clone())
+    }
+}
+
+pub struct DenseSolver<A: DenseAnalysis> {
+    entry: SecondaryMap<BlockId, A::Fact>,
+    exit: SecondaryMap<BlockId, A::Fact>,
+    /// Blocks whose facts are computed at least once. The facts of the other
+    /// blocks, e.g., the blocks that are unreachable in a forward analysis,
+    /// don't flow into their neighbors.
+    visited: SecondaryMap<BlockId, bool>,
+    work: VecDeque<BlockId>,
+    in_work: SecondaryMap<BlockId, bool>,
+}
+
+impl<A: DenseAnalysis> DenseSolver<A> {
+    pub fn new() -> Self {
+        Self {
+            entry: SecondaryMap::default(),
+            exit: SecondaryMap::default(),
+            visited: SecondaryMap::default(),
+            work: VecDeque::default(),
+            in_work: SecondaryMap::default(),
+        }
+    }
+
+    pub fn solve(&mut self, analysis: &A, func: &Function, cfg: &ControlFlowGraph) {
+        self.clear();
+
+        // Visiting blocks in reverse post order for a forward analysis, and in
+        // post order for a backward one, makes most facts flow in one sweep.
+        let mut blocks: Vec<_> = cfg.post_order().collect();
+        if A::DIRECTION == Direction::Forward {
+            blocks.reverse();
+        }
+        for block in blocks {
+            self.push(block);
+        }
+
+        while let Some(block) = self.work.pop_front() {
+            self.in_work[block] = false;
+            match A::DIRECTION {
+                Direction::Forward => self.visit_forward(analysis, func, cfg, block),
+                Direction::Backward => self.visit_backward(analysis, func, cfg, block),
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entry.clear();
+        self.exit.clear();
+        self.visited.clear();
+        self.work.clear();
+        self.in_work.clear();
+    }
+
+    /// Returns the fact at the entry of `block`.
+    pub fn entry_fact(&self, block: BlockId) -> &A::Fact {
+        &self.entry[block]
+    }
+
+    /// Returns the fact at the exit of `block`.
+    pub fn exit_fact(&self, block: BlockId) -> &A::Fact {
+        &self.exit[block]
+    }
+
+    /// Returns the fact that flows into `inst`, i.e., the fact before `inst`
+    /// for a forward analysis, and the fact after `inst` for a backward one.
+    pub fn fact_at(&self, analysis: &A, func: &Function, inst: InstId) -> A::Fact {
+        let block = func.layout.inst_block(inst);
+        match A::DIRECTION {
+            Direction::Forward => {
+                let mut fact = self.entry[block].clone();
+                for prev in func.layout.iter_inst(block) {
+                    if prev == inst {
+                        break;
+                    }
+                    analysis.transfer(func, prev, &mut fact);
+                }
+                fact
+            }
+
+            Direction::Backward => {
+                let mut fact = self.exit[block].clone();
+                let mut next = func.layout.last_inst_of(block);
+                while let Some(next_inst) = next {
+                    if next_inst == inst {
+                        break;
+                    }
+                    analysis.transfer(func, next_inst, &mut fact);
+                    next = func.layout.prev_inst_of(next_inst);
+                }
+                fact
+            }
+        }
+    }
+
+    fn visit_forward(
+        &mut self,
+        analysis: &A,
+        func: &Function,
+        cfg: &ControlFlowGraph,
+        block: BlockId,
+    ) {
+        let is_entry = func.layout.entry_block() == Some(block);
+        let mut fact = if is_entry {
+            analysis.boundary(func)
+        } else {
+            A::Fact::default()
+        };
+        let mut is_reached = is_entry;
+        for &pred in cfg.preds_of(block) {
+            if let Some(edge_fact) = self.transfer_edge(analysis, func, pred, block) {
+                fact.join(&edge_fact);
+                is_reached = true;
+            }
+        }
+        // A block that no taken edge leads to is unreachable, so nothing flows
+        // out of it.
+        if !is_reached {
+            return;
+        }
+        self.entry[block] = fact.clone();
+
+        for inst in func.layout.iter_inst(block) {
+            analysis.transfer(func, inst, &mut fact);
+        }
+
+        if self.update(block, fact, false) {
+            for &succ in cfg.succs_of(block) {
+                self.push(succ);
+            }
+        }
+    }
+
+    fn visit_backward(
+        &mut self,
+        analysis: &A,
+        func: &Function,
+        cfg: &ControlFlowGraph,
+        block: BlockId,
+    ) {
+        let mut fact = if cfg.succ_num_of(block) == 0 {
+            analysis.boundary(func)
+        } else {
+            A::Fact::default()
+        };
+        for &succ in cfg.succs_of(block) {
+            if let Some(edge_fact) = self.transfer_edge(analysis, func, block, succ) {
+                fact.join(&edge_fact);
+            }
+        }
+        self.exit[block] = fact.clone();
+
+        let mut next = func.layout.last_inst_of(block);
+        while let Some(inst) = next {
+            analysis.transfer(func, inst, &mut fact);
+            next = func.layout.prev_inst_of(inst);
+        }
+
+        if self.update(block, fact, true) {
+            for &pred in cfg.preds_of(block) {
+                self.push(pred);
+            }
+        }
+    }
+
+    /// Returns the fact that flows along the edge from `from` to `to` in the
+    /// direction of the analysis.
+    fn transfer_edge(
+        &self,
+        analysis: &A,
+        func: &Function,
+        from: BlockId,
+        to: BlockId,
+    ) -> Option<A::Fact> {
+        let (src, fact) = match A::DIRECTION {
+            Direction::Forward => (from, &self.exit[from]),
+            Direction::Backward => (to, &self.entry[to]),
+        };
+        if !self.visited[src] {
+            return None;
+        }
+
+        let branch = func
+            .layout
+            .last_inst_of(from)
+            .and_then(|inst| func.dfg.branch_info(inst));
+        match branch {
+            Some(branch) => analysis.transfer_edge(func, branch.branch_kind(), from, to, fact),
+            None => Some(fact.clone()),
+        }
+    }
+
+    /// Stores the fact that flows out of `block`, and returns `true` if the
+    /// neighbors of `block` need to be revisited.
+    fn update(&mut self, block: BlockId, fact: A::Fact, is_entry: bool) -> bool {
+        let slot = if is_entry {
+            &mut self.entry[block]
+        } else {
+            &mut self.exit[block]
+        };
+        let changed = !self.visited[block] || *slot != fact;
+        *slot = fact;
+        self.visited[block] = true;
+        changed
+    }
+
+    fn push(&mut self, block: BlockId) {
+        if !self.in_work[block] {
+            self.in_work[block] = true;
+            self.work.push_back(block);
+        }
+    }
+}
+
+impl<A: DenseAnalysis> Default for DenseSolver<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashSet;
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::Add,
+            control_flow::{Br, Jump, Phi, Return},
+        },
+        prelude::*,
+        Type, ValueId,
+    };
+
+    use super::*;
+    use crate::domtree::DomTree;
+
+    /// Values that are used later.
+    struct Liveness;
+
+    #[derive(Debug, Clone, Default, PartialEq)]
+    struct Live(FxHashSet<ValueId>);
+
+    impl Lattice for Live {
+        fn join(&mut self, other: &Self) -> bool {
+            let len = self.0.len();
+            self.0.extend(other.0.iter().copied());
+            self.0.len() != len
+        }
+    }
+
+    impl DenseAnalysis for Liveness {
+        type Fact = Live;
+
+        const DIRECTION: Direction = Direction::Backward;
+
+        fn boundary(&self, _func: &Function) -> Live {
+            Live::default()
+        }
+
+        fn transfer(&self, func: &Function, inst: InstId, fact: &mut Live) {
+            if let Some(result) = func.dfg.inst_result(inst) {
+                fact.0.remove(&result);
+            }
+            // Arguments of a phi are used at the end of the predecessors.
+            if !func.dfg.is_phi(inst) {
+                func.dfg.inst(inst).for_each_value(&mut |value| {
+                    if func.dfg.value_imm(value).is_none() {
+                        fact.0.insert(value);
+                    }
+                });
+            }
+        }
+
+        fn transfer_edge(
+            &self,
+            func: &Function,
+            _branch: BranchKind,
+            from: BlockId,
+            to: BlockId,
+            fact: &Live,
+        ) -> Option<Live> {
+            let mut fact = fact.clone();
+            for inst in func.layout.iter_inst(to) {
+                let Some(phi) = func.dfg.cast_phi(inst) else {
+                    break;
+                };
+                for (value, block) in phi.args() {
+                    if *block == from && func.dfg.value_imm(*value).is_none() {
+                        fact.0.insert(*value);
+                    }
+                }
+            }
+            Some(fact)
+        }
+    }
+
+    /// Blocks that are on every path from the entry.
+    struct Dominators;
+
+    /// `None` is the set of all blocks, which is the least element of the
+    /// lattice whose join is the intersection.
+    #[derive(Clone, Default, PartialEq)]
+    struct Doms(Option<FxHashSet<BlockId>>);
+
+    impl Lattice for Doms {
+        fn join(&mut self, other: &Self) -> bool {
+            let Some(other) = &other.0 else {
+                return false;
+            };
+            match &mut self.0 {
+                Some(doms) => {
+                    let len = doms.len();
+                    doms.retain(|block| other.contains(block));
+                    doms.len() != len
+                }
+                None => {
+                    self.0 = Some(other.clone());
+                    true
+                }
+            }
+        }
+    }
+
+    impl DenseAnalysis for Dominators {
+        type Fact = Doms;
+
+        const DIRECTION: Direction = Direction::Forward;
+
+        fn boundary(&self, _func: &Function) -> Doms {
+            Doms(Some(FxHashSet::default()))
+        }
+
+        fn transfer(&self, func: &Function, inst: InstId, fact: &mut Doms) {
+            if let Some(doms) = &mut fact.0 {
+                doms.insert(func.layout.inst_block(inst));
+            }
+        }
+    }
+
+    /// Blocks that can be passed through from the entry, where a `br` on an
+    /// immediate condition takes only one of its destinations.
+    struct Reachability;
+
+    #[derive(Debug, Clone, Default, PartialEq)]
+    struct Passed(FxHashSet<BlockId>);
+
+    impl Lattice for Passed {
+        fn join(&mut self, other: &Self) -> bool {
+            let len = self.0.len();
+            self.0.extend(other.0.iter().copied());
+            self.0.len() != len
+        }
+    }
+
+    impl DenseAnalysis for Reachability {
+        type Fact = Passed;
+
+        const DIRECTION: Direction = Direction::Forward;
+
+        fn boundary(&self, _func: &Function) -> Passed {
+            Passed::default()
+        }
+
+        fn transfer(&self, func: &Function, inst: InstId, fact: &mut Passed) {
+            if func.dfg.is_terminator(inst) {
+                fact.0.insert(func.layout.inst_block(inst));
+            }
+        }
+
+        fn transfer_edge(
+            &self,
+            func: &Function,
+            branch: BranchKind,
+            _from: BlockId,
+            to: BlockId,
+            fact: &Passed,
+        ) -> Option<Passed> {
+            if let BranchKind::Br(br) = branch {
+                let cond = func.dfg.value_imm(*br.cond());
+                let taken = cond.map(|cond| {
+                    if cond.is_zero() {
+                        *br.z_dest()
+                    } else {
+                        *br.nz_dest()
+                    }
+                });
+                if taken.is_some_and(|taken| taken != to) {
+                    return None;
+                }
+            }
+            Some(fact.clone())
+        }
+    }
+
+    #[test]
+    fn pruned_edge() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let b4 = builder.append_block();
+
+        let arg = builder.args()[0];
+        builder.switch_to_block(b0);
+        let one = builder.make_imm_value(true);
+        builder.insert_inst_no_result_with(|| Br::new(is, one, b1, b2));
+
+        builder.switch_to_block(b1);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b3));
+
+        // `block2` is only reached through the edge that is never taken, so
+        // nothing flows from it into `block4`.
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b4));
+
+        builder.switch_to_block(b3);
+        let v0 = builder.insert_inst_with(|| Add::new(is, arg, arg), Type::I32);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b4));
+
+        builder.switch_to_block(b4);
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(arg)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+
+            let mut solver = DenseSolver::new();
+            solver.solve(&Reachability, func, &cfg);
+            let passed = |blocks: &[BlockId]| Passed(blocks.iter().copied().collect());
+            assert_eq!(*solver.entry_fact(b2), passed(&[]));
+            assert_eq!(*solver.exit_fact(b2), passed(&[]));
+            assert_eq!(*solver.entry_fact(b4), passed(&[b0, b1, b3]));
+
+            let add = func.dfg.value_inst(v0).unwrap();
+            let jump = func.layout.last_inst_of(b3).unwrap();
+            assert_eq!(solver.fact_at(&Reachability, func, add), passed(&[b0, b1]));
+            assert_eq!(solver.fact_at(&Reachability, func, jump), passed(&[b0, b1]));
+        });
+    }
+
+    #[test]
+    fn liveness_and_dominators() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I32, Type::I1], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+
+        let (arg, cond) = (builder.args()[0], builder.args()[1]);
+        builder.switch_to_block(b0);
+        let v0 = builder.insert_inst_with(|| Add::new(is, arg, arg), Type::I32);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let v1 = builder.insert_inst_with(|| Phi::new(is, vec![(v0, b0)]), Type::I32);
+        let v2 = builder.insert_inst_with(|| Add::new(is, v1, arg), Type::I32);
+        builder.insert_inst_no_result_with(|| Br::new(is, cond, b2, b3));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b1));
+        builder.append_phi_arg(v1, v2, b2);
+
+        builder.switch_to_block(b3);
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut cfg = ControlFlowGraph::new();
+            cfg.compute(func);
+
+            let mut liveness = DenseSolver::new();
+            liveness.solve(&Liveness, func, &cfg);
+            let live = |values: &[ValueId]| Live(values.iter().copied().collect());
+            assert_eq!(*liveness.exit_fact(b0), live(&[arg, cond, v0]));
+            assert_eq!(*liveness.entry_fact(b1), live(&[arg, cond]));
+            assert_eq!(*liveness.exit_fact(b1), live(&[arg, cond, v1, v2]));
+            assert_eq!(*liveness.exit_fact(b2), live(&[arg, cond, v2]));
+            assert_eq!(*liveness.entry_fact(b3), live(&[v1]));
+
+            let phi = func.dfg.value_inst(v1).unwrap();
+            assert_eq!(
+                liveness.fact_at(&Liveness, func, phi),
+                live(&[arg, cond, v1])
+            );
+
+            let mut domtree = DomTree::new();
+            domtree.compute(&cfg);
+            let mut dominators = DenseSolver::new();
+            dominators.solve(&Dominators, func, &cfg);
+            for block in [b0, b1, b2, b3] {
+                let doms = dominators.exit_fact(block).0.as_ref().unwrap();
+                for dom in [b0, b1, b2, b3] {
+                    assert_eq!(doms.contains(&dom), domtree.dominates(dom, block));
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,26 @@
+//! This module contains a generic engine for dataflow analyses.
+//!
+//! An analysis is defined by a [`Lattice`] of facts and transfer functions of
+//! instructions and control flow edges, and is solved by either of
+//! * [`DenseSolver`], which computes the facts at the entry and the exit of
+//!   each block by iterating over the CFG forward or backward, e.g., liveness
+//!   and available expressions.
+//! * [`SparseSolver`], which computes the fact of each SSA value by
+//!   propagating facts from definitions to uses through the edges that can be
+//!   taken, in the manner of SCCP, e.g., constants and known bits.
+
+mod dense;
+mod sparse;
+
+pub use dense::{DenseAnalysis, DenseSolver, Direction};
+pub use sparse::{SparseAnalysis, SparseSolver};
+
+/// A join-semilattice of facts. `Default` gives the least element, which
+/// means that nothing has flowed in yet.
+///
+/// Transfer functions must be monotone, and a lattice with infinite ascending
+/// chains must be widened to make the solvers terminate.
+pub trait Lattice: Clone + PartialEq + Default {
+    /// Joins `other` into `self`, and returns `true` if `self` is changed.
+    fn join(&mut self, other: &Self) -> bool;
+}
@@ -0,0 +1,427 @@
+use cranelift_entity::SecondaryMap;
+use rustc_hash::FxHashSet;
+use sonatina_ir::{inst::control_flow::BranchKind, BlockId, Function, InstId, ValueId};
+
+use super::Lattice;
+
+/// An analysis that computes a fact of each SSA value.
+pub trait SparseAnalysis {
+    type Fact: Lattice;
+
+    /// Returns the fact of a value that isn't defined by an instruction,
+    /// e.g., a function argument or an immediate.
+    fn value_fact(&self, func: &Function, value: ValueId) -> Self::Fact;
+
+    /// Returns the fact of the result of `inst`, where `fact_of` returns the
+    /// fact of an operand. The solver computes the fact of a phi by joining
+    /// the facts of the arguments that flow through reachable edges.
+    fn transfer(
+        &self,
+        func: &Function,
+        inst: InstId,
+        fact_of: &dyn Fn(ValueId) -> Self::Fact,
+    ) -> Self::Fact;
+
+    /// Returns `true` if `branch` may jump to `dest` under the facts given by
+    /// `fact_of`.
+    fn is_feasible_edge(
+        &self,
+        _func: &Function,
+        _branch: BranchKind,
+        _dest: BlockId,
+        _fact_of: &dyn Fn(ValueId) -> Self::Fact,
+    ) -> bool {
+        true
+    }
+
+    /// Returns the fact to store when the fact of a value changes from `old`
+    /// to `new`, where `updates` is the number of the previous changes. An
+    /// analysis whose lattice has infinite ascending chains widens the facts
+    /// here.
+    fn widen(&self, _old: &Self::Fact, new: Self::Fact, _updates: u32) -> Self::Fact {
+        new
+    }
+}
+
+pub struct SparseSolver<A: SparseAnalysis> {
+    facts: SecondaryMap<ValueId, A::Fact>,
+    /// The number of times the fact of each value has changed.
+    updates: SecondaryMap<ValueId, u32>,
+    reachable_blocks: SecondaryMap<BlockId, bool>,
+    reachable_edges: FxHashSet<(BlockId, BlockId)>,
+
+    flow_work: Vec<(BlockId, BlockId)>,
+    ssa_work: Vec<InstId>,
+}
+
+impl<A: SparseAnalysis> SparseSolver<A> {
+    pub fn new() -> Self {
+        Self {
+            facts: SecondaryMap::default(),
+            updates: SecondaryMap::default(),
+            reachable_blocks: SecondaryMap::default(),
+            reachable_edges: FxHashSet::default(),
+            flow_work: Vec::default(),
+            ssa_work: Vec::default(),
+        }
+    }
+
+    pub fn solve(&mut self, analysis: &A, func: &Function) {
+        self.clear();
+
+        let Some(entry_block) = func.layout.entry_block() else {
+            return;
+        };
+        self.reachable_blocks[entry_block] = true;
+        self.eval_insts_in(analysis, func, entry_block);
+
+        loop {
+            if let Some((from, to)) = self.flow_work.pop() {
+                self.eval_edge(analysis, func, from, to);
+            } else if let Some(inst) = self.ssa_work.pop() {
+                if self.reachable_blocks[func.layout.inst_block(inst)] {
+                    self.eval_inst(analysis, func, inst);
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.facts.clear();
+        self.updates.clear();
+        self.reachable_blocks.clear();
+        self.reachable_edges.clear();
+        self.flow_work.clear();
+        self.ssa_work.clear();
+    }
+
+    /// Returns the fact of `value`. The fact of a value that is defined in
+    /// an unreachable block is the least element.
+    pub fn fact(&self, analysis: &A, func: &Function, value: ValueId) -> A::Fact {
+        if func.dfg.value_inst(value).is_some() {
+            self.facts[value].clone()
+        } else {
+            analysis.value_fact(func, value)
+        }
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.reachable_blocks[block]
+    }
+
+    pub fn is_reachable_edge(&self, from: BlockId, to: BlockId) -> bool {
+        self.reachable_edges.contains(&(from, to))
+    }
+
+    fn eval_edge(&mut self, analysis: &A, func: &Function, from: BlockId, to: BlockId) {
+        if !self.reachable_edges.insert((from, to)) {
+            return;
+        }
+
+        if self.reachable_blocks[to] {
+            // Only phis are affected by the new edge.
+            for inst in func.layout.iter_inst(to) {
+                if !func.dfg.is_phi(inst) {
+                    break;
+                }
+                self.eval_inst(analysis, func, inst);
+            }
+        } else {
+            self.reachable_blocks[to] = true;
+            self.eval_insts_in(analysis, func, to);
+        }
+    }
+
+    fn eval_insts_in(&mut self, analysis: &A, func: &Function, block: BlockId) {
+        for inst in func.layout.iter_inst(block) {
+            self.eval_inst(analysis, func, inst);
+        }
+    }
+
+    fn eval_inst(&mut self, analysis: &A, func: &Function, inst: InstId) {
+        let dfg = &func.dfg;
+        let block = func.layout.inst_block(inst);
+
+        if let Some(branch) = dfg.branch_info(inst) {
+            let fact_of = |value| self.fact(analysis, func, value);
+            let dests: Vec<_> = branch
+                .dests()
+                .into_iter()
+                .filter(|dest| {
+                    analysis.is_feasible_edge(func, branch.branch_kind(), *dest, &fact_of)
+                })
+                .collect();
+            self.flow_work
+                .extend(dests.into_iter().map(|dest| (block, dest)));
+            return;
+        }
+
+        let Some(result) = dfg.inst_result(inst) else {
+            return;
+        };
+
+        let fact = if let Some(phi) = dfg.cast_phi(inst) {
+            let mut fact = A::Fact::default();
+            for (arg, pred) in phi.args() {
+                if self.is_reachable_edge(*pred, block) {
+                    fact.join(&self.fact(analysis, func, *arg));
+                }
+            }
+            fact
+        } else {
+            analysis.transfer(func, inst, &|value| self.fact(analysis, func, value))
+        };
+
+        self.update(analysis, func, result, fact);
+    }
+
+    fn update(&mut self, analysis: &A, func: &Function, value: ValueId, fact: A::Fact) {
+        let fact = analysis.widen(&self.facts[value], fact, self.updates[value]);
+        if self.facts[value] == fact {
+            return;
+        }
+
+        self.facts[value] = fact;
+        self.updates[value] += 1;
+        self.ssa_work.extend(func.dfg.users(value).copied());
+    }
+}
+
+impl<A: SparseAnalysis> Default for SparseSolver<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::Add,
+            control_flow::{Br, Jump, Phi, Return},
+        },
+        prelude::*,
+        Type, Value,
+    };
+
+    use super::*;
+
+    /// Upper bounds of non-negative values.
+    struct UpperBound;
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    enum Bound {
+        #[default]
+        Bot,
+        Le(usize),
+        Top,
+    }
+
+    impl Lattice for Bound {
+        fn join(&mut self, other: &Self) -> bool {
+            let joined = match (*self, *other) {
+                (Bound::Bot, other) | (other, Bound::Bot) => other,
+                (Bound::Le(lhs), Bound::Le(rhs)) => Bound::Le(lhs.max(rhs)),
+                _ => Bound::Top,
+            };
+            let changed = *self != joined;
+            *self = joined;
+            changed
+        }
+    }
+
+    impl SparseAnalysis for UpperBound {
+        type Fact = Bound;
+
+        fn value_fact(&self, func: &Function, value: ValueId) -> Bound {
+            match func.dfg.value(value) {
+                Value::Immediate { imm, .. } => Bound::Le(imm.as_usize()),
+                _ => Bound::Top,
+            }
+        }
+
+        fn transfer(
+            &self,
+            func: &Function,
+            inst: InstId,
+            fact_of: &dyn Fn(ValueId) -> Bound,
+        ) -> Bound {
+            let Some(add) = <&Add as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(inst))
+            else {
+                return Bound::Top;
+            };
+            match (fact_of(*add.lhs()), fact_of(*add.rhs())) {
+                (Bound::Bot, _) | (_, Bound::Bot) => Bound::Bot,
+                (Bound::Le(lhs), Bound::Le(rhs)) => Bound::Le(lhs + rhs),
+                _ => Bound::Top,
+            }
+        }
+
+        fn is_feasible_edge(
+            &self,
+            _func: &Function,
+            branch: BranchKind,
+            dest: BlockId,
+            fact_of: &dyn Fn(ValueId) -> Bound,
+        ) -> bool {
+            let BranchKind::Br(br) = branch else {
+                return true;
+            };
+            match fact_of(*br.cond()) {
+                Bound::Bot => false,
+                Bound::Le(0) => dest == *br.z_dest(),
+                _ => true,
+            }
+        }
+
+        fn widen(&self, old: &Bound, new: Bound, updates: u32) -> Bound {
+            if updates >= 4 && *old != new {
+                Bound::Top
+            } else {
+                new
+            }
+        }
+    }
+
+    #[test]
+    fn unreachable_edge() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        let zero = builder.make_imm_value(false);
+        builder.insert_inst_no_result_with(|| Br::new(is, zero, b1, b2));
+
+        builder.switch_to_block(b1);
+        let ten = builder.make_imm_value(10i32);
+        let v0 = builder.insert_inst_with(|| Add::new(is, ten, ten), Type::I32);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b3));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b3));
+
+        builder.switch_to_block(b3);
+        let one = builder.make_imm_value(1i32);
+        let v1 = builder.insert_inst_with(|| Phi::new(is, vec![(v0, b1), (one, b2)]), Type::I32);
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut solver = SparseSolver::new();
+            solver.solve(&UpperBound, func);
+
+            assert!(!solver.is_reachable(b1));
+            assert!(solver.is_reachable(b2));
+            assert!(solver.is_reachable(b3));
+            assert!(!solver.is_reachable_edge(b0, b1));
+            assert!(!solver.is_reachable_edge(b1, b3));
+            assert!(solver.is_reachable_edge(b2, b3));
+
+            // The values in unreachable blocks are never evaluated.
+            assert_eq!(solver.fact(&UpperBound, func, v0), Bound::Bot);
+            assert_eq!(solver.fact(&UpperBound, func, v1), Bound::Le(1));
+        });
+    }
+
+    #[test]
+    fn phi_joins_executable_edges() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+
+        // `block1` is reachable, but its edge to `block3` is never taken.
+        let (cond, arg) = (builder.args()[0], builder.args()[1]);
+        builder.switch_to_block(b0);
+        builder.insert_inst_no_result_with(|| Br::new(is, cond, b1, b2));
+
+        builder.switch_to_block(b1);
+        let zero = builder.make_imm_value(false);
+        builder.insert_inst_no_result_with(|| Br::new(is, zero, b3, b2));
+
+        builder.switch_to_block(b2);
+        let two = builder.make_imm_value(2i32);
+        let v0 = builder.insert_inst_with(|| Phi::new(is, vec![(arg, b0), (two, b1)]), Type::I32);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b3));
+
+        builder.switch_to_block(b3);
+        let hundred = builder.make_imm_value(100i32);
+        let v1 =
+            builder.insert_inst_with(|| Phi::new(is, vec![(hundred, b1), (two, b2)]), Type::I32);
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut solver = SparseSolver::new();
+            solver.solve(&UpperBound, func);
+
+            assert!(solver.is_reachable(b1));
+            assert!(!solver.is_reachable_edge(b1, b3));
+            assert_eq!(solver.fact(&UpperBound, func, v0), Bound::Top);
+            assert_eq!(solver.fact(&UpperBound, func, v1), Bound::Le(2));
+        });
+    }
+
+    #[test]
+    fn widening_terminates() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+
+        let cond = builder.args()[0];
+        builder.switch_to_block(b0);
+        let zero = builder.make_imm_value(0i32);
+        builder.insert_inst_no_result_with(|| Jump::new(is, b1));
+
+        // The bound of the counter grows by one in each iteration, and never
+        // converges without widening.
+        builder.switch_to_block(b1);
+        let v0 = builder.insert_inst_with(|| Phi::new(is, vec![(zero, b0)]), Type::I32);
+        let one = builder.make_imm_value(1i32);
+        let v1 = builder.insert_inst_with(|| Add::new(is, v0, one), Type::I32);
+        builder.append_phi_arg(v0, v1, b1);
+        builder.insert_inst_no_result_with(|| Br::new(is, cond, b1, b2));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut solver = SparseSolver::new();
+            solver.solve(&UpperBound, func);
+
+            assert!(solver.is_reachable(b2));
+            assert_eq!(solver.fact(&UpperBound, func, v0), Bound::Top);
+            assert_eq!(solver.fact(&UpperBound, func, v1), Bound::Top);
+        });
+    }
+}
@@ -1,5 +1,8 @@
+pub mod alias_analysis;
 pub mod critical_edge;
+pub mod dataflow;
 pub mod domtree;
 pub mod loop_analysis;
 pub mod optim;
 pub mod post_domtree;
+pub mod range_analysis;
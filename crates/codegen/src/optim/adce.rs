@@ -49,18 +49,20 @@ impl AdceSolver {
         self.post_domtree.compute(func);
         let pdf_set = self.post_domtree.compute_df();
 
-        // TODO: We should remove this restriction.
-        // ref: <https://reviews.llvm.org/D35851>
-        if self.has_infinite_loop(func) {
-            return false;
-        }
-
         for block in func.layout.iter_block() {
             for inst in func.layout.iter_inst(block) {
                 if matches!(func.dfg.side_effect(inst), SideEffect::Write) {
                     self.mark_inst(func, inst);
                 }
             }
+
+            // Keep infinite loops, and the branches leading only to them, since
+            // removing them would make the function terminate.
+            if !self.post_domtree.reaches_exit(block) {
+                if let Some(last_inst) = func.layout.last_inst_of(block) {
+                    self.mark_inst(func, last_inst);
+                }
+            }
         }
 
         while let Some(inst) = self.worklist.pop() {
@@ -70,16 +72,6 @@ impl AdceSolver {
         self.eliminate_dead_code(func)
     }
 
-    fn has_infinite_loop(&self, func: &Function) -> bool {
-        for block in func.layout.iter_block() {
-            if !self.post_domtree.is_reachable(block) {
-                return true;
-            }
-        }
-
-        false
-    }
-
     fn mark_inst(&mut self, func: &Function, inst: InstId) {
         let mut mark_inst = |inst, block| {
             if !self.does_inst_live(inst) {
@@ -0,0 +1,885 @@
+//! This module contains a solver for global value numbering.
+//!
+//! Values are partitioned into congruence classes by the RPO-based optimistic
+//! value numbering described in Taylor Simpson.: Value-Driven Redundancy
+//! Elimination: PhD thesis, Rice University, 1996. The function is traversed
+//! in RPO until the classes don't change, ignoring edges that are never taken.
+//! The conditions of branches are used to infer operands in the blocks that
+//! are reached only through them, and each expression is simplified with the
+//! rules of the [instruction simplification](super::insn_simplify) before it's
+//! looked up.
+//!
+//! Once the classes are fixed, an expression that uses phis is translated into
+//! each predecessor of the phis' block. If all the translated expressions are
+//! available, a new phi of them replaces the expression (phi-of-ops), which
+//! eliminates redundancies that are only partial on the phis.
+//!
+//! Finally, each value is replaced with a member of its class that dominates
+//! it, and unreachable edges and blocks are removed.
+
+use cranelift_entity::{EntityRef, SecondaryMap};
+use rustc_hash::{FxHashMap, FxHashSet};
+use smallvec::SmallVec;
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::{control_flow::BranchKind, data, evm},
+    prelude::*,
+    BlockId, ControlFlowGraph, DataFlowGraph, Function, Immediate, InstId, Type, Value, ValueId,
+};
+
+use super::insn_simplify::{
+    egraph::{CastOp, EClassId, EGraph, ENode, Op},
+    pattern::{self, Rewrite},
+    rules, ToENode,
+};
+use crate::domtree::DomTree;
+
+/// The maximum number of rule applications when an expression is simplified.
+const SIMPLIFY_ITER_LIMIT: usize = 4;
+
+/// The maximum number of nested blocks that an expression is translated
+/// through.
+const PHI_TRANSLATE_LIMIT: usize = 4;
+
+pub struct GvnSolver {
+    rules: Vec<Rewrite>,
+    egraph: EGraph,
+    /// The value number of each value, which is the representative of its
+    /// congruence class. `None` means that the value isn't visited yet.
+    vn: SecondaryMap<ValueId, Option<ValueId>>,
+    /// The members of each class.
+    classes: FxHashMap<ValueId, Vec<ValueId>>,
+    /// The expression that each value is computed by.
+    exprs: SecondaryMap<ValueId, Option<Expr>>,
+    /// The class of each expression.
+    table: FxHashMap<Expr, ValueId>,
+    reachable_edges: FxHashSet<(BlockId, BlockId)>,
+    /// The position of each instruction in the RPO. Phis inserted by the
+    /// solver have `0`.
+    rank: SecondaryMap<InstId, u32>,
+}
+
+impl GvnSolver {
+    pub fn new() -> Self {
+        Self {
+            rules: rules::rules(),
+            egraph: EGraph::new(),
+            vn: SecondaryMap::default(),
+            classes: FxHashMap::default(),
+            exprs: SecondaryMap::default(),
+            table: FxHashMap::default(),
+            reachable_edges: FxHashSet::default(),
+            rank: SecondaryMap::default(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.egraph.clear();
+        self.vn.clear();
+        self.classes.clear();
+        self.exprs.clear();
+        self.table.clear();
+        self.reachable_edges.clear();
+        self.rank.clear();
+    }
+
+    /// Run global value numbering on the function.
+    /// `cfg` and `domtree` must be computed for `func`, and are recomputed
+    /// after the function is modified.
+    pub fn run(&mut self, func: &mut Function, cfg: &mut ControlFlowGraph, domtree: &mut DomTree) {
+        self.clear();
+        if func.layout.entry_block().is_none() {
+            return;
+        }
+
+        let rpo = domtree.rpo().to_vec();
+        let insts = rpo.iter().flat_map(|&block| func.layout.iter_inst(block));
+        for (rank, inst) in insts.enumerate() {
+            self.rank[inst] = rank as u32 + 1;
+        }
+
+        self.number_values(func, cfg, domtree, &rpo);
+        self.insert_value_phis(func, cfg, domtree, &rpo);
+        self.eliminate(func, domtree, &rpo);
+        self.remove_unreachable_edges(func, cfg);
+
+        cfg.compute(func);
+        domtree.compute(cfg);
+    }
+
+    /// Computes the congruence classes of the values in reachable blocks.
+    fn number_values(
+        &mut self,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        domtree: &DomTree,
+        rpo: &[BlockId],
+    ) {
+        loop {
+            let mut changed = false;
+            // The expressions of the previous iteration may be based on the
+            // classes that are split in this iteration.
+            self.table.clear();
+
+            for &block in rpo {
+                if !self.is_reachable_block(func, cfg, block) {
+                    continue;
+                }
+
+                let insts: Vec<_> = func.layout.iter_inst(block).collect();
+                for inst in insts {
+                    if let Some(result) = func.dfg.inst_result(inst) {
+                        let vn = self.eval_inst(func, cfg, domtree, block, inst, result);
+                        if self.vn[result] != Some(vn) {
+                            self.set_vn(result, vn);
+                            changed = true;
+                        }
+                    }
+                    changed |= self.eval_branch(func, cfg, domtree, block, inst);
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// Returns the value number of the result of `inst`.
+    fn eval_inst(
+        &mut self,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        domtree: &DomTree,
+        block: BlockId,
+        inst: InstId,
+        result: ValueId,
+    ) -> ValueId {
+        if func.dfg.is_phi(inst) {
+            return self.eval_phi(func, cfg, block, inst, result);
+        }
+        if !is_numberable(&func.dfg, inst) {
+            return result;
+        }
+
+        let expr = self.make_expr(func, cfg, domtree, block, inst);
+        self.exprs[result] = Some(expr.clone());
+        if let Some(vn) = self.simplify(func, &expr, self.rank[inst]) {
+            return vn;
+        }
+        *self.table.entry(expr).or_insert(result)
+    }
+
+    fn eval_phi(
+        &mut self,
+        func: &Function,
+        cfg: &ControlFlowGraph,
+        block: BlockId,
+        inst: InstId,
+        result: ValueId,
+    ) -> ValueId {
+        let mut args = SmallVec::new();
+        for &pred in cfg.preds_of(block) {
+            if !self.is_reachable_edge(pred, block) {
+                continue;
+            }
+            let Some(arg) = phi_arg_of(&func.dfg, inst, pred) else {
+                return result;
+            };
+            // Arguments that aren't visited yet are optimistically assumed to
+            // be congruent with the others.
+            if let Some(vn) = self.vn_of(&func.dfg, arg) {
+                args.push(vn);
+            }
+        }
+
+        match args.first() {
+            Some(&first) if args.iter().all(|&arg| arg == first) => first,
+            Some(_) => {
+                let expr = Expr::Phi(block, args);
+                self.exprs[result] = Some(expr.clone());
+                *self.table.entry(expr).or_insert(result)
+            }
+            None => result,
+        }
+    }
+
+    /// Marks the edges that can be taken from `inst`. Returns `true` if a new
+    /// edge is found.
+    fn eval_branch(
+        &mut self,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        domtree: &DomTree,
+        block: BlockId,
+        inst: InstId,
+    ) -> bool {
+        let Some(bi) = func.dfg.branch_info(inst) else {
+            return false;
+        };
+        let mut dests = bi.dests();
+        let cond = match bi.branch_kind() {
+            BranchKind::Jump(_) => None,
+            BranchKind::Br(br) => Some(*br.cond()),
+            BranchKind::BrTable(brt) => Some(*brt.scrutinee()),
+        };
+
+        if let Some(cond) = cond {
+            let cond = self.infer(func, cfg, domtree, cond, block);
+            if let Some(imm) = func.dfg.value_imm(cond) {
+                dests = match func.dfg.branch_info(inst).unwrap().branch_kind() {
+                    BranchKind::Br(br) if imm.is_zero() => vec![*br.z_dest()],
+                    BranchKind::Br(br) => vec![*br.nz_dest()],
+                    BranchKind::BrTable(brt) => brt
+                        .table()
+                        .iter()
+                        .find(|(value, _)| func.dfg.value_imm(*value) == Some(imm))
+                        .map(|(_, dest)| *dest)
+                        .or(*brt.default())
+                        .into_iter()
+                        .collect(),
+                    BranchKind::Jump(_) => unreachable!(),
+                };
+            }
+        }
+
+        let mut changed = false;
+        for dest in dests {
+            changed |= self.reachable_edges.insert((block, dest));
+        }
+        changed
+    }
+
+    /// Makes the expression of `inst` in `block`, whose operands are replaced
+    /// with their value numbers.
+    fn make_expr(
+        &mut self,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        domtree: &DomTree,
+        block: BlockId,
+        inst: InstId,
+    ) -> Expr {
+        let dfg = &func.dfg;
+        let mut operands = SmallVec::<[ValueId; 4]>::new();
+        let node = match InstDowncast::downcast(dfg.inst_set(), dfg.inst(inst)) {
+            Some(to_enode) => {
+                let to_enode: &dyn ToENode = to_enode;
+                // Each operand is lowered to its position so that it can be
+                // replaced with its value number.
+                Some(to_enode.to_enode(&mut |value| {
+                    operands.push(value);
+                    EClassId::new(operands.len() - 1)
+                }))
+            }
+            None => {
+                dfg.inst(inst)
+                    .for_each_value(&mut |value| operands.push(value));
+                None
+            }
+        };
+
+        let args: SmallVec<[ValueId; 4]> = operands
+            .into_iter()
+            .map(|value| self.infer(func, cfg, domtree, value, block))
+            .collect();
+
+        let expr = match node {
+            Some(ENode::Op(op, _)) => Expr::Op(op, args.into_iter().collect()),
+            Some(ENode::Cast(op, ty, _)) => Expr::Cast(op, ty, args[0]),
+            _ => {
+                let dfg = &func.dfg;
+                let result = dfg.inst_result(inst).unwrap();
+                Expr::Inst(dfg.inst(inst).as_text(), dfg.value_ty(result), args)
+            }
+        };
+        self.canonicalize(&func.dfg, expr)
+    }
+
+    /// Returns the value number of `value` in `block`, refined by the
+    /// conditions of the branches that `block` can only be reached through.
+    fn infer(
+        &mut self,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        domtree: &DomTree,
+        value: ValueId,
+        block: BlockId,
+    ) -> ValueId {
+        let vn = self.vn_of(&func.dfg, value).unwrap_or(value);
+
+        let mut block = block;
+        loop {
+            if let Some(pred) = self.single_reachable_pred(cfg, block) {
+                if let Some(inferred) = self.infer_on_edge(func, pred, block, vn) {
+                    return inferred;
+                }
+            }
+
+            match domtree.idom_of(block) {
+                Some(idom) => block = idom,
+                None => return vn,
+            }
+        }
+    }
+
+    /// Returns the value that `vn` is equal to when the edge from `pred` to
+    /// `block` is taken.
+    fn infer_on_edge(
+        &self,
+        func: &mut Function,
+        pred: BlockId,
+        block: BlockId,
+        vn: ValueId,
+    ) -> Option<ValueId> {
+        let term = func.layout.last_inst_of(pred)?;
+        let (cond, is_taken_if_true) = match func.dfg.branch_info(term)?.branch_kind() {
+            BranchKind::Br(br) if br.nz_dest() != br.z_dest() => {
+                (*br.cond(), *br.nz_dest() == block)
+            }
+
+            BranchKind::BrTable(brt) => {
+                if *brt.default() == Some(block) {
+                    return None;
+                }
+                let mut cases = brt.table().iter().filter(|(_, dest)| *dest == block);
+                let (case, _) = *cases.next()?;
+                if cases.next().is_some() {
+                    return None;
+                }
+
+                let scrutinee = self.vn_of(&func.dfg, *brt.scrutinee());
+                return (scrutinee == Some(vn))
+                    .then(|| self.vn_of(&func.dfg, case).unwrap_or(case));
+            }
+
+            _ => return None,
+        };
+
+        if self.vn_of(&func.dfg, cond) == Some(vn) {
+            return Some(func.dfg.make_imm_value(is_taken_if_true));
+        }
+
+        // The operands of commutative operations are sorted by rank, so the
+        // higher-ranked one is replaced with the other.
+        match self.exprs[cond].as_ref()? {
+            Expr::Op(Op::Eq, args) if is_taken_if_true && args[1] == vn => Some(args[0]),
+            Expr::Op(Op::Ne, args) if !is_taken_if_true && args[1] == vn => Some(args[0]),
+            Expr::Op(Op::IsZero, args) if is_taken_if_true && args[0] == vn => {
+                let ty = func.dfg.value_ty(vn);
+                Some(func.dfg.make_imm_value(Immediate::zero(ty)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Simplifies `expr` with the rules of the instruction simplification.
+    /// Returns the value number of the result if it's a constant or one of
+    /// the values that `expr` is built from.
+    ///
+    /// Only the class members defined before `rank` are used to expand the
+    /// operands; otherwise, a value could be simplified by its own expression
+    /// from the previous iteration.
+    fn simplify(&mut self, func: &mut Function, expr: &Expr, rank: u32) -> Option<ValueId> {
+        if !matches!(expr, Expr::Op(..) | Expr::Cast(..)) {
+            return None;
+        }
+
+        let dfg = &func.dfg;
+        let egraph = &mut self.egraph;
+        egraph.clear();
+
+        // The operands are expanded with the expressions of their class
+        // members, so that the rules can look through them.
+        let mut children = SmallVec::<[EClassId; 2]>::new();
+        for &arg in expr.args() {
+            let id = add_leaf(egraph, dfg, arg);
+            if dfg.value_imm(arg).is_some() {
+                children.push(id);
+                continue;
+            }
+
+            for &member in self.classes.get(&arg).into_iter().flatten() {
+                let is_before = dfg
+                    .value_inst(member)
+                    .is_some_and(|inst| self.rank[inst] < rank);
+                let Some(member_expr) = self.exprs[member].as_ref().filter(|_| is_before) else {
+                    continue;
+                };
+                if let Some(node) = member_expr.to_enode(&mut |value| add_leaf(egraph, dfg, value))
+                {
+                    let member_id = egraph.add(node);
+                    egraph.union(id, member_id);
+                }
+            }
+            children.push(id);
+        }
+
+        let node = match expr {
+            Expr::Op(op, _) => ENode::Op(*op, children),
+            Expr::Cast(op, ty, _) => ENode::Cast(*op, *ty, children[0]),
+            _ => unreachable!(),
+        };
+        let root = egraph.add(node);
+        egraph.rebuild();
+
+        for _ in 0..SIMPLIFY_ITER_LIMIT {
+            let changed = pattern::apply_rules(egraph, &self.rules);
+            egraph.rebuild();
+            if !changed {
+                break;
+            }
+        }
+
+        let class = egraph.class(root);
+        if let Some(imm) = class.constant {
+            return Some(func.dfg.make_imm_value(imm));
+        }
+        let leaves: SmallVec<[ValueId; 4]> = class
+            .nodes
+            .iter()
+            .filter_map(|node| match node {
+                ENode::Value(value) => Some(*value),
+                _ => None,
+            })
+            .collect();
+        leaves
+            .into_iter()
+            .min_by_key(|&value| self.rank_key(&func.dfg, value))
+    }
+
+    /// Replaces expressions whose operands are phis with phis of the
+    /// expressions translated into the predecessors.
+    fn insert_value_phis(
+        &mut self,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        domtree: &DomTree,
+        rpo: &[BlockId],
+    ) {
+        for &block in rpo {
+            if !self.is_reachable_block(func, cfg, block) {
+                continue;
+            }
+
+            let insts: Vec<_> = func.layout.iter_inst(block).collect();
+            for inst in insts {
+                let Some(result) = func.dfg.inst_result(inst) else {
+                    continue;
+                };
+                let Some(expr @ (Expr::Op(..) | Expr::Cast(..))) = self.exprs[result].clone()
+                else {
+                    continue;
+                };
+                let Some(vn) = self.vn[result] else {
+                    continue;
+                };
+                if func.dfg.value_imm(vn).is_some()
+                    || self.find_leader(func, domtree, result).is_some()
+                {
+                    continue;
+                }
+
+                let Some(phi_block) = self.phi_block_of(func, &expr) else {
+                    continue;
+                };
+                if !domtree.dominates(phi_block, block) {
+                    continue;
+                }
+
+                if let Some(new_vn) = self.translate(func, cfg, domtree, &expr, phi_block, 0) {
+                    if new_vn != vn {
+                        self.merge_class(&func.dfg, vn, new_vn);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Translates `expr` into each predecessor of `block`, which contains the
+    /// phis that `expr` uses. Returns the value number of a value that is
+    /// equal to `expr` at the top of `block`.
+    fn translate(
+        &mut self,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        domtree: &DomTree,
+        expr: &Expr,
+        block: BlockId,
+        depth: usize,
+    ) -> Option<ValueId> {
+        if depth > PHI_TRANSLATE_LIMIT {
+            return None;
+        }
+
+        let mut has_phi = false;
+        for &arg in expr.args() {
+            if self.is_phi_of(func, arg, block) {
+                has_phi = true;
+            } else if !is_available_at_top(func, domtree, arg, block) {
+                return None;
+            }
+        }
+        if !has_phi {
+            return None;
+        }
+
+        let preds: Vec<_> = cfg
+            .preds_of(block)
+            .copied()
+            .filter(|&pred| self.is_reachable_edge(pred, block))
+            .collect();
+        let mut args = Vec::with_capacity(preds.len());
+        for pred in preds {
+            let translated = self.translate_into(func, expr, block, pred);
+            let vn = match self.lookup(func, &translated) {
+                Some(vn) => vn,
+                None => self.translate(func, cfg, domtree, &translated, pred, depth + 1)?,
+            };
+            let value = self.available_member(func, domtree, vn, pred)?;
+            args.push((value, pred));
+        }
+
+        let vns: SmallVec<[ValueId; 4]> = args
+            .iter()
+            .map(|&(value, _)| self.vn_of(&func.dfg, value).unwrap_or(value))
+            .collect();
+        let first = *vns.first()?;
+        if vns.iter().all(|&vn| vn == first) {
+            return Some(first);
+        }
+
+        let expr = Expr::Phi(block, vns);
+        if let Some(&vn) = self.table.get(&expr) {
+            return Some(vn);
+        }
+
+        let ty = func.dfg.value_ty(args[0].0);
+        let phi = func.dfg.make_phi(args);
+        let mut inserter = InstInserter::at_location(CursorLocation::BlockTop(block));
+        let inst = inserter.insert_inst_data(func, phi);
+        let result = inserter.make_result(func, inst, ty);
+        inserter.attach_result(func, inst, result);
+
+        self.set_vn(result, result);
+        self.exprs[result] = Some(expr.clone());
+        self.table.insert(expr, result);
+        Some(result)
+    }
+
+    /// Replaces the phis of `block` in `expr` with their arguments from
+    /// `pred`.
+    fn translate_into(&self, func: &Function, expr: &Expr, block: BlockId, pred: BlockId) -> Expr {
+        let dfg = &func.dfg;
+        let expr = expr.map_args(|arg| {
+            if !self.is_phi_of(func, arg, block) {
+                return arg;
+            }
+            let inst = dfg.value_inst(arg).unwrap();
+            let value = phi_arg_of(dfg, inst, pred).unwrap();
+            self.vn_of(dfg, value).unwrap_or(value)
+        });
+        self.canonicalize(dfg, expr)
+    }
+
+    fn lookup(&mut self, func: &mut Function, expr: &Expr) -> Option<ValueId> {
+        self.simplify(func, expr, u32::MAX)
+            .or_else(|| self.table.get(expr).copied())
+    }
+
+    /// Returns the block of the phis that `expr` uses, if all of them are in
+    /// the same block.
+    fn phi_block_of(&self, func: &Function, expr: &Expr) -> Option<BlockId> {
+        let mut phi_block = None;
+        for &arg in expr.args() {
+            let Some(inst) = func.dfg.value_inst(arg) else {
+                continue;
+            };
+            if !func.dfg.is_phi(inst) {
+                continue;
+            }
+
+            let block = func.layout.inst_block(inst);
+            match phi_block {
+                Some(phi_block) if phi_block != block => return None,
+                _ => phi_block = Some(block),
+            }
+        }
+        phi_block
+    }
+
+    /// Returns a member of the class of `vn` that is available at the end of
+    /// `block`.
+    fn available_member(
+        &self,
+        func: &Function,
+        domtree: &DomTree,
+        vn: ValueId,
+        block: BlockId,
+    ) -> Option<ValueId> {
+        if !matches!(func.dfg.value(vn), Value::Inst { .. }) {
+            return Some(vn);
+        }
+
+        self.classes
+            .get(&vn)?
+            .iter()
+            .copied()
+            .filter(|&member| match func.dfg.value_inst(member) {
+                Some(inst) => {
+                    func.layout.is_inst_inserted(inst)
+                        && domtree.dominates(func.layout.inst_block(inst), block)
+                }
+                None => true,
+            })
+            .min_by_key(|&member| self.rank_key(&func.dfg, member))
+    }
+
+    /// Returns a member of the class of `value` other than `value` itself that
+    /// dominates `value`.
+    fn find_leader(&self, func: &Function, domtree: &DomTree, value: ValueId) -> Option<ValueId> {
+        let vn = self.vn[value]?;
+        if !matches!(func.dfg.value(vn), Value::Inst { .. }) {
+            return Some(vn);
+        }
+
+        let user = func.dfg.value_inst(value)?;
+        let user_block = func.layout.inst_block(user);
+        self.classes
+            .get(&vn)?
+            .iter()
+            .copied()
+            .filter(|&member| member != value)
+            .filter(|&member| match func.dfg.value_inst(member) {
+                Some(inst) if !func.layout.is_inst_inserted(inst) => false,
+                Some(inst) => {
+                    let block = func.layout.inst_block(inst);
+                    if block == user_block {
+                        self.rank[inst] < self.rank[user]
+                    } else {
+                        domtree.dominates(block, user_block)
+                    }
+                }
+                None => true,
+            })
+            .min_by_key(|&member| self.rank_key(&func.dfg, member))
+    }
+
+    /// Replaces each value with a dominating member of its class.
+    fn eliminate(&mut self, func: &mut Function, domtree: &DomTree, rpo: &[BlockId]) {
+        for &block in rpo {
+            let insts: Vec<_> = func.layout.iter_inst(block).collect();
+            for inst in insts {
+                let Some(result) = func.dfg.inst_result(inst) else {
+                    continue;
+                };
+                if !func.dfg.is_phi(inst) && !is_numberable(&func.dfg, inst) {
+                    continue;
+                }
+
+                if let Some(leader) = self.find_leader(func, domtree, result) {
+                    InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                    func.dfg.change_to_alias(result, leader);
+                }
+            }
+        }
+    }
+
+    fn remove_unreachable_edges(&self, func: &mut Function, cfg: &ControlFlowGraph) {
+        let blocks: Vec<_> = func.layout.iter_block().collect();
+        let (reachable, unreachable): (Vec<_>, Vec<_>) = blocks
+            .into_iter()
+            .partition(|&block| self.is_reachable_block(func, cfg, block));
+
+        for block in reachable {
+            let insts: Vec<_> = func.layout.iter_inst(block).collect();
+            for inst in insts {
+                if let Some(phi) = func.dfg.cast_phi_mut(inst) {
+                    phi.retain(|pred| self.reachable_edges.contains(&(pred, block)));
+
+                    // Remove phi function if it has just one argument.
+                    if phi.args().len() == 1 {
+                        let phi_arg = phi.args()[0].0;
+                        let phi_value = func.dfg.inst_result(inst).unwrap();
+                        InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                        func.dfg.change_to_alias(phi_value, phi_arg);
+                    }
+                } else if let Some(bi) = func.dfg.branch_info(inst) {
+                    for dest in bi.dests() {
+                        if !self.is_reachable_edge(block, dest) {
+                            func.dfg.remove_branch_dest(inst, dest);
+                        }
+                    }
+                }
+            }
+        }
+
+        for block in unreachable {
+            InstInserter::at_location(CursorLocation::BlockTop(block)).remove_block(func);
+        }
+    }
+
+    /// Merges the class `from` into the class `to`.
+    fn merge_class(&mut self, dfg: &DataFlowGraph, from: ValueId, to: ValueId) {
+        for member in self.classes.remove(&from).unwrap_or_default() {
+            self.vn[member] = Some(to);
+            self.classes.entry(to).or_default().push(member);
+        }
+
+        let replace = |value| if value == from { to } else { value };
+        for expr in self.exprs.values_mut().flatten() {
+            *expr = expr.map_args(replace);
+        }
+        let table = std::mem::take(&mut self.table);
+        for (expr, vn) in table {
+            let expr = self.canonicalize(dfg, expr.map_args(replace));
+            self.table.insert(expr, replace(vn));
+        }
+    }
+
+    fn set_vn(&mut self, value: ValueId, vn: ValueId) {
+        if let Some(old) = self.vn[value] {
+            if let Some(members) = self.classes.get_mut(&old) {
+                members.retain(|&member| member != value);
+            }
+        }
+        self.vn[value] = Some(vn);
+        self.classes.entry(vn).or_default().push(value);
+    }
+
+    fn vn_of(&self, dfg: &DataFlowGraph, value: ValueId) -> Option<ValueId> {
+        match dfg.value(value) {
+            Value::Inst { .. } => self.vn[value],
+            _ => Some(value),
+        }
+    }
+
+    /// Sorts the operands of a commutative operation by rank.
+    fn canonicalize(&self, dfg: &DataFlowGraph, mut expr: Expr) -> Expr {
+        if let Expr::Op(op, args) = &mut expr {
+            if op.is_commutative() {
+                args.sort_by_key(|&arg| self.rank_key(dfg, arg));
+            }
+        }
+        expr
+    }
+
+    /// Returns the key to order values. Constants come first, then
+    /// arguments, then the results of instructions in RPO.
+    fn rank_key(&self, dfg: &DataFlowGraph, value: ValueId) -> (u32, u32) {
+        let rank = match dfg.value(value) {
+            Value::Immediate { .. } => 0,
+            Value::Inst { inst, .. } => self.rank[*inst] + 2,
+            _ => 1,
+        };
+        (rank, value.as_u32())
+    }
+
+    fn is_phi_of(&self, func: &Function, value: ValueId, block: BlockId) -> bool {
+        func.dfg
+            .value_inst(value)
+            .is_some_and(|inst| func.dfg.is_phi(inst) && func.layout.inst_block(inst) == block)
+    }
+
+    fn single_reachable_pred(&self, cfg: &ControlFlowGraph, block: BlockId) -> Option<BlockId> {
+        let mut preds = cfg
+            .preds_of(block)
+            .filter(|&&pred| self.is_reachable_edge(pred, block));
+        let pred = *preds.next()?;
+        preds.next().is_none().then_some(pred)
+    }
+
+    fn is_reachable_block(&self, func: &Function, cfg: &ControlFlowGraph, block: BlockId) -> bool {
+        func.layout.entry_block() == Some(block)
+            || cfg
+                .preds_of(block)
+                .any(|&pred| self.is_reachable_edge(pred, block))
+    }
+
+    fn is_reachable_edge(&self, from: BlockId, to: BlockId) -> bool {
+        self.reachable_edges.contains(&(from, to))
+    }
+}
+
+impl Default for GvnSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An expression whose operands are value numbers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Expr {
+    Op(Op, SmallVec<[ValueId; 2]>),
+    Cast(CastOp, Type, ValueId),
+    /// A phi whose arguments are ordered by their predecessors.
+    Phi(BlockId, SmallVec<[ValueId; 4]>),
+    /// An instruction without side effects that isn't represented in the
+    /// e-graph, identified by its name and result type.
+    Inst(&'static str, Type, SmallVec<[ValueId; 4]>),
+}
+
+impl Expr {
+    fn args(&self) -> &[ValueId] {
+        match self {
+            Self::Op(_, args) => args,
+            Self::Cast(_, _, arg) => std::slice::from_ref(arg),
+            Self::Phi(_, args) | Self::Inst(_, _, args) => args,
+        }
+    }
+
+    fn map_args(&self, mut f: impl FnMut(ValueId) -> ValueId) -> Self {
+        match self {
+            Self::Op(op, args) => Self::Op(*op, args.iter().map(|&arg| f(arg)).collect()),
+            Self::Cast(op, ty, arg) => Self::Cast(*op, *ty, f(*arg)),
+            Self::Phi(block, args) => Self::Phi(*block, args.iter().map(|&arg| f(arg)).collect()),
+            Self::Inst(name, ty, args) => {
+                Self::Inst(name, *ty, args.iter().map(|&arg| f(arg)).collect())
+            }
+        }
+    }
+
+    fn to_enode(&self, leaf: &mut dyn FnMut(ValueId) -> EClassId) -> Option<ENode> {
+        match self {
+            Self::Op(op, args) => Some(ENode::Op(*op, args.iter().map(|&arg| leaf(arg)).collect())),
+            Self::Cast(op, ty, arg) => Some(ENode::Cast(*op, *ty, leaf(*arg))),
+            _ => None,
+        }
+    }
+}
+
+fn add_leaf(egraph: &mut EGraph, dfg: &DataFlowGraph, value: ValueId) -> EClassId {
+    match dfg.value_imm(value) {
+        Some(imm) => egraph.add(ENode::Imm(imm)),
+        None => egraph.add_value(value, dfg.value_ty(value)),
+    }
+}
+
+/// Returns `true` if the result of `inst` is determined by its operands.
+fn is_numberable(dfg: &DataFlowGraph, inst: InstId) -> bool {
+    let isb = dfg.inst_set();
+    let data = dfg.inst(inst);
+
+    !data.side_effect().has_effect()
+        && dfg.inst_result(inst).is_some()
+        && !dfg.is_phi(inst)
+        // These depend on a function or the memory rather than their operands.
+        && <&data::GetFunctionPtr as InstDowncast>::downcast(isb, data).is_none()
+        && <&evm::EvmContractSize as InstDowncast>::downcast(isb, data).is_none()
+        && <&evm::EvmKeccak256 as InstDowncast>::downcast(isb, data).is_none()
+}
+
+fn is_available_at_top(func: &Function, domtree: &DomTree, value: ValueId, block: BlockId) -> bool {
+    match func.dfg.value_inst(value) {
+        Some(inst) => domtree.strictly_dominates(func.layout.inst_block(inst), block),
+        None => true,
+    }
+}
+
+fn phi_arg_of(dfg: &DataFlowGraph, inst: InstId, pred: BlockId) -> Option<ValueId> {
+    dfg.cast_phi(inst)?
+        .args()
+        .iter()
+        .find(|(_, block)| *block == pred)
+        .map(|(value, _)| *value)
+}
@@ -0,0 +1,453 @@
+//! This module contains a function inliner.
+//!
+//! The inliner works on a whole module. Functions are visited in the
+//! post-order of the call graph so that a callee is already processed when it
+//! is inlined into its callers, and calls that are exposed by inlining are not
+//! inlined again. Recursive functions are never inlined.
+//!
+//! Whether a call is inlined is decided by the cost of the callee, which is
+//! the number of its instructions. A callee is inlined if its cost is within
+//! [`InlinerConfig::threshold`], which is relaxed for `Private` callees because
+//! they are never called from outside of the module. A `Private` callee
+//! that has only one call site in the module is inlined as long as its cost is
+//! within [`InlinerConfig::single_call_threshold`].
+
+use cranelift_entity::{PrimaryMap, SecondaryMap};
+use rustc_hash::{FxHashMap, FxHashSet};
+use smallvec::SmallVec;
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::{control_flow, data},
+    module::FuncRef,
+    prelude::*,
+    visitor::{VisitableMut, VisitorMut},
+    BlockId, Function, Inst, InstId, Module, Value, ValueId,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InlinerConfig {
+    /// The maximum cost of a callee to be inlined.
+    pub threshold: usize,
+
+    /// The additional cost allowed for a `Private` callee.
+    pub private_bonus: usize,
+
+    /// The maximum cost of a `Private` callee that is called only once in the
+    /// module.
+    pub single_call_threshold: usize,
+}
+
+impl Default for InlinerConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 8,
+            private_bonus: 8,
+            single_call_threshold: 256,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Inliner {
+    config: InlinerConfig,
+
+    /// Callees of each function.
+    callees: FxHashMap<FuncRef, Vec<FuncRef>>,
+
+    /// The number of call sites of each function. The function whose address
+    /// is taken is regarded as having an unknown number of call sites.
+    call_sites: FxHashMap<FuncRef, usize>,
+
+    /// Functions that can reach themselves in the call graph.
+    recursive: FxHashSet<FuncRef>,
+
+    /// Cached bodies of callees.
+    bodies: FxHashMap<FuncRef, Option<CalleeBody>>,
+}
+
+impl Inliner {
+    pub fn new(config: InlinerConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.callees.clear();
+        self.call_sites.clear();
+        self.recursive.clear();
+        self.bodies.clear();
+    }
+
+    /// Inlines calls in all functions in the module.
+    /// Returns `true` if any call is inlined.
+    pub fn run(&mut self, module: &Module) -> bool {
+        self.clear();
+        self.build_call_graph(module);
+
+        let mut changed = false;
+        for caller in self.post_order(module) {
+            let call_sites = module
+                .func_store
+                .view(caller, |func| self.collect_call_sites(module, func));
+
+            for (call, callee) in call_sites {
+                self.bodies
+                    .entry(callee)
+                    .or_insert_with(|| module.func_store.view(callee, CalleeBody::new));
+                let Some(body) = &self.bodies[&callee] else {
+                    continue;
+                };
+                if !self.should_inline(module, callee, body) {
+                    continue;
+                }
+
+                module
+                    .func_store
+                    .modify(caller, |func| inline_call(func, call, body));
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    fn build_call_graph(&mut self, module: &Module) {
+        for func_ref in module.funcs() {
+            if !has_definition(module, func_ref) {
+                continue;
+            }
+
+            let mut callees = Vec::new();
+            module.func_store.view(func_ref, |func| {
+                let is = func.inst_set();
+                for block in func.layout.iter_block() {
+                    for inst in func.layout.iter_inst(block) {
+                        let inst = func.dfg.inst(inst);
+                        if let Some(call) =
+                            <&control_flow::Call as InstDowncast>::downcast(is, inst)
+                        {
+                            let num = self.call_sites.entry(*call.callee()).or_default();
+                            *num = num.saturating_add(1);
+                            callees.push(*call.callee());
+                        } else if let Some(ptr) =
+                            <&data::GetFunctionPtr as InstDowncast>::downcast(is, inst)
+                        {
+                            self.call_sites.insert(*ptr.func(), usize::MAX);
+                        }
+                    }
+                }
+            });
+
+            callees.sort_unstable();
+            callees.dedup();
+            self.callees.insert(func_ref, callees);
+        }
+
+        for &func_ref in self.callees.keys() {
+            if self.reaches(func_ref, func_ref) {
+                self.recursive.insert(func_ref);
+            }
+        }
+    }
+
+    /// Returns `true` if `to` is reachable from `from` through at least one
+    /// call.
+    fn reaches(&self, from: FuncRef, to: FuncRef) -> bool {
+        let mut visited = FxHashSet::default();
+        let mut stack = vec![from];
+        while let Some(func_ref) = stack.pop() {
+            for &callee in self.callees.get(&func_ref).into_iter().flatten() {
+                if callee == to {
+                    return true;
+                }
+                if visited.insert(callee) {
+                    stack.push(callee);
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns the functions in the post-order of the call graph.
+    fn post_order(&self, module: &Module) -> Vec<FuncRef> {
+        let mut order = Vec::new();
+        let mut visited = FxHashSet::default();
+
+        for func_ref in module.funcs() {
+            if !self.callees.contains_key(&func_ref) || !visited.insert(func_ref) {
+                continue;
+            }
+
+            let mut stack = vec![(func_ref, 0)];
+            while let Some((func_ref, idx)) = stack.pop() {
+                let callees = &self.callees[&func_ref];
+                if let Some(&callee) = callees.get(idx) {
+                    stack.push((func_ref, idx + 1));
+                    if self.callees.contains_key(&callee) && visited.insert(callee) {
+                        stack.push((callee, 0));
+                    }
+                } else {
+                    order.push(func_ref);
+                }
+            }
+        }
+
+        order
+    }
+
+    /// Collects calls in `func` whose callee is a candidate for inlining.
+    fn collect_call_sites(&self, module: &Module, func: &Function) -> Vec<(InstId, FuncRef)> {
+        let is = func.inst_set();
+        let mut call_sites = Vec::new();
+        for block in func.layout.iter_block() {
+            for inst in func.layout.iter_inst(block) {
+                let Some(call) =
+                    <&control_flow::Call as InstDowncast>::downcast(is, func.dfg.inst(inst))
+                else {
+                    continue;
+                };
+
+                let callee = *call.callee();
+                if has_definition(module, callee) && !self.recursive.contains(&callee) {
+                    call_sites.push((inst, callee));
+                }
+            }
+        }
+        call_sites
+    }
+
+    fn should_inline(&self, module: &Module, callee: FuncRef, body: &CalleeBody) -> bool {
+        let is_private = module
+            .ctx
+            .func_sig(callee, |sig| sig.linkage().is_private());
+        if !is_private {
+            return body.cost <= self.config.threshold;
+        }
+
+        if self.call_sites.get(&callee).copied().unwrap_or_default() == 1 {
+            body.cost <= self.config.single_call_threshold
+        } else {
+            body.cost <= self.config.threshold + self.config.private_bonus
+        }
+    }
+}
+
+fn has_definition(module: &Module, func_ref: FuncRef) -> bool {
+    module
+        .ctx
+        .func_sig(func_ref, |sig| sig.linkage().has_definition())
+}
+
+/// An instruction of the callee and its result.
+type CalleeInst = (Box<dyn Inst>, Option<ValueId>);
+
+/// A copy of the callee taken out of the function store so that the callee
+/// and the caller are not locked at the same time.
+struct CalleeBody {
+    values: PrimaryMap<ValueId, Value>,
+    blocks: Vec<(BlockId, Vec<CalleeInst>)>,
+    cost: usize,
+}
+
+impl CalleeBody {
+    /// Returns `None` if the function can't be inlined, i.e., the function has
+    /// no body or its entry block has phis.
+    fn new(func: &Function) -> Option<Self> {
+        let entry = func.layout.entry_block()?;
+        if func
+            .layout
+            .first_inst_of(entry)
+            .is_some_and(|inst| func.dfg.is_phi(inst))
+        {
+            return None;
+        }
+
+        let mut cost = 0;
+        let blocks = func
+            .layout
+            .iter_block()
+            .map(|block| {
+                let insts = func
+                    .layout
+                    .iter_inst(block)
+                    .map(|inst| {
+                        if !func.dfg.is_phi(inst) {
+                            cost += 1;
+                        }
+                        (
+                            dyn_clone::clone_box(func.dfg.inst(inst)),
+                            func.dfg.inst_result(inst),
+                        )
+                    })
+                    .collect();
+                (block, insts)
+            })
+            .collect();
+
+        Some(Self {
+            values: func.dfg.values.clone(),
+            blocks,
+            cost,
+        })
+    }
+}
+
+/// Inlines `body` into the position of `call`.
+fn inline_call(func: &mut Function, call: InstId, body: &CalleeBody) {
+    let is = func.inst_set();
+    let args: SmallVec<[ValueId; 8]> =
+        <&control_flow::Call as InstDowncast>::downcast(is, func.dfg.inst(call))
+            .unwrap()
+            .args()
+            .clone();
+    let call_result = func.dfg.inst_result(call);
+    let block = func.layout.inst_block(call);
+
+    // Move the instructions following the call into a continuation block.
+    let cont = func.dfg.make_block();
+    func.layout.insert_block_after(cont, block);
+    let mut next = func.layout.next_inst_of(call);
+    while let Some(inst) = next {
+        next = func.layout.next_inst_of(inst);
+        func.layout.remove_inst(inst);
+        func.layout.append_inst(inst, cont);
+    }
+    rewrite_succ_phis(func, cont, block);
+
+    // Map the blocks and the values of the callee.
+    let mut mapper = Mapper::default();
+    let mut after = block;
+    for (callee_block, _) in &body.blocks {
+        let new_block = func.dfg.make_block();
+        func.layout.insert_block_after(new_block, after);
+        mapper.blocks.insert(*callee_block, new_block);
+        after = new_block;
+    }
+    for (value, data) in body.values.iter() {
+        let mapped = match data {
+            Value::Arg { idx, .. } => args[*idx],
+            Value::Immediate { imm, .. } => func.dfg.make_imm_value(*imm),
+            // Results are defined when the instructions are inserted.
+            Value::Inst { ty, .. } | Value::Undef { ty } => {
+                func.dfg.make_value(Value::Undef { ty: *ty })
+            }
+            Value::Global { .. } => func.dfg.make_value(data.clone()),
+        };
+        mapper.values[value] = Some(mapped);
+    }
+
+    let mut returns = Vec::new();
+    for (callee_block, insts) in &body.blocks {
+        let new_block = mapper.blocks[callee_block];
+        for (inst, result) in insts {
+            if let Some(ret) = <&control_flow::Return as InstDowncast>::downcast(is, inst.as_ref())
+            {
+                returns.push((ret.arg().map(|arg| mapper.value(arg)), new_block));
+                let jump = func.dfg.make_jump(cont);
+                let jump = func.dfg.make_inst(jump);
+                func.layout.append_inst(jump, new_block);
+                continue;
+            }
+
+            let mut inst = dyn_clone::clone_box(inst.as_ref());
+            inst.accept_mut(&mut mapper);
+            let inst = func.dfg.make_inst_dyn(inst);
+            func.layout.append_inst(inst, new_block);
+
+            if let Some(result) = result {
+                let result = mapper.value(*result);
+                let ty = func.dfg.value_ty(result);
+                func.dfg.values[result] = Value::Inst { inst, ty };
+                func.dfg.attach_result(inst, result);
+            }
+        }
+    }
+
+    // Replace the call with a jump to the inlined entry block.
+    InstInserter::at_location(CursorLocation::At(call)).remove_inst(func);
+    let entry = mapper.blocks[&body.blocks[0].0];
+    let jump = func.dfg.make_jump(entry);
+    let jump = func.dfg.make_inst(jump);
+    func.layout.append_inst(jump, block);
+
+    let Some(call_result) = call_result else {
+        return;
+    };
+    let ret_value = match returns.as_slice() {
+        [] => {
+            let ty = func.dfg.value_ty(call_result);
+            func.dfg.make_undef_value(ty)
+        }
+        [(value, _), rest @ ..] if rest.iter().all(|(v, _)| v == value) => value.unwrap(),
+        _ => {
+            let args = returns
+                .iter()
+                .map(|(value, block)| (value.unwrap(), *block))
+                .collect();
+            let phi = func.dfg.make_phi(args);
+            let phi = func.dfg.make_inst(phi);
+            func.layout.prepend_inst(phi, cont);
+
+            let ty = func.dfg.value_ty(call_result);
+            let result = func.dfg.make_value(Value::Inst { inst: phi, ty });
+            func.dfg.attach_result(phi, result);
+            result
+        }
+    };
+    func.dfg.change_to_alias(call_result, ret_value);
+}
+
+/// Rewrites phi arguments from `from` to `block` in the successors of `block`.
+fn rewrite_succ_phis(func: &mut Function, block: BlockId, from: BlockId) {
+    let Some(last_inst) = func.layout.last_inst_of(block) else {
+        return;
+    };
+    let Some(branch) = func.dfg.branch_info(last_inst) else {
+        return;
+    };
+
+    let mut dests = branch.dests();
+    dests.sort_unstable();
+    dests.dedup();
+
+    let mut mapper = Mapper::default();
+    mapper.blocks.insert(from, block);
+    for dest in dests {
+        let phis: Vec<_> = func
+            .layout
+            .iter_inst(dest)
+            .take_while(|&inst| func.dfg.is_phi(inst))
+            .collect();
+        for phi in phis {
+            func.dfg.cast_phi_mut(phi).unwrap().accept_mut(&mut mapper);
+        }
+    }
+}
+
+/// Maps values and blocks of the callee to the ones of the caller. Values and
+/// blocks that are not in the map are left unchanged.
+#[derive(Default)]
+struct Mapper {
+    values: SecondaryMap<ValueId, Option<ValueId>>,
+    blocks: FxHashMap<BlockId, BlockId>,
+}
+
+impl Mapper {
+    fn value(&self, value: ValueId) -> ValueId {
+        self.values[value].unwrap_or(value)
+    }
+}
+
+impl VisitorMut for Mapper {
+    fn visit_value_id(&mut self, item: &mut ValueId) {
+        *item = self.value(*item);
+    }
+
+    fn visit_block_id(&mut self, item: &mut BlockId) {
+        if let Some(block) = self.blocks.get(item) {
+            *item = *block;
+        }
+    }
+}
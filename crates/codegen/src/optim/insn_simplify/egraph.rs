@@ -0,0 +1,466 @@
+//! A minimal e-graph, which compactly represents a set of terms together with
+//! an equivalence relation over them.
+//!
+//! Each e-class is annotated with the type of its terms and, if known, the
+//! constant all of them evaluate to. Classes that are found to be constant are
+//! merged with the class of the constant, so that constant folding happens as
+//! a side effect of building and rewriting the graph.
+
+use cranelift_entity::{entity_impl, PrimaryMap};
+use rustc_hash::{FxHashMap, FxHashSet};
+use smallvec::SmallVec;
+use sonatina_ir::{Immediate, InstId, Type, ValueId};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EClassId(u32);
+entity_impl!(EClassId, "eclass");
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ENode {
+    /// A value that is opaque to the e-graph, e.g., a function argument or the
+    /// result of an instruction that is not simplified.
+    Value(ValueId),
+    Imm(Immediate),
+    Op(Op, SmallVec<[EClassId; 2]>),
+    Cast(CastOp, Type, EClassId),
+    /// A phi instruction with its arguments.
+    ///
+    /// Phis are identified by the instruction so that phis in different
+    /// blocks are never merged by congruence.
+    Phi(InstId, SmallVec<[EClassId; 4]>),
+}
+
+impl ENode {
+    pub fn children(&self) -> &[EClassId] {
+        match self {
+            Self::Value(_) | Self::Imm(_) => &[],
+            Self::Op(_, args) => args,
+            Self::Cast(_, _, arg) => std::slice::from_ref(arg),
+            Self::Phi(_, args) => args,
+        }
+    }
+
+    fn children_mut(&mut self) -> &mut [EClassId] {
+        match self {
+            Self::Value(_) | Self::Imm(_) => &mut [],
+            Self::Op(_, args) => args,
+            Self::Cast(_, _, arg) => std::slice::from_mut(arg),
+            Self::Phi(_, args) => args,
+        }
+    }
+}
+
+/// Operations on integers that are represented in the e-graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    Neg,
+    Not,
+    Add,
+    Sub,
+    Mul,
+    And,
+    Or,
+    Xor,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Slt,
+    Sgt,
+    Sle,
+    Sge,
+    IsZero,
+    EvmUdiv,
+    EvmSdiv,
+    EvmUmod,
+    EvmSmod,
+    EvmAddMod,
+    EvmMulMod,
+    EvmExp,
+    EvmByte,
+}
+
+impl Op {
+    /// Returns the operation whose text form in the IR is `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let op = match name {
+            "neg" => Self::Neg,
+            "not" => Self::Not,
+            "add" => Self::Add,
+            "sub" => Self::Sub,
+            "mul" => Self::Mul,
+            "and" => Self::And,
+            "or" => Self::Or,
+            "xor" => Self::Xor,
+            "eq" => Self::Eq,
+            "ne" => Self::Ne,
+            "lt" => Self::Lt,
+            "gt" => Self::Gt,
+            "le" => Self::Le,
+            "ge" => Self::Ge,
+            "slt" => Self::Slt,
+            "sgt" => Self::Sgt,
+            "sle" => Self::Sle,
+            "sge" => Self::Sge,
+            "is_zero" => Self::IsZero,
+            "evm_udiv" => Self::EvmUdiv,
+            "evm_sdiv" => Self::EvmSdiv,
+            "evm_umod" => Self::EvmUmod,
+            "evm_smod" => Self::EvmSmod,
+            "evm_add_mod" => Self::EvmAddMod,
+            "evm_mul_mod" => Self::EvmMulMod,
+            "evm_exp" => Self::EvmExp,
+            "evm_byte" => Self::EvmByte,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Self::Neg | Self::Not | Self::IsZero => 1,
+            Self::EvmAddMod | Self::EvmMulMod => 3,
+            _ => 2,
+        }
+    }
+
+    pub fn is_commutative(self) -> bool {
+        matches!(
+            self,
+            Self::Add | Self::Mul | Self::And | Self::Or | Self::Xor | Self::Eq | Self::Ne
+        )
+    }
+
+    /// Returns `true` if the operation yields `i1` regardless of the type of
+    /// its operands.
+    pub fn is_predicate(self) -> bool {
+        matches!(
+            self,
+            Self::Eq
+                | Self::Ne
+                | Self::Lt
+                | Self::Gt
+                | Self::Le
+                | Self::Ge
+                | Self::Slt
+                | Self::Sgt
+                | Self::Sle
+                | Self::Sge
+                | Self::IsZero
+        )
+    }
+
+    /// Returns the index of the operand whose type is the type of the result,
+    /// unless the operation is a predicate.
+    pub fn ty_operand(self) -> usize {
+        match self {
+            Self::EvmByte => 1,
+            _ => 0,
+        }
+    }
+
+    /// Evaluates the operation with the semantics of the corresponding
+    /// instruction, e.g., `evm_udiv` by zero yields zero.
+    pub fn eval(self, args: &[Immediate]) -> Immediate {
+        match (self, args) {
+            (Self::Neg, &[arg]) => -arg,
+            (Self::Not, &[arg]) => !arg,
+            (Self::IsZero, &[arg]) => arg.is_zero().into(),
+            (Self::Add, &[lhs, rhs]) => lhs + rhs,
+            (Self::Sub, &[lhs, rhs]) => lhs - rhs,
+            (Self::Mul, &[lhs, rhs]) => lhs * rhs,
+            (Self::And, &[lhs, rhs]) => lhs & rhs,
+            (Self::Or, &[lhs, rhs]) => lhs | rhs,
+            (Self::Xor, &[lhs, rhs]) => lhs ^ rhs,
+            (Self::Eq, &[lhs, rhs]) => lhs.imm_eq(rhs),
+            (Self::Ne, &[lhs, rhs]) => lhs.imm_ne(rhs),
+            (Self::Lt, &[lhs, rhs]) => lhs.lt(rhs),
+            (Self::Gt, &[lhs, rhs]) => lhs.gt(rhs),
+            (Self::Le, &[lhs, rhs]) => lhs.le(rhs),
+            (Self::Ge, &[lhs, rhs]) => lhs.ge(rhs),
+            (Self::Slt, &[lhs, rhs]) => lhs.slt(rhs),
+            (Self::Sgt, &[lhs, rhs]) => lhs.sgt(rhs),
+            (Self::Sle, &[lhs, rhs]) => lhs.sle(rhs),
+            (Self::Sge, &[lhs, rhs]) => lhs.sge(rhs),
+            (Self::EvmUdiv, &[lhs, rhs]) => lhs.evm_udiv(rhs),
+            (Self::EvmSdiv, &[lhs, rhs]) => lhs.evm_sdiv(rhs),
+            (Self::EvmUmod, &[lhs, rhs]) => lhs.evm_umod(rhs),
+            (Self::EvmSmod, &[lhs, rhs]) => lhs.evm_smod(rhs),
+            (Self::EvmAddMod, &[lhs, rhs, modulus]) => lhs.evm_add_mod(rhs, modulus),
+            (Self::EvmMulMod, &[lhs, rhs, modulus]) => lhs.evm_mul_mod(rhs, modulus),
+            (Self::EvmExp, &[base, exponent]) => base.evm_exp(exponent),
+            (Self::EvmByte, &[pos, value]) => pos.evm_byte(value),
+            _ => unreachable!("wrong number of arguments for `{self:?}`"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CastOp {
+    Sext,
+    Zext,
+    Trunc,
+}
+
+impl CastOp {
+    /// Returns the cast whose text form in the IR is `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let op = match name {
+            "sext" => Self::Sext,
+            "zext" => Self::Zext,
+            "trunc" => Self::Trunc,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    pub fn eval(self, arg: Immediate, ty: Type) -> Immediate {
+        match self {
+            Self::Sext => arg.sext(ty),
+            Self::Zext => arg.zext(ty),
+            Self::Trunc => arg.trunc(ty),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EClass {
+    pub nodes: Vec<ENode>,
+    pub ty: Type,
+    /// The constant that all terms in the class evaluate to.
+    pub constant: Option<Immediate>,
+    /// The nodes that have this class as a child, with their classes.
+    parents: Vec<(ENode, EClassId)>,
+}
+
+#[derive(Debug, Default)]
+pub struct EGraph {
+    /// The union-find of e-classes.
+    leaders: PrimaryMap<EClassId, EClassId>,
+    /// Classes indexed by canonical ids.
+    classes: FxHashMap<EClassId, EClass>,
+    /// The class of each canonical node.
+    memo: FxHashMap<ENode, EClassId>,
+    /// Classes whose parents need to be repaired by [`EGraph::rebuild`].
+    pending: Vec<EClassId>,
+}
+
+impl EGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.leaders.clear();
+        self.classes.clear();
+        self.memo.clear();
+        self.pending.clear();
+    }
+
+    /// Returns the canonical id of the class.
+    pub fn find(&self, mut id: EClassId) -> EClassId {
+        while self.leaders[id] != id {
+            id = self.leaders[id];
+        }
+        id
+    }
+
+    pub fn class(&self, id: EClassId) -> &EClass {
+        &self.classes[&self.find(id)]
+    }
+
+    /// Returns the canonical ids of all classes.
+    pub fn class_ids(&self) -> impl Iterator<Item = EClassId> + '_ {
+        self.classes.keys().copied()
+    }
+
+    /// Returns the number of distinct nodes in the e-graph.
+    pub fn node_num(&self) -> usize {
+        self.memo.len()
+    }
+
+    /// Adds a leaf for `value` of `ty`.
+    pub fn add_value(&mut self, value: ValueId, ty: Type) -> EClassId {
+        self.add_with_ty(ENode::Value(value), ty)
+    }
+
+    /// Adds `node` and returns its class.
+    ///
+    /// # Panics
+    /// Panics if `node` is [`ENode::Value`], use [`EGraph::add_value`] instead.
+    pub fn add(&mut self, node: ENode) -> EClassId {
+        let ty = match &node {
+            ENode::Value(_) => panic!("use `add_value` to add a value"),
+            ENode::Imm(imm) => imm.ty(),
+            ENode::Op(op, _) if op.is_predicate() => Type::I1,
+            ENode::Cast(_, ty, _) => *ty,
+            ENode::Op(op, args) => self.class(args[op.ty_operand()]).ty,
+            ENode::Phi(_, args) => self.class(args[0]).ty,
+        };
+        self.add_with_ty(node, ty)
+    }
+
+    fn add_with_ty(&mut self, mut node: ENode, ty: Type) -> EClassId {
+        self.canonicalize(&mut node);
+        if let Some(&id) = self.memo.get(&node) {
+            return self.find(id);
+        }
+
+        let id = self.leaders.next_key();
+        self.leaders.push(id);
+        for &child in node.children() {
+            let child = self.find(child);
+            let parents = &mut self.classes.get_mut(&child).unwrap().parents;
+            parents.push((node.clone(), id));
+        }
+
+        let constant = self.eval(&node);
+        self.memo.insert(node.clone(), id);
+        self.classes.insert(
+            id,
+            EClass {
+                nodes: vec![node],
+                ty,
+                constant,
+                parents: Vec::new(),
+            },
+        );
+
+        if let Some(imm) = constant {
+            self.merge_constant(id, imm);
+        }
+        self.find(id)
+    }
+
+    /// Merges the classes of `a` and `b`, and returns `true` if they were
+    /// different.
+    ///
+    /// The congruence of the e-graph is restored only by
+    /// [`EGraph::rebuild`].
+    pub fn union(&mut self, a: EClassId, b: EClassId) -> bool {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+
+        let size = |id| {
+            let class = &self.classes[&id];
+            class.nodes.len() + class.parents.len()
+        };
+        if size(a) < size(b) {
+            std::mem::swap(&mut a, &mut b);
+        }
+
+        self.leaders[b] = a;
+        let absorbed = self.classes.remove(&b).unwrap();
+        let class = self.classes.get_mut(&a).unwrap();
+        debug_assert_eq!(class.ty, absorbed.ty);
+        class.nodes.extend(absorbed.nodes);
+        class.parents.extend(absorbed.parents);
+        class.constant = class.constant.or(absorbed.constant);
+
+        self.pending.push(a);
+        true
+    }
+
+    /// Restores the congruence of the e-graph, i.e., merges classes that
+    /// contain nodes that became identical by unions, and propagates
+    /// constants to parents.
+    pub fn rebuild(&mut self) {
+        while let Some(id) = self.pending.pop() {
+            let id = self.find(id);
+            if let Some(imm) = self.classes[&id].constant {
+                self.merge_constant(id, imm);
+            }
+            self.repair(self.find(id));
+        }
+
+        for class in self.classes.values_mut() {
+            let mut seen = FxHashSet::default();
+            for node in class.nodes.iter_mut() {
+                for child in node.children_mut() {
+                    *child = Self::find_in(&self.leaders, *child);
+                }
+            }
+            class.nodes.retain(|node| seen.insert(node.clone()));
+        }
+    }
+
+    /// Re-canonicalizes the parents of `id`.
+    fn repair(&mut self, id: EClassId) {
+        let parents = std::mem::take(&mut self.classes.get_mut(&id).unwrap().parents);
+
+        let mut repaired: FxHashMap<ENode, EClassId> = FxHashMap::default();
+        for (mut node, parent) in parents {
+            self.memo.remove(&node);
+            self.canonicalize(&mut node);
+            let mut parent = self.find(parent);
+
+            if let Some(imm) = self.eval(&node) {
+                let class = self.classes.get_mut(&parent).unwrap();
+                if class.constant.is_none() {
+                    class.constant = Some(imm);
+                    self.pending.push(parent);
+                }
+            }
+
+            if let Some(&other) = self.memo.get(&node) {
+                self.union(other, parent);
+                parent = self.find(parent);
+            }
+            if let Some(&other) = repaired.get(&node) {
+                self.union(other, parent);
+                parent = self.find(parent);
+            }
+            self.memo.insert(node.clone(), parent);
+            repaired.insert(node, parent);
+        }
+
+        let id = self.find(id);
+        let parents = &mut self.classes.get_mut(&id).unwrap().parents;
+        parents.extend(repaired);
+    }
+
+    /// Merges the class of `id` with the class of `imm`.
+    fn merge_constant(&mut self, id: EClassId, imm: Immediate) {
+        let node = ENode::Imm(imm);
+        if self.classes[&self.find(id)].nodes.contains(&node) {
+            return;
+        }
+
+        let imm_id = self.add(node);
+        self.union(id, imm_id);
+    }
+
+    /// Evaluates `node` if all of its children are constant.
+    fn eval(&self, node: &ENode) -> Option<Immediate> {
+        let constant = |id| self.class(id).constant;
+        match node {
+            ENode::Value(_) | ENode::Phi(..) => None,
+            ENode::Imm(imm) => Some(*imm),
+            ENode::Op(op, args) => {
+                let args: SmallVec<[Immediate; 2]> = args
+                    .iter()
+                    .map(|&arg| constant(arg))
+                    .collect::<Option<_>>()?;
+                Some(op.eval(&args))
+            }
+            ENode::Cast(op, ty, arg) => Some(op.eval(constant(*arg)?, *ty)),
+        }
+    }
+
+    fn canonicalize(&self, node: &mut ENode) {
+        for child in node.children_mut() {
+            *child = self.find(*child);
+        }
+    }
+
+    fn find_in(leaders: &PrimaryMap<EClassId, EClassId>, mut id: EClassId) -> EClassId {
+        while leaders[id] != id {
+            id = leaders[id];
+        }
+        id
+    }
+}
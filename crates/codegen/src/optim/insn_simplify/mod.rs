@@ -0,0 +1,668 @@
+//! This module contains a solver for instruction simplification based on
+//! equality saturation.
+//!
+//! Arithmetic, logic, comparison, cast and pure EVM arithmetic instructions
+//! are lowered to an e-graph, which is then saturated with the rewrite rules in [`rules`].
+//! Finally, each instruction is replaced with the cheapest equivalent form
+//! found in the e-graph.
+//!
+//! To add a new simplification, add a rule to [`rules`]; no hand-written
+//! matcher is required.
+
+pub mod egraph;
+pub mod pattern;
+pub mod rules;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use smallvec::{smallvec, SmallVec};
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::{arith, cast, cmp, evm, logic},
+    prelude::*,
+    BlockId, ControlFlowGraph, DataFlowGraph, Function, InstId, Value, ValueId,
+};
+use sonatina_macros::inst_prop;
+
+use self::{
+    egraph::{CastOp, EClassId, EGraph, ENode, Op},
+    pattern::Rewrite,
+};
+use crate::domtree::DomTree;
+
+/// The maximum number of rule applications over the whole e-graph.
+const ITER_LIMIT: usize = 8;
+
+/// Saturation stops when the e-graph grows beyond this number of nodes.
+const NODE_LIMIT: usize = 10_000;
+
+const INFINITE_COST: u32 = u32::MAX;
+
+pub struct InsnSimplifySolver {
+    rules: Vec<Rewrite>,
+    egraph: EGraph,
+    /// The e-class of each value that appears in the e-graph.
+    classes: FxHashMap<ValueId, EClassId>,
+    /// Instructions that are lowered to operations in the e-graph.
+    insts: FxHashSet<InstId>,
+    phis: Vec<InstId>,
+    /// The cheapest node of each class, and its cost.
+    best: FxHashMap<EClassId, (u32, ENode)>,
+    /// Values that are inserted to the function for each class.
+    materialized: FxHashMap<EClassId, Vec<ValueId>>,
+    /// The position of each instruction in its block.
+    order: FxHashMap<InstId, usize>,
+    cfg: ControlFlowGraph,
+    domtree: DomTree,
+}
+
+impl InsnSimplifySolver {
+    pub fn new() -> Self {
+        Self {
+            rules: rules::rules(),
+            egraph: EGraph::new(),
+            classes: FxHashMap::default(),
+            insts: FxHashSet::default(),
+            phis: Vec::default(),
+            best: FxHashMap::default(),
+            materialized: FxHashMap::default(),
+            order: FxHashMap::default(),
+            cfg: ControlFlowGraph::new(),
+            domtree: DomTree::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.egraph.clear();
+        self.classes.clear();
+        self.insts.clear();
+        self.phis.clear();
+        self.best.clear();
+        self.materialized.clear();
+        self.order.clear();
+        self.cfg.clear();
+        self.domtree.clear();
+    }
+
+    pub fn run(&mut self, func: &mut Function) {
+        self.clear();
+        if func.layout.entry_block().is_none() {
+            return;
+        }
+
+        self.cfg.compute(func);
+        self.domtree.compute(&self.cfg);
+
+        self.build(func);
+        self.saturate(func);
+        self.compute_best(&func.dfg);
+        self.rewrite(func);
+        self.remove_dead_insts(func);
+    }
+
+    /// Lowers the instructions in reachable blocks to the e-graph.
+    fn build(&mut self, func: &Function) {
+        let rpo = self.domtree.rpo().to_vec();
+        for block in rpo {
+            for (pos, inst) in func.layout.iter_inst(block).enumerate() {
+                // Leave a gap so that instructions inserted before `inst` can
+                // be ordered.
+                self.order.insert(inst, pos * 2 + 1);
+
+                let Some(result) = func.dfg.inst_result(inst) else {
+                    continue;
+                };
+
+                if func.dfg.is_phi(inst) {
+                    self.phis.push(inst);
+                    continue;
+                }
+
+                let Some(node) = self.lower(&func.dfg, inst) else {
+                    continue;
+                };
+                let id = self.egraph.add(node);
+                let leaf = self.class_of(&func.dfg, result);
+                self.egraph.union(leaf, id);
+                self.insts.insert(inst);
+            }
+        }
+
+        // Phis are lowered after all other instructions because their
+        // arguments may be defined later in the RPO.
+        for &inst in &self.phis {
+            let phi = func.dfg.cast_phi(inst).unwrap();
+            if phi.args().is_empty() {
+                continue;
+            }
+            let args = phi
+                .args()
+                .iter()
+                .map(|(arg, _)| {
+                    Self::class_of_in(&mut self.egraph, &mut self.classes, &func.dfg, *arg)
+                })
+                .collect();
+
+            let id = self.egraph.add(ENode::Phi(inst, args));
+            let result = func.dfg.inst_result(inst).unwrap();
+            let leaf = Self::class_of_in(&mut self.egraph, &mut self.classes, &func.dfg, result);
+            self.egraph.union(leaf, id);
+        }
+
+        self.egraph.rebuild();
+    }
+
+    /// Applies the rules until the e-graph is saturated or a limit is reached.
+    fn saturate(&mut self, func: &Function) {
+        for _ in 0..ITER_LIMIT {
+            let node_num = self.egraph.node_num();
+
+            let mut changed = pattern::apply_rules(&mut self.egraph, &self.rules);
+            changed |= self.merge_phis(func);
+            self.egraph.rebuild();
+
+            changed |= self.egraph.node_num() != node_num;
+            if !changed || self.egraph.node_num() > NODE_LIMIT {
+                break;
+            }
+        }
+    }
+
+    /// Merges a phi with its arguments if all of them are equivalent, ignoring
+    /// the phi itself.
+    ///
+    /// The merge is done only if the arguments are constant or contain a value
+    /// that is available at the phi, so that the phi can be replaced with it.
+    fn merge_phis(&mut self, func: &Function) -> bool {
+        let mut changed = false;
+
+        for &inst in &self.phis {
+            let result = func.dfg.inst_result(inst).unwrap();
+            let Some(&phi_class) = self.classes.get(&result) else {
+                continue;
+            };
+            let phi_class = self.egraph.find(phi_class);
+
+            let mut arg_class = None;
+            let mut is_same = true;
+            for (arg, _) in func.dfg.cast_phi(inst).unwrap().args() {
+                let class = self.egraph.find(self.classes[arg]);
+                if class == phi_class {
+                    continue;
+                }
+                match arg_class {
+                    Some(arg_class) if arg_class != class => is_same = false,
+                    _ => arg_class = Some(class),
+                }
+            }
+
+            let Some(arg_class) = arg_class.filter(|_| is_same) else {
+                continue;
+            };
+            if self.egraph.class(arg_class).constant.is_some()
+                || self.available_value(func, arg_class, inst, None).is_some()
+            {
+                changed |= self.egraph.union(phi_class, arg_class);
+            }
+        }
+
+        changed
+    }
+
+    /// Computes the cheapest node of each class.
+    fn compute_best(&mut self, dfg: &DataFlowGraph) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in self.egraph.class_ids() {
+                for node in &self.egraph.class(id).nodes {
+                    let cost = self.node_cost(dfg, node);
+                    if cost == INFINITE_COST {
+                        continue;
+                    }
+
+                    match self.best.get(&id) {
+                        Some((best_cost, _)) if *best_cost <= cost => {}
+                        _ => {
+                            self.best.insert(id, (cost, node.clone()));
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn node_cost(&self, dfg: &DataFlowGraph, node: &ENode) -> u32 {
+        let op_cost = match node {
+            ENode::Imm(_) => return 0,
+            ENode::Value(value) => {
+                // Results of simplified instructions may be replaced, so they
+                // can't be used as leaves.
+                return match dfg.value_inst(*value) {
+                    Some(inst) if self.insts.contains(&inst) => INFINITE_COST,
+                    _ => 0,
+                };
+            }
+            ENode::Phi(..) => return INFINITE_COST,
+            ENode::Op(op, args) => {
+                // Prefer `lt`-family comparisons over their flipped forms.
+                let flipped = matches!(op, Op::Gt | Op::Ge | Op::Sgt | Op::Sge);
+                1 + args.len() as u32 + flipped as u32
+            }
+            ENode::Cast(..) => 2,
+        };
+
+        node.children().iter().fold(op_cost, |cost, child| {
+            let child_cost = self
+                .best
+                .get(&self.egraph.find(*child))
+                .map_or(INFINITE_COST, |(cost, _)| *cost);
+            cost.saturating_add(child_cost)
+        })
+    }
+
+    /// Replaces instructions with their simplest equivalents.
+    fn rewrite(&mut self, func: &mut Function) {
+        let insts: Vec<_> = self
+            .domtree
+            .rpo()
+            .iter()
+            .flat_map(|block| func.layout.iter_inst(*block))
+            .filter(|inst| self.insts.contains(inst) || func.dfg.is_phi(*inst))
+            .collect();
+
+        for inst in insts {
+            let result = func.dfg.inst_result(inst).unwrap();
+            let Some(&class) = self.classes.get(&result) else {
+                continue;
+            };
+            let class = self.egraph.find(class);
+
+            let new_value = match self.egraph.class(class).constant {
+                Some(imm) => Some(func.dfg.make_imm_value(imm)),
+                None => self.available_value(func, class, inst, Some(result)),
+            };
+            if let Some(new_value) = new_value {
+                InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                func.dfg.change_to_alias(result, new_value);
+                continue;
+            }
+
+            if func.dfg.is_phi(inst) {
+                continue;
+            }
+
+            let Some((best_cost, best_node)) = self.best.get(&class).cloned() else {
+                continue;
+            };
+            let current = self.lower(&func.dfg, inst).unwrap();
+            if best_cost >= self.node_cost(&func.dfg, &current) {
+                continue;
+            }
+
+            if let Some(args) = self.materialize_children(func, &best_node, inst) {
+                let new_inst = make_inst(func.dfg.inst_set(), &best_node, &args);
+                func.dfg.replace_inst(inst, new_inst);
+            }
+        }
+    }
+
+    /// Returns values for the children of `node` at `at`, inserting
+    /// instructions before `at` if needed.
+    fn materialize_children(
+        &mut self,
+        func: &mut Function,
+        node: &ENode,
+        at: InstId,
+    ) -> Option<SmallVec<[ValueId; 2]>> {
+        node.children()
+            .iter()
+            .map(|child| self.materialize(func, *child, at))
+            .collect()
+    }
+
+    /// Returns a value of `class` that is available at `at`, inserting
+    /// instructions before `at` if needed.
+    fn materialize(&mut self, func: &mut Function, class: EClassId, at: InstId) -> Option<ValueId> {
+        let class = self.egraph.find(class);
+        if let Some(imm) = self.egraph.class(class).constant {
+            return Some(func.dfg.make_imm_value(imm));
+        }
+        if let Some(value) = self.available_value(func, class, at, None) {
+            return Some(value);
+        }
+
+        let (_, node) = self.best.get(&class).cloned()?;
+        if !matches!(node, ENode::Op(..) | ENode::Cast(..)) {
+            return None;
+        }
+
+        let args = self.materialize_children(func, &node, at)?;
+        let inst = make_inst(func.dfg.inst_set(), &node, &args);
+        let mut inserter = InstInserter::at_location(CursorLocation::At(at));
+        let inst = inserter.insert_inst_data_dyn(func, inst);
+        let result = inserter.make_result(func, inst, self.egraph.class(class).ty);
+        inserter.attach_result(func, inst, result);
+
+        self.order.insert(inst, self.order[&at] - 1);
+        self.classes.insert(result, class);
+        self.materialized.entry(class).or_default().push(result);
+        Some(result)
+    }
+
+    /// Returns a value in `class` other than `except` that is available at
+    /// `at`.
+    fn available_value(
+        &self,
+        func: &Function,
+        class: EClassId,
+        at: InstId,
+        except: Option<ValueId>,
+    ) -> Option<ValueId> {
+        let class = self.egraph.find(class);
+        let leaves = self
+            .egraph
+            .class(class)
+            .nodes
+            .iter()
+            .filter_map(|node| match node {
+                ENode::Value(value) => Some(*value),
+                _ => None,
+            });
+        let materialized = self.materialized.get(&class).into_iter().flatten().copied();
+
+        leaves
+            .chain(materialized)
+            .filter(|value| Some(*value) != except)
+            .find(|value| self.is_available(func, *value, at))
+    }
+
+    /// Returns `true` if `value` can be used at `at`.
+    fn is_available(&self, func: &Function, value: ValueId, at: InstId) -> bool {
+        let def = match func.dfg.value(value) {
+            Value::Inst { inst, .. } => *inst,
+            _ => return true,
+        };
+        if !func.layout.is_inst_inserted(def) {
+            return false;
+        }
+
+        let def_block = func.layout.inst_block(def);
+        let at_block = func.layout.inst_block(at);
+        if func.dfg.is_phi(at) {
+            // Phis in the same block are evaluated simultaneously.
+            self.domtree.strictly_dominates(def_block, at_block)
+        } else if def_block == at_block {
+            self.order[&def] < self.order[&at]
+        } else {
+            self.domtree.dominates(def_block, at_block)
+        }
+    }
+
+    /// Removes instructions whose results become unused by the rewrite.
+    fn remove_dead_insts(&self, func: &mut Function) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let blocks: Vec<BlockId> = func.layout.iter_block().collect();
+            for block in blocks {
+                let insts: Vec<_> = func.layout.iter_inst(block).collect();
+                for inst in insts {
+                    let is_simplifiable = func.dfg.is_phi(inst)
+                        || <&dyn ToENode as InstDowncast>::downcast(
+                            func.inst_set(),
+                            func.dfg.inst(inst),
+                        )
+                        .is_some();
+                    let is_dead = func
+                        .dfg
+                        .inst_result(inst)
+                        .is_some_and(|result| func.dfg.users_num(result) == 0);
+
+                    if is_simplifiable && is_dead {
+                        InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    fn lower(&mut self, dfg: &DataFlowGraph, inst: InstId) -> Option<ENode> {
+        let to_enode: &dyn ToENode = InstDowncast::downcast(dfg.inst_set(), dfg.inst(inst))?;
+        let (egraph, classes) = (&mut self.egraph, &mut self.classes);
+        Some(to_enode.to_enode(&mut |value| Self::class_of_in(egraph, classes, dfg, value)))
+    }
+
+    fn class_of(&mut self, dfg: &DataFlowGraph, value: ValueId) -> EClassId {
+        Self::class_of_in(&mut self.egraph, &mut self.classes, dfg, value)
+    }
+
+    fn class_of_in(
+        egraph: &mut EGraph,
+        classes: &mut FxHashMap<ValueId, EClassId>,
+        dfg: &DataFlowGraph,
+        value: ValueId,
+    ) -> EClassId {
+        if let Some(&id) = classes.get(&value) {
+            return egraph.find(id);
+        }
+
+        let id = match dfg.value_imm(value) {
+            Some(imm) => egraph.add(ENode::Imm(imm)),
+            None => egraph.add_value(value, dfg.value_ty(value)),
+        };
+        classes.insert(value, id);
+        id
+    }
+}
+
+impl Default for InsnSimplifySolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Instructions that are represented as operations in the e-graph.
+#[inst_prop]
+pub trait ToENode {
+    /// Returns the e-node of the instruction, where `class_of` returns the
+    /// e-class of an operand.
+    fn to_enode(&self, class_of: &mut dyn FnMut(ValueId) -> EClassId) -> ENode;
+
+    type Members = (
+        arith::Neg,
+        arith::Add,
+        arith::Sub,
+        arith::Mul,
+        logic::Not,
+        logic::And,
+        logic::Or,
+        logic::Xor,
+        cmp::Eq,
+        cmp::Ne,
+        cmp::Lt,
+        cmp::Gt,
+        cmp::Le,
+        cmp::Ge,
+        cmp::Slt,
+        cmp::Sgt,
+        cmp::Sle,
+        cmp::Sge,
+        cmp::IsZero,
+        cast::Sext,
+        cast::Zext,
+        cast::Trunc,
+        evm::EvmUdiv,
+        evm::EvmSdiv,
+        evm::EvmUmod,
+        evm::EvmSmod,
+        evm::EvmAddMod,
+        evm::EvmMulMod,
+        evm::EvmExp,
+        evm::EvmByte,
+    );
+}
+
+macro_rules! impl_unary {
+    ($($ty:ty => $op:ident, $arg:ident;)*) => {
+        $(
+            impl ToENode for $ty {
+                fn to_enode(&self, class_of: &mut dyn FnMut(ValueId) -> EClassId) -> ENode {
+                    ENode::Op(Op::$op, smallvec![class_of(*self.$arg())])
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_binary {
+    ($($ty:ty => $op:ident;)*) => {
+        $(
+            impl ToENode for $ty {
+                fn to_enode(&self, class_of: &mut dyn FnMut(ValueId) -> EClassId) -> ENode {
+                    ENode::Op(Op::$op, smallvec![class_of(*self.lhs()), class_of(*self.rhs())])
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_ternary {
+    ($($ty:ty => $op:ident;)*) => {
+        $(
+            impl ToENode for $ty {
+                fn to_enode(&self, class_of: &mut dyn FnMut(ValueId) -> EClassId) -> ENode {
+                    let args = smallvec![
+                        class_of(*self.lhs()),
+                        class_of(*self.rhs()),
+                        class_of(*self.modulus())
+                    ];
+                    ENode::Op(Op::$op, args)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_cast {
+    ($($ty:ty => $op:ident;)*) => {
+        $(
+            impl ToENode for $ty {
+                fn to_enode(&self, class_of: &mut dyn FnMut(ValueId) -> EClassId) -> ENode {
+                    ENode::Cast(CastOp::$op, *self.ty(), class_of(*self.from()))
+                }
+            }
+        )*
+    };
+}
+
+impl_unary! {
+    arith::Neg => Neg, arg;
+    logic::Not => Not, arg;
+    cmp::IsZero => IsZero, lhs;
+}
+
+impl_binary! {
+    arith::Add => Add;
+    arith::Sub => Sub;
+    arith::Mul => Mul;
+    logic::And => And;
+    logic::Or => Or;
+    logic::Xor => Xor;
+    cmp::Eq => Eq;
+    cmp::Ne => Ne;
+    cmp::Lt => Lt;
+    cmp::Gt => Gt;
+    cmp::Le => Le;
+    cmp::Ge => Ge;
+    cmp::Slt => Slt;
+    cmp::Sgt => Sgt;
+    cmp::Sle => Sle;
+    cmp::Sge => Sge;
+    evm::EvmUdiv => EvmUdiv;
+    evm::EvmSdiv => EvmSdiv;
+    evm::EvmUmod => EvmUmod;
+    evm::EvmSmod => EvmSmod;
+}
+
+impl_ternary! {
+    evm::EvmAddMod => EvmAddMod;
+    evm::EvmMulMod => EvmMulMod;
+}
+
+impl ToENode for evm::EvmExp {
+    fn to_enode(&self, class_of: &mut dyn FnMut(ValueId) -> EClassId) -> ENode {
+        ENode::Op(
+            Op::EvmExp,
+            smallvec![class_of(*self.base()), class_of(*self.exponent())],
+        )
+    }
+}
+
+impl ToENode for evm::EvmByte {
+    fn to_enode(&self, class_of: &mut dyn FnMut(ValueId) -> EClassId) -> ENode {
+        ENode::Op(
+            Op::EvmByte,
+            smallvec![class_of(*self.pos()), class_of(*self.value())],
+        )
+    }
+}
+
+impl_cast! {
+    cast::Sext => Sext;
+    cast::Zext => Zext;
+    cast::Trunc => Trunc;
+}
+
+/// Makes the instruction of `node` whose operands are `args`.
+fn make_inst(isb: &dyn InstSetBase, node: &ENode, args: &[ValueId]) -> Box<dyn Inst> {
+    match (node, args) {
+        (ENode::Op(op, _), &[arg]) => match op {
+            Op::Neg => Box::new(arith::Neg::new_unchecked(isb, arg)),
+            Op::Not => Box::new(logic::Not::new_unchecked(isb, arg)),
+            Op::IsZero => Box::new(cmp::IsZero::new_unchecked(isb, arg)),
+            _ => unreachable!(),
+        },
+
+        (ENode::Op(op, _), &[lhs, rhs]) => match op {
+            Op::Add => Box::new(arith::Add::new_unchecked(isb, lhs, rhs)),
+            Op::Sub => Box::new(arith::Sub::new_unchecked(isb, lhs, rhs)),
+            Op::Mul => Box::new(arith::Mul::new_unchecked(isb, lhs, rhs)),
+            Op::And => Box::new(logic::And::new_unchecked(isb, lhs, rhs)),
+            Op::Or => Box::new(logic::Or::new_unchecked(isb, lhs, rhs)),
+            Op::Xor => Box::new(logic::Xor::new_unchecked(isb, lhs, rhs)),
+            Op::Eq => Box::new(cmp::Eq::new_unchecked(isb, lhs, rhs)),
+            Op::Ne => Box::new(cmp::Ne::new_unchecked(isb, lhs, rhs)),
+            Op::Lt => Box::new(cmp::Lt::new_unchecked(isb, lhs, rhs)),
+            Op::Gt => Box::new(cmp::Gt::new_unchecked(isb, lhs, rhs)),
+            Op::Le => Box::new(cmp::Le::new_unchecked(isb, lhs, rhs)),
+            Op::Ge => Box::new(cmp::Ge::new_unchecked(isb, lhs, rhs)),
+            Op::Slt => Box::new(cmp::Slt::new_unchecked(isb, lhs, rhs)),
+            Op::Sgt => Box::new(cmp::Sgt::new_unchecked(isb, lhs, rhs)),
+            Op::Sle => Box::new(cmp::Sle::new_unchecked(isb, lhs, rhs)),
+            Op::Sge => Box::new(cmp::Sge::new_unchecked(isb, lhs, rhs)),
+            Op::EvmUdiv => Box::new(evm::EvmUdiv::new_unchecked(isb, lhs, rhs)),
+            Op::EvmSdiv => Box::new(evm::EvmSdiv::new_unchecked(isb, lhs, rhs)),
+            Op::EvmUmod => Box::new(evm::EvmUmod::new_unchecked(isb, lhs, rhs)),
+            Op::EvmSmod => Box::new(evm::EvmSmod::new_unchecked(isb, lhs, rhs)),
+            Op::EvmExp => Box::new(evm::EvmExp::new_unchecked(isb, lhs, rhs)),
+            Op::EvmByte => Box::new(evm::EvmByte::new_unchecked(isb, lhs, rhs)),
+            _ => unreachable!(),
+        },
+
+        (ENode::Op(op, _), &[lhs, rhs, modulus]) => match op {
+            Op::EvmAddMod => Box::new(evm::EvmAddMod::new_unchecked(isb, lhs, rhs, modulus)),
+            Op::EvmMulMod => Box::new(evm::EvmMulMod::new_unchecked(isb, lhs, rhs, modulus)),
+            _ => unreachable!(),
+        },
+
+        (ENode::Cast(op, ty, _), &[from]) => match op {
+            CastOp::Sext => Box::new(cast::Sext::new_unchecked(isb, from, *ty)),
+            CastOp::Zext => Box::new(cast::Zext::new_unchecked(isb, from, *ty)),
+            CastOp::Trunc => Box::new(cast::Trunc::new_unchecked(isb, from, *ty)),
+        },
+
+        _ => unreachable!("`{node:?}` can't be made into an instruction"),
+    }
+}
@@ -0,0 +1,352 @@
+//! Patterns and rewrite rules over the e-graph.
+//!
+//! A pattern is written as an s-expression whose operators are the names of
+//! the corresponding instructions, e.g., `(and ?a (not ?a))`. Pattern
+//! variables start with `?`, and the literals `0`, `1` and `-1` match any
+//! constant of that value regardless of its type.
+
+use smallvec::SmallVec;
+use sonatina_ir::{Immediate, Type};
+
+use super::egraph::{CastOp, EClassId, EGraph, ENode, Op};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    Var(usize),
+    Lit(Literal),
+    Op(Op, Vec<Pattern>),
+    Cast(CastOp, Box<Pattern>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Literal {
+    Zero,
+    One,
+    AllOne,
+}
+
+impl Literal {
+    fn matches(self, imm: Immediate) -> bool {
+        match self {
+            Self::Zero => imm.is_zero(),
+            Self::One => imm.is_one(),
+            Self::AllOne => imm.is_all_one(),
+        }
+    }
+
+    fn to_imm(self, ty: Type) -> Immediate {
+        match self {
+            Self::Zero => Immediate::zero(ty),
+            Self::One => Immediate::one(ty),
+            Self::AllOne => Immediate::all_one(ty),
+        }
+    }
+}
+
+/// The classes bound to pattern variables.
+#[derive(Debug, Clone)]
+pub struct Subst(SmallVec<[Option<EClassId>; 4]>);
+
+impl Subst {
+    fn new(var_num: usize) -> Self {
+        Self(smallvec::smallvec![None; var_num])
+    }
+
+    /// Returns the class bound to the `idx`-th variable.
+    pub fn get(&self, idx: usize) -> EClassId {
+        self.0[idx].unwrap()
+    }
+}
+
+/// A condition that must hold for a rewrite to be applied to a match.
+pub type Condition = fn(&EGraph, &Subst) -> bool;
+
+/// A rewrite rule stating that `lhs` is equal to `rhs`.
+pub struct Rewrite {
+    pub name: &'static str,
+    lhs: Pattern,
+    rhs: Pattern,
+    var_num: usize,
+    cond: Option<Condition>,
+}
+
+impl Rewrite {
+    /// Parses `lhs` and `rhs` into a rule.
+    ///
+    /// # Panics
+    /// Panics if either pattern is malformed or if `rhs` refers to a variable
+    /// that `lhs` doesn't bind.
+    pub fn new(name: &'static str, lhs: &str, rhs: &str) -> Self {
+        let mut vars = Vec::new();
+        let lhs = Parser::new(lhs, &mut vars, true)
+            .parse()
+            .unwrap_or_else(|err| panic!("malformed lhs of `{name}`: {err}"));
+        let rhs = Parser::new(rhs, &mut vars, false)
+            .parse()
+            .unwrap_or_else(|err| panic!("malformed rhs of `{name}`: {err}"));
+
+        Self {
+            name,
+            lhs,
+            rhs,
+            var_num: vars.len(),
+            cond: None,
+        }
+    }
+
+    pub fn with_cond(mut self, cond: Condition) -> Self {
+        self.cond = Some(cond);
+        self
+    }
+
+    /// Returns all matches of the lhs in the e-graph.
+    pub fn search(&self, egraph: &EGraph) -> Vec<(EClassId, Subst)> {
+        let mut matches = Vec::new();
+        for id in egraph.class_ids() {
+            let mut substs = Vec::new();
+            match_pattern(egraph, &self.lhs, id, Subst::new(self.var_num), &mut substs);
+            matches.extend(substs.into_iter().map(|subst| (id, subst)));
+        }
+        matches
+    }
+
+    /// Adds the rhs instantiated with `subst` to the e-graph, and merges it
+    /// with `root`. Returns `true` if the e-graph is changed.
+    pub fn apply(&self, egraph: &mut EGraph, root: EClassId, subst: &Subst) -> bool {
+        if self.cond.is_some_and(|cond| !cond(egraph, subst)) {
+            return false;
+        }
+
+        let ty = egraph.class(root).ty;
+        if infer_ty(egraph, &self.rhs, subst).is_some_and(|rhs_ty| rhs_ty != ty) {
+            return false;
+        }
+
+        let id = instantiate(egraph, &self.rhs, subst, ty);
+        egraph.union(root, id)
+    }
+}
+
+/// Searches all matches of `rules` first, then applies them. Returns `true` if
+/// the e-graph is changed.
+///
+/// The e-graph needs to be rebuilt afterwards.
+pub fn apply_rules(egraph: &mut EGraph, rules: &[Rewrite]) -> bool {
+    let matches: Vec<_> = rules
+        .iter()
+        .flat_map(|rule| {
+            rule.search(egraph)
+                .into_iter()
+                .map(move |(root, subst)| (rule, root, subst))
+        })
+        .collect();
+
+    let mut changed = false;
+    for (rule, root, subst) in matches {
+        changed |= rule.apply(egraph, root, &subst);
+    }
+    changed
+}
+
+fn match_pattern(egraph: &EGraph, pat: &Pattern, id: EClassId, subst: Subst, out: &mut Vec<Subst>) {
+    let id = egraph.find(id);
+    let class = egraph.class(id);
+
+    match pat {
+        Pattern::Var(idx) => match subst.0[*idx] {
+            Some(bound) if egraph.find(bound) != id => {}
+            Some(_) => out.push(subst),
+            None => {
+                let mut subst = subst;
+                subst.0[*idx] = Some(id);
+                out.push(subst);
+            }
+        },
+
+        Pattern::Lit(lit) => {
+            if class.constant.is_some_and(|imm| lit.matches(imm)) {
+                out.push(subst);
+            }
+        }
+
+        Pattern::Op(op, args) => {
+            for node in &class.nodes {
+                if let ENode::Op(node_op, children) = node {
+                    if node_op == op {
+                        match_children(egraph, args, children, subst.clone(), out);
+                    }
+                }
+            }
+        }
+
+        Pattern::Cast(op, arg) => {
+            for node in &class.nodes {
+                if let ENode::Cast(node_op, _, child) = node {
+                    if node_op == op {
+                        match_pattern(egraph, arg, *child, subst.clone(), out);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn match_children(
+    egraph: &EGraph,
+    pats: &[Pattern],
+    children: &[EClassId],
+    subst: Subst,
+    out: &mut Vec<Subst>,
+) {
+    let mut substs = vec![subst];
+    for (pat, &child) in pats.iter().zip(children) {
+        let mut next = Vec::new();
+        for subst in substs {
+            match_pattern(egraph, pat, child, subst, &mut next);
+        }
+        if next.is_empty() {
+            return;
+        }
+        substs = next;
+    }
+    out.extend(substs);
+}
+
+/// Returns the type of `pat` if it can be determined without context.
+fn infer_ty(egraph: &EGraph, pat: &Pattern, subst: &Subst) -> Option<Type> {
+    match pat {
+        Pattern::Var(idx) => Some(egraph.class(subst.get(*idx)).ty),
+        Pattern::Lit(_) | Pattern::Cast(..) => None,
+        Pattern::Op(op, _) if op.is_predicate() => Some(Type::I1),
+        Pattern::Op(op, args) if op.ty_operand() != 0 => {
+            infer_ty(egraph, &args[op.ty_operand()], subst)
+        }
+        Pattern::Op(_, args) => args.iter().find_map(|arg| infer_ty(egraph, arg, subst)),
+    }
+}
+
+/// Adds `pat` of type `ty` to the e-graph.
+fn instantiate(egraph: &mut EGraph, pat: &Pattern, subst: &Subst, ty: Type) -> EClassId {
+    match pat {
+        Pattern::Var(idx) => subst.get(*idx),
+
+        Pattern::Lit(lit) => egraph.add(ENode::Imm(lit.to_imm(ty))),
+
+        Pattern::Op(op, args) => {
+            let arg_ty = if op.is_predicate() {
+                args.iter()
+                    .find_map(|arg| infer_ty(egraph, arg, subst))
+                    .expect("type of predicate operands can't be inferred")
+            } else {
+                ty
+            };
+            let children = args
+                .iter()
+                .enumerate()
+                .map(|(idx, arg)| {
+                    // Operands other than the one typed as the result, e.g.,
+                    // the position of `evm_byte`, have their own types.
+                    let arg_ty = if op.is_predicate() || idx == op.ty_operand() {
+                        arg_ty
+                    } else {
+                        infer_ty(egraph, arg, subst).unwrap_or(arg_ty)
+                    };
+                    instantiate(egraph, arg, subst, arg_ty)
+                })
+                .collect();
+            egraph.add(ENode::Op(*op, children))
+        }
+
+        Pattern::Cast(op, arg) => {
+            let arg_ty =
+                infer_ty(egraph, arg, subst).expect("type of cast operand can't be inferred");
+            let child = instantiate(egraph, arg, subst, arg_ty);
+            egraph.add(ENode::Cast(*op, ty, child))
+        }
+    }
+}
+
+struct Parser<'a, 'v> {
+    tokens: std::iter::Peekable<std::vec::IntoIter<&'a str>>,
+    vars: &'v mut Vec<String>,
+    allow_new_var: bool,
+}
+
+impl<'a, 'v> Parser<'a, 'v> {
+    fn new(src: &'a str, vars: &'v mut Vec<String>, allow_new_var: bool) -> Self {
+        let mut tokens = Vec::new();
+        for word in src.split_whitespace() {
+            let mut rest = word;
+            while !rest.is_empty() {
+                let end = match rest.find(['(', ')']) {
+                    Some(0) => 1,
+                    Some(pos) => pos,
+                    None => rest.len(),
+                };
+                tokens.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
+
+        Self {
+            tokens: tokens.into_iter().peekable(),
+            vars,
+            allow_new_var,
+        }
+    }
+
+    fn parse(mut self) -> Result<Pattern, String> {
+        let pat = self.parse_pattern()?;
+        match self.tokens.next() {
+            Some(token) => Err(format!("unexpected `{token}`")),
+            None => Ok(pat),
+        }
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, String> {
+        match self.tokens.next() {
+            Some("(") => {
+                let name = self.tokens.next().ok_or("unexpected end of pattern")?;
+                let mut args = Vec::new();
+                while self.tokens.peek().is_some_and(|token| *token != ")") {
+                    args.push(self.parse_pattern()?);
+                }
+                if self.tokens.next() != Some(")") {
+                    return Err("missing `)`".to_string());
+                }
+
+                if let Some(op) = Op::from_name(name) {
+                    if args.len() != op.arity() {
+                        return Err(format!("`{name}` takes {} operands", op.arity()));
+                    }
+                    Ok(Pattern::Op(op, args))
+                } else if let Some(op) = CastOp::from_name(name) {
+                    match <[Pattern; 1]>::try_from(args) {
+                        Ok([arg]) => Ok(Pattern::Cast(op, Box::new(arg))),
+                        Err(_) => Err(format!("`{name}` takes 1 operand")),
+                    }
+                } else {
+                    Err(format!("unknown operator `{name}`"))
+                }
+            }
+
+            Some(var) if var.starts_with('?') => {
+                if let Some(idx) = self.vars.iter().position(|v| *v == var) {
+                    Ok(Pattern::Var(idx))
+                } else if self.allow_new_var {
+                    self.vars.push(var.to_string());
+                    Ok(Pattern::Var(self.vars.len() - 1))
+                } else {
+                    Err(format!("unbound variable `{var}`"))
+                }
+            }
+
+            Some("0") => Ok(Pattern::Lit(Literal::Zero)),
+            Some("1") => Ok(Pattern::Lit(Literal::One)),
+            Some("-1") => Ok(Pattern::Lit(Literal::AllOne)),
+
+            Some(token) => Err(format!("unexpected `{token}`")),
+            None => Err("unexpected end of pattern".to_string()),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! The rule set of the instruction simplification.
+//!
+//! Each rule is written as `"name": "lhs" => "rhs"`, optionally followed by
+//! `if cond` where `cond` is a [`Condition`](super::pattern::Condition).
+//! A rule only adds the rhs to the e-graph without removing the lhs, so it
+//! doesn't need to rewrite to a cheaper form; the cheapest form in the
+//! e-graph is chosen when the function is rewritten.
+
+use sonatina_ir::U256;
+
+use super::{
+    egraph::EGraph,
+    pattern::{Rewrite, Subst},
+};
+
+macro_rules! rules {
+    ($($name:literal: $lhs:literal => $rhs:literal $(if $cond:expr)?;)*) => {
+        vec![$(Rewrite::new($name, $lhs, $rhs)$(.with_cond($cond))?),*]
+    };
+}
+
+pub fn rules() -> Vec<Rewrite> {
+    let mut rules = Vec::new();
+    rules.extend(commutativity());
+    rules.extend(arith());
+    rules.extend(and());
+    rules.extend(or());
+    rules.extend(xor());
+    rules.extend(not());
+    rules.extend(cmp());
+    rules.extend(cast());
+    rules.extend(evm());
+    rules
+}
+
+fn commutativity() -> Vec<Rewrite> {
+    rules! {
+        "add-comm": "(add ?a ?b)" => "(add ?b ?a)";
+        "mul-comm": "(mul ?a ?b)" => "(mul ?b ?a)";
+        "and-comm": "(and ?a ?b)" => "(and ?b ?a)";
+        "or-comm": "(or ?a ?b)" => "(or ?b ?a)";
+        "xor-comm": "(xor ?a ?b)" => "(xor ?b ?a)";
+        "eq-comm": "(eq ?a ?b)" => "(eq ?b ?a)";
+        "ne-comm": "(ne ?a ?b)" => "(ne ?b ?a)";
+    }
+}
+
+fn arith() -> Vec<Rewrite> {
+    rules! {
+        "add-zero": "(add ?a 0)" => "?a";
+        "sub-zero": "(sub ?a 0)" => "?a";
+        "sub-self": "(sub ?a ?a)" => "0";
+        "zero-sub": "(sub 0 ?a)" => "(neg ?a)";
+        "mul-zero": "(mul ?a 0)" => "0";
+        "mul-one": "(mul ?a 1)" => "?a";
+        "mul-all-one": "(mul ?a -1)" => "(neg ?a)";
+        "add-neg": "(add ?a (neg ?b))" => "(sub ?a ?b)";
+        "sub-neg": "(sub ?a (neg ?b))" => "(add ?a ?b)";
+        "add-sub-cancel": "(sub (add ?a ?b) ?b)" => "?a";
+        "sub-add-cancel": "(add (sub ?a ?b) ?b)" => "?a";
+        "neg-neg": "(neg (neg ?a))" => "?a";
+        "neg-not": "(neg (not ?a))" => "(add ?a 1)";
+    }
+}
+
+fn and() -> Vec<Rewrite> {
+    rules! {
+        "and-self": "(and ?a ?a)" => "?a";
+        "and-zero": "(and ?a 0)" => "0";
+        "and-all-one": "(and ?a -1)" => "?a";
+        "and-not-self": "(and ?a (not ?a))" => "0";
+        "and-absorb": "(and ?a (or ?a ?b))" => "?a";
+        "and-or-not": "(and (or ?a ?b) (or ?a (not ?b)))" => "?a";
+    }
+}
+
+fn or() -> Vec<Rewrite> {
+    rules! {
+        "or-self": "(or ?a ?a)" => "?a";
+        "or-zero": "(or ?a 0)" => "?a";
+        "or-all-one": "(or ?a -1)" => "-1";
+        "or-not-self": "(or ?a (not ?a))" => "-1";
+        "or-absorb": "(or ?a (and ?a ?b))" => "?a";
+        "or-not-and": "(or ?a (not (and ?a ?b)))" => "-1";
+        "or-xor-and-not": "(or (xor ?a ?b) (and ?a (not ?b)))" => "(xor ?a ?b)";
+        "or-xor-not-and": "(or (xor (not ?a) ?b) (and ?a ?b))" => "(xor (not ?a) ?b)";
+        "or-or-xor": "(or (or ?a ?b) (xor ?a ?b))" => "(or ?a ?b)";
+        "or-and-not-or": "(or (and (not ?a) ?b) (not (or ?a ?b)))" => "(not ?a)";
+    }
+}
+
+fn xor() -> Vec<Rewrite> {
+    rules! {
+        "xor-zero": "(xor ?a 0)" => "?a";
+        "xor-self": "(xor ?a ?a)" => "0";
+        "xor-not-self": "(xor ?a (not ?a))" => "-1";
+        "xor-xor-cancel": "(xor ?a (xor ?a ?b))" => "?b";
+        "xor-or-and-not": "(xor (or ?a ?b) (and (not ?a) ?b))" => "?a";
+        "xor-and-or-not": "(xor (and ?a ?b) (or (not ?a) ?b))" => "(not ?a)";
+    }
+}
+
+fn not() -> Vec<Rewrite> {
+    rules! {
+        "not-not": "(not (not ?a))" => "?a";
+        "not-neg": "(not (neg ?a))" => "(sub ?a 1)";
+        "not-eq": "(not (eq ?a ?b))" => "(ne ?a ?b)";
+        "not-ne": "(not (ne ?a ?b))" => "(eq ?a ?b)";
+        "not-lt": "(not (lt ?a ?b))" => "(le ?b ?a)";
+        "not-le": "(not (le ?a ?b))" => "(lt ?b ?a)";
+        "not-slt": "(not (slt ?a ?b))" => "(sle ?b ?a)";
+        "not-sle": "(not (sle ?a ?b))" => "(slt ?b ?a)";
+        "not-is-zero": "(not (is_zero ?a))" => "(ne ?a 0)";
+    }
+}
+
+fn cmp() -> Vec<Rewrite> {
+    rules! {
+        "eq-self": "(eq ?a ?a)" => "1";
+        "ne-self": "(ne ?a ?a)" => "0";
+        "lt-self": "(lt ?a ?a)" => "0";
+        "le-self": "(le ?a ?a)" => "1";
+        "slt-self": "(slt ?a ?a)" => "0";
+        "sle-self": "(sle ?a ?a)" => "1";
+        "gt-flip": "(gt ?a ?b)" => "(lt ?b ?a)";
+        "ge-flip": "(ge ?a ?b)" => "(le ?b ?a)";
+        "sgt-flip": "(sgt ?a ?b)" => "(slt ?b ?a)";
+        "sge-flip": "(sge ?a ?b)" => "(sle ?b ?a)";
+        "eq-zero": "(eq ?a 0)" => "(is_zero ?a)";
+    }
+}
+
+fn cast() -> Vec<Rewrite> {
+    rules! {
+        "sext-sext": "(sext (sext ?a))" => "(sext ?a)";
+        "zext-zext": "(zext (zext ?a))" => "(zext ?a)";
+        "trunc-trunc": "(trunc (trunc ?a))" => "(trunc ?a)";
+        "trunc-sext": "(trunc (sext ?a))" => "?a";
+        "trunc-zext": "(trunc (zext ?a))" => "?a";
+    }
+}
+
+/// Rules of the EVM arithmetic, where division and modulo by zero yield zero.
+fn evm() -> Vec<Rewrite> {
+    rules! {
+        "evm-add-mod-comm": "(evm_add_mod ?a ?b ?m)" => "(evm_add_mod ?b ?a ?m)";
+        "evm-mul-mod-comm": "(evm_mul_mod ?a ?b ?m)" => "(evm_mul_mod ?b ?a ?m)";
+        "evm-udiv-zero": "(evm_udiv ?a 0)" => "0";
+        "evm-udiv-one": "(evm_udiv ?a 1)" => "?a";
+        "evm-sdiv-zero": "(evm_sdiv ?a 0)" => "0";
+        "evm-sdiv-one": "(evm_sdiv ?a 1)" => "?a";
+        "evm-sdiv-all-one": "(evm_sdiv ?a -1)" => "(neg ?a)";
+        "evm-umod-zero": "(evm_umod ?a 0)" => "0";
+        "evm-umod-one": "(evm_umod ?a 1)" => "0";
+        "evm-umod-self": "(evm_umod ?a ?a)" => "0";
+        "evm-smod-zero": "(evm_smod ?a 0)" => "0";
+        "evm-smod-one": "(evm_smod ?a 1)" => "0";
+        "evm-smod-all-one": "(evm_smod ?a -1)" => "0";
+        "evm-smod-self": "(evm_smod ?a ?a)" => "0";
+        "evm-add-mod-zero": "(evm_add_mod ?a ?b 0)" => "0";
+        "evm-add-mod-one": "(evm_add_mod ?a ?b 1)" => "0";
+        "evm-mul-mod-zero": "(evm_mul_mod ?a ?b 0)" => "0";
+        "evm-mul-mod-one": "(evm_mul_mod ?a ?b 1)" => "0";
+        "evm-mul-mod-zero-lhs": "(evm_mul_mod 0 ?a ?m)" => "0";
+        "evm-exp-zero": "(evm_exp ?a 0)" => "1";
+        "evm-exp-one": "(evm_exp ?a 1)" => "?a";
+        "evm-exp-one-base": "(evm_exp 1 ?a)" => "1";
+        "evm-byte-zero": "(evm_byte ?p 0)" => "0";
+        "evm-byte-out-of-range": "(evm_byte ?p ?a)" => "0" if is_out_of_word;
+    }
+}
+
+/// Returns `true` if the position bound to `?p` is known to be outside a
+/// 32-byte word.
+fn is_out_of_word(egraph: &EGraph, subst: &Subst) -> bool {
+    egraph
+        .class(subst.get(0))
+        .constant
+        .is_some_and(|pos| pos.as_i256().to_u256() >= U256::from(32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rules() {
+        let rules = rules();
+        for (i, rule) in rules.iter().enumerate() {
+            assert!(
+                rules[..i].iter().all(|other| other.name != rule.name),
+                "duplicated rule name `{}`",
+                rule.name
+            );
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! This module contains a solver for Interprocedural Sparse Conditional
+//! Constant Propagation.
+//!
+//! The solver works on a whole module. Starting from the entry points of the
+//! module, it repeatedly runs [`SccpSolver`] on the reachable functions until
+//! the cells of arguments and return values of all functions converge.
+//! * Arguments of a function are the join of the arguments at all reachable
+//!   call sites. Arguments of an entry point are always `Top`, since it may be
+//!   called from the outside of the module.
+//! * The result of a call is the join of the values returned by the callee.
+//!
+//! Entry points are `Public` functions, functions whose address is taken, and
+//! main functions of contracts. Functions that are not reachable from the
+//! entry points are neither analyzed nor modified, and calls in them are
+//! ignored.
+
+use rustc_hash::FxHashMap;
+use sonatina_ir::{
+    inst::{control_flow, data, evm},
+    module::FuncRef,
+    prelude::*,
+    ControlFlowGraph, Function, Module,
+};
+
+use super::sccp::{CallContext, LatticeCell, SccpSolver};
+
+#[derive(Debug, Default)]
+pub struct IpSccpSolver {
+    solver: SccpSolver,
+
+    /// Cells of the arguments of each reachable function.
+    args: FxHashMap<FuncRef, Vec<LatticeCell>>,
+
+    /// Cells of the return values of each function defined in the module.
+    rets: FxHashMap<FuncRef, LatticeCell>,
+}
+
+impl IpSccpSolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.solver.clear();
+        self.args.clear();
+        self.rets.clear();
+    }
+
+    /// Run interprocedural constant propagation on the module.
+    pub fn run(&mut self, module: &Module) {
+        self.clear();
+
+        for func_ref in module.funcs() {
+            if !has_definition(module, func_ref) {
+                continue;
+            }
+
+            self.rets.insert(func_ref, LatticeCell::Bot);
+            if module
+                .ctx
+                .func_sig(func_ref, |sig| sig.linkage().is_public())
+            {
+                self.mark_entry(module, func_ref);
+            }
+        }
+
+        // Propagate optimistically first, then resolve branches whose
+        // conditions are still unknown, e.g., depend on calls that never
+        // return.
+        for resolve_undef in [false, true] {
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for func_ref in module.funcs() {
+                    if self.is_reachable(func_ref) {
+                        changed |= module.func_store.view(func_ref, |func| {
+                            self.analyze(module, func_ref, func, resolve_undef)
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut cfg = ControlFlowGraph::default();
+        for func_ref in module.funcs() {
+            if !self.is_reachable(func_ref) {
+                continue;
+            }
+
+            let call_ctx = self.call_ctx(func_ref);
+            module.func_store.modify(func_ref, |func| {
+                self.solver.set_call_ctx(call_ctx);
+                self.solver.solve(func, true);
+                self.solver.apply(func, &mut cfg);
+            });
+        }
+    }
+
+    /// Returns `true` if the function is reachable from the entry points of
+    /// the module.
+    pub fn is_reachable(&self, func_ref: FuncRef) -> bool {
+        self.args.contains_key(&func_ref)
+    }
+
+    /// Runs SCCP on the function, and joins the cells flowing into callees and
+    /// out of the function. Returns `true` if any cell is changed.
+    fn analyze(
+        &mut self,
+        module: &Module,
+        func_ref: FuncRef,
+        func: &Function,
+        resolve_undef: bool,
+    ) -> bool {
+        let call_ctx = self.call_ctx(func_ref);
+        self.solver.set_call_ctx(call_ctx);
+        self.solver.solve(func, resolve_undef);
+
+        let is = func.inst_set();
+        let mut changed = false;
+        for block in func.layout.iter_block() {
+            if !self.solver.is_reachable_block(block) {
+                continue;
+            }
+
+            for inst_id in func.layout.iter_inst(block) {
+                let inst = func.dfg.inst(inst_id);
+                if let Some(call) = <&control_flow::Call as InstDowncast>::downcast(is, inst) {
+                    let args: Vec<_> = call
+                        .args()
+                        .iter()
+                        .map(|arg| self.solver.cell(func, *arg))
+                        .collect();
+                    changed |= self.join_args(module, *call.callee(), &args);
+                } else if let Some(ptr) =
+                    <&data::GetFunctionPtr as InstDowncast>::downcast(is, inst)
+                {
+                    changed |= self.mark_entry(module, *ptr.func());
+                } else if let Some(size) =
+                    <&evm::EvmContractSize as InstDowncast>::downcast(is, inst)
+                {
+                    changed |= self.mark_entry(module, *size.contract());
+                } else if let Some(ret) =
+                    <&control_flow::Return as InstDowncast>::downcast(is, inst)
+                {
+                    let Some(arg) = ret.arg() else {
+                        continue;
+                    };
+                    let cell = self.solver.cell(func, *arg);
+                    let ret_cell = self.rets.get_mut(&func_ref).unwrap();
+                    let joined = ret_cell.join(cell);
+                    if joined != *ret_cell {
+                        *ret_cell = joined;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Joins `args` to the argument cells of `callee`.
+    fn join_args(&mut self, module: &Module, callee: FuncRef, args: &[LatticeCell]) -> bool {
+        if !has_definition(module, callee) {
+            return false;
+        }
+
+        let mut changed = false;
+        let cells = self.args.entry(callee).or_insert_with(|| {
+            changed = true;
+            vec![LatticeCell::Bot; args.len()]
+        });
+        for (cell, arg) in cells.iter_mut().zip(args) {
+            let joined = cell.join(*arg);
+            if joined != *cell {
+                *cell = joined;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// Marks the function as an entry point whose arguments are unknown.
+    fn mark_entry(&mut self, module: &Module, func_ref: FuncRef) -> bool {
+        if !has_definition(module, func_ref) {
+            return false;
+        }
+
+        let arg_num = module.ctx.func_sig(func_ref, |sig| sig.args().len());
+        self.join_args(module, func_ref, &vec![LatticeCell::Top; arg_num])
+    }
+
+    fn call_ctx(&self, func_ref: FuncRef) -> CallContext {
+        CallContext {
+            args: self.args[&func_ref].clone(),
+            rets: self.rets.clone(),
+        }
+    }
+}
+
+fn has_definition(module: &Module, func_ref: FuncRef) -> bool {
+    module
+        .ctx
+        .func_sig(func_ref, |sig| sig.linkage().has_definition())
+}
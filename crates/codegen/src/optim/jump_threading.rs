@@ -0,0 +1,270 @@
+//! This module contains a solver for jump threading.
+//!
+//! The solver performs the following until the CFG doesn't change:
+//! * A `br` whose condition is implied by a dominating `br` on the same
+//!   condition is turned into a jump.
+//! * If a block contains only phis and a `br`, and the condition is known on
+//!   the edge from a predecessor, the predecessor is redirected to the known
+//!   destination. The condition is known on the edge if it's a constant, or
+//!   implied by a dominating `br` on the same condition. A phi used as the
+//!   condition is resolved to its argument from the predecessor.
+//!
+//! Loop headers are never threaded so that loops stay reducible.
+
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::control_flow,
+    prelude::*,
+    BlockId, ControlFlowGraph, Function, InstId, ValueId,
+};
+
+use super::simplify_cfg::{phi_arg, phis_of, remove_phi_args};
+use crate::domtree::DomTree;
+
+#[derive(Debug, Default)]
+pub struct JumpThreadingSolver {}
+
+impl JumpThreadingSolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {}
+
+    /// Run jump threading on the function.
+    /// `cfg` and `domtree` are recomputed after each modification of the
+    /// function, so they are up to date after the run.
+    pub fn run(&mut self, func: &mut Function, cfg: &mut ControlFlowGraph, domtree: &mut DomTree) {
+        self.clear();
+        if func.layout.entry_block().is_none() {
+            return;
+        }
+
+        loop {
+            cfg.compute(func);
+            domtree.compute(cfg);
+
+            let rpo = domtree.rpo().to_vec();
+            let changed = rpo.into_iter().any(|block| {
+                self.fold_implied_branch(func, cfg, domtree, block)
+                    || self.thread_block(func, cfg, domtree, block)
+            });
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// Turns the `br` of the block into a jump if its condition is implied by
+    /// a dominating `br`.
+    fn fold_implied_branch(
+        &self,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        domtree: &DomTree,
+        block: BlockId,
+    ) -> bool {
+        let Some((term, cond, nz_dest, z_dest)) = branch_of(func, block) else {
+            return false;
+        };
+        let Some(known) = implied_cond(func, cfg, domtree, cond, block) else {
+            return false;
+        };
+
+        let (taken, removed) = if known {
+            (nz_dest, z_dest)
+        } else {
+            (z_dest, nz_dest)
+        };
+        remove_phi_args(func, removed, block);
+        let jump = func.dfg.make_jump(taken);
+        func.dfg.replace_inst(term, Box::new(jump));
+        true
+    }
+
+    /// Redirects the predecessors of the block to the destination of its `br`
+    /// if the condition is known on the edge from the predecessor.
+    fn thread_block(
+        &self,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        domtree: &DomTree,
+        block: BlockId,
+    ) -> bool {
+        if Some(block) == func.layout.entry_block() {
+            return false;
+        }
+        let Some((term, cond, nz_dest, z_dest)) = branch_of(func, block) else {
+            return false;
+        };
+
+        // The block must contain only phis and the branch, and the phis must
+        // not be used other than the branch and phis of successors.
+        let phis = phis_of(func, block);
+        if func.layout.iter_inst(block).count() != phis.len() + 1
+            || cfg
+                .preds_of(block)
+                .any(|pred| domtree.dominates(block, *pred))
+            || phis
+                .iter()
+                .any(|phi| !is_local_phi(func, cfg, block, term, *phi))
+        {
+            return false;
+        }
+
+        let cond_phi = func.dfg.value_inst(cond).filter(|inst| phis.contains(inst));
+        let preds: Vec<_> = cfg.preds_of(block).copied().collect();
+        for pred in preds {
+            let cond = match cond_phi {
+                Some(phi) => phi_arg(func, phi, pred).unwrap(),
+                None => cond,
+            };
+            let Some(known) = edge_cond(func, cfg, domtree, cond, pred, block) else {
+                continue;
+            };
+
+            let dest = if known { nz_dest } else { z_dest };
+            if !thread_edge(func, cfg, pred, block, dest) {
+                continue;
+            }
+
+            // Remove the block once its last predecessor is threaded, the phi
+            // arguments flowing from the block to the successors go away
+            // with it.
+            if cfg.pred_num_of(block) == 1 {
+                for succ in [nz_dest, z_dest] {
+                    remove_phi_args(func, succ, block);
+                }
+                InstInserter::at_location(CursorLocation::BlockTop(block)).remove_block(func);
+            }
+
+            // `cfg` and `domtree` need to be recomputed.
+            return true;
+        }
+
+        false
+    }
+}
+
+/// Returns the terminator of the block and its condition and destinations if
+/// the block ends with a `br` whose destinations differ.
+fn branch_of(func: &Function, block: BlockId) -> Option<(InstId, ValueId, BlockId, BlockId)> {
+    let term = func.layout.last_inst_of(block)?;
+    let br =
+        <&control_flow::Br as InstDowncast>::downcast(func.dfg.inst_set(), func.dfg.inst(term))?;
+    (br.nz_dest() != br.z_dest()).then_some((term, *br.cond(), *br.nz_dest(), *br.z_dest()))
+}
+
+/// Returns the value of `cond` on the edge from `pred` to `block`, if it's
+/// known statically.
+fn edge_cond(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    domtree: &DomTree,
+    cond: ValueId,
+    pred: BlockId,
+    block: BlockId,
+) -> Option<bool> {
+    if let Some(imm) = func.dfg.value_imm(cond) {
+        return Some(!imm.is_zero());
+    }
+
+    match branch_of(func, pred) {
+        Some((_, pred_cond, nz_dest, _)) if pred_cond == cond && nz_dest == block => Some(true),
+        Some((_, pred_cond, _, z_dest)) if pred_cond == cond && z_dest == block => Some(false),
+        _ => implied_cond(func, cfg, domtree, cond, pred),
+    }
+}
+
+/// Returns the value of `cond` in the block if it's implied by a `br` on
+/// `cond` whose edge dominates the block.
+fn implied_cond(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    domtree: &DomTree,
+    cond: ValueId,
+    block: BlockId,
+) -> Option<bool> {
+    // An edge dominates the block if its destination dominates the block and
+    // has no other predecessors.
+    let mut dom = block;
+    loop {
+        if cfg.pred_num_of(dom) == 1 && Some(dom) != func.layout.entry_block() {
+            let pred = *cfg.preds_of(dom).next().unwrap();
+            match branch_of(func, pred) {
+                Some((_, pred_cond, nz_dest, _)) if pred_cond == cond && nz_dest == dom => {
+                    return Some(true)
+                }
+                Some((_, pred_cond, _, z_dest)) if pred_cond == cond && z_dest == dom => {
+                    return Some(false)
+                }
+                _ => {}
+            }
+        }
+        dom = domtree.idom_of(dom)?;
+    }
+}
+
+/// Returns `true` if the result of the phi is used only by the terminator of
+/// the block and as arguments flowing from the block to its successors.
+fn is_local_phi(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    block: BlockId,
+    term: InstId,
+    phi: InstId,
+) -> bool {
+    let result = func.dfg.inst_result(phi).unwrap();
+    func.dfg.users(result).all(|&user| {
+        if user == term {
+            return true;
+        }
+        let user_block = func.layout.inst_block(user);
+        cfg.succs_of(block).any(|succ| *succ == user_block)
+            && func.dfg.cast_phi(user).is_some_and(|user_phi| {
+                user_phi
+                    .args()
+                    .iter()
+                    .all(|(v, b)| *v != result || *b == block)
+            })
+    })
+}
+
+/// Redirects the edge from `pred` to `block` to `dest`, and adds the
+/// arguments flowing through `block` to phis in `dest`.
+/// Returns `false` if a phi in `dest` would get different values from `pred`.
+fn thread_edge(
+    func: &mut Function,
+    cfg: &ControlFlowGraph,
+    pred: BlockId,
+    block: BlockId,
+    dest: BlockId,
+) -> bool {
+    let block_phis = phis_of(func, block);
+    let dest_phis = phis_of(func, dest);
+    let mut values = Vec::with_capacity(dest_phis.len());
+    for &phi in &dest_phis {
+        let mut value = phi_arg(func, phi, block).unwrap();
+        if let Some(inst) = func.dfg.value_inst(value) {
+            if block_phis.contains(&inst) {
+                value = phi_arg(func, inst, pred).unwrap();
+            }
+        }
+
+        if cfg.succs_of(pred).any(|succ| *succ == dest) {
+            if phi_arg(func, phi, pred) != Some(value) {
+                return false;
+            }
+        } else {
+            values.push((phi, value));
+        }
+    }
+
+    for (phi, value) in values {
+        func.dfg.append_phi_arg(phi, value, pred);
+    }
+    remove_phi_args(func, block, pred);
+    let pred_term = func.layout.last_inst_of(pred).unwrap();
+    func.dfg.rewrite_branch_dest(pred_term, block, dest);
+    true
+}
@@ -1,58 +1,127 @@
-// TODO: Add control flow hoisting.
+//! This module contains a solver for loop invariant code motion.
+//!
+//! The solver performs the following for each loop:
+//! * Loop invariant instructions are hoisted to the preheader of the loop,
+//!   which is created if the loop doesn't have a unique one.
+//! * Instructions whose results are used only after the loop are sunk to the
+//!   exit block of the loop.
+//!
+//! Instructions without side effects are hoisted even if they are executed
+//! only on some paths through the loop, because they never trap on EVM.
+//! Loads are hoisted only if nothing in the loop may write to the accessed
+//! location, and they are executed on every iteration, since hoisting them may
+//! expand memory or warm up storage slots that are otherwise never touched.
+
 use rustc_hash::{FxHashMap, FxHashSet};
 use sonatina_ir::{
     func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::{evm, SideEffect},
+    prelude::*,
     BlockId, ControlFlowGraph, Function, InstId, ValueId,
 };
 
-use crate::loop_analysis::{Loop, LoopTree};
+use crate::{
+    alias_analysis::{Alias, AliasAnalysis, MemoryAccess, MemoryLocation, Slot, StorageAccess},
+    domtree::DomTree,
+    loop_analysis::{Loop, LoopTree},
+};
 
 #[derive(Debug)]
 pub struct LicmSolver {
     invariants: Vec<InstId>,
+
+    /// Blocks in the loop in RPO.
+    blocks: Vec<BlockId>,
+
+    /// Writes to memory and storage in the loop.
+    effects: LoopEffects,
+
+    domtree: DomTree,
+    aa: AliasAnalysis,
 }
 
 impl LicmSolver {
     pub fn new() -> Self {
         Self {
             invariants: Vec::default(),
+            blocks: Vec::default(),
+            effects: LoopEffects::default(),
+            domtree: DomTree::default(),
+            aa: AliasAnalysis::default(),
         }
     }
 
     pub fn clear(&mut self) {
         self.invariants.clear();
+        self.blocks.clear();
+        self.effects.clear();
     }
 
     /// Run loop invariant code motion ont the function.
-    /// This method also modifies `cfg` and `lpt` htt
+    /// This method also modifies `cfg` and `lpt`.
     pub fn run(&mut self, func: &mut Function, cfg: &mut ControlFlowGraph, lpt: &mut LoopTree) {
+        self.domtree.compute(cfg);
+        self.aa.compute(func);
+
         for lp in lpt.loops() {
-            self.collect_invaliants(func, cfg, lpt, lp);
+            self.clear();
+            self.blocks.extend(lpt.iter_blocks_post_order(cfg, lp));
+            self.blocks.reverse();
+            self.collect_effects(func, cfg, lpt, lp);
 
+            self.collect_invaliants(func);
             if !self.invariants.is_empty() {
                 let preheader = self.create_preheader(func, cfg, lpt, lp);
+                // The new preheader changes the dominators of the loop header.
+                self.domtree.compute(cfg);
                 self.hoist_invariants(func, preheader);
-                self.invariants.clear();
             }
+
+            self.sink_insts(func, cfg, lpt, lp);
         }
     }
 
-    /// Collect loop invariants int the `lp`.
-    /// The found invariants are inserted to `Self::invariants`.
-    fn collect_invaliants(
+    /// Collect writes to memory and storage, and exiting blocks of the loop.
+    fn collect_effects(
         &mut self,
         func: &Function,
         cfg: &ControlFlowGraph,
         lpt: &LoopTree,
         lp: Loop,
     ) {
-        let mut block_in_loop_rpo: Vec<_> = lpt.iter_blocks_post_order(cfg, lp).collect();
-        block_in_loop_rpo.reverse();
+        let effects = &mut self.effects;
+        for &block in &self.blocks {
+            if cfg.succs_of(block).any(|succ| !lpt.is_in_loop(*succ, lp)) {
+                effects.exiting_blocks.push(block);
+            }
 
+            for inst in func.layout.iter_inst(block) {
+                match MemoryAccess::of(&func.dfg, inst) {
+                    MemoryAccess::Store(loc, _) | MemoryAccess::Copy { dest: loc, .. } => {
+                        effects.memory_writes.push(loc)
+                    }
+                    MemoryAccess::ReadAll | MemoryAccess::ReadWriteAll => {
+                        effects.clobbers_memory = true
+                    }
+                    MemoryAccess::Load(..) | MemoryAccess::Read(_) | MemoryAccess::None => {}
+                }
+
+                match StorageAccess::of(&func.dfg, inst) {
+                    StorageAccess::Store(slot, _) => effects.storage_writes.push(slot),
+                    StorageAccess::ReadWriteAll => effects.clobbers_storage = true,
+                    StorageAccess::Load(_) | StorageAccess::ReadAll | StorageAccess::None => {}
+                }
+            }
+        }
+    }
+
+    /// Collect loop invariants int the loop.
+    /// The found invariants are inserted to `Self::invariants`.
+    fn collect_invaliants(&mut self, func: &Function) {
         let mut loop_var = FxHashSet::default();
-        for block in block_in_loop_rpo {
+        for &block in &self.blocks {
             for inst in func.layout.iter_inst(block) {
-                if self.is_invariant(func, &loop_var, inst) {
+                if self.is_invariant(func, &loop_var, block, inst) {
                     self.invariants.push(inst);
                 } else if let Some(result) = func.dfg.inst_result(inst) {
                     loop_var.insert(result);
@@ -66,9 +135,10 @@ impl LicmSolver {
         &self,
         func: &Function,
         loop_var: &FxHashSet<ValueId>,
+        block: BlockId,
         inst_id: InstId,
     ) -> bool {
-        if !self.is_safe_to_hoist(func, inst_id) {
+        if !self.is_safe_to_hoist(func, block, inst_id) {
             return false;
         }
 
@@ -79,10 +149,107 @@ impl LicmSolver {
     }
 
     /// Returns `true` if the `inst` is safe to hoist.
-    fn is_safe_to_hoist(&self, func: &Function, inst_id: InstId) -> bool {
-        !(func.dfg.side_effect(inst_id).has_effect()
+    fn is_safe_to_hoist(&self, func: &Function, block: BlockId, inst_id: InstId) -> bool {
+        let side_effect = func.dfg.side_effect(inst_id);
+        if side_effect == SideEffect::Write
             || func.dfg.is_branch(inst_id)
-            || func.dfg.is_phi(inst_id))
+            || func.dfg.is_phi(inst_id)
+        {
+            return false;
+        }
+
+        match MemoryAccess::of(&func.dfg, inst_id) {
+            MemoryAccess::Load(loc, _) | MemoryAccess::Read(loc) => {
+                return self.effects.is_memory_invariant(&self.aa, &loc)
+                    && self.is_executed_every_iteration(block);
+            }
+            MemoryAccess::None => {}
+            _ => return false,
+        }
+
+        if let StorageAccess::Load(slot) = StorageAccess::of(&func.dfg, inst_id) {
+            return self.effects.is_storage_invariant(&slot)
+                && self.is_executed_every_iteration(block);
+        }
+
+        match side_effect {
+            SideEffect::None => true,
+            // Calldata is immutable.
+            SideEffect::Read => <&evm::EvmCalldataLoad as InstDowncast>::downcast(
+                func.dfg.inst_set(),
+                func.dfg.inst(inst_id),
+            )
+            .is_some(),
+            SideEffect::Write => false,
+        }
+    }
+
+    /// Returns `true` if the `block` is executed on every iteration that
+    /// leaves the loop. This is never the case for a loop without exits.
+    fn is_executed_every_iteration(&self, block: BlockId) -> bool {
+        !self.effects.exiting_blocks.is_empty()
+            && self
+                .effects
+                .exiting_blocks
+                .iter()
+                .all(|exiting| self.domtree.dominates(block, *exiting))
+    }
+
+    /// Sink instructions whose results are used only after the loop to the
+    /// exit block, if the loop has a unique exit block whose predecessors are
+    /// all in the loop.
+    fn sink_insts(&self, func: &mut Function, cfg: &ControlFlowGraph, lpt: &LoopTree, lp: Loop) {
+        let mut exits = self.effects.exiting_blocks.iter().flat_map(|block| {
+            cfg.succs_of(*block)
+                .copied()
+                .filter(|succ| !lpt.is_in_loop(*succ, lp))
+        });
+        let Some(exit) = exits.next() else {
+            return;
+        };
+        if exits.any(|block| block != exit)
+            || cfg.preds_of(exit).any(|pred| !lpt.is_in_loop(*pred, lp))
+        {
+            return;
+        }
+
+        // Visit instructions in reverse order so that an instruction used only
+        // by sunk instructions can be sunk as well.
+        for &block in self.blocks.iter().rev() {
+            if !self.domtree.dominates(block, exit) {
+                continue;
+            }
+
+            let insts: Vec<_> = func.layout.iter_inst(block).collect();
+            for inst in insts.into_iter().rev() {
+                if func.dfg.side_effect(inst).has_effect()
+                    || func.dfg.is_branch(inst)
+                    || func.dfg.is_phi(inst)
+                {
+                    continue;
+                }
+                let Some(result) = func.dfg.inst_result(inst) else {
+                    continue;
+                };
+
+                let is_used_only_after_loop = func.dfg.users_num(result) > 0
+                    && func.dfg.users(result).all(|user| {
+                        !func.dfg.is_phi(*user)
+                            && self.domtree.dominates(exit, func.layout.inst_block(*user))
+                    });
+                if !is_used_only_after_loop {
+                    continue;
+                }
+
+                let first_inst = func
+                    .layout
+                    .iter_inst(exit)
+                    .find(|inst| !func.dfg.is_phi(*inst))
+                    .unwrap();
+                func.layout.remove_inst(inst);
+                func.layout.insert_inst_before(inst, first_inst);
+            }
+        }
     }
 
     /// Returns preheader of the loop.
@@ -208,6 +375,51 @@ impl LicmSolver {
     }
 }
 
+#[derive(Debug, Default)]
+struct LoopEffects {
+    /// Blocks in the loop that have a successor outside the loop.
+    exiting_blocks: Vec<BlockId>,
+
+    /// Memory locations that may be written in the loop.
+    memory_writes: Vec<MemoryLocation>,
+
+    /// `true` if the loop may write to any memory location, or observe the
+    /// memory size.
+    clobbers_memory: bool,
+
+    /// Storage slots that may be written in the loop.
+    storage_writes: Vec<Slot>,
+
+    /// `true` if the loop may write to any storage slot.
+    clobbers_storage: bool,
+}
+
+impl LoopEffects {
+    fn clear(&mut self) {
+        self.exiting_blocks.clear();
+        self.memory_writes.clear();
+        self.clobbers_memory = false;
+        self.storage_writes.clear();
+        self.clobbers_storage = false;
+    }
+
+    fn is_memory_invariant(&self, aa: &AliasAnalysis, loc: &MemoryLocation) -> bool {
+        !self.clobbers_memory
+            && self
+                .memory_writes
+                .iter()
+                .all(|write| aa.alias(loc, write) == Alias::No)
+    }
+
+    fn is_storage_invariant(&self, slot: &Slot) -> bool {
+        !self.clobbers_storage
+            && self
+                .storage_writes
+                .iter()
+                .all(|write| slot.alias(write) == Alias::No)
+    }
+}
+
 impl Default for LicmSolver {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,411 @@
+//! This module contains a solver for promoting memory slots to SSA values.
+//!
+//! Aggregate `alloca`s whose fields are only accessed through `gep`s with
+//! constant field indices are first split into one `alloca` per field
+//! (scalar replacement of aggregates).
+//!
+//! Then, each `alloca` of a scalar type that is only loaded from and stored to
+//! is promoted to SSA values. Phis are placed at the iterated dominance
+//! frontiers of the blocks that store to the slot, and each load is replaced
+//! with the value that reaches it. The algorithm is based on Ron Cytron, Jeanne
+//! Ferrante, Barry K. Rosen, Mark N. Wegman, and F. Kenneth Zadeck.:
+//! Efficiently computing static single assignment form and the control
+//! dependence graph: ACM Transactions on Programming Languages and Systems
+//! Volume 13 Issue 4 Oct. 1991 pp 451–490: <https://doi.org/10.1145/115372.115320>
+
+use std::collections::BTreeMap;
+
+use cranelift_entity::SecondaryMap;
+use rustc_hash::FxHashMap;
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::data::{Alloca, Gep, Mload, Mstore},
+    prelude::*,
+    types::CompoundType,
+    BlockId, ControlFlowGraph, DataFlowGraph, Function, InstId, Type, Value, ValueId, U256,
+};
+
+use crate::domtree::{DFSet, DomTree};
+
+/// The maximum number of fields of an aggregate `alloca` to be split.
+const MAX_SPLIT_FIELDS: usize = 32;
+
+#[derive(Debug)]
+pub struct Mem2RegSolver {
+    /// Promotable `alloca`s.
+    slots: Vec<Slot>,
+    slot_of: FxHashMap<ValueId, usize>,
+
+    /// Phis inserted for each slot, keyed by their blocks.
+    phis: BTreeMap<BlockId, Vec<(usize, InstId)>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    alloca: InstId,
+    ty: Type,
+}
+
+impl Mem2RegSolver {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            slot_of: FxHashMap::default(),
+            phis: BTreeMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.slot_of.clear();
+        self.phis.clear();
+    }
+
+    /// Promotes `alloca`s in the function to SSA values.
+    /// `cfg` and `domtree` must be computed beforehand; they are still valid
+    /// after the promotion because the control flow is not changed.
+    pub fn run(&mut self, func: &mut Function, cfg: &ControlFlowGraph, domtree: &DomTree) {
+        self.clear();
+
+        self.split_aggregates(func);
+
+        self.collect_slots(func, domtree);
+        if self.slots.is_empty() {
+            return;
+        }
+
+        let df = domtree.compute_df(cfg);
+        self.place_phis(func, &df);
+        self.rename(func, cfg, domtree);
+        self.remove_trivial_phis(func);
+
+        for slot in &self.slots {
+            InstInserter::at_location(CursorLocation::At(slot.alloca)).remove_inst(func);
+        }
+    }
+
+    /// Splits aggregate `alloca`s into `alloca`s of their fields. The new
+    /// `alloca`s are split again if they are also aggregates.
+    fn split_aggregates(&mut self, func: &mut Function) {
+        let mut worklist: Vec<_> = func
+            .layout
+            .iter_block()
+            .flat_map(|block| func.layout.iter_inst(block))
+            .filter(|&inst| cast_alloca(&func.dfg, inst).is_some())
+            .collect();
+        worklist.reverse();
+
+        while let Some(alloca) = worklist.pop() {
+            if let Some(fields) = self.split(func, alloca) {
+                worklist.extend(fields.into_iter().rev());
+            }
+        }
+    }
+
+    /// Splits the `alloca` if all its uses are `gep`s into its fields.
+    /// Returns the `alloca`s of the fields on success.
+    fn split(&self, func: &mut Function, alloca: InstId) -> Option<Vec<InstId>> {
+        let ty = *cast_alloca(&func.dfg, alloca)?.ty();
+        let ptr = func.dfg.inst_result(alloca)?;
+
+        let field_tys = match ty.resolve_compound(func.ctx())? {
+            CompoundType::Array { elem, len } if len <= MAX_SPLIT_FIELDS => vec![elem; len],
+            CompoundType::Struct(s) if s.fields.len() <= MAX_SPLIT_FIELDS => s.fields.to_vec(),
+            _ => return None,
+        };
+        let field_ptr_tys: Vec<_> = field_tys.iter().map(|ty| ty.to_ptr(func.ctx())).collect();
+
+        let geps: Vec<_> = func.dfg.users(ptr).copied().collect();
+        for &gep in &geps {
+            let values = cast_gep(&func.dfg, gep)?.values();
+            if values.len() < 3 || values[0] != ptr || values[1..].contains(&ptr) {
+                return None;
+            }
+            if !func
+                .dfg
+                .value_imm(values[1])
+                .is_some_and(|imm| imm.is_zero())
+            {
+                return None;
+            }
+
+            let idx = const_index(&func.dfg, values[2], field_tys.len())?;
+            let result = func.dfg.inst_result(gep)?;
+            if values.len() == 3 && func.dfg.value_ty(result) != field_ptr_tys[idx] {
+                return None;
+            }
+            if !is_safe_ptr(&func.dfg, result) {
+                return None;
+            }
+        }
+
+        let is = func.inst_set();
+        let mut inserter = InstInserter::at_location(CursorLocation::At(alloca));
+        let mut fields = Vec::with_capacity(field_tys.len());
+        for (&field_ty, &field_ptr_ty) in field_tys.iter().zip(&field_ptr_tys) {
+            let inst = inserter.insert_inst_data(func, Alloca::new_unchecked(is, field_ty));
+            let result = inserter.make_result(func, inst, field_ptr_ty);
+            inserter.attach_result(func, inst, result);
+            inserter.set_location(CursorLocation::At(inst));
+            fields.push((inst, result));
+        }
+
+        for gep in geps {
+            let values = cast_gep(&func.dfg, gep).unwrap().values().clone();
+            let idx = const_index(&func.dfg, values[2], field_tys.len()).unwrap();
+            let field_ptr = fields[idx].1;
+
+            if values.len() == 3 {
+                let result = func.dfg.inst_result(gep).unwrap();
+                func.dfg.change_to_alias(result, field_ptr);
+                InstInserter::at_location(CursorLocation::At(gep)).remove_inst(func);
+            } else {
+                // `gep ptr 0 idx rest..` is equivalent to `gep field_ptr 0 rest..`.
+                let mut new_values = values;
+                new_values.remove(2);
+                new_values[0] = field_ptr;
+                func.dfg
+                    .replace_inst(gep, Box::new(Gep::new_unchecked(is, new_values)));
+            }
+        }
+
+        InstInserter::at_location(CursorLocation::At(alloca)).remove_inst(func);
+        Some(fields.into_iter().map(|(inst, _)| inst).collect())
+    }
+
+    /// Collects `alloca`s of scalar types that are only loaded and stored in
+    /// reachable blocks.
+    fn collect_slots(&mut self, func: &Function, domtree: &DomTree) {
+        for &block in domtree.rpo() {
+            for inst in func.layout.iter_inst(block) {
+                let Some(alloca) = cast_alloca(&func.dfg, inst) else {
+                    continue;
+                };
+                let ty = *alloca.ty();
+                let Some(ptr) = func.dfg.inst_result(inst) else {
+                    continue;
+                };
+                if !(ty.is_integral() || ty.is_pointer(func.ctx()))
+                    || !self.is_promotable(func, domtree, ptr, ty)
+                {
+                    continue;
+                }
+
+                self.slot_of.insert(ptr, self.slots.len());
+                self.slots.push(Slot { alloca: inst, ty });
+            }
+        }
+    }
+
+    fn is_promotable(&self, func: &Function, domtree: &DomTree, ptr: ValueId, ty: Type) -> bool {
+        func.dfg.users(ptr).all(|&user| {
+            if !is_reachable(domtree, func.layout.inst_block(user)) {
+                return false;
+            }
+
+            if let Some(load) = cast_mload(&func.dfg, user) {
+                *load.addr() == ptr && *load.ty() == ty
+            } else if let Some(store) = cast_mstore(&func.dfg, user) {
+                *store.addr() == ptr && *store.value() != ptr && *store.ty() == ty
+            } else {
+                false
+            }
+        })
+    }
+
+    /// Places phis at the iterated dominance frontiers of the blocks that
+    /// store to each slot.
+    fn place_phis(&mut self, func: &mut Function, df: &DFSet) {
+        for (idx, slot) in self.slots.iter().enumerate() {
+            let ptr = func.dfg.inst_result(slot.alloca).unwrap();
+            let mut worklist: Vec<_> = func
+                .dfg
+                .users(ptr)
+                .filter(|&&user| cast_mstore(&func.dfg, user).is_some())
+                .map(|&user| func.layout.inst_block(user))
+                .collect();
+
+            let mut phi_blocks = Vec::new();
+            while let Some(block) = worklist.pop() {
+                for &frontier in df.frontiers(block) {
+                    if !phi_blocks.contains(&frontier) {
+                        phi_blocks.push(frontier);
+                        worklist.push(frontier);
+                    }
+                }
+            }
+
+            for block in phi_blocks {
+                let phi = func.dfg.make_phi(vec![]);
+                let phi = func.dfg.make_inst(phi);
+                self.phis.entry(block).or_default().push((idx, phi));
+            }
+        }
+
+        // Insert the phis in the order of slots.
+        for (&block, phis) in self.phis.iter_mut() {
+            phis.sort_unstable();
+            for &(idx, phi) in phis.iter() {
+                let ty = self.slots[idx].ty;
+                let result = func.dfg.make_value(Value::Inst { inst: phi, ty });
+                func.dfg.attach_result(phi, result);
+            }
+            for &(_, phi) in phis.iter().rev() {
+                func.layout.prepend_inst(phi, block);
+            }
+        }
+    }
+
+    /// Replaces loads with the values reaching them, and removes stores.
+    fn rename(&self, func: &mut Function, cfg: &ControlFlowGraph, domtree: &DomTree) {
+        let undefs: Vec<_> = self
+            .slots
+            .iter()
+            .map(|slot| func.dfg.make_undef_value(slot.ty))
+            .collect();
+
+        // The values of the slots at the end of each block.
+        let mut exits: SecondaryMap<BlockId, Vec<ValueId>> = SecondaryMap::default();
+
+        for &block in domtree.rpo() {
+            // If the block has no phi for a slot, the value reaching the block
+            // is the value at the end of its immediate dominator.
+            let mut current = match domtree.idom_of(block) {
+                Some(idom) => exits[idom].clone(),
+                None => undefs.clone(),
+            };
+            for &(idx, phi) in self.phis.get(&block).into_iter().flatten() {
+                current[idx] = func.dfg.inst_result(phi).unwrap();
+            }
+
+            let insts: Vec<_> = func.layout.iter_inst(block).collect();
+            for inst in insts {
+                if let Some(load) = cast_mload(&func.dfg, inst) {
+                    let Some(&idx) = self.slot_of.get(load.addr()) else {
+                        continue;
+                    };
+                    let result = func.dfg.inst_result(inst).unwrap();
+                    func.dfg.change_to_alias(result, current[idx]);
+                } else if let Some(store) = cast_mstore(&func.dfg, inst) {
+                    let Some(&idx) = self.slot_of.get(store.addr()) else {
+                        continue;
+                    };
+                    current[idx] = *store.value();
+                } else {
+                    continue;
+                }
+
+                InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+            }
+
+            exits[block] = current;
+        }
+
+        for (&block, phis) in &self.phis {
+            for &pred in cfg.preds_of(block) {
+                if !is_reachable(domtree, pred) {
+                    continue;
+                }
+                for &(idx, phi) in phis {
+                    func.dfg.append_phi_arg(phi, exits[pred][idx], pred);
+                }
+            }
+        }
+    }
+
+    /// Removes the inserted phis that have no users other than themselves,
+    /// and replaces the phis whose arguments are all the same value with the
+    /// value.
+    fn remove_trivial_phis(&self, func: &mut Function) {
+        let mut phis: Vec<_> = self.phis.values().flatten().map(|&(_, phi)| phi).collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            phis.retain(|&phi| {
+                let result = func.dfg.inst_result(phi).unwrap();
+                let phi_data = func.dfg.cast_phi(phi).unwrap();
+
+                let mut args = phi_data
+                    .args()
+                    .iter()
+                    .map(|(value, _)| *value)
+                    .filter(|&value| value != result);
+                let same_arg = args.next().filter(|&first| args.all(|arg| arg == first));
+
+                if let Some(value) = same_arg {
+                    func.dfg.change_to_alias(result, value);
+                } else if !func.dfg.users(result).all(|&user| user == phi) {
+                    return true;
+                }
+
+                InstInserter::at_location(CursorLocation::At(phi)).remove_inst(func);
+                changed = true;
+                false
+            });
+        }
+    }
+}
+
+impl Default for Mem2RegSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns `true` if the block is reachable from the entry block. Unlike
+/// [`DomTree::is_reachable`], this returns `true` for the entry block.
+fn is_reachable(domtree: &DomTree, block: BlockId) -> bool {
+    domtree.rpo().first() == Some(&block) || domtree.is_reachable(block)
+}
+
+/// Returns `true` if `ptr` is only used as an address of loads and stores, or
+/// as a base of `gep`s that don't step over `ptr` and whose results are also
+/// safe.
+fn is_safe_ptr(dfg: &DataFlowGraph, ptr: ValueId) -> bool {
+    dfg.users(ptr).all(|&user| {
+        if let Some(load) = cast_mload(dfg, user) {
+            *load.addr() == ptr
+        } else if let Some(store) = cast_mstore(dfg, user) {
+            *store.addr() == ptr && *store.value() != ptr
+        } else if let Some(gep) = cast_gep(dfg, user) {
+            let values = gep.values();
+            values.len() >= 2
+                && values[0] == ptr
+                && !values[1..].contains(&ptr)
+                && dfg.value_imm(values[1]).is_some_and(|imm| imm.is_zero())
+                && dfg
+                    .inst_result(user)
+                    .is_some_and(|result| is_safe_ptr(dfg, result))
+        } else {
+            false
+        }
+    })
+}
+
+/// Returns the value of `value` if it's a constant less than `len`.
+fn const_index(dfg: &DataFlowGraph, value: ValueId, len: usize) -> Option<usize> {
+    let imm = dfg.value_imm(value)?;
+    if imm.is_negative() {
+        return None;
+    }
+
+    let idx = imm.as_i256().to_u256();
+    (idx < U256::from(len)).then(|| idx.as_usize())
+}
+
+fn cast_alloca(dfg: &DataFlowGraph, inst: InstId) -> Option<&Alloca> {
+    InstDowncast::downcast(dfg.inst_set(), dfg.inst(inst))
+}
+
+fn cast_gep(dfg: &DataFlowGraph, inst: InstId) -> Option<&Gep> {
+    InstDowncast::downcast(dfg.inst_set(), dfg.inst(inst))
+}
+
+fn cast_mload(dfg: &DataFlowGraph, inst: InstId) -> Option<&Mload> {
+    InstDowncast::downcast(dfg.inst_set(), dfg.inst(inst))
+}
+
+fn cast_mstore(dfg: &DataFlowGraph, inst: InstId) -> Option<&Mstore> {
+    InstDowncast::downcast(dfg.inst_set(), dfg.inst(inst))
+}
@@ -0,0 +1,209 @@
+//! This module contains a solver for redundant memory access elimination.
+//!
+//! The solver works on the linear memory accessed by `mload`/`mstore`,
+//! `evm_mstore8` and the copy instructions, and performs the following:
+//! * A load is replaced with the value stored to or loaded from the same
+//!   location with the same type on all paths reaching the load.
+//! * A store of the value that the location is known to hold is removed.
+//! * A store is removed if the location is overwritten on all paths before
+//!   it's read.
+//!
+//! Locations are compared with [`AliasAnalysis`]. A load is forwarded only
+//! from a location with the same base, offset and size.
+//!
+//! External calls can't access the memory of the caller except the regions
+//! passed as arguments and return data, but internal calls may access any
+//! location, so they are regarded as reading and writing all locations.
+//!
+//! Loops are handled conservatively: nothing is assumed about locations at
+//! the header of a loop, or at the end of a block that has a back edge.
+
+use cranelift_entity::SecondaryMap;
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    BlockId, ControlFlowGraph, Function, Type, ValueId,
+};
+
+use crate::alias_analysis::{Alias, AliasAnalysis, MemoryAccess, MemoryLocation};
+
+#[derive(Debug, Default)]
+pub struct MemoryElimSolver {
+    /// Locations with known values at the end of each block, which is `None`
+    /// if the block is not visited yet.
+    known: SecondaryMap<BlockId, Option<Vec<KnownValue>>>,
+
+    /// Locations that are overwritten before read at the start of each
+    /// block, which is `None` if the block is not visited yet.
+    overwritten: SecondaryMap<BlockId, Option<Vec<MemoryLocation>>>,
+}
+
+impl MemoryElimSolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.known.clear();
+        self.overwritten.clear();
+    }
+
+    pub fn run(&mut self, func: &mut Function, cfg: &ControlFlowGraph, aa: &AliasAnalysis) {
+        self.clear();
+
+        let mut rpo: Vec<_> = cfg.post_order().collect();
+        rpo.reverse();
+
+        for &block in &rpo {
+            self.forward_block(func, cfg, aa, block);
+        }
+        for &block in rpo.iter().rev() {
+            self.eliminate_dead_stores(func, cfg, aa, block);
+        }
+    }
+
+    /// Forwards known values of locations to loads in the block, and removes
+    /// stores that don't change the locations.
+    fn forward_block(
+        &mut self,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        aa: &AliasAnalysis,
+        block: BlockId,
+    ) {
+        let mut known = self.meet_preds(cfg, block);
+
+        let insts: Vec<_> = func.layout.iter_inst(block).collect();
+        for inst in insts {
+            match MemoryAccess::of(&func.dfg, inst) {
+                MemoryAccess::Load(loc, ty) => {
+                    let result = func.dfg.inst_result(inst).unwrap();
+                    let value = known.iter().find_map(|(known_loc, known_ty, value)| {
+                        (*known_loc == loc
+                            && *known_ty == ty
+                            && func.dfg.value_ty(*value) == func.dfg.value_ty(result))
+                        .then_some(*value)
+                    });
+
+                    if let Some(value) = value {
+                        func.dfg.change_to_alias(result, value);
+                        InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                    } else {
+                        known.push((loc, ty, result));
+                    }
+                }
+
+                MemoryAccess::Store(loc, Some((ty, value))) => {
+                    if known.contains(&(loc, ty, value)) {
+                        InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                    } else {
+                        known.retain(|(known_loc, ..)| aa.alias(&loc, known_loc) == Alias::No);
+                        known.push((loc, ty, value));
+                    }
+                }
+
+                MemoryAccess::Store(loc, None) | MemoryAccess::Copy { dest: loc, .. } => {
+                    known.retain(|(known_loc, ..)| aa.alias(&loc, known_loc) == Alias::No);
+                }
+
+                MemoryAccess::Read(_) | MemoryAccess::ReadAll => {}
+
+                MemoryAccess::ReadWriteAll => known.clear(),
+
+                MemoryAccess::None => {}
+            }
+        }
+
+        self.known[block] = Some(known);
+    }
+
+    /// Removes stores in the block that are overwritten before read.
+    fn eliminate_dead_stores(
+        &mut self,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        aa: &AliasAnalysis,
+        block: BlockId,
+    ) {
+        let mut overwritten = self.meet_succs(cfg, block);
+
+        let insts: Vec<_> = func.layout.iter_inst(block).collect();
+        for inst in insts.into_iter().rev() {
+            match MemoryAccess::of(&func.dfg, inst) {
+                MemoryAccess::Load(loc, _) | MemoryAccess::Read(loc) => {
+                    overwritten
+                        .retain(|overwritten_loc| aa.alias(&loc, overwritten_loc) == Alias::No);
+                }
+
+                MemoryAccess::Store(loc, _) => {
+                    if overwritten
+                        .iter()
+                        .any(|overwritten_loc| overwritten_loc.covers(&loc))
+                    {
+                        InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                    } else {
+                        overwritten.push(loc);
+                    }
+                }
+
+                MemoryAccess::Copy { src, dest, partial } => {
+                    if !partial {
+                        overwritten.push(dest);
+                    }
+                    if let Some(src) = src {
+                        overwritten
+                            .retain(|overwritten_loc| aa.alias(&src, overwritten_loc) == Alias::No);
+                    }
+                }
+
+                MemoryAccess::ReadAll | MemoryAccess::ReadWriteAll => overwritten.clear(),
+
+                MemoryAccess::None => {}
+            }
+        }
+
+        self.overwritten[block] = Some(overwritten);
+    }
+
+    /// Returns the locations with the same known values at the end of all
+    /// predecessors.
+    fn meet_preds(&self, cfg: &ControlFlowGraph, block: BlockId) -> Vec<KnownValue> {
+        let mut preds = cfg.preds_of(block);
+        let Some(first) = preds.next() else {
+            return Vec::new();
+        };
+
+        let Some(mut known) = self.known[*first].clone() else {
+            return Vec::new();
+        };
+        for pred in preds {
+            let Some(pred_known) = &self.known[*pred] else {
+                return Vec::new();
+            };
+            known.retain(|entry| pred_known.contains(entry));
+        }
+        known
+    }
+
+    /// Returns the locations that are overwritten before read in all
+    /// successors.
+    fn meet_succs(&self, cfg: &ControlFlowGraph, block: BlockId) -> Vec<MemoryLocation> {
+        let mut succs = cfg.succs_of(block);
+        let Some(first) = succs.next() else {
+            return Vec::new();
+        };
+
+        let Some(mut overwritten) = self.overwritten[*first].clone() else {
+            return Vec::new();
+        };
+        for succ in succs {
+            let Some(succ_overwritten) = &self.overwritten[*succ] else {
+                return Vec::new();
+            };
+            overwritten.retain(|loc| succ_overwritten.contains(loc));
+        }
+        overwritten
+    }
+}
+
+/// A location known to hold a value of a type.
+type KnownValue = (MemoryLocation, Type, ValueId);
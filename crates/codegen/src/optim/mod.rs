@@ -1,3 +1,13 @@
 pub mod adce;
+pub mod gvn;
+pub mod inliner;
+pub mod insn_simplify;
+pub mod ipsccp;
+pub mod jump_threading;
 pub mod licm;
+pub mod mem2reg;
+pub mod memory_elim;
+pub mod range_simplify;
 pub mod sccp;
+pub mod simplify_cfg;
+pub mod storage_elim;
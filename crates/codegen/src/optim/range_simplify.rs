@@ -0,0 +1,271 @@
+//! This module contains a solver that simplifies instructions with the facts
+//! computed by [`RangeAnalysis`].
+//!
+//! The solver performs the following:
+//! * An instruction whose result is known to be a constant is replaced with
+//!   the constant, e.g., a bounds check `lt v 4` where `v` is in `[0, 3]`.
+//! * `and` with a mask is removed if the bits cleared by the mask are known to
+//!   be zero.
+//! * A case of `br_table` is removed if the scrutinee never matches it, and
+//!   the default destination is removed if the cases cover all the values the
+//!   scrutinee can take.
+//! * `add`, `sub`, `mul`, `and`, `or` and `xor` of zero extended values are
+//!   computed in the narrower type if the result fits in it. The low bits of
+//!   the results of these instructions depend only on the low bits of the
+//!   operands, so the narrowed instruction yields the same value.
+
+use rustc_hash::FxHashSet;
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::{
+        arith, cast,
+        control_flow::{BrTable, Branch},
+        logic,
+    },
+    prelude::*,
+    BlockId, ControlFlowGraph, Function, InstId, Value, U256,
+};
+
+use super::simplify_cfg::remove_phi_args;
+use crate::range_analysis::RangeAnalysis;
+
+#[derive(Default)]
+pub struct RangeSimplifySolver {
+    ranges: RangeAnalysis,
+}
+
+impl RangeSimplifySolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+
+    pub fn run(&mut self, func: &mut Function, cfg: &mut ControlFlowGraph) {
+        self.ranges.compute(func);
+
+        let blocks: Vec<_> = func.layout.iter_block().collect();
+        for block in blocks {
+            // Facts of the values in unreachable blocks are unknown.
+            if !self.ranges.is_reachable(block) {
+                continue;
+            }
+
+            let mut next_inst = func.layout.first_inst_of(block);
+            while let Some(inst) = next_inst {
+                next_inst = func.layout.next_inst_of(inst);
+                let _ = self.fold_constant(func, inst)
+                    || self.remove_redundant_mask(func, inst)
+                    || self.narrow(func, inst)
+                    || self.prune_br_table(func, cfg, inst);
+            }
+        }
+    }
+
+    fn fold_constant(&self, func: &mut Function, inst: InstId) -> bool {
+        let Some(result) = func.dfg.inst_result(inst) else {
+            return false;
+        };
+        if func.dfg.side_effect(inst).has_effect() {
+            return false;
+        }
+        let Some(imm) = self
+            .ranges
+            .fact(func, result)
+            .and_then(|fact| fact.as_constant())
+        else {
+            return false;
+        };
+
+        InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+        let imm = func.dfg.make_imm_value(imm);
+        func.dfg.change_to_alias(result, imm);
+        true
+    }
+
+    fn remove_redundant_mask(&self, func: &mut Function, inst: InstId) -> bool {
+        let Some(and) =
+            <&logic::And as InstDowncast>::downcast(func.inst_set(), func.dfg.inst(inst))
+        else {
+            return false;
+        };
+        let (lhs, rhs) = (*and.lhs(), *and.rhs());
+
+        let is_within = |value, mask| match func.dfg.value_imm(mask) {
+            Some(mask) => self
+                .ranges
+                .fact(func, value)
+                .is_some_and(|fact| fact.is_within_mask(mask)),
+            None => false,
+        };
+        let masked = if is_within(lhs, rhs) {
+            lhs
+        } else if is_within(rhs, lhs) {
+            rhs
+        } else {
+            return false;
+        };
+
+        let result = func.dfg.inst_result(inst).unwrap();
+        InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+        func.dfg.change_to_alias(result, masked);
+        true
+    }
+
+    fn narrow(&self, func: &mut Function, inst: InstId) -> bool {
+        let is = func.inst_set();
+        let data = func.dfg.inst(inst);
+        if !is_narrowable(is, data) {
+            return false;
+        }
+        let result = func.dfg.inst_result(inst).unwrap();
+        let ty = func.dfg.value_ty(result);
+
+        // All the operands but immediates must be zero extended from the same
+        // type.
+        let mut narrow_ty = None;
+        let mut operands = Vec::new();
+        let mut is_narrowable_operands = true;
+        data.for_each_value(&mut |value| {
+            if func.dfg.value_imm(value).is_some() {
+                operands.push((value, None));
+                return;
+            }
+
+            let from = func.dfg.value_inst(value).and_then(|def| {
+                <&cast::Zext as InstDowncast>::downcast(is, func.dfg.inst(def))
+                    .map(|zext| *zext.from())
+            });
+            match from {
+                Some(from) if narrow_ty.is_none_or(|ty| ty == func.dfg.value_ty(from)) => {
+                    narrow_ty = Some(func.dfg.value_ty(from));
+                    operands.push((value, Some(from)));
+                }
+                _ => is_narrowable_operands = false,
+            }
+        });
+
+        let Some(narrow_ty) = narrow_ty else {
+            return false;
+        };
+        if !is_narrowable_operands
+            || !self
+                .ranges
+                .fact(func, result)
+                .is_some_and(|fact| fact.fits_unsigned(narrow_ty))
+        {
+            return false;
+        }
+
+        let operands: Vec<_> = operands
+            .into_iter()
+            .map(|(value, from)| match from {
+                Some(from) => (value, from),
+                None => {
+                    let imm = func.dfg.value_imm(value).unwrap().trunc(narrow_ty);
+                    (value, func.dfg.make_imm_value(imm))
+                }
+            })
+            .collect();
+
+        let mut narrowed = dyn_clone::clone_box(func.dfg.inst(inst));
+        narrowed.for_each_value_mut(&mut |value| {
+            if let Some((_, narrow)) = operands.iter().find(|(v, _)| v == value) {
+                *value = *narrow;
+            }
+        });
+
+        let narrowed = func.dfg.make_inst_dyn(narrowed);
+        func.layout.insert_inst_before(narrowed, inst);
+        let narrowed_result = func.dfg.make_value(Value::Inst {
+            inst: narrowed,
+            ty: narrow_ty,
+        });
+        func.dfg.attach_result(narrowed, narrowed_result);
+
+        let zext = cast::Zext::new_unchecked(is, narrowed_result, ty);
+        func.dfg.replace_inst(inst, Box::new(zext));
+        true
+    }
+
+    fn prune_br_table(
+        &self,
+        func: &mut Function,
+        cfg: &mut ControlFlowGraph,
+        inst: InstId,
+    ) -> bool {
+        let is = func.inst_set();
+        let Some(brt) = <&BrTable as InstDowncast>::downcast(is, func.dfg.inst(inst)) else {
+            return false;
+        };
+        let Some(scrutinee) = self.ranges.fact(func, *brt.scrutinee()) else {
+            return false;
+        };
+
+        let mut table = Vec::with_capacity(brt.table().len());
+        let mut cases = FxHashSet::default();
+        for &(value, dest) in brt.table() {
+            let case = self.ranges.fact(func, value);
+            if case.is_some_and(|case| scrutinee.is_equal(&case) == Some(false)) {
+                continue;
+            }
+            if let Some(case) = case.filter(|case| case.as_constant().is_some()) {
+                cases.insert(case.unsigned.min);
+            }
+            table.push((value, dest));
+        }
+
+        // The remaining constant cases are all in the range of the scrutinee,
+        // so they cover the range if there are as many of them as its values.
+        let range = scrutinee.unsigned;
+        let default = if U256::from(cases.len()) > range.max - range.min {
+            None
+        } else {
+            *brt.default()
+        };
+
+        if table.len() == brt.table().len() && default == *brt.default() {
+            return false;
+        }
+
+        let mut new_dests: Vec<BlockId> = default.into_iter().collect();
+        new_dests.extend(table.iter().map(|(_, dest)| *dest));
+        let Some(&first) = new_dests.first() else {
+            return false;
+        };
+
+        let block = func.layout.inst_block(inst);
+        let mut removed = brt.dests();
+        removed.retain(|dest| !new_dests.contains(dest));
+        removed.sort_unstable();
+        removed.dedup();
+        let scrutinee = *brt.scrutinee();
+
+        for dest in removed {
+            remove_phi_args(func, dest, block);
+            cfg.remove_edge(block, dest);
+        }
+
+        if new_dests.iter().all(|dest| *dest == first) {
+            let jump = func.dfg.make_jump(first);
+            func.dfg.replace_inst(inst, Box::new(jump));
+        } else {
+            let brt = BrTable::new_unchecked(is, scrutinee, default, table);
+            func.dfg.replace_inst(inst, Box::new(brt));
+        }
+        true
+    }
+}
+
+/// Returns `true` if the low bits of the result of `inst` depend only on the
+/// low bits of the operands.
+fn is_narrowable(is: &dyn InstSetBase, inst: &dyn Inst) -> bool {
+    <&arith::Add as InstDowncast>::downcast(is, inst).is_some()
+        || <&arith::Sub as InstDowncast>::downcast(is, inst).is_some()
+        || <&arith::Mul as InstDowncast>::downcast(is, inst).is_some()
+        || <&logic::And as InstDowncast>::downcast(is, inst).is_some()
+        || <&logic::Or as InstDowncast>::downcast(is, inst).is_some()
+        || <&logic::Xor as InstDowncast>::downcast(is, inst).is_some()
+}
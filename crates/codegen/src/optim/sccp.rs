@@ -3,19 +3,37 @@
 //! The algorithm is based on Mark N. Wegman., Frank Kcnncth Zadeck.: Constant
 //! propagation with conditional branches: ACM Transactions on Programming
 //! Languages and Systems Volume 13 Issue 2 April 1991 pp 181–210: <https://doi.org/10.1145/103135.103136>
+//!
+//! Besides the evaluation of instructions through [`Interpret`], the solver
+//! folds
+//! * pure EVM instructions whose result is determined by one operand, e.g.,
+//!   `evm_umod` by zero or `evm_exp` to the power of zero.
+//! * `evm_keccak256` of bytes that are written by `mstore` and `evm_mstore8`
+//!   with constant operands earlier in the same block, which is how event
+//!   topics and mapping slots are computed.
 
 use std::collections::BTreeSet;
 
 use cranelift_entity::SecondaryMap;
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHashSet};
 use sonatina_ir::{
     func_cursor::{CursorLocation, FuncCursor, InstInserter},
-    inst::control_flow::{Branch, BranchKind},
-    interpret::{Action, EvalValue, Interpret, State},
+    inst::{
+        control_flow::{self, Branch, BranchKind},
+        data, evm,
+    },
+    interpret::{Action, DataSource, EnvQuery, EvalValue, ExtCall, Interpret, State},
+    isa::Endian,
+    module::FuncRef,
     prelude::*,
-    BlockId, ControlFlowGraph, DataFlowGraph, Function, Immediate, InstId, Type, ValueId,
+    BlockId, ControlFlowGraph, DataFlowGraph, Function, Immediate, InstId, Type, ValueId, U256,
 };
 
+use crate::alias_analysis::{MemoryAccess, MemoryLocation};
+
+/// `evm_keccak256` of more bytes than this is not folded.
+const MAX_HASHED_LEN: u64 = 1024;
+
 #[derive(Debug)]
 pub struct SccpSolver {
     lattice: SecondaryMap<ValueId, LatticeCell>,
@@ -24,6 +42,27 @@ pub struct SccpSolver {
 
     flow_work: Vec<FlowEdge>,
     ssa_work: Vec<ValueId>,
+
+    /// `evm_keccak256` instructions that depend on the operands of stores
+    /// before them, which are re-evaluated when the cells of the operands
+    /// change.
+    mem_users: FxHashMap<ValueId, FxHashSet<InstId>>,
+
+    /// Cells of values flowing into the function, which are given by
+    /// interprocedural SCCP.
+    call_ctx: CallContext,
+}
+
+/// Lattice cells of values flowing across function boundaries.
+#[derive(Debug, Default)]
+pub(super) struct CallContext {
+    /// Cells of the function arguments. An argument is `Top` unless its cell
+    /// is a constant.
+    pub(super) args: Vec<LatticeCell>,
+
+    /// Cells of the return values of callees. The result of a call to a
+    /// function that isn't in the map is `Top`.
+    pub(super) rets: FxHashMap<FuncRef, LatticeCell>,
 }
 
 impl SccpSolver {
@@ -34,9 +73,37 @@ impl SccpSolver {
             reachable_blocks: BTreeSet::default(),
             flow_work: Vec::default(),
             ssa_work: Vec::default(),
+            mem_users: FxHashMap::default(),
+            call_ctx: CallContext::default(),
         }
     }
+
     pub fn run(&mut self, func: &mut Function, cfg: &mut ControlFlowGraph) {
+        self.call_ctx = CallContext::default();
+        self.solve(func, true);
+        self.apply(func, cfg);
+    }
+
+    pub fn clear(&mut self) {
+        self.lattice.clear();
+        self.reachable_edges.clear();
+        self.reachable_blocks.clear();
+        self.flow_work.clear();
+        self.ssa_work.clear();
+        self.mem_users.clear();
+    }
+
+    pub(super) fn set_call_ctx(&mut self, call_ctx: CallContext) {
+        self.call_ctx = call_ctx;
+    }
+
+    /// Propagates constants in the function under `call_ctx`.
+    ///
+    /// A branch whose condition is `Bot` has no reachable destination, which
+    /// happens if the condition is undefined or depends on a call that never
+    /// returns. If `resolve_undef` is `true`, such a condition is regarded as
+    /// `Top` once the propagation converges.
+    pub(super) fn solve(&mut self, func: &Function, resolve_undef: bool) {
         self.clear();
 
         let entry_block = match func.layout.entry_block() {
@@ -44,15 +111,53 @@ impl SccpSolver {
             _ => return,
         };
 
-        // Function arguments must be `LatticeCell::Top`
-        for arg in &func.arg_values {
-            self.lattice[*arg] = LatticeCell::Top;
+        // Function arguments are `LatticeCell::Top` unless they're known to be
+        // constants.
+        for (idx, arg) in func.arg_values.iter().enumerate() {
+            self.lattice[*arg] = match self.call_ctx.args.get(idx) {
+                Some(cell @ LatticeCell::Const(_)) => *cell,
+                _ => LatticeCell::Top,
+            };
         }
 
         // Evaluate all values in entry block.
         self.reachable_blocks.insert(entry_block);
         self.eval_insts_in(func, entry_block);
 
+        loop {
+            self.propagate(func);
+            if !resolve_undef || !self.resolve_undef_branches(func) {
+                break;
+            }
+        }
+    }
+
+    /// Removes unreachable edges and blocks, and folds constants found by
+    /// `solve`.
+    pub(super) fn apply(&mut self, func: &mut Function, cfg: &mut ControlFlowGraph) {
+        if func.layout.entry_block().is_none() {
+            return;
+        }
+
+        self.remove_unreachable_edges(func);
+        cfg.compute(func);
+        self.fold_args(func);
+        self.fold_insts(func, cfg);
+    }
+
+    /// Returns the cell of `value` computed by `solve`.
+    pub(super) fn cell(&self, func: &Function, value: ValueId) -> LatticeCell {
+        match func.dfg.value_imm(value) {
+            Some(imm) => LatticeCell::Const(imm),
+            None => self.lattice[value],
+        }
+    }
+
+    pub(super) fn is_reachable_block(&self, block: BlockId) -> bool {
+        self.reachable_blocks.contains(&block)
+    }
+
+    fn propagate(&mut self, func: &Function) {
         let mut changed = true;
         while changed {
             changed = false;
@@ -74,23 +179,46 @@ impl SccpSolver {
                         }
                     }
                 }
+
+                let mem_users: Vec<_> = self
+                    .mem_users
+                    .get(&value)
+                    .into_iter()
+                    .flatten()
+                    .copied()
+                    .collect();
+                for user in mem_users {
+                    self.eval_inst(func, user);
+                }
             }
         }
-
-        self.remove_unreachable_edges(func);
-        cfg.compute(func);
-        self.fold_insts(func, cfg);
     }
 
-    pub fn clear(&mut self) {
-        self.lattice.clear();
-        self.reachable_edges.clear();
-        self.reachable_blocks.clear();
-        self.flow_work.clear();
-        self.ssa_work.clear();
+    /// Makes the conditions of reachable branches that are still `Bot`
+    /// `Top`. Returns `true` if any condition is changed.
+    fn resolve_undef_branches(&mut self, func: &Function) -> bool {
+        let mut changed = false;
+        let blocks: Vec<_> = self.reachable_blocks.iter().copied().collect();
+        for block in blocks {
+            let Some(last_inst) = func.layout.last_inst_of(block) else {
+                continue;
+            };
+            let cond = match func.dfg.branch_info(last_inst).map(|bi| bi.branch_kind()) {
+                Some(BranchKind::Br(br)) => *br.cond(),
+                Some(BranchKind::BrTable(brt)) => *brt.scrutinee(),
+                Some(BranchKind::Jump(_)) | None => continue,
+            };
+
+            if self.lattice[cond].is_bot() {
+                self.set_lattice_cell(cond, LatticeCell::Top);
+                changed = true;
+            }
+        }
+
+        changed
     }
 
-    fn eval_edge(&mut self, func: &mut Function, edge: FlowEdge) {
+    fn eval_edge(&mut self, func: &Function, edge: FlowEdge) {
         let dest = edge.to;
 
         if self.reachable_edges.contains(&edge) {
@@ -177,6 +305,32 @@ impl SccpSolver {
 
         let inst = func.dfg.inst(inst_id);
         if inst.side_effect().has_effect() {
+            // The result of a call is known only if the callee always returns
+            // the same constant.
+            if let Some(result) = func.dfg.inst_result(inst_id) {
+                let cell = <&control_flow::Call as InstDowncast>::downcast(func.inst_set(), inst)
+                    .map_or(LatticeCell::Top, |call| {
+                        self.call_ctx
+                            .rets
+                            .get(call.callee())
+                            .copied()
+                            .unwrap_or(LatticeCell::Top)
+                    });
+                self.set_lattice_cell(result, cell);
+            }
+            return;
+        }
+
+        let inst_result = func.dfg.inst_result(inst_id).unwrap();
+        if let Some(hash) = <&evm::EvmKeccak256 as InstDowncast>::downcast(func.inst_set(), inst) {
+            let cell = self.eval_keccak(func, inst_id, hash);
+            self.set_lattice_cell(inst_result, cell);
+            return;
+        }
+
+        let ty = func.dfg.value_ty(inst_result);
+        if let Some(imm) = self.eval_evm_absorbing(func, inst, ty) {
+            self.set_lattice_cell(inst_result, LatticeCell::Const(imm));
             return;
         }
 
@@ -185,16 +339,169 @@ impl SccpSolver {
             i.interpret(&mut cell_state)
         });
 
-        let inst_result = func.dfg.inst_result(inst_id).unwrap();
-        let cell = if let Some(EvalValue::Imm(value)) = value {
-            LatticeCell::Const(value)
-        } else {
-            cell_state.cell()
+        let cell = match value {
+            Some(EvalValue::Imm(value)) if !cell_state.is_overdefined => LatticeCell::Const(value),
+            _ => cell_state.cell(),
         };
 
         self.set_lattice_cell(inst_result, cell);
     }
 
+    /// Returns the result of a pure EVM instruction if it's determined by one
+    /// of the operands regardless of the others, e.g., the EVM yields zero for
+    /// division and modulo by zero.
+    fn eval_evm_absorbing(&self, func: &Function, inst: &dyn Inst, ty: Type) -> Option<Immediate> {
+        let is = func.inst_set();
+        let is_zero = |value: &ValueId| self.cell(func, *value).is_zero();
+        let is_one = |value: &ValueId| {
+            self.cell(func, *value)
+                .to_imm()
+                .is_some_and(|imm| imm.is_one())
+        };
+
+        macro_rules! downcast {
+            ($ty:ty) => {
+                <&$ty as InstDowncast>::downcast(is, inst)
+            };
+        }
+
+        let is_zero_result = if let Some(div) = downcast!(evm::EvmUdiv) {
+            is_zero(div.rhs())
+        } else if let Some(div) = downcast!(evm::EvmSdiv) {
+            is_zero(div.rhs())
+        } else if let Some(rem) = downcast!(evm::EvmUmod) {
+            is_zero(rem.rhs()) || is_one(rem.rhs())
+        } else if let Some(rem) = downcast!(evm::EvmSmod) {
+            is_zero(rem.rhs()) || is_one(rem.rhs())
+        } else if let Some(add_mod) = downcast!(evm::EvmAddMod) {
+            is_zero(add_mod.modulus()) || is_one(add_mod.modulus())
+        } else if let Some(mul_mod) = downcast!(evm::EvmMulMod) {
+            is_zero(mul_mod.modulus())
+                || is_one(mul_mod.modulus())
+                || is_zero(mul_mod.lhs())
+                || is_zero(mul_mod.rhs())
+        } else if let Some(exp) = downcast!(evm::EvmExp) {
+            if is_zero(exp.exponent()) || is_one(exp.base()) {
+                return Some(Immediate::one(ty));
+            }
+            false
+        } else if let Some(byte) = downcast!(evm::EvmByte) {
+            let is_out_of_word = self
+                .cell(func, *byte.pos())
+                .to_imm()
+                .is_some_and(|pos| pos.as_i256().to_u256() >= U256::from(32));
+            is_out_of_word || is_zero(byte.value())
+        } else {
+            false
+        };
+
+        is_zero_result.then(|| Immediate::zero(ty))
+    }
+
+    /// Evaluates `evm_keccak256` by reading the hashed bytes from the stores
+    /// before it in the same block.
+    ///
+    /// The result is `Top` unless every byte is written by an `mstore` or
+    /// `evm_mstore8` whose address and value are constants, and no other
+    /// instruction in between may write the bytes.
+    fn eval_keccak(
+        &mut self,
+        func: &Function,
+        hash_inst: InstId,
+        hash: &evm::EvmKeccak256,
+    ) -> LatticeCell {
+        let (addr, len) = match (self.cell(func, *hash.addr()), self.cell(func, *hash.len())) {
+            (LatticeCell::Const(addr), LatticeCell::Const(len)) => (addr, len),
+            (LatticeCell::Top, _) | (_, LatticeCell::Top) => return LatticeCell::Top,
+            _ => return LatticeCell::Bot,
+        };
+        let (Some(addr), Some(len)) = (to_offset(addr), to_offset(len)) else {
+            return LatticeCell::Top;
+        };
+        if len > MAX_HASHED_LEN {
+            return LatticeCell::Top;
+        }
+
+        let is = func.inst_set();
+        let ctx = &func.dfg.ctx;
+        let mut hashed = vec![None; len as usize];
+        let mut unknown = hashed.len();
+        let mut next_inst = func.layout.prev_inst_of(hash_inst);
+
+        while unknown > 0 {
+            let Some(inst_id) = next_inst else {
+                // The bytes may be written in other blocks.
+                return LatticeCell::Top;
+            };
+            next_inst = func.layout.prev_inst_of(inst_id);
+
+            let inst = func.dfg.inst(inst_id);
+            let (store_addr, store_value, ty) =
+                if let Some(store) = <&data::Mstore as InstDowncast>::downcast(is, inst) {
+                    (*store.addr(), *store.value(), *store.ty())
+                } else if let Some(store) = <&evm::EvmMstore8 as InstDowncast>::downcast(is, inst) {
+                    (*store.addr(), *store.val(), Type::I8)
+                } else {
+                    match MemoryAccess::of(&func.dfg, inst_id) {
+                        MemoryAccess::Store(dest, _) | MemoryAccess::Copy { dest, .. }
+                            if may_overlap(&dest, addr, len) =>
+                        {
+                            return LatticeCell::Top;
+                        }
+                        MemoryAccess::ReadWriteAll => return LatticeCell::Top,
+                        _ => {}
+                    }
+                    continue;
+                };
+
+            for value in [store_addr, store_value] {
+                if func.dfg.value_imm(value).is_none() {
+                    self.mem_users.entry(value).or_default().insert(hash_inst);
+                }
+            }
+
+            let store_addr = match self.cell(func, store_addr) {
+                LatticeCell::Const(store_addr) => store_addr,
+                LatticeCell::Top => return LatticeCell::Top,
+                LatticeCell::Bot => return LatticeCell::Bot,
+            };
+            let Some(store_addr) = to_offset(store_addr) else {
+                return LatticeCell::Top;
+            };
+            let size = ctx.size_of_unchecked(ty) as u64;
+
+            // The range of the hashed bytes that the store writes.
+            let start = store_addr.max(addr);
+            let end = (store_addr + size).min(addr + len);
+            if (start..end).all(|pos| hashed[(pos - addr) as usize].is_some()) {
+                continue;
+            }
+
+            if !(ty.is_integral() || ty.is_pointer(ctx)) || size > 32 {
+                return LatticeCell::Top;
+            }
+            let bytes = match self.cell(func, store_value) {
+                LatticeCell::Const(value) => to_bytes(value, size as usize, ctx.endian()),
+                LatticeCell::Top => return LatticeCell::Top,
+                LatticeCell::Bot => return LatticeCell::Bot,
+            };
+            for pos in start..end {
+                let byte = &mut hashed[(pos - addr) as usize];
+                if byte.is_none() {
+                    *byte = Some(bytes[(pos - store_addr) as usize]);
+                    unknown -= 1;
+                }
+            }
+        }
+
+        let mut cell_state = CellState::new(&self.lattice, &func.dfg);
+        cell_state.memory = Some((addr, hashed.into_iter().map(Option::unwrap).collect()));
+        match hash.interpret(&mut cell_state) {
+            EvalValue::Imm(value) if !cell_state.is_overdefined => LatticeCell::Const(value),
+            _ => LatticeCell::Top,
+        }
+    }
+
     fn eval_branch(&mut self, inst: InstId, bi: &dyn Branch) {
         match bi.branch_kind() {
             BranchKind::Jump(jump) => {
@@ -208,7 +515,7 @@ impl SccpSolver {
                     self.flow_work.push(FlowEdge::new(inst, *br.z_dest()));
                     self.flow_work.push(FlowEdge::new(inst, *br.nz_dest()));
                 } else if v_cell.is_bot() {
-                    unreachable!();
+                    // The condition is not known yet.
                 } else if v_cell.is_zero() {
                     self.flow_work.push(FlowEdge::new(inst, *br.z_dest()));
                 } else {
@@ -235,10 +542,9 @@ impl SccpSolver {
                     return;
                 }
 
-                // Verifier verifies that the use of the argument must dominated by the its
-                // definition, so `v_cell` must not be bot.
+                // The scrutinee is not known yet.
                 if v_cell.is_bot() {
-                    unreachable!()
+                    return;
                 }
 
                 let mut contains_top = false;
@@ -314,6 +620,16 @@ impl SccpSolver {
         }
     }
 
+    fn fold_args(&self, func: &mut Function) {
+        for idx in 0..func.arg_values.len() {
+            let arg = func.arg_values[idx];
+            if let Some(imm) = self.lattice[arg].to_imm() {
+                let new_value = func.dfg.make_imm_value(imm);
+                func.dfg.change_to_alias(arg, new_value);
+            }
+        }
+    }
+
     fn fold(&self, func: &mut Function, inst: InstId) {
         let inst_result = match func.dfg.inst_result(inst) {
             Some(result) => result,
@@ -322,7 +638,11 @@ impl SccpSolver {
 
         match self.lattice[inst_result].to_imm() {
             Some(imm) => {
-                InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                // An instruction with side effects, e.g., a call returning a
+                // constant, must be kept.
+                if !func.dfg.side_effect(inst).has_effect() {
+                    InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                }
                 let new_value = func.dfg.make_imm_value(imm);
                 func.dfg.change_to_alias(inst_result, new_value);
             }
@@ -377,6 +697,33 @@ impl SccpSolver {
     }
 }
 
+/// Converts an address or a length into `u64`. Returns `None` if it's beyond
+/// any memory that can be accessed in practice.
+fn to_offset(imm: Immediate) -> Option<u64> {
+    let value = imm.as_i256().to_u256();
+    (value <= U256::from(u32::MAX)).then(|| value.as_u64())
+}
+
+/// Returns `true` if `loc` may overlap `len` bytes from the constant `addr`.
+fn may_overlap(loc: &MemoryLocation, addr: u64, len: u64) -> bool {
+    if loc.base.is_some() || loc.offset < 0 {
+        return true;
+    }
+
+    let offset = loc.offset as u64;
+    loc.size
+        .is_none_or(|size| offset < addr + len && addr < offset.saturating_add(size))
+}
+
+/// Returns the bytes of `imm` in the order they're stored to the memory.
+fn to_bytes(imm: Immediate, size: usize, endian: Endian) -> Vec<u8> {
+    let value = imm.as_i256().to_u256();
+    match endian {
+        Endian::Be => value.to_big_endian()[32 - size..].to_vec(),
+        Endian::Le => value.to_little_endian()[..size].to_vec(),
+    }
+}
+
 impl Default for SccpSolver {
     fn default() -> Self {
         Self::new()
@@ -396,7 +743,7 @@ impl FlowEdge {
 }
 
 #[derive(Debug, Clone, Copy)]
-enum LatticeCell {
+pub(super) enum LatticeCell {
     Top,
     Const(Immediate),
     Bot,
@@ -413,7 +760,7 @@ impl PartialEq for LatticeCell {
 }
 
 impl LatticeCell {
-    fn to_imm(self) -> Option<Immediate> {
+    pub(super) fn to_imm(self) -> Option<Immediate> {
         match self {
             Self::Top | Self::Bot => None,
             Self::Const(imm) => Some(imm),
@@ -435,7 +782,7 @@ impl LatticeCell {
         matches!(self, Self::Bot)
     }
 
-    fn join(self, rhs: Self) -> Self {
+    pub(super) fn join(self, rhs: Self) -> Self {
         match (self, rhs) {
             (Self::Top, _) | (_, Self::Top) => Self::Top,
             (Self::Const(v1), Self::Const(v2)) => {
@@ -460,6 +807,12 @@ struct CellState<'a, 'i> {
     map: &'i SecondaryMap<ValueId, LatticeCell>,
     used_val: FxHashSet<ValueId>,
     dfg: &'a DataFlowGraph,
+    /// Set when the result depends on a state that can't be known at compile
+    /// time, e.g., memory contents or function addresses.
+    is_overdefined: bool,
+    /// The address and the contents of the memory bytes that are known to be
+    /// constants.
+    memory: Option<(u64, Vec<u8>)>,
 }
 impl<'a, 'i> CellState<'a, 'i> {
     fn new(map: &'i SecondaryMap<ValueId, LatticeCell>, dfg: &'a DataFlowGraph) -> Self {
@@ -467,10 +820,16 @@ impl<'a, 'i> CellState<'a, 'i> {
             map,
             used_val: Default::default(),
             dfg,
+            is_overdefined: false,
+            memory: None,
         }
     }
 
     fn cell(&self) -> LatticeCell {
+        if self.is_overdefined {
+            return LatticeCell::Top;
+        }
+
         for &used in self.used_val.iter() {
             if self.map[used] == LatticeCell::Top {
                 return LatticeCell::Top;
@@ -491,11 +850,7 @@ impl State for CellState<'_, '_> {
         }
     }
 
-    fn call_func(
-        &mut self,
-        _func: sonatina_ir::module::FuncRef,
-        _args: Vec<EvalValue>,
-    ) -> EvalValue {
+    fn call_func(&mut self, _func: FuncRef, _args: Vec<EvalValue>) -> EvalValue {
         panic!("call instuctuion must not be Interpreted")
     }
 
@@ -509,8 +864,22 @@ impl State for CellState<'_, '_> {
         panic!("flow sensitive operation must not be interpreted")
     }
 
-    fn load(&mut self, _addr: EvalValue, _ty: Type) -> EvalValue {
-        panic!("instruction with side effect must not be interpreted")
+    fn load(&mut self, addr: EvalValue, ty: Type) -> EvalValue {
+        // `evm_keccak256` reads memory though it has no side effect.
+        let byte = match (&self.memory, addr.as_imm().and_then(to_offset)) {
+            (Some((start, bytes)), Some(addr)) if ty == Type::I8 && addr >= *start => {
+                bytes.get((addr - start) as usize).copied()
+            }
+            _ => None,
+        };
+
+        match byte {
+            Some(byte) => Immediate::I8(byte as i8).into(),
+            None => {
+                self.is_overdefined = true;
+                EvalValue::Undef
+            }
+        }
     }
 
     fn store(&mut self, _addr: EvalValue, _value: EvalValue, _ty: Type) -> EvalValue {
@@ -521,7 +890,72 @@ impl State for CellState<'_, '_> {
         panic!("instruction with side effect must not be interpreted")
     }
 
+    fn func_ptr(&mut self, _func: FuncRef) -> EvalValue {
+        self.is_overdefined = true;
+        EvalValue::Undef
+    }
+
+    fn resolve_func_ptr(&mut self, _ptr: EvalValue) -> Option<FuncRef> {
+        self.is_overdefined = true;
+        None
+    }
+
     fn dfg(&self) -> &DataFlowGraph {
         self.dfg
     }
+
+    fn malloc(&mut self, _size: EvalValue) -> EvalValue {
+        panic!("instruction with side effect must not be interpreted")
+    }
+
+    fn msize(&mut self) -> EvalValue {
+        panic!("instruction with side effect must not be interpreted")
+    }
+
+    fn env(&mut self, _query: EnvQuery) -> EvalValue {
+        panic!("instruction with side effect must not be interpreted")
+    }
+
+    fn env_data(&mut self, _src: DataSource, _offset: usize, _len: usize) -> Vec<u8> {
+        panic!("instruction with side effect must not be interpreted")
+    }
+
+    fn sload(&mut self, _key: EvalValue) -> EvalValue {
+        panic!("instruction with side effect must not be interpreted")
+    }
+
+    fn sstore(&mut self, _key: EvalValue, _value: EvalValue) {
+        panic!("instruction with side effect must not be interpreted")
+    }
+
+    fn tload(&mut self, _key: EvalValue) -> EvalValue {
+        panic!("instruction with side effect must not be interpreted")
+    }
+
+    fn tstore(&mut self, _key: EvalValue, _value: EvalValue) {
+        panic!("instruction with side effect must not be interpreted")
+    }
+
+    fn emit_log(&mut self, _data: Vec<u8>, _topics: Vec<EvalValue>) {
+        panic!("instruction with side effect must not be interpreted")
+    }
+
+    fn ext_call(&mut self, _call: ExtCall) -> (EvalValue, Vec<u8>) {
+        panic!("instruction with side effect must not be interpreted")
+    }
+
+    fn ext_create(
+        &mut self,
+        _value: EvalValue,
+        _code: Vec<u8>,
+        _salt: Option<EvalValue>,
+    ) -> EvalValue {
+        panic!("instruction with side effect must not be interpreted")
+    }
+
+    fn contract_size(&mut self, _contract: FuncRef) -> EvalValue {
+        // The size is only known after code generation.
+        self.is_overdefined = true;
+        EvalValue::Undef
+    }
 }
@@ -0,0 +1,299 @@
+//! This module contains a solver for CFG simplification.
+//!
+//! The solver repeats the following until the CFG doesn't change:
+//! * A branch whose destinations are all the same or whose condition is a
+//!   constant is turned into a jump.
+//! * Blocks unreachable from the entry block are removed.
+//! * A block is merged into its predecessor if the predecessor only jumps to
+//!   the block and the block has no other predecessors.
+//! * A block that contains only a jump is removed by redirecting its
+//!   predecessors to the destination, if phis in the destination get the same
+//!   value from each predecessor.
+
+use rustc_hash::FxHashSet;
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    inst::control_flow::BranchKind,
+    BlockId, ControlFlowGraph, Function, InstId, ValueId,
+};
+
+#[derive(Debug, Default)]
+pub struct SimplifyCfgSolver {
+    reachable_blocks: FxHashSet<BlockId>,
+}
+
+impl SimplifyCfgSolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.reachable_blocks.clear();
+    }
+
+    pub fn run(&mut self, func: &mut Function, cfg: &mut ControlFlowGraph) {
+        self.clear();
+        cfg.compute(func);
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            let blocks: Vec<_> = func.layout.iter_block().collect();
+            for &block in &blocks {
+                changed |= self.fold_branch(func, cfg, block);
+            }
+
+            changed |= self.remove_unreachable_blocks(func, cfg);
+
+            for &block in &blocks {
+                if !func.layout.is_block_inserted(block) {
+                    continue;
+                }
+                changed |= self.merge_into_pred(func, cfg, block)
+                    || self.thread_empty_block(func, cfg, block);
+            }
+        }
+
+        // Exits are not tracked by the edge updates above.
+        cfg.compute(func);
+    }
+
+    /// Turns the terminator of the block into a jump if the destination is
+    /// determined statically.
+    fn fold_branch(&self, func: &mut Function, cfg: &mut ControlFlowGraph, block: BlockId) -> bool {
+        let Some(term) = func.layout.last_inst_of(block) else {
+            return false;
+        };
+        let Some(bi) = func.dfg.branch_info(term) else {
+            return false;
+        };
+
+        let dests = bi.dests();
+        let taken = match bi.branch_kind() {
+            BranchKind::Jump(_) => return false,
+
+            BranchKind::Br(br) => match func.dfg.value_imm(*br.cond()) {
+                Some(cond) if cond.is_zero() => *br.z_dest(),
+                Some(_) => *br.nz_dest(),
+                None if br.z_dest() == br.nz_dest() => *br.z_dest(),
+                None => return false,
+            },
+
+            BranchKind::BrTable(_) => {
+                // A `br_table` without any destination has nothing to fold.
+                let Some(&first) = dests.first() else {
+                    return false;
+                };
+                if dests.iter().any(|dest| *dest != first) {
+                    return false;
+                }
+                first
+            }
+        };
+
+        for dest in dests {
+            if dest != taken {
+                remove_phi_args(func, dest, block);
+                cfg.remove_edge(block, dest);
+            }
+        }
+
+        let jump = func.dfg.make_jump(taken);
+        func.dfg.replace_inst(term, Box::new(jump));
+        true
+    }
+
+    /// Removes blocks unreachable from the entry block.
+    fn remove_unreachable_blocks(
+        &mut self,
+        func: &mut Function,
+        cfg: &mut ControlFlowGraph,
+    ) -> bool {
+        self.reachable_blocks.clear();
+        self.reachable_blocks.extend(cfg.post_order());
+
+        let unreachable: Vec<_> = func
+            .layout
+            .iter_block()
+            .filter(|block| !self.reachable_blocks.contains(block))
+            .collect();
+
+        for &block in &unreachable {
+            let succs: Vec<_> = cfg.succs_of(block).copied().collect();
+            for succ in succs {
+                if self.reachable_blocks.contains(&succ) {
+                    remove_phi_args(func, succ, block);
+                }
+                cfg.remove_edge(block, succ);
+            }
+
+            InstInserter::at_location(CursorLocation::BlockTop(block)).remove_block(func);
+        }
+
+        !unreachable.is_empty()
+    }
+
+    /// Merges the block into its predecessor if the predecessor jumps only to
+    /// the block and the block has no other predecessors.
+    fn merge_into_pred(
+        &self,
+        func: &mut Function,
+        cfg: &mut ControlFlowGraph,
+        block: BlockId,
+    ) -> bool {
+        if cfg.pred_num_of(block) != 1 || Some(block) == func.layout.entry_block() {
+            return false;
+        }
+        let pred = *cfg.preds_of(block).next().unwrap();
+        if pred == block || cfg.succ_num_of(pred) != 1 {
+            return false;
+        }
+        let Some(pred_term) = func.layout.last_inst_of(pred) else {
+            return false;
+        };
+        if func.dfg.cast_jump(pred_term).is_none() {
+            return false;
+        }
+
+        // Phis in the block have only one argument from the predecessor.
+        let insts: Vec<_> = func.layout.iter_inst(block).collect();
+        for &inst in &insts {
+            let Some(phi) = func.dfg.cast_phi(inst) else {
+                break;
+            };
+            let arg = phi.args()[0].0;
+            let result = func.dfg.inst_result(inst).unwrap();
+            func.dfg.change_to_alias(result, arg);
+            func.dfg.untrack_inst(inst);
+            func.layout.remove_inst(inst);
+        }
+
+        InstInserter::at_location(CursorLocation::At(pred_term)).remove_inst(func);
+        for inst in insts {
+            if func.layout.is_inst_inserted(inst) {
+                func.layout.remove_inst(inst);
+                func.layout.append_inst(inst, pred);
+            }
+        }
+
+        let succs: Vec<_> = cfg.succs_of(block).copied().collect();
+        for succ in succs {
+            rewrite_phi_blocks(func, succ, block, pred);
+            cfg.remove_edge(block, succ);
+            cfg.add_edge(pred, succ);
+        }
+        cfg.remove_edge(pred, block);
+        func.layout.remove_block(block);
+
+        true
+    }
+
+    /// Removes the block if it contains only a jump, by redirecting all its
+    /// predecessors to the destination.
+    fn thread_empty_block(
+        &self,
+        func: &mut Function,
+        cfg: &mut ControlFlowGraph,
+        block: BlockId,
+    ) -> bool {
+        if Some(block) == func.layout.entry_block() || cfg.pred_num_of(block) == 0 {
+            return false;
+        }
+        let Some(term) = func.layout.first_inst_of(block) else {
+            return false;
+        };
+        let Some(jump) = func.dfg.cast_jump(term) else {
+            return false;
+        };
+        let dest = *jump.dest();
+        if dest == block {
+            return false;
+        }
+
+        // Each phi in the destination must get the same value from a
+        // predecessor that already jumps to the destination.
+        let preds: Vec<_> = cfg.preds_of(block).copied().collect();
+        let phis = phis_of(func, dest);
+        let mut values = Vec::with_capacity(phis.len());
+        for &phi in &phis {
+            let Some(value) = phi_arg(func, phi, block) else {
+                return false;
+            };
+            for &pred in &preds {
+                if cfg.succs_of(pred).any(|succ| *succ == dest)
+                    && phi_arg(func, phi, pred) != Some(value)
+                {
+                    return false;
+                }
+            }
+            values.push(value);
+        }
+
+        remove_phi_args(func, dest, block);
+        for (&phi, value) in phis.iter().zip(values) {
+            for &pred in &preds {
+                if phi_arg(func, phi, pred).is_none() {
+                    func.dfg.append_phi_arg(phi, value, pred);
+                }
+            }
+        }
+
+        for pred in preds {
+            let pred_term = func.layout.last_inst_of(pred).unwrap();
+            func.dfg.rewrite_branch_dest(pred_term, block, dest);
+            cfg.remove_edge(pred, block);
+            cfg.add_edge(pred, dest);
+        }
+        cfg.remove_edge(block, dest);
+        InstInserter::at_location(CursorLocation::BlockTop(block)).remove_block(func);
+
+        true
+    }
+}
+
+pub(super) fn phis_of(func: &Function, block: BlockId) -> Vec<InstId> {
+    func.layout
+        .iter_inst(block)
+        .take_while(|inst| func.dfg.is_phi(*inst))
+        .collect()
+}
+
+pub(super) fn phi_arg(func: &Function, phi: InstId, block: BlockId) -> Option<ValueId> {
+    func.dfg
+        .cast_phi(phi)
+        .unwrap()
+        .args()
+        .iter()
+        .find_map(|(value, from)| (*from == block).then_some(*value))
+}
+
+/// Removes the arguments of phis in `block` that flow from `from`.
+pub(super) fn remove_phi_args(func: &mut Function, block: BlockId, from: BlockId) {
+    for phi in phis_of(func, block) {
+        if let Some(value) = func.dfg.cast_phi_mut(phi).unwrap().remove_phi_arg(from) {
+            if !func
+                .dfg
+                .cast_phi(phi)
+                .unwrap()
+                .args()
+                .iter()
+                .any(|(v, _)| *v == value)
+            {
+                func.dfg.remove_user(value, phi);
+            }
+        }
+    }
+}
+
+/// Rewrites the arguments of phis in `block` that flow from `from` to flow
+/// from `to`.
+fn rewrite_phi_blocks(func: &mut Function, block: BlockId, from: BlockId, to: BlockId) {
+    for phi in phis_of(func, block) {
+        for (_, arg_block) in func.dfg.cast_phi_mut(phi).unwrap().args_mut() {
+            if *arg_block == from {
+                *arg_block = to;
+            }
+        }
+    }
+}
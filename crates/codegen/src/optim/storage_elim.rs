@@ -0,0 +1,184 @@
+//! This module contains a solver for redundant storage access elimination.
+//!
+//! The solver works on `evm_sload`/`evm_sstore` and their transient
+//! counterparts `evm_tload`/`evm_tstore`, and performs the following:
+//! * A load is replaced with the value stored to or loaded from the same slot
+//!   on all paths reaching the load.
+//! * A store of the value that the slot is known to hold is removed.
+//! * A store is removed if the slot is overwritten on all paths before it's
+//!   read.
+//!
+//! Two slots are known to be the same if their keys are the same value, and
+//! known to be different if they are different constants, or the same value
+//! plus different constants. Calls, including `evm_call` and
+//! `evm_delegate_call`, may re-enter the contract and access any slot, so they
+//! are regarded as reading and writing all slots. `evm_static_call` can't
+//! write to storage even if it re-enters, so it's regarded as only reading
+//! all slots.
+//!
+//! Loops are handled conservatively: nothing is assumed about slots at the
+//! header of a loop, or at the end of a block that has a back edge.
+
+use cranelift_entity::SecondaryMap;
+use sonatina_ir::{
+    func_cursor::{CursorLocation, FuncCursor, InstInserter},
+    BlockId, ControlFlowGraph, Function, ValueId,
+};
+
+use crate::alias_analysis::{Alias, Slot, StorageAccess};
+
+#[derive(Debug, Default)]
+pub struct StorageElimSolver {
+    /// Slots with known values at the end of each block, which is `None` if
+    /// the block is not visited yet.
+    known: SecondaryMap<BlockId, Option<Vec<(Slot, ValueId)>>>,
+
+    /// Slots that are overwritten before read at the start of each block,
+    /// which is `None` if the block is not visited yet.
+    overwritten: SecondaryMap<BlockId, Option<Vec<Slot>>>,
+}
+
+impl StorageElimSolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.known.clear();
+        self.overwritten.clear();
+    }
+
+    pub fn run(&mut self, func: &mut Function, cfg: &ControlFlowGraph) {
+        self.clear();
+
+        let mut rpo: Vec<_> = cfg.post_order().collect();
+        rpo.reverse();
+
+        for &block in &rpo {
+            self.forward_block(func, cfg, block);
+        }
+        for &block in rpo.iter().rev() {
+            self.eliminate_dead_stores(func, cfg, block);
+        }
+    }
+
+    /// Forwards known values of slots to loads in the block, and removes
+    /// stores that don't change the slots.
+    fn forward_block(&mut self, func: &mut Function, cfg: &ControlFlowGraph, block: BlockId) {
+        let mut known = self.meet_preds(cfg, block);
+
+        let insts: Vec<_> = func.layout.iter_inst(block).collect();
+        for inst in insts {
+            match StorageAccess::of(&func.dfg, inst) {
+                StorageAccess::Load(slot) => {
+                    let result = func.dfg.inst_result(inst).unwrap();
+                    let ty = func.dfg.value_ty(result);
+                    let value = known.iter().find_map(|(known_slot, value)| {
+                        (slot.alias(known_slot) == Alias::Must && func.dfg.value_ty(*value) == ty)
+                            .then_some(*value)
+                    });
+
+                    if let Some(value) = value {
+                        func.dfg.change_to_alias(result, value);
+                        InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                    } else {
+                        known.push((slot, result));
+                    }
+                }
+
+                StorageAccess::Store(slot, value) => {
+                    if known.contains(&(slot, value)) {
+                        InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                    } else {
+                        known.retain(|(known_slot, _)| slot.alias(known_slot) == Alias::No);
+                        known.push((slot, value));
+                    }
+                }
+
+                StorageAccess::ReadAll => {}
+
+                StorageAccess::ReadWriteAll => known.clear(),
+
+                StorageAccess::None => {}
+            }
+        }
+
+        self.known[block] = Some(known);
+    }
+
+    /// Removes stores in the block that are overwritten before read.
+    fn eliminate_dead_stores(
+        &mut self,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        block: BlockId,
+    ) {
+        let mut overwritten = self.meet_succs(cfg, block);
+
+        let insts: Vec<_> = func.layout.iter_inst(block).collect();
+        for inst in insts.into_iter().rev() {
+            match StorageAccess::of(&func.dfg, inst) {
+                StorageAccess::Load(slot) => {
+                    overwritten
+                        .retain(|overwritten_slot| slot.alias(overwritten_slot) == Alias::No);
+                }
+
+                StorageAccess::Store(slot, _) => {
+                    if overwritten
+                        .iter()
+                        .any(|overwritten_slot| slot.alias(overwritten_slot) == Alias::Must)
+                    {
+                        InstInserter::at_location(CursorLocation::At(inst)).remove_inst(func);
+                    } else {
+                        overwritten.push(slot);
+                    }
+                }
+
+                StorageAccess::ReadAll | StorageAccess::ReadWriteAll => overwritten.clear(),
+
+                StorageAccess::None => {}
+            }
+        }
+
+        self.overwritten[block] = Some(overwritten);
+    }
+
+    /// Returns the slots with the same known values at the end of all
+    /// predecessors.
+    fn meet_preds(&self, cfg: &ControlFlowGraph, block: BlockId) -> Vec<(Slot, ValueId)> {
+        let mut preds = cfg.preds_of(block);
+        let Some(first) = preds.next() else {
+            return Vec::new();
+        };
+
+        let Some(mut known) = self.known[*first].clone() else {
+            return Vec::new();
+        };
+        for pred in preds {
+            let Some(pred_known) = &self.known[*pred] else {
+                return Vec::new();
+            };
+            known.retain(|entry| pred_known.contains(entry));
+        }
+        known
+    }
+
+    /// Returns the slots that are overwritten before read in all successors.
+    fn meet_succs(&self, cfg: &ControlFlowGraph, block: BlockId) -> Vec<Slot> {
+        let mut succs = cfg.succs_of(block);
+        let Some(first) = succs.next() else {
+            return Vec::new();
+        };
+
+        let Some(mut overwritten) = self.overwritten[*first].clone() else {
+            return Vec::new();
+        };
+        for succ in succs {
+            let Some(succ_overwritten) = &self.overwritten[*succ] else {
+                return Vec::new();
+            };
+            overwritten.retain(|slot| succ_overwritten.contains(slot));
+        }
+        overwritten
+    }
+}
@@ -1,5 +1,11 @@
 //! This module contains implementation of `Post Dominator Tree`.
+//!
+//! Blocks in an infinite loop never reach a real exit. To make every block
+//! post dominated by the dummy exit block, a block in each such loop is
+//! regarded as a virtual exit that has an edge to the dummy exit block.
 
+use cranelift_entity::SecondaryMap;
+use rustc_hash::FxHashSet;
 use sonatina_ir::{BlockId, ControlFlowGraph, Function};
 
 use super::domtree::{DFSet, DomTree};
@@ -17,6 +23,12 @@ pub struct PostDomTree {
 
     /// Dominator tree of reverse control flow graph.
     domtree: DomTree,
+
+    /// `true` if the block reaches a real exit block.
+    reaches_exit: SecondaryMap<BlockId, bool>,
+
+    /// Blocks that have a virtual edge to the dummy exit block.
+    virtual_exits: Vec<BlockId>,
 }
 
 impl Default for PostDomTree {
@@ -26,6 +38,8 @@ impl Default for PostDomTree {
             exit: BlockId(0),
             rcfg: ControlFlowGraph::default(),
             domtree: DomTree::default(),
+            reaches_exit: SecondaryMap::default(),
+            virtual_exits: Vec::default(),
         }
     }
 }
@@ -56,6 +70,7 @@ impl PostDomTree {
         for exit in &real_exits {
             self.rcfg.add_edge(*exit, self.exit);
         }
+        self.add_virtual_exits(func);
 
         self.rcfg.reverse_edges(self.exit, &[self.entry]);
         self.domtree.compute(&self.rcfg);
@@ -72,6 +87,8 @@ impl PostDomTree {
     pub fn clear(&mut self) {
         self.rcfg.clear();
         self.domtree.clear();
+        self.reaches_exit.clear();
+        self.virtual_exits.clear();
     }
 
     /// Compute post dominance frontiers of each blocks.
@@ -89,6 +106,65 @@ impl PostDomTree {
     pub fn is_reachable(&self, block: BlockId) -> bool {
         self.domtree.is_reachable(block)
     }
+
+    /// Returns `true` if the block reaches a real exit block, i.e., the block
+    /// isn't in an infinite loop and doesn't lead only to infinite loops.
+    pub fn reaches_exit(&self, block: BlockId) -> bool {
+        self.reaches_exit[block]
+    }
+
+    /// Returns blocks that are regarded as virtual exits of infinite loops.
+    pub fn virtual_exits(&self) -> &[BlockId] {
+        &self.virtual_exits
+    }
+
+    /// Adds virtual edges from infinite loops to the dummy exit block.
+    /// This must be called before the edges of `rcfg` are reversed.
+    fn add_virtual_exits(&mut self, func: &Function) {
+        mark_reaching_blocks(&self.rcfg, &mut self.reaches_exit, self.exit);
+        let mut reaches_dummy_exit = self.reaches_exit.clone();
+
+        for block in func.layout.iter_block() {
+            if reaches_dummy_exit[block] {
+                continue;
+            }
+
+            // Walk along unvisited successors until it gets stuck. All the
+            // successors of the last block are on the walked path, so the
+            // block is in a loop, or is the end of a path without any exit.
+            let mut visited = FxHashSet::default();
+            visited.insert(block);
+            let mut virtual_exit = block;
+            while let Some(&succ) = self
+                .rcfg
+                .succs_of(virtual_exit)
+                .find(|succ| visited.insert(**succ))
+            {
+                virtual_exit = succ;
+            }
+
+            self.rcfg.add_edge(virtual_exit, self.exit);
+            self.virtual_exits.push(virtual_exit);
+            mark_reaching_blocks(&self.rcfg, &mut reaches_dummy_exit, self.exit);
+        }
+    }
+}
+
+/// Marks blocks from which `to` is reachable.
+fn mark_reaching_blocks(
+    cfg: &ControlFlowGraph,
+    marks: &mut SecondaryMap<BlockId, bool>,
+    to: BlockId,
+) {
+    let mut worklist = vec![to];
+    while let Some(block) = worklist.pop() {
+        for &pred in cfg.preds_of(block) {
+            if !marks[pred] {
+                marks[pred] = true;
+                worklist.push(pred);
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -244,8 +320,10 @@ mod tests {
         let func_ref = module.funcs()[0];
         let (post_dom_tree, pdf) = module.func_store.view(func_ref, calc_dom);
 
-        assert!(!post_dom_tree.is_reachable(a));
-        assert!(test_pdf(&pdf, a, &[]));
+        assert!(post_dom_tree.is_reachable(a));
+        assert!(!post_dom_tree.reaches_exit(a));
+        assert_eq!(post_dom_tree.virtual_exits(), &[a]);
+        assert!(test_pdf(&pdf, a, &[a]));
     }
 
     #[test]
@@ -0,0 +1,1179 @@
+//! This module contains an analysis of the bits and the ranges of integer
+//! values.
+//!
+//! For each integer value, the analysis computes the bits that are known to be
+//! zero or one, and the unsigned and signed ranges that contain the value. The
+//! three facts refine each other, e.g., an unsigned range `[0, 255]` of an
+//! `i256` means that all bits but the lowest eight are zero, and vice versa.
+//!
+//! The facts are propagated over the SSA graph by [`SparseSolver`], which skips
+//! the destinations of branches that are never taken under the facts. Ranges
+//! of values defined in loops can grow for many iterations, so a fact that
+//! keeps changing is widened to the ranges implied by its known bits, which
+//! converge quickly.
+//!
+//! Division and remainder by zero are assumed not to happen, except for EVM
+//! instructions, which yield zero.
+
+use std::cmp;
+
+use sonatina_ir::{
+    inst::{arith, cast, cmp as icmp, control_flow::BranchKind, evm, logic},
+    prelude::*,
+    BlockId, Function, Immediate, InstId, Type, Value, ValueId, I256, U256,
+};
+use sonatina_macros::inst_prop;
+
+use crate::dataflow::{Lattice, SparseAnalysis, SparseSolver};
+
+/// A fact is widened once it has changed this many times.
+const WIDEN_LIMIT: u32 = 4;
+
+#[derive(Default)]
+pub struct RangeAnalysis {
+    solver: SparseSolver<Ranges>,
+}
+
+impl RangeAnalysis {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn compute(&mut self, func: &Function) {
+        self.solver.solve(&Ranges, func);
+    }
+
+    pub fn clear(&mut self) {
+        self.solver.clear();
+    }
+
+    /// Returns the fact of an integer value, or `None` if the value isn't an
+    /// integer or is defined in an unreachable block.
+    pub fn fact(&self, func: &Function, value: ValueId) -> Option<IntFact> {
+        self.solver.fact(&Ranges, func, value)
+    }
+
+    /// Returns `true` if `block` is reachable from the entry through the edges
+    /// that can be taken under the facts.
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.solver.is_reachable(block)
+    }
+}
+
+/// The analysis solved by [`SparseSolver`], whose least element is `None`.
+struct Ranges;
+
+impl SparseAnalysis for Ranges {
+    type Fact = Option<IntFact>;
+
+    fn value_fact(&self, func: &Function, value: ValueId) -> Option<IntFact> {
+        let ty = func.dfg.value_ty(value);
+        if !ty.is_integral() {
+            return None;
+        }
+
+        match func.dfg.value(value) {
+            Value::Immediate { imm, .. } => Some(IntFact::constant(*imm)),
+            _ => Some(IntFact::full(ty)),
+        }
+    }
+
+    fn transfer(
+        &self,
+        func: &Function,
+        inst: InstId,
+        fact_of: &dyn Fn(ValueId) -> Option<IntFact>,
+    ) -> Option<IntFact> {
+        let dfg = &func.dfg;
+        let ty = dfg.value_ty(dfg.inst_result(inst)?);
+        if !ty.is_integral() {
+            return None;
+        }
+
+        let mut has_untracked_arg = false;
+        dfg.inst(inst).for_each_value(&mut |value| {
+            has_untracked_arg |= !dfg.value_ty(value).is_integral();
+        });
+
+        match <&dyn Transfer as InstDowncast>::downcast(func.inst_set(), dfg.inst(inst)) {
+            Some(transfer) if !has_untracked_arg => transfer.transfer(fact_of, ty),
+            _ => Some(IntFact::full(ty)),
+        }
+    }
+
+    fn is_feasible_edge(
+        &self,
+        _func: &Function,
+        branch: BranchKind,
+        dest: BlockId,
+        fact_of: &dyn Fn(ValueId) -> Option<IntFact>,
+    ) -> bool {
+        match branch {
+            BranchKind::Jump(_) => true,
+
+            BranchKind::Br(br) => {
+                let Some(cond) = fact_of(*br.cond()) else {
+                    return false;
+                };
+                let is_zero = cond.as_constant().map(Immediate::is_zero);
+                (dest == *br.nz_dest() && is_zero != Some(true))
+                    || (dest == *br.z_dest() && is_zero != Some(false))
+            }
+
+            BranchKind::BrTable(brt) => {
+                let Some(scrutinee) = fact_of(*brt.scrutinee()) else {
+                    return false;
+                };
+                *brt.default() == Some(dest)
+                    || brt.table().iter().any(|(value, block)| {
+                        *block == dest
+                            && fact_of(*value)
+                                .is_some_and(|value| scrutinee.is_equal(&value) != Some(false))
+                    })
+            }
+        }
+    }
+
+    fn widen(&self, old: &Option<IntFact>, new: Option<IntFact>, updates: u32) -> Option<IntFact> {
+        match (old, new) {
+            (Some(old), Some(new)) if updates >= WIDEN_LIMIT => Some(old.join(&new).widen()),
+            _ => new,
+        }
+    }
+}
+
+impl Lattice for Option<IntFact> {
+    fn join(&mut self, other: &Self) -> bool {
+        let Some(other) = other else {
+            return false;
+        };
+
+        let joined = match self {
+            Some(fact) => fact.join(other),
+            None => *other,
+        };
+        let changed = *self != Some(joined);
+        *self = Some(joined);
+        changed
+    }
+}
+
+/// Facts about the bits and the ranges of an integer value.
+///
+/// The bits and the bounds are stored as unsigned integers of the width of
+/// `ty`, and the bits beyond the width are zero in all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntFact {
+    pub ty: Type,
+    pub bits: KnownBits,
+    /// The range of the value interpreted as an unsigned integer.
+    pub unsigned: Range<U256>,
+    /// The range of the value interpreted as a signed integer.
+    pub signed: Range<I256>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KnownBits {
+    /// Bits that are known to be zero.
+    pub zero: U256,
+    /// Bits that are known to be one.
+    pub one: U256,
+}
+
+/// An inclusive range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range<T> {
+    pub min: T,
+    pub max: T,
+}
+
+impl IntFact {
+    /// Returns the fact that holds for any value of `ty`.
+    pub fn full(ty: Type) -> Self {
+        let width = width(ty);
+        Self {
+            ty,
+            bits: KnownBits::default(),
+            unsigned: full_unsigned(width),
+            signed: full_signed(width),
+        }
+    }
+
+    pub fn constant(imm: Immediate) -> Self {
+        let width = width(imm.ty());
+        let val = to_unsigned(imm.as_i256(), width);
+        let signed = to_signed(val, width);
+        Self {
+            ty: imm.ty(),
+            bits: KnownBits {
+                zero: !val & mask(width),
+                one: val,
+            },
+            unsigned: Range::new(val, val),
+            signed: Range::new(signed, signed),
+        }
+    }
+
+    pub fn as_constant(&self) -> Option<Immediate> {
+        let Range { min, max } = self.unsigned;
+        (min == max).then(|| Immediate::from_i256(to_signed(min, width(self.ty)), self.ty))
+    }
+
+    /// Returns `true` if the value is in the unsigned range of `ty`, i.e.,
+    /// zero extension of the value truncated to `ty` gives the value back.
+    pub fn fits_unsigned(&self, ty: Type) -> bool {
+        self.unsigned.max <= mask(width(ty))
+    }
+
+    /// Returns `true` if the value is in the signed range of `ty`, i.e.,
+    /// sign extension of the value truncated to `ty` gives the value back.
+    pub fn fits_signed(&self, ty: Type) -> bool {
+        let width = width(ty);
+        full_signed(width).contains(self.signed.min) && full_signed(width).contains(self.signed.max)
+    }
+
+    /// Returns `true` if the bits that are cleared in `mask` are known to be
+    /// zero, i.e., `and` of the value and `mask` is the value itself.
+    pub fn is_within_mask(&self, mask: Immediate) -> bool {
+        let width = width(self.ty);
+        let mask = to_unsigned(mask.as_i256(), width);
+        (self.bits.zero | mask) == self::mask(width)
+    }
+
+    /// Makes a fact from facts that hold independently, refining each of them
+    /// with the others.
+    fn new(ty: Type, bits: KnownBits, unsigned: Range<U256>, signed: Range<I256>) -> Self {
+        let width = width(ty);
+        let mut unsigned = unsigned.intersect(bits.unsigned(width));
+        let mut signed = signed.intersect(bits.signed(width));
+        if let Some(range) = unsigned_of_signed(&signed, width) {
+            unsigned = unsigned.intersect(range);
+        }
+        if let Some(range) = signed_of_unsigned(&unsigned, width) {
+            signed = signed.intersect(range);
+        }
+
+        // The bits that the bounds of the unsigned range have in common are
+        // known.
+        let prefix = mask(width) & !mask((unsigned.min ^ unsigned.max).bits());
+        let zero = bits.zero | (prefix & !unsigned.min);
+        let one = bits.one | (prefix & unsigned.min);
+        // The facts contradict each other only in unreachable code.
+        let bits = if (zero & one).is_zero() {
+            KnownBits { zero, one }
+        } else {
+            bits
+        };
+
+        Self {
+            ty,
+            bits,
+            unsigned,
+            signed,
+        }
+    }
+
+    fn from_bits(ty: Type, bits: KnownBits) -> Self {
+        let width = width(ty);
+        Self::new(ty, bits, full_unsigned(width), full_signed(width))
+    }
+
+    fn from_unsigned(ty: Type, min: U256, max: U256) -> Self {
+        Self::new(
+            ty,
+            KnownBits::default(),
+            Range::new(min, max),
+            full_signed(width(ty)),
+        )
+    }
+
+    fn from_bool(val: Option<bool>) -> Self {
+        match val {
+            Some(val) => Self::constant(val.into()),
+            None => Self::full(Type::I1),
+        }
+    }
+
+    fn join(&self, rhs: &Self) -> Self {
+        Self::new(
+            self.ty,
+            KnownBits {
+                zero: self.bits.zero & rhs.bits.zero,
+                one: self.bits.one & rhs.bits.one,
+            },
+            Range::new(
+                cmp::min(self.unsigned.min, rhs.unsigned.min),
+                cmp::max(self.unsigned.max, rhs.unsigned.max),
+            ),
+            Range::new(
+                cmp::min(self.signed.min, rhs.signed.min),
+                cmp::max(self.signed.max, rhs.signed.max),
+            ),
+        )
+    }
+
+    /// Drops the ranges that aren't implied by the known bits.
+    fn widen(&self) -> Self {
+        Self::from_bits(self.ty, self.bits)
+    }
+
+    fn width(&self) -> usize {
+        width(self.ty)
+    }
+
+    fn add(&self, rhs: &Self) -> Self {
+        let width = self.width();
+        let bits = KnownBits::add_carry(&self.bits, &rhs.bits, false, width);
+
+        let (max, overflow) = self.unsigned.max.overflowing_add(rhs.unsigned.max);
+        let unsigned = if overflow || max > mask(width) {
+            full_unsigned(width)
+        } else {
+            Range::new(self.unsigned.min + rhs.unsigned.min, max)
+        };
+
+        let signed = match (
+            signed_add(self.signed.min, rhs.signed.min, width),
+            signed_add(self.signed.max, rhs.signed.max, width),
+        ) {
+            (Some(min), Some(max)) => Range::new(min, max),
+            _ => full_signed(width),
+        };
+
+        Self::new(self.ty, bits, unsigned, signed)
+    }
+
+    fn sub(&self, rhs: &Self) -> Self {
+        let width = self.width();
+        let bits = KnownBits::add_carry(&self.bits, &rhs.bits.not(), true, width);
+
+        let unsigned = if self.unsigned.min >= rhs.unsigned.max {
+            Range::new(
+                self.unsigned.min - rhs.unsigned.max,
+                self.unsigned.max - rhs.unsigned.min,
+            )
+        } else {
+            full_unsigned(width)
+        };
+
+        let signed = match (
+            signed_sub(self.signed.min, rhs.signed.max, width),
+            signed_sub(self.signed.max, rhs.signed.min, width),
+        ) {
+            (Some(min), Some(max)) => Range::new(min, max),
+            _ => full_signed(width),
+        };
+
+        Self::new(self.ty, bits, unsigned, signed)
+    }
+
+    fn neg(&self) -> Self {
+        Self::constant(Immediate::zero(self.ty)).sub(self)
+    }
+
+    fn mul(&self, rhs: &Self) -> Self {
+        let width = self.width();
+
+        // The product has at least as many trailing zeros as the operands
+        // have in total.
+        let trailing_zeros = self.bits.trailing_zeros() + rhs.bits.trailing_zeros();
+        let bits = KnownBits {
+            zero: mask(cmp::min(trailing_zeros, width)),
+            one: U256::zero(),
+        };
+
+        let unsigned = match self.unsigned.max.checked_mul(rhs.unsigned.max) {
+            Some(max) if max <= mask(width) => {
+                Range::new(self.unsigned.min * rhs.unsigned.min, max)
+            }
+            _ => full_unsigned(width),
+        };
+
+        Self::new(self.ty, bits, unsigned, full_signed(width))
+    }
+
+    fn udiv(&self, rhs: &Self) -> Self {
+        if rhs.unsigned.min.is_zero() {
+            Self::from_unsigned(self.ty, U256::zero(), self.unsigned.max)
+        } else {
+            Self::from_unsigned(
+                self.ty,
+                self.unsigned.min / rhs.unsigned.max,
+                self.unsigned.max / rhs.unsigned.min,
+            )
+        }
+    }
+
+    fn umod(&self, rhs: &Self) -> Self {
+        if self.unsigned.max < rhs.unsigned.min {
+            *self
+        } else if rhs.unsigned.max.is_zero() {
+            Self::constant(Immediate::zero(self.ty))
+        } else {
+            let max = cmp::min(self.unsigned.max, rhs.unsigned.max - 1);
+            Self::from_unsigned(self.ty, U256::zero(), max)
+        }
+    }
+
+    /// Returns the fact of the result of `evm_add_mod` and `evm_mul_mod` whose
+    /// modulus is `self`.
+    fn evm_mod(&self) -> Self {
+        if self.unsigned.max.is_zero() {
+            *self
+        } else {
+            Self::from_unsigned(self.ty, U256::zero(), self.unsigned.max - 1)
+        }
+    }
+
+    fn evm_byte(&self, _value: &Self) -> Self {
+        Self::from_unsigned(self.ty, U256::zero(), U256::from(u8::MAX))
+    }
+
+    fn shl(&self, bits: &Self) -> Self {
+        let width = self.width();
+        let Some(amount) = shift_amount(bits) else {
+            return Self::full(self.ty);
+        };
+        if amount >= width {
+            return Self::constant(Immediate::zero(self.ty));
+        }
+
+        let known = KnownBits {
+            zero: ((self.bits.zero << amount) | mask(amount)) & mask(width),
+            one: (self.bits.one << amount) & mask(width),
+        };
+        let unsigned = if self.unsigned.max <= mask(width) >> amount {
+            Range::new(self.unsigned.min << amount, self.unsigned.max << amount)
+        } else {
+            full_unsigned(width)
+        };
+
+        Self::new(self.ty, known, unsigned, full_signed(width))
+    }
+
+    fn shr(&self, bits: &Self) -> Self {
+        let width = self.width();
+        let Some(amount) = shift_amount(bits) else {
+            return Self::from_unsigned(self.ty, U256::zero(), self.unsigned.max);
+        };
+        if amount >= width {
+            return Self::constant(Immediate::zero(self.ty));
+        }
+
+        let known = KnownBits {
+            zero: (self.bits.zero >> amount) | (mask(width) & !(mask(width) >> amount)),
+            one: self.bits.one >> amount,
+        };
+        let unsigned = Range::new(self.unsigned.min >> amount, self.unsigned.max >> amount);
+
+        Self::new(self.ty, known, unsigned, full_signed(width))
+    }
+
+    fn not(&self) -> Self {
+        let width = self.width();
+        let unsigned = Range::new(
+            mask(width) - self.unsigned.max,
+            mask(width) - self.unsigned.min,
+        );
+        Self::new(self.ty, self.bits.not(), unsigned, full_signed(width))
+    }
+
+    fn and(&self, rhs: &Self) -> Self {
+        let bits = KnownBits {
+            zero: self.bits.zero | rhs.bits.zero,
+            one: self.bits.one & rhs.bits.one,
+        };
+        let max = cmp::min(self.unsigned.max, rhs.unsigned.max);
+        let width = self.width();
+        Self::new(
+            self.ty,
+            bits,
+            Range::new(U256::zero(), max),
+            full_signed(width),
+        )
+    }
+
+    fn or(&self, rhs: &Self) -> Self {
+        let bits = KnownBits {
+            zero: self.bits.zero & rhs.bits.zero,
+            one: self.bits.one | rhs.bits.one,
+        };
+        let min = cmp::max(self.unsigned.min, rhs.unsigned.min);
+        let width = self.width();
+        Self::new(
+            self.ty,
+            bits,
+            Range::new(min, mask(width)),
+            full_signed(width),
+        )
+    }
+
+    fn xor(&self, rhs: &Self) -> Self {
+        let bits = KnownBits {
+            zero: (self.bits.zero & rhs.bits.zero) | (self.bits.one & rhs.bits.one),
+            one: (self.bits.zero & rhs.bits.one) | (self.bits.one & rhs.bits.zero),
+        };
+        Self::from_bits(self.ty, bits)
+    }
+
+    fn zext(&self, ty: Type) -> Self {
+        let bits = KnownBits {
+            zero: self.bits.zero | (mask(width(ty)) & !mask(self.width())),
+            one: self.bits.one,
+        };
+        Self::new(ty, bits, self.unsigned, full_signed(width(ty)))
+    }
+
+    fn sext(&self, ty: Type) -> Self {
+        let sign = sign_bit(self.width());
+        let ext = mask(width(ty)) & !mask(self.width());
+        let mut bits = self.bits;
+        if !(bits.zero & sign).is_zero() {
+            bits.zero |= ext;
+        } else if !(bits.one & sign).is_zero() {
+            bits.one |= ext;
+        }
+        Self::new(ty, bits, full_unsigned(width(ty)), self.signed)
+    }
+
+    fn trunc(&self, ty: Type) -> Self {
+        let width = width(ty);
+        let bits = KnownBits {
+            zero: self.bits.zero & mask(width),
+            one: self.bits.one & mask(width),
+        };
+        let unsigned = if self.fits_unsigned(ty) {
+            self.unsigned
+        } else {
+            full_unsigned(width)
+        };
+        let signed = if self.fits_signed(ty) {
+            self.signed
+        } else {
+            full_signed(width)
+        };
+        Self::new(ty, bits, unsigned, signed)
+    }
+
+    fn lt(&self, rhs: &Self) -> Self {
+        Self::from_bool(self.ult(rhs))
+    }
+
+    fn gt(&self, rhs: &Self) -> Self {
+        Self::from_bool(rhs.ult(self))
+    }
+
+    fn le(&self, rhs: &Self) -> Self {
+        Self::from_bool(rhs.ult(self).map(|val| !val))
+    }
+
+    fn ge(&self, rhs: &Self) -> Self {
+        Self::from_bool(self.ult(rhs).map(|val| !val))
+    }
+
+    fn slt(&self, rhs: &Self) -> Self {
+        Self::from_bool(self.signed_lt(rhs))
+    }
+
+    fn sgt(&self, rhs: &Self) -> Self {
+        Self::from_bool(rhs.signed_lt(self))
+    }
+
+    fn sle(&self, rhs: &Self) -> Self {
+        Self::from_bool(rhs.signed_lt(self).map(|val| !val))
+    }
+
+    fn sge(&self, rhs: &Self) -> Self {
+        Self::from_bool(self.signed_lt(rhs).map(|val| !val))
+    }
+
+    fn cmp_eq(&self, rhs: &Self) -> Self {
+        Self::from_bool(self.is_equal(rhs))
+    }
+
+    fn cmp_ne(&self, rhs: &Self) -> Self {
+        Self::from_bool(self.is_equal(rhs).map(|val| !val))
+    }
+
+    fn is_zero(&self) -> Self {
+        self.cmp_eq(&Self::constant(Immediate::zero(self.ty)))
+    }
+
+    fn ult(&self, rhs: &Self) -> Option<bool> {
+        if self.unsigned.max < rhs.unsigned.min {
+            Some(true)
+        } else if self.unsigned.min >= rhs.unsigned.max {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    fn signed_lt(&self, rhs: &Self) -> Option<bool> {
+        if self.signed.max < rhs.signed.min {
+            Some(true)
+        } else if self.signed.min >= rhs.signed.max {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `Some(true)` if the values are known to be equal, and
+    /// `Some(false)` if they are known to be different.
+    pub fn is_equal(&self, rhs: &Self) -> Option<bool> {
+        let conflicting_bits = (self.bits.zero & rhs.bits.one) | (self.bits.one & rhs.bits.zero);
+        if !conflicting_bits.is_zero()
+            || self.unsigned.max < rhs.unsigned.min
+            || rhs.unsigned.max < self.unsigned.min
+        {
+            Some(false)
+        } else if self.as_constant().is_some() && self.unsigned == rhs.unsigned {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+impl KnownBits {
+    fn not(&self) -> Self {
+        Self {
+            zero: self.one,
+            one: self.zero,
+        }
+    }
+
+    fn trailing_zeros(&self) -> usize {
+        (!self.zero).trailing_zeros() as usize
+    }
+
+    fn unsigned(&self, width: usize) -> Range<U256> {
+        Range::new(self.one, !self.zero & mask(width))
+    }
+
+    fn signed(&self, width: usize) -> Range<I256> {
+        let sign = sign_bit(width);
+        let min = self.one;
+        let max = !self.zero & mask(width);
+        if (self.zero | self.one) & sign == sign {
+            // Both bounds have the same sign, so their order is kept.
+            Range::new(to_signed(min, width), to_signed(max, width))
+        } else {
+            Range::new(to_signed(min | sign, width), to_signed(max & !sign, width))
+        }
+    }
+
+    /// Returns the known bits of `lhs + rhs + carry`.
+    ///
+    /// The algorithm is the one of `KnownBits::computeForAddCarry` in LLVM.
+    fn add_carry(lhs: &Self, rhs: &Self, carry: bool, width: usize) -> Self {
+        let mask = mask(width);
+        let carry = if carry { U256::one() } else { U256::zero() };
+
+        // The sums where all unknown bits are one and zero, respectively.
+        let sum_of_max = (!lhs.zero & mask)
+            .overflowing_add(!rhs.zero & mask)
+            .0
+            .overflowing_add(carry)
+            .0
+            & mask;
+        let sum_of_min = lhs.one.overflowing_add(rhs.one).0.overflowing_add(carry).0 & mask;
+
+        // A carry into a bit is known if it's the same in both sums.
+        let carry_zero = !(sum_of_max ^ lhs.zero ^ rhs.zero);
+        let carry_one = sum_of_min ^ lhs.one ^ rhs.one;
+        let known = (lhs.zero | lhs.one) & (rhs.zero | rhs.one) & (carry_zero | carry_one) & mask;
+
+        Self {
+            zero: !sum_of_max & known,
+            one: sum_of_min & known,
+        }
+    }
+}
+
+impl<T: Ord + Copy> Range<T> {
+    pub fn new(min: T, max: T) -> Self {
+        debug_assert!(min <= max);
+        Self { min, max }
+    }
+
+    pub fn contains(&self, val: T) -> bool {
+        self.min <= val && val <= self.max
+    }
+
+    /// Returns the intersection of the ranges. The ranges are disjoint only in
+    /// unreachable code, where `self` is returned.
+    fn intersect(&self, rhs: Self) -> Self {
+        let min = cmp::max(self.min, rhs.min);
+        let max = cmp::min(self.max, rhs.max);
+        if min <= max {
+            Self { min, max }
+        } else {
+            *self
+        }
+    }
+}
+
+/// Transfer functions of instructions, which compute the fact of the result
+/// from the facts of the operands.
+#[inst_prop]
+trait Transfer {
+    /// Returns `None` if the fact of an operand isn't computed yet.
+    fn transfer(&self, fact_of: &dyn Fn(ValueId) -> Option<IntFact>, ty: Type) -> Option<IntFact>;
+
+    type Members = (
+        arith::Neg,
+        arith::Add,
+        arith::Sub,
+        arith::Mul,
+        arith::Udiv,
+        arith::Umod,
+        arith::Shl,
+        arith::Shr,
+        logic::Not,
+        logic::And,
+        logic::Or,
+        logic::Xor,
+        icmp::Lt,
+        icmp::Gt,
+        icmp::Le,
+        icmp::Ge,
+        icmp::Slt,
+        icmp::Sgt,
+        icmp::Sle,
+        icmp::Sge,
+        icmp::Eq,
+        icmp::Ne,
+        icmp::IsZero,
+        cast::Zext,
+        cast::Sext,
+        cast::Trunc,
+        evm::EvmUdiv,
+        evm::EvmUmod,
+        evm::EvmAddMod,
+        evm::EvmMulMod,
+        evm::EvmByte,
+    );
+}
+
+macro_rules! impl_unary {
+    ($($ty:ty: $arg:ident => $f:expr;)*) => {
+        $(
+            impl Transfer for $ty {
+                fn transfer(
+                    &self,
+                    fact_of: &dyn Fn(ValueId) -> Option<IntFact>,
+                    _ty: Type,
+                ) -> Option<IntFact> {
+                    Some($f(&fact_of(*self.$arg())?))
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_binary {
+    ($($ty:ty: $lhs:ident, $rhs:ident => $f:expr;)*) => {
+        $(
+            impl Transfer for $ty {
+                fn transfer(
+                    &self,
+                    fact_of: &dyn Fn(ValueId) -> Option<IntFact>,
+                    _ty: Type,
+                ) -> Option<IntFact> {
+                    Some($f(&fact_of(*self.$lhs())?, &fact_of(*self.$rhs())?))
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_cast {
+    ($($ty:ty => $f:expr;)*) => {
+        $(
+            impl Transfer for $ty {
+                fn transfer(
+                    &self,
+                    fact_of: &dyn Fn(ValueId) -> Option<IntFact>,
+                    ty: Type,
+                ) -> Option<IntFact> {
+                    Some($f(&fact_of(*self.from())?, ty))
+                }
+            }
+        )*
+    };
+}
+
+impl_unary! {
+    arith::Neg: arg => IntFact::neg;
+    logic::Not: arg => IntFact::not;
+    icmp::IsZero: lhs => IntFact::is_zero;
+    evm::EvmAddMod: modulus => IntFact::evm_mod;
+    evm::EvmMulMod: modulus => IntFact::evm_mod;
+}
+
+impl_binary! {
+    arith::Add: lhs, rhs => IntFact::add;
+    arith::Sub: lhs, rhs => IntFact::sub;
+    arith::Mul: lhs, rhs => IntFact::mul;
+    arith::Udiv: lhs, rhs => IntFact::udiv;
+    arith::Umod: lhs, rhs => IntFact::umod;
+    arith::Shl: value, bits => IntFact::shl;
+    arith::Shr: value, bits => IntFact::shr;
+    logic::And: lhs, rhs => IntFact::and;
+    logic::Or: lhs, rhs => IntFact::or;
+    logic::Xor: lhs, rhs => IntFact::xor;
+    icmp::Lt: lhs, rhs => IntFact::lt;
+    icmp::Gt: lhs, rhs => IntFact::gt;
+    icmp::Le: lhs, rhs => IntFact::le;
+    icmp::Ge: lhs, rhs => IntFact::ge;
+    icmp::Slt: lhs, rhs => IntFact::slt;
+    icmp::Sgt: lhs, rhs => IntFact::sgt;
+    icmp::Sle: lhs, rhs => IntFact::sle;
+    icmp::Sge: lhs, rhs => IntFact::sge;
+    icmp::Eq: lhs, rhs => IntFact::cmp_eq;
+    icmp::Ne: lhs, rhs => IntFact::cmp_ne;
+    evm::EvmUdiv: lhs, rhs => IntFact::udiv;
+    evm::EvmUmod: lhs, rhs => IntFact::umod;
+    evm::EvmByte: pos, value => IntFact::evm_byte;
+}
+
+impl_cast! {
+    cast::Zext => IntFact::zext;
+    cast::Sext => IntFact::sext;
+    cast::Trunc => IntFact::trunc;
+}
+
+fn width(ty: Type) -> usize {
+    match ty {
+        Type::I1 => 1,
+        Type::I8 => 8,
+        Type::I16 => 16,
+        Type::I32 => 32,
+        Type::I64 => 64,
+        Type::I128 => 128,
+        Type::I256 => 256,
+        _ => unreachable!("`{ty:?}` isn't an integer type"),
+    }
+}
+
+/// Returns the mask of the lowest `width` bits.
+fn mask(width: usize) -> U256 {
+    if width >= 256 {
+        U256::MAX
+    } else {
+        (U256::one() << width) - 1
+    }
+}
+
+fn sign_bit(width: usize) -> U256 {
+    U256::one() << (width - 1)
+}
+
+fn full_unsigned(width: usize) -> Range<U256> {
+    Range::new(U256::zero(), mask(width))
+}
+
+fn full_signed(width: usize) -> Range<I256> {
+    Range::new(
+        to_signed(sign_bit(width), width),
+        I256::from_u256(mask(width) >> 1),
+    )
+}
+
+/// Sign-extends a `width`-bit unsigned integer.
+fn to_signed(val: U256, width: usize) -> I256 {
+    if (val & sign_bit(width)).is_zero() {
+        I256::from_u256(val)
+    } else {
+        I256::from_u256(val | !mask(width))
+    }
+}
+
+/// Truncates a signed integer to a `width`-bit unsigned integer.
+fn to_unsigned(val: I256, width: usize) -> U256 {
+    val.to_u256() & mask(width)
+}
+
+fn unsigned_of_signed(range: &Range<I256>, width: usize) -> Option<Range<U256>> {
+    (range.min.is_negative() == range.max.is_negative())
+        .then(|| Range::new(to_unsigned(range.min, width), to_unsigned(range.max, width)))
+}
+
+fn signed_of_unsigned(range: &Range<U256>, width: usize) -> Option<Range<I256>> {
+    let sign = sign_bit(width);
+    ((range.max < sign) || (range.min >= sign))
+        .then(|| Range::new(to_signed(range.min, width), to_signed(range.max, width)))
+}
+
+fn signed_add(lhs: I256, rhs: I256, width: usize) -> Option<I256> {
+    let res = lhs.overflowing_add(rhs).0;
+    let overflow = lhs.is_negative() == rhs.is_negative() && res.is_negative() != lhs.is_negative();
+    (!overflow && full_signed(width).contains(res)).then_some(res)
+}
+
+fn signed_sub(lhs: I256, rhs: I256, width: usize) -> Option<I256> {
+    let res = lhs.overflowing_sub(rhs).0;
+    let overflow = lhs.is_negative() != rhs.is_negative() && res.is_negative() != lhs.is_negative();
+    (!overflow && full_signed(width).contains(res)).then_some(res)
+}
+
+/// Returns the amount of a shift if it's a constant.
+fn shift_amount(bits: &IntFact) -> Option<usize> {
+    let amount = bits.as_constant()?.zext(Type::I256).as_i256().to_u256();
+    Some(if amount > U256::from(256) {
+        256
+    } else {
+        amount.as_usize()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use sonatina_ir::{
+        builder::test_util::*,
+        inst::{
+            arith::{Add, Mul, Shl, Shr},
+            cast::{Trunc, Zext},
+            cmp::{Gt, Lt},
+            control_flow::{Br, BrTable, Jump, Phi, Return},
+            logic::And,
+        },
+        isa::Isa,
+    };
+
+    use super::*;
+
+    #[test]
+    fn redundant_mask() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I8], Type::I256);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let arg = builder.args()[0];
+        let v0 = builder.insert_inst_with(|| Zext::new(is, arg, Type::I256), Type::I256);
+        let mask = builder.make_imm_value(I256::from(0xffu8));
+        let v1 = builder.insert_inst_with(|| And::new(is, v0, mask), Type::I256);
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut ra = RangeAnalysis::new();
+            ra.compute(func);
+
+            let fact = ra.fact(func, v0).unwrap();
+            assert!(fact.is_within_mask(Immediate::I256(0xffu8.into())));
+            assert!(!fact.is_within_mask(Immediate::I256(0x7fu8.into())));
+            assert_eq!(fact.unsigned, Range::new(U256::zero(), U256::from(255)));
+            assert_eq!(ra.fact(func, v1), Some(fact));
+        });
+    }
+
+    #[test]
+    fn bounds_check() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I256], Type::I1);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let arg = builder.args()[0];
+        let three = builder.make_imm_value(I256::from(3u8));
+        let four = builder.make_imm_value(I256::from(4u8));
+        let v0 = builder.insert_inst_with(|| And::new(is, arg, three), Type::I256);
+        let v1 = builder.insert_inst_with(|| Lt::new(is, v0, four), Type::I1);
+        let v2 = builder.insert_inst_with(|| Gt::new(is, v0, three), Type::I1);
+        let v3 = builder.insert_inst_with(|| Lt::new(is, v0, three), Type::I1);
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(v1)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut ra = RangeAnalysis::new();
+            ra.compute(func);
+
+            let constant = |value| ra.fact(func, value).unwrap().as_constant();
+            assert_eq!(constant(v1), Some(true.into()));
+            assert_eq!(constant(v2), Some(false.into()));
+            assert_eq!(constant(v3), None);
+        });
+    }
+
+    #[test]
+    fn unreachable_dest() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I256], Type::Unit);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+        let b3 = builder.append_block();
+        let b4 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        let arg = builder.args()[0];
+        let three = builder.make_imm_value(I256::from(3u8));
+        let four = builder.make_imm_value(I256::from(4u8));
+        let v0 = builder.insert_inst_with(|| And::new(is, arg, three), Type::I256);
+        let v1 = builder.insert_inst_with(|| Lt::new(is, v0, four), Type::I1);
+        builder.insert_inst_no_result_with(|| Br::new(is, v1, b1, b2));
+
+        builder.switch_to_block(b1);
+        let one = builder.make_imm_value(I256::one());
+        let five = builder.make_imm_value(I256::from(5u8));
+        let table = vec![(one, b3), (five, b4)];
+        builder.insert_inst_no_result_with(|| BrTable::new(is, v0, Some(b2), table));
+
+        for block in [b2, b3, b4] {
+            builder.switch_to_block(block);
+            builder.insert_inst_no_result_with(|| Return::new(is, None));
+        }
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut ra = RangeAnalysis::new();
+            ra.compute(func);
+
+            assert!(ra.is_reachable(b1));
+            assert!(ra.is_reachable(b2));
+            assert!(ra.is_reachable(b3));
+            assert!(!ra.is_reachable(b4));
+        });
+    }
+
+    #[test]
+    fn narrowing() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I8, Type::I8], Type::I256);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let (lhs, rhs) = (builder.args()[0], builder.args()[1]);
+        let v0 = builder.insert_inst_with(|| Zext::new(is, lhs, Type::I256), Type::I256);
+        let v1 = builder.insert_inst_with(|| Zext::new(is, rhs, Type::I256), Type::I256);
+        let v2 = builder.insert_inst_with(|| Add::new(is, v0, v1), Type::I256);
+        let v3 = builder.insert_inst_with(|| Mul::new(is, v0, v1), Type::I256);
+        let v4 = builder.insert_inst_with(|| Mul::new(is, v2, v3), Type::I256);
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(v4)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut ra = RangeAnalysis::new();
+            ra.compute(func);
+
+            let sum = ra.fact(func, v2).unwrap();
+            assert_eq!(sum.unsigned, Range::new(U256::zero(), U256::from(510)));
+            assert!(sum.fits_unsigned(Type::I16));
+            assert!(sum.fits_signed(Type::I16));
+            assert!(!sum.fits_unsigned(Type::I8));
+
+            let product = ra.fact(func, v3).unwrap();
+            assert!(product.fits_unsigned(Type::I16));
+            assert!(!product.fits_signed(Type::I16));
+            assert!(ra.fact(func, v4).unwrap().fits_unsigned(Type::I32));
+        });
+    }
+
+    #[test]
+    fn shift_and_trunc() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I256], Type::I8);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let arg = builder.args()[0];
+        let c248 = builder.make_imm_value(I256::from(248u8));
+        let c8 = builder.make_imm_value(I256::from(8u8));
+        let v0 = builder.insert_inst_with(|| Shr::new(is, c248, arg), Type::I256);
+        let v1 = builder.insert_inst_with(|| Shl::new(is, c8, v0), Type::I256);
+        let v2 = builder.insert_inst_with(|| Trunc::new(is, v1, Type::I8), Type::I8);
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(v2)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut ra = RangeAnalysis::new();
+            ra.compute(func);
+
+            let byte = ra.fact(func, v0).unwrap();
+            assert_eq!(byte.unsigned, Range::new(U256::zero(), U256::from(255)));
+
+            let shifted = ra.fact(func, v1).unwrap();
+            assert_eq!(shifted.bits.zero, !U256::from(0xff00));
+            assert_eq!(shifted.unsigned.max, U256::from(0xff00));
+
+            assert_eq!(
+                ra.fact(func, v2).unwrap().as_constant(),
+                Some(Immediate::I8(0))
+            );
+        });
+    }
+
+    #[test]
+    fn loop_counter() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I1], Type::I256);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        let b1 = builder.append_block();
+        let b2 = builder.append_block();
+
+        builder.switch_to_block(b0);
+        let zero = builder.make_imm_value(I256::zero());
+        builder.insert_inst_no_result_with(|| Jump::new(is, b1));
+
+        builder.switch_to_block(b1);
+        let cond = builder.args()[0];
+        let v0 = builder.insert_inst_with(|| Phi::new(is, vec![(zero, b0)]), Type::I256);
+        let two = builder.make_imm_value(I256::from(2u8));
+        let v1 = builder.insert_inst_with(|| Add::new(is, v0, two), Type::I256);
+        builder.append_phi_arg(v0, v1, b1);
+        builder.insert_inst_no_result_with(|| Br::new(is, cond, b1, b2));
+
+        builder.switch_to_block(b2);
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(v0)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let func_ref = module.funcs()[0];
+        module.func_store.view(func_ref, |func| {
+            let mut ra = RangeAnalysis::new();
+            ra.compute(func);
+
+            let counter = ra.fact(func, v0).unwrap();
+            assert_eq!(counter.bits.zero, U256::one());
+            assert_eq!(counter.unsigned.min, U256::zero());
+            assert_eq!(counter.unsigned.max, U256::MAX - 1);
+
+            let next = ra.fact(func, v1).unwrap();
+            assert_eq!(next.bits.zero, U256::one());
+        });
+    }
+}
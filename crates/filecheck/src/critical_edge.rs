@@ -0,0 +1,23 @@
+use std::path::{Path, PathBuf};
+
+use sonatina_codegen::critical_edge::CriticalEdgeSplitter;
+use sonatina_ir::{ControlFlowGraph, Function};
+
+use super::{FuncTransform, FIXTURE_ROOT};
+
+#[derive(Default)]
+pub struct CriticalEdgeTransform {
+    cfg: ControlFlowGraph,
+}
+
+impl FuncTransform for CriticalEdgeTransform {
+    fn transform(&mut self, func: &mut Function) {
+        self.cfg.compute(func);
+        let mut splitter = CriticalEdgeSplitter::new();
+        splitter.run(func, &mut self.cfg);
+    }
+
+    fn test_root(&self) -> PathBuf {
+        Path::new(FIXTURE_ROOT).join("critical_edge")
+    }
+}
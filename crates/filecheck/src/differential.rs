@@ -0,0 +1,482 @@
+//! Differential testing of transformations with the interpreter.
+//!
+//! Each public function in the fixtures is run on a set of inputs before and
+//! after a transformation, and the test fails if the transformation changes
+//! the observable behavior of the function, i.e., the result of the call and
+//! the state changes made to the host.
+//!
+//! Inputs are taken from comments in the same form as the interpreter tests,
+//! e.g., `#[(1.i32, 2.i32) -> 3.i32]`, and are also randomly generated if all
+//! arguments of the function are integers.
+
+use std::{
+    collections::HashSet,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    time,
+};
+
+use sonatina_interpreter::{ExecResult, InMemoryHost, Machine, Tracer};
+use sonatina_ir::{
+    inst::control_flow,
+    interpret::{EvalValue, HaltReason},
+    ir_writer::FuncWriter,
+    module::FuncRef,
+    Immediate, InstDowncast, Linkage, Module, Type, I256,
+};
+use sonatina_parser::{
+    ast::{Value, ValueKind},
+    syntax::{FromSyntax, Node, Parser, Rule},
+    ParsedModule, PestParser,
+};
+
+use super::{parse_file, print_results, sntn_files, FileCheckResult, FuncTransform, FIXTURE_ROOT};
+
+/// The gas limit of a single run, which stops functions that don't terminate
+/// with the given inputs.
+const GAS_LIMIT: u64 = 100_000;
+
+/// The number of random inputs generated for each function.
+const RANDOM_INPUTS_NUM: usize = 16;
+
+pub struct DiffTestRunner {
+    transformer: Box<dyn FuncTransform>,
+    results: Vec<FileCheckResult>,
+    timer: time::Instant,
+}
+
+impl DiffTestRunner {
+    pub fn new(transformer: impl FuncTransform + 'static) -> Self {
+        Self {
+            transformer: Box::new(transformer),
+            results: Vec::new(),
+            timer: time::Instant::now(),
+        }
+    }
+
+    pub fn attach_transformer(&mut self, transformer: impl FuncTransform + 'static) {
+        self.transformer = Box::new(transformer);
+    }
+
+    pub fn run(&mut self) {
+        for path in sntn_files(&self.transformer.test_root()) {
+            let mut tester = DiffTester::new(self.transformer.as_mut(), &path);
+            self.results.extend(tester.test());
+        }
+    }
+
+    pub fn print_results(&self) {
+        print_results(&self.results, self.timer);
+    }
+
+    pub fn failed_num(&self) -> usize {
+        self.results.iter().filter(|res| !res.is_ok()).count()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failed_num() == 0
+    }
+}
+
+struct DiffTester<'a> {
+    transformer: &'a mut dyn FuncTransform,
+    file_path: &'a Path,
+}
+
+impl<'a> DiffTester<'a> {
+    fn new(transformer: &'a mut dyn FuncTransform, file_path: &'a Path) -> Self {
+        Self {
+            transformer,
+            file_path,
+        }
+    }
+
+    fn test(&mut self) -> Vec<FileCheckResult> {
+        let (original, transformed) = match (parse_file(self.file_path), parse_file(self.file_path))
+        {
+            (Ok(original), Ok(transformed)) => (original, transformed),
+            (Err(msg), _) | (_, Err(msg)) => {
+                return vec![FileCheckResult::new(self.test_path(None), Err(msg))]
+            }
+        };
+
+        let module = &transformed.module;
+        self.transformer.transform_module(module);
+        for func_ref in module.funcs() {
+            if module
+                .ctx
+                .func_sig(func_ref, |sig| sig.linkage().has_definition())
+            {
+                module
+                    .func_store
+                    .modify(func_ref, |func| self.transformer.transform(func));
+            }
+        }
+
+        let cases: Vec<_> = original
+            .module
+            .funcs()
+            .into_iter()
+            .filter(|&func_ref| {
+                original
+                    .module
+                    .ctx
+                    .func_sig(func_ref, |sig| sig.linkage() == Linkage::Public)
+            })
+            .map(|func_ref| {
+                let inputs = match undefined_callee(&original.module, func_ref) {
+                    Some(callee) => Err(callee),
+                    None => Ok(collect_inputs(&original, func_ref)),
+                };
+                (func_ref, inputs)
+            })
+            .collect();
+
+        let mut before = Machine::new(original.module);
+        let mut after = Machine::new(transformed.module);
+        before.set_gas_limit(GAS_LIMIT);
+        after.set_gas_limit(GAS_LIMIT);
+        before.enable_strict_mode();
+        after.enable_strict_mode();
+
+        cases
+            .into_iter()
+            .map(|(func_ref, inputs)| {
+                let name = func_name(&before, func_ref);
+                let inputs = match inputs {
+                    Ok(inputs) => inputs,
+                    Err(callee) => {
+                        return FileCheckResult::ignored(
+                            self.test_path(Some(&name)),
+                            format!("`%{callee}` has no definition to interpret"),
+                        )
+                    }
+                };
+                let result = inputs.and_then(|inputs| {
+                    inputs
+                        .into_iter()
+                        .try_for_each(|input| compare(&mut before, &mut after, func_ref, input))
+                });
+                FileCheckResult::new(self.test_path(Some(&name)), result)
+            })
+            .collect()
+    }
+
+    /// Returns the path of the test, e.g., `differential/sccp/const_loop.sntn/const_loop`.
+    fn test_path(&self, func_name: Option<&str>) -> PathBuf {
+        let root = Path::new(FIXTURE_ROOT);
+        let file = self.file_path.strip_prefix(root).unwrap();
+
+        let mut path = root.join("differential").join(file);
+        if let Some(func_name) = func_name {
+            path.push(func_name);
+        }
+        path
+    }
+}
+
+struct Input {
+    args: Vec<Immediate>,
+    /// The expected return value specified in the comment.
+    ret: Option<Immediate>,
+}
+
+/// Runs `func_ref` with `input` on both machines and compares the results.
+fn compare(
+    before: &mut Machine,
+    after: &mut Machine,
+    func_ref: FuncRef,
+    input: Input,
+) -> Result<(), String> {
+    let args = args_of(&input.args);
+
+    let arg_texts: Vec<_> = input.args.iter().map(|arg| format!("{arg}")).collect();
+    let arg_texts = arg_texts.join(", ");
+
+    // A panic while running the original function is an interpreter bug, which
+    // must not be hidden by treating the input as passed.
+    let expected = run(before, func_ref, args.clone())
+        .map_err(|msg| format!("args: ({arg_texts})\npanicked before the transformation: {msg}"))?;
+    // The input triggers undefined behavior of the original function, so the
+    // transformed function can do anything. The same applies to inputs that
+    // don't terminate.
+    if matches!(
+        expected.result,
+        ExecResult::Halt(HaltReason::OutOfGas) | ExecResult::UndefinedBehavior(_)
+    ) {
+        return Ok(());
+    }
+
+    let error = match run(after, func_ref, args.clone()) {
+        Ok(evaluated) => {
+            if !refines(&expected.result, &evaluated.result) {
+                Some(format!(
+                    "expected: {}\nevaluated: {}",
+                    write_result(&expected.result),
+                    write_result(&evaluated.result)
+                ))
+            } else if expected.host_state() != evaluated.host_state() {
+                Some(format!(
+                    "host state mismatch\nexpected: {:?}\nevaluated: {:?}",
+                    expected.host_state(),
+                    evaluated.host_state()
+                ))
+            } else {
+                match input.ret {
+                    Some(ret) if evaluated.result != ExecResult::Value(EvalValue::Imm(ret)) => {
+                        Some(format!(
+                            "expected: {ret}\nevaluated: {}",
+                            write_result(&evaluated.result)
+                        ))
+                    }
+                    _ => None,
+                }
+            }
+        }
+        Err(msg) => Some(format!("panicked after the transformation: {msg}")),
+    };
+
+    let Some(error) = error else {
+        return Ok(());
+    };
+
+    let transformed = after.funcs.get(&func_ref).unwrap();
+    let transformed = FuncWriter::new(func_ref, transformed).dump_string();
+    Err(format!(
+        "args: ({arg_texts})\n{error}\n\ntransformed:\n{transformed}\ntrace before:\n{}\ntrace after:\n{}",
+        trace(before, func_ref, args.clone()),
+        trace(after, func_ref, args),
+    ))
+}
+
+fn args_of(args: &[Immediate]) -> Vec<EvalValue> {
+    args.iter().copied().map(EvalValue::Imm).collect()
+}
+
+struct Outcome {
+    result: ExecResult,
+    host: InMemoryHost,
+}
+
+impl Outcome {
+    /// Returns the observable state of the host.
+    fn host_state(&self) -> impl PartialEq + std::fmt::Debug + '_ {
+        let host = &self.host;
+        (
+            (
+                &host.storage,
+                &host.transient_storage,
+                &host.storages,
+                &host.transient_storages,
+            ),
+            (&host.balances, &host.codes, &host.nonces),
+            &host.logs,
+        )
+    }
+}
+
+/// Runs `func_ref` on a fresh state, and returns an error message if the
+/// interpreter panics.
+fn run(machine: &mut Machine, func_ref: FuncRef, args: Vec<EvalValue>) -> Result<Outcome, String> {
+    machine.clear_state();
+    *machine.host_mut() = InMemoryHost::default();
+
+    // Suppress the panic message, the panic is reported as a test result.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| machine.run(func_ref, args)));
+    panic::set_hook(hook);
+
+    match result {
+        Ok(result) => Ok(Outcome {
+            result,
+            host: std::mem::take(machine.host_mut()),
+        }),
+        Err(payload) => {
+            machine.clear_state();
+            if let Some(msg) = payload.downcast_ref::<&str>() {
+                Err(msg.to_string())
+            } else if let Some(msg) = payload.downcast_ref::<String>() {
+                Err(msg.clone())
+            } else {
+                Err("unknown panic".to_string())
+            }
+        }
+    }
+}
+
+fn trace(machine: &mut Machine, func_ref: FuncRef, args: Vec<EvalValue>) -> String {
+    machine.set_observer(Tracer::new());
+    let _ = run(machine, func_ref, args);
+    let trace = machine.observer::<Tracer>().unwrap().trace().to_string();
+    machine.take_observer();
+    trace
+}
+
+/// Returns `true` if `evaluated` is a valid result of a function that returns
+/// `expected`, i.e., an undefined value can be refined to any value.
+fn refines(expected: &ExecResult, evaluated: &ExecResult) -> bool {
+    match (expected, evaluated) {
+        (ExecResult::Value(expected), ExecResult::Value(evaluated)) => {
+            refines_value(expected, evaluated)
+        }
+        _ => expected == evaluated,
+    }
+}
+
+fn refines_value(expected: &EvalValue, evaluated: &EvalValue) -> bool {
+    match (expected, evaluated) {
+        (EvalValue::Undef, _) => true,
+        (EvalValue::Aggregate { fields: lhs, .. }, EvalValue::Aggregate { fields: rhs, .. }) => {
+            lhs.len() == rhs.len() && lhs.iter().zip(rhs).all(|(l, r)| refines_value(l, r))
+        }
+        _ => expected == evaluated,
+    }
+}
+
+fn write_result(result: &ExecResult) -> String {
+    match result {
+        ExecResult::Value(value) => value.to_string(),
+        ExecResult::Halt(reason) => format!("{reason:?}"),
+        ExecResult::UndefinedBehavior(ub) => format!("undefined behavior: {}", ub.kind),
+    }
+}
+
+fn func_name(machine: &Machine, func_ref: FuncRef) -> String {
+    machine
+        .module_ctx
+        .func_sig(func_ref, |sig| sig.name().to_string())
+}
+
+/// Returns the name of a function without a definition that `func_ref` calls
+/// directly or indirectly.
+fn undefined_callee(module: &Module, func_ref: FuncRef) -> Option<String> {
+    let mut visited = HashSet::new();
+    let mut worklist = vec![func_ref];
+    while let Some(func_ref) = worklist.pop() {
+        if !visited.insert(func_ref) {
+            continue;
+        }
+        if !module
+            .ctx
+            .func_sig(func_ref, |sig| sig.linkage().has_definition())
+        {
+            return Some(module.ctx.func_sig(func_ref, |sig| sig.name().to_string()));
+        }
+
+        module.func_store.view(func_ref, |func| {
+            let is = func.inst_set();
+            for block in func.layout.iter_block() {
+                for inst in func.layout.iter_inst(block) {
+                    if let Some(call) =
+                        <&control_flow::Call as InstDowncast>::downcast(is, func.dfg.inst(inst))
+                    {
+                        worklist.push(*call.callee());
+                    }
+                }
+            }
+        });
+    }
+
+    None
+}
+
+/// Collects inputs specified in the comments of the function, and generates
+/// random inputs if possible.
+fn collect_inputs(module: &ParsedModule, func_ref: FuncRef) -> Result<Vec<Input>, String> {
+    let mut inputs = Vec::new();
+    for comment in &module.debug.func_comments[func_ref] {
+        if let Some(input) = parse_input(comment)? {
+            inputs.push(input);
+        }
+    }
+
+    let arg_tys: Vec<Type> = module
+        .module
+        .ctx
+        .func_sig(func_ref, |sig| sig.args().to_vec());
+    if arg_tys.iter().all(|ty| ty.is_integral()) {
+        let mut rng = Rng::new(func_ref.as_u32() as u64 + 1);
+        for i in 0..RANDOM_INPUTS_NUM {
+            let args = arg_tys
+                .iter()
+                .map(|&ty| Immediate::from_i256(rng.arg(i), ty))
+                .collect();
+            inputs.push(Input { args, ret: None });
+        }
+    }
+
+    Ok(inputs)
+}
+
+/// Parses a comment in the form of `#[(args,*) (-> ret)?]`, returns `None` if
+/// the comment is not an input specification.
+fn parse_input(comment: &str) -> Result<Option<Input>, String> {
+    let Some(spec) = comment
+        .strip_prefix('#')
+        .and_then(|c| c.trim().strip_prefix("[("))
+        .and_then(|c| c.strip_suffix(']'))
+    else {
+        return Ok(None);
+    };
+
+    let invalid = || format!("invalid `{comment}`, `#[(args_list) -> ret]` is expected");
+    let (args, ret) = spec.split_once(')').ok_or_else(invalid)?;
+    let args = args
+        .split(',')
+        .map(str::trim)
+        .filter(|arg| !arg.is_empty())
+        .map(parse_imm)
+        .collect::<Result<_, _>>()?;
+
+    let ret = ret.trim();
+    let ret = if ret.is_empty() {
+        None
+    } else {
+        let ret = ret.strip_prefix("->").ok_or_else(invalid)?;
+        Some(parse_imm(ret.trim())?)
+    };
+
+    Ok(Some(Input { args, ret }))
+}
+
+fn parse_imm(input: &str) -> Result<Immediate, String> {
+    let mut pairs = Parser::parse(Rule::value, input).map_err(|err| err.to_string())?;
+    let mut node = Node::new(pairs.next().unwrap());
+    match Value::from_syntax(&mut node).kind {
+        ValueKind::Immediate(imm) => Ok(imm),
+        _ => Err(format!("`{input}` is not an immediate")),
+    }
+}
+
+/// A xorshift random number generator, which makes the generated inputs
+/// reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Generates an argument of the `i`-th input. Small values are preferred
+    /// since they tend to exercise more interesting paths, e.g., loop counts.
+    fn arg(&mut self, i: usize) -> I256 {
+        match i {
+            0 => I256::zero(),
+            1 => I256::one(),
+            2 => I256::all_one(),
+            _ if self.next() & 1 == 0 => I256::from(self.next() % 16),
+            _ => {
+                let bytes: Vec<u8> = (0..4).flat_map(|_| self.next().to_be_bytes()).collect();
+                I256::from_be_bytes(&bytes)
+            }
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use std::path::{Path, PathBuf};
+
+use sonatina_codegen::optim::inliner::Inliner;
+use sonatina_ir::{Function, Module};
+
+use super::{FuncTransform, FIXTURE_ROOT};
+
+#[derive(Default)]
+pub struct InlinerTransform {
+    inliner: Inliner,
+}
+
+impl FuncTransform for InlinerTransform {
+    fn transform(&mut self, _func: &mut Function) {}
+
+    fn transform_module(&mut self, module: &Module) {
+        self.inliner.run(module);
+    }
+
+    fn test_root(&self) -> PathBuf {
+        Path::new(FIXTURE_ROOT).join("inliner")
+    }
+}
@@ -0,0 +1,25 @@
+use std::path::{Path, PathBuf};
+
+use sonatina_codegen::{domtree::DomTree, optim::jump_threading::JumpThreadingSolver};
+use sonatina_ir::{ControlFlowGraph, Function};
+
+use super::{FuncTransform, FIXTURE_ROOT};
+
+#[derive(Default)]
+pub struct JumpThreadingTransform {
+    domtree: DomTree,
+    cfg: ControlFlowGraph,
+}
+
+impl FuncTransform for JumpThreadingTransform {
+    fn transform(&mut self, func: &mut Function) {
+        self.cfg.compute(func);
+        self.domtree.compute(&self.cfg);
+        let mut solver = JumpThreadingSolver::new();
+        solver.run(func, &mut self.cfg, &mut self.domtree);
+    }
+
+    fn test_root(&self) -> PathBuf {
+        Path::new(FIXTURE_ROOT).join("jump_threading")
+    }
+}
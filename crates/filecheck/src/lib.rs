@@ -1,8 +1,18 @@
 pub mod adce;
+pub mod critical_edge;
+pub mod differential;
 pub mod gvn;
+pub mod inliner;
 pub mod insn_simplify;
+pub mod ipsccp;
+pub mod jump_threading;
 pub mod licm;
+pub mod mem2reg;
+pub mod memory_elim;
+pub mod range_simplify;
 pub mod sccp;
+pub mod simplify_cfg;
+pub mod storage_elim;
 
 use std::{
     fs,
@@ -11,7 +21,7 @@ use std::{
     time,
 };
 
-use sonatina_ir::{ir_writer::FuncWriter, module::FuncRef, Function};
+use sonatina_ir::{ir_writer::FuncWriter, module::FuncRef, Function, Module};
 use sonatina_parser::{parse_module, ParsedModule};
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 use walkdir::WalkDir;
@@ -21,6 +31,10 @@ pub(crate) const FIXTURE_ROOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixt
 pub trait FuncTransform {
     fn transform(&mut self, func: &mut Function);
 
+    /// Transforms the whole module before each function is transformed.
+    /// Interprocedural transformations override this.
+    fn transform_module(&mut self, _module: &Module) {}
+
     fn test_root(&self) -> PathBuf;
 }
 
@@ -44,62 +58,14 @@ impl FileCheckRunner {
     }
 
     pub fn run(&mut self) {
-        for ent in WalkDir::new(self.transformer.test_root())
-            .into_iter()
-            .filter_map(|e| match e {
-                Ok(ent) => {
-                    if ent.file_type().is_file()
-                        && ent.path().extension().map_or(false, |ext| ext == "sntn")
-                    {
-                        Some(ent)
-                    } else {
-                        None
-                    }
-                }
-                _ => None,
-            })
-        {
-            let mut checker = FileChecker::new(self.transformer.as_mut(), ent.path());
+        for path in sntn_files(&self.transformer.test_root()) {
+            let mut checker = FileChecker::new(self.transformer.as_mut(), &path);
             self.results.extend(checker.check());
         }
     }
 
     pub fn print_results(&self) {
-        let tests_num = self.results.len();
-        let failed_num = self.failed_num();
-        let is_success = failed_num == 0;
-
-        let mut stdout = StandardStream::stdout(ColorChoice::Always);
-        writeln!(stdout, "\nrunning {} tests", tests_num).unwrap();
-        for res in &self.results {
-            res.print_result(&mut stdout).unwrap();
-        }
-
-        write!(stdout, "\ntest result: ").unwrap();
-        if is_success {
-            stdout
-                .set_color(ColorSpec::new().set_fg(Color::Green.into()))
-                .unwrap();
-            write!(stdout, "ok").unwrap();
-        } else {
-            stdout
-                .set_color(ColorSpec::new().set_fg(Color::Red.into()))
-                .unwrap();
-            write!(stdout, "FAILED").unwrap();
-        }
-        stdout.reset().unwrap();
-
-        let elapsed = self.timer.elapsed();
-
-        writeln!(
-            stdout,
-            ". {} passed; {} failed; 0 ignored; 0 measured; 0 filtered out; finished in {}.{:02}s\n",
-            tests_num - failed_num,
-            failed_num,
-            elapsed.as_secs(),
-            elapsed.subsec_millis() / 10,
-        )
-        .unwrap();
+        print_results(&self.results, self.timer);
     }
 
     pub fn failed_num(&self) -> usize {
@@ -131,6 +97,7 @@ impl<'a> FileChecker<'a> {
         };
 
         let module = &parsed_module.module;
+        self.transformer.transform_module(module);
 
         module
             .funcs()
@@ -166,19 +133,7 @@ impl<'a> FileChecker<'a> {
     }
 
     fn parse_file(&self) -> Result<ParsedModule, String> {
-        let input = fs::read_to_string(self.file_path).unwrap();
-
-        match parse_module(&input) {
-            Ok(module) => Ok(module),
-            Err(errs) => {
-                let mut v = vec![];
-                for e in errs {
-                    e.print(&mut v, self.file_path.to_str().unwrap(), &input, true)
-                        .unwrap()
-                }
-                Err(String::from_utf8(v).unwrap())
-            }
-        }
+        parse_file(self.file_path)
     }
 
     fn build_checker(&self, directives: &[String]) -> filecheck::Checker {
@@ -192,15 +147,105 @@ impl<'a> FileChecker<'a> {
     }
 }
 
+/// Returns all `.sntn` files under `root`.
+pub(crate) fn sntn_files(root: &Path) -> Vec<PathBuf> {
+    WalkDir::new(root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| match e {
+            Ok(ent) => {
+                if ent.file_type().is_file()
+                    && ent.path().extension().is_some_and(|ext| ext == "sntn")
+                {
+                    Some(ent.into_path())
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+pub(crate) fn parse_file(file_path: &Path) -> Result<ParsedModule, String> {
+    let input = fs::read_to_string(file_path).unwrap();
+
+    match parse_module(&input) {
+        Ok(module) => Ok(module),
+        Err(errs) => {
+            let mut v = vec![];
+            for e in errs {
+                e.print(&mut v, file_path.to_str().unwrap(), &input, true)
+                    .unwrap()
+            }
+            Err(String::from_utf8(v).unwrap())
+        }
+    }
+}
+
+pub(crate) fn print_results(results: &[FileCheckResult], timer: time::Instant) {
+    let tests_num = results.len();
+    let failed_num = results.iter().filter(|res| !res.is_ok()).count();
+    let ignored_num = results.iter().filter(|res| res.is_ignored()).count();
+    let is_success = failed_num == 0;
+
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    writeln!(stdout, "\nrunning {} tests", tests_num).unwrap();
+    for res in results {
+        res.print_result(&mut stdout).unwrap();
+    }
+
+    write!(stdout, "\ntest result: ").unwrap();
+    if is_success {
+        stdout
+            .set_color(ColorSpec::new().set_fg(Color::Green.into()))
+            .unwrap();
+        write!(stdout, "ok").unwrap();
+    } else {
+        stdout
+            .set_color(ColorSpec::new().set_fg(Color::Red.into()))
+            .unwrap();
+        write!(stdout, "FAILED").unwrap();
+    }
+    stdout.reset().unwrap();
+
+    let elapsed = timer.elapsed();
+
+    writeln!(
+        stdout,
+        ". {} passed; {} failed; {} ignored; 0 measured; 0 filtered out; finished in {}.{:02}s\n",
+        tests_num - failed_num - ignored_num,
+        failed_num,
+        ignored_num,
+        elapsed.as_secs(),
+        elapsed.subsec_millis() / 10,
+    )
+    .unwrap();
+}
+
 #[derive(Debug)]
 pub struct FileCheckResult {
     path: PathBuf,
     result: Result<(), String>,
+    /// The reason why the test isn't run.
+    ignored: Option<String>,
 }
 
 impl FileCheckResult {
-    fn new(path: PathBuf, result: Result<(), String>) -> Self {
-        Self { path, result }
+    pub(crate) fn new(path: PathBuf, result: Result<(), String>) -> Self {
+        Self {
+            path,
+            result,
+            ignored: None,
+        }
+    }
+
+    pub(crate) fn ignored(path: PathBuf, reason: String) -> Self {
+        Self {
+            path,
+            result: Ok(()),
+            ignored: Some(reason),
+        }
     }
 
     fn print_result(&self, stdout: &mut StandardStream) -> io::Result<()> {
@@ -212,6 +257,13 @@ impl FileCheckResult {
                 .replace('/', "::")
                 .replace(".sntn", "")
         )?;
+        if let Some(reason) = &self.ignored {
+            stdout.set_color(ColorSpec::new().set_fg(Color::Yellow.into()))?;
+            writeln!(stdout, " ignored, {reason}")?;
+            stdout.reset()?;
+            return Ok(());
+        }
+
         match &self.result {
             Ok(()) => {
                 stdout.set_color(ColorSpec::new().set_fg(Color::Green.into()))?;
@@ -231,4 +283,8 @@ impl FileCheckResult {
     fn is_ok(&self) -> bool {
         self.result.is_ok()
     }
+
+    fn is_ignored(&self) -> bool {
+        self.ignored.is_some()
+    }
 }
@@ -1,5 +1,10 @@
 use sonatina_filecheck::{
-    adce::AdceTransform, licm::LicmTransformer, sccp::SccpTransform, FileCheckRunner,
+    adce::AdceTransform, critical_edge::CriticalEdgeTransform, differential::DiffTestRunner,
+    gvn::GvnTransform, inliner::InlinerTransform, insn_simplify::InsnSimplifyTransform,
+    ipsccp::IpSccpTransform, jump_threading::JumpThreadingTransform, licm::LicmTransformer,
+    mem2reg::Mem2RegTransform, memory_elim::MemoryElimTransform,
+    range_simplify::RangeSimplifyTransform, sccp::SccpTransform,
+    simplify_cfg::SimplifyCfgTransform, storage_elim::StorageElimTransform, FileCheckRunner,
 };
 
 fn main() {
@@ -9,18 +14,89 @@ fn main() {
     runner.attach_transformer(AdceTransform::default());
     runner.run();
 
-    // TODO: Uncomment here when we implement corresponding egglog optimization
-    // pass. runner.attach_transformer(InsnSimplifyTransform::default());
-    // runner.run();
+    runner.attach_transformer(InsnSimplifyTransform::default());
+    runner.run();
+
+    runner.attach_transformer(GvnTransform::default());
+    runner.run();
+
+    runner.attach_transformer(Mem2RegTransform::default());
+    runner.run();
+
+    runner.attach_transformer(StorageElimTransform::default());
+    runner.run();
+
+    runner.attach_transformer(MemoryElimTransform::default());
+    runner.run();
 
-    // runner.attach_transformer(GvnTransform::default());
-    // runner.run();
+    runner.attach_transformer(SimplifyCfgTransform::default());
+    runner.run();
+
+    runner.attach_transformer(JumpThreadingTransform::default());
+    runner.run();
+
+    runner.attach_transformer(InlinerTransform::default());
+    runner.run();
+
+    runner.attach_transformer(IpSccpTransform::default());
+    runner.run();
 
     runner.attach_transformer(LicmTransformer::default());
     runner.run();
 
+    runner.attach_transformer(CriticalEdgeTransform::default());
+    runner.run();
+
+    runner.attach_transformer(RangeSimplifyTransform::default());
+    runner.run();
+
     runner.print_results();
-    if !runner.is_ok() {
+
+    let mut diff_runner = DiffTestRunner::new(SccpTransform::default());
+    diff_runner.run();
+
+    diff_runner.attach_transformer(AdceTransform::default());
+    diff_runner.run();
+
+    diff_runner.attach_transformer(InsnSimplifyTransform::default());
+    diff_runner.run();
+
+    diff_runner.attach_transformer(GvnTransform::default());
+    diff_runner.run();
+
+    diff_runner.attach_transformer(Mem2RegTransform::default());
+    diff_runner.run();
+
+    diff_runner.attach_transformer(StorageElimTransform::default());
+    diff_runner.run();
+
+    diff_runner.attach_transformer(MemoryElimTransform::default());
+    diff_runner.run();
+
+    diff_runner.attach_transformer(SimplifyCfgTransform::default());
+    diff_runner.run();
+
+    diff_runner.attach_transformer(JumpThreadingTransform::default());
+    diff_runner.run();
+
+    diff_runner.attach_transformer(InlinerTransform::default());
+    diff_runner.run();
+
+    diff_runner.attach_transformer(IpSccpTransform::default());
+    diff_runner.run();
+
+    diff_runner.attach_transformer(LicmTransformer::default());
+    diff_runner.run();
+
+    diff_runner.attach_transformer(CriticalEdgeTransform::default());
+    diff_runner.run();
+
+    diff_runner.attach_transformer(RangeSimplifyTransform::default());
+    diff_runner.run();
+
+    diff_runner.print_results();
+
+    if !runner.is_ok() || !diff_runner.is_ok() {
         std::process::exit(101);
     }
 }
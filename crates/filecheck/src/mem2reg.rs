@@ -0,0 +1,25 @@
+use std::path::{Path, PathBuf};
+
+use sonatina_codegen::{domtree::DomTree, optim::mem2reg::Mem2RegSolver};
+use sonatina_ir::{ControlFlowGraph, Function};
+
+use super::{FuncTransform, FIXTURE_ROOT};
+
+#[derive(Default)]
+pub struct Mem2RegTransform {
+    domtree: DomTree,
+    cfg: ControlFlowGraph,
+}
+
+impl FuncTransform for Mem2RegTransform {
+    fn transform(&mut self, func: &mut Function) {
+        self.cfg.compute(func);
+        self.domtree.compute(&self.cfg);
+        let mut solver = Mem2RegSolver::new();
+        solver.run(func, &self.cfg, &self.domtree);
+    }
+
+    fn test_root(&self) -> PathBuf {
+        Path::new(FIXTURE_ROOT).join("mem2reg")
+    }
+}
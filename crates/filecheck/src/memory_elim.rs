@@ -0,0 +1,25 @@
+use std::path::{Path, PathBuf};
+
+use sonatina_codegen::{alias_analysis::AliasAnalysis, optim::memory_elim::MemoryElimSolver};
+use sonatina_ir::{ControlFlowGraph, Function};
+
+use super::{FuncTransform, FIXTURE_ROOT};
+
+#[derive(Default)]
+pub struct MemoryElimTransform {
+    cfg: ControlFlowGraph,
+    aa: AliasAnalysis,
+}
+
+impl FuncTransform for MemoryElimTransform {
+    fn transform(&mut self, func: &mut Function) {
+        self.cfg.compute(func);
+        self.aa.compute(func);
+        let mut solver = MemoryElimSolver::new();
+        solver.run(func, &self.cfg, &self.aa);
+    }
+
+    fn test_root(&self) -> PathBuf {
+        Path::new(FIXTURE_ROOT).join("memory_elim")
+    }
+}
@@ -0,0 +1,23 @@
+use std::path::{Path, PathBuf};
+
+use sonatina_codegen::optim::range_simplify::RangeSimplifySolver;
+use sonatina_ir::{ControlFlowGraph, Function};
+
+use super::{FuncTransform, FIXTURE_ROOT};
+
+#[derive(Default)]
+pub struct RangeSimplifyTransform {
+    cfg: ControlFlowGraph,
+}
+
+impl FuncTransform for RangeSimplifyTransform {
+    fn transform(&mut self, func: &mut Function) {
+        self.cfg.compute(func);
+        let mut solver = RangeSimplifySolver::new();
+        solver.run(func, &mut self.cfg);
+    }
+
+    fn test_root(&self) -> PathBuf {
+        Path::new(FIXTURE_ROOT).join("range_simplify")
+    }
+}
@@ -0,0 +1,23 @@
+use std::path::{Path, PathBuf};
+
+use sonatina_codegen::optim::storage_elim::StorageElimSolver;
+use sonatina_ir::{ControlFlowGraph, Function};
+
+use super::{FuncTransform, FIXTURE_ROOT};
+
+#[derive(Default)]
+pub struct StorageElimTransform {
+    cfg: ControlFlowGraph,
+}
+
+impl FuncTransform for StorageElimTransform {
+    fn transform(&mut self, func: &mut Function) {
+        self.cfg.compute(func);
+        let mut solver = StorageElimSolver::new();
+        solver.run(func, &self.cfg);
+    }
+
+    fn test_root(&self) -> PathBuf {
+        Path::new(FIXTURE_ROOT).join("storage_elim")
+    }
+}
@@ -0,0 +1,78 @@
+//! Contracts that are executed by the interpreter.
+//!
+//! The interpreter doesn't execute EVM bytecode. Instead, the code of an
+//! account refers to the IR function that is executed when the account is
+//! called, or when the code is used as init code of `evm_create`. Such code is
+//! created by [`contract_code`].
+//!
+//! Any bytes following the reference are ignored on execution, so they can be
+//! used to pass constructor arguments as `evm_code_copy` reads them.
+
+use sha3::{Digest, Keccak256};
+use sonatina_ir::{module::FuncRef, U256};
+
+/// The first byte of contract code. This is the `INVALID` opcode, so the code
+/// halts immediately if it's executed on a real EVM.
+pub const CODE_PREFIX: u8 = 0xfe;
+
+/// The length of the code created by [`contract_code`].
+pub const CODE_LEN: usize = 5;
+
+/// Returns the code that executes `func` as the entry function.
+pub fn contract_code(func: FuncRef) -> Vec<u8> {
+    let mut code = Vec::with_capacity(CODE_LEN);
+    code.push(CODE_PREFIX);
+    code.extend_from_slice(&func.as_u32().to_be_bytes());
+    code
+}
+
+/// Returns the entry function referred to by `code`, or `None` if `code` is
+/// not created by [`contract_code`].
+pub fn entry_func(code: &[u8]) -> Option<FuncRef> {
+    match code {
+        [CODE_PREFIX, b0, b1, b2, b3, ..] => {
+            Some(FuncRef::from_u32(u32::from_be_bytes([*b0, *b1, *b2, *b3])))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the address of the account created by `evm_create`, i.e.,
+/// `keccak256(rlp([creator, nonce]))[12..]`.
+pub fn create_address(creator: U256, nonce: u64) -> U256 {
+    let nonce = nonce.to_be_bytes();
+    let nonce = &nonce[nonce.iter().take_while(|b| **b == 0).count()..];
+
+    let mut payload = vec![0x80 + 20];
+    payload.extend_from_slice(&address_bytes(creator));
+    match nonce {
+        [b] if *b < 0x80 => payload.push(*b),
+        _ => {
+            payload.push(0x80 + nonce.len() as u8);
+            payload.extend_from_slice(nonce);
+        }
+    }
+
+    let mut rlp = vec![0xc0 + payload.len() as u8];
+    rlp.extend_from_slice(&payload);
+    hash_to_address(&rlp)
+}
+
+/// Returns the address of the account created by `evm_create2`, i.e.,
+/// `keccak256(0xff ++ creator ++ salt ++ keccak256(init_code))[12..]`.
+pub fn create2_address(creator: U256, salt: U256, init_code: &[u8]) -> U256 {
+    let mut data = vec![0xff];
+    data.extend_from_slice(&address_bytes(creator));
+    data.extend_from_slice(&salt.to_big_endian());
+    data.extend_from_slice(&Keccak256::digest(init_code));
+    hash_to_address(&data)
+}
+
+fn address_bytes(addr: U256) -> [u8; 20] {
+    addr.to_big_endian()[12..].try_into().unwrap()
+}
+
+fn hash_to_address(data: &[u8]) -> U256 {
+    let hash = Keccak256::digest(data);
+    U256::from_big_endian(&hash[12..])
+}
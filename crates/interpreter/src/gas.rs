@@ -0,0 +1,603 @@
+//! Gas metering of the interpreter.
+//!
+//! The cost of each instruction is estimated from the cost of the EVM opcodes
+//! that the instruction is expected to be lowered to. Costs of EVM specific
+//! instructions follow the gas schedule of the EVM version of the module.
+//!
+//! NOTE: Gas refunds and the intrinsic gas of a transaction are not taken into
+//! account.
+
+use std::collections::{HashMap, HashSet};
+
+use macros::inst_prop;
+use sonatina_ir::{
+    inst::{arith::*, cast::*, cmp::*, control_flow::*, data::*, evm::*, logic::*},
+    Type, ValueId, U256,
+};
+use sonatina_triple::EvmVersion;
+
+#[inst_prop]
+pub trait GasCost {
+    /// Returns the gas consumed by the instruction excluding the memory
+    /// expansion cost, which is charged by the [`GasMeter`] after execution.
+    ///
+    /// This method is called before the instruction is executed.
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64;
+
+    type Members = All;
+}
+
+/// Provides the information required to compute dynamic gas costs.
+pub trait GasCtx {
+    fn evm_version(&self) -> EvmVersion;
+
+    /// Returns the value of `value` as a word, or `None` if the value is
+    /// undefined.
+    fn lookup_word(&mut self, value: ValueId) -> Option<U256>;
+
+    /// Returns the size of `ty` in bytes.
+    fn size_of(&self, ty: Type) -> usize;
+
+    /// Marks `addr` as accessed and returns `true` if it was cold.
+    fn access_account(&mut self, addr: U256) -> bool;
+
+    /// Marks the storage slot `key` of the current account as accessed.
+    fn access_slot(&mut self, key: U256) -> SlotAccess;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotAccess {
+    pub is_cold: bool,
+    /// The value of the slot at the beginning of the transaction.
+    pub original: U256,
+    pub current: U256,
+}
+
+/// Keeps track of the gas used and the access status of accounts and storage
+/// slots during a single transaction.
+#[derive(Debug, Clone)]
+pub struct GasMeter {
+    evm_version: EvmVersion,
+    limit: Option<u64>,
+    used: u64,
+    memory_words: u64,
+    warm_accounts: HashSet<U256>,
+    /// Storage slots are identified by the address of the account and the key.
+    warm_slots: HashSet<(U256, U256)>,
+    original_values: HashMap<(U256, U256), U256>,
+}
+
+impl GasMeter {
+    pub fn new(evm_version: EvmVersion) -> Self {
+        Self {
+            evm_version,
+            limit: None,
+            used: 0,
+            memory_words: 0,
+            warm_accounts: HashSet::new(),
+            warm_slots: HashSet::new(),
+            original_values: HashMap::new(),
+        }
+    }
+
+    pub fn evm_version(&self) -> EvmVersion {
+        self.evm_version
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Sets the maximum amount of gas that a single transaction can use.
+    pub fn set_limit(&mut self, limit: Option<u64>) {
+        self.limit = limit;
+    }
+
+    pub fn is_out_of_gas(&self) -> bool {
+        self.limit.is_some_and(|limit| self.used > limit)
+    }
+
+    /// Resets the meter for a new transaction.
+    ///
+    /// `memory_size` is the size of the memory that is already expanded, and
+    /// `warm_accounts` are the accounts that are warm from the beginning of
+    /// the transaction.
+    pub fn reset(&mut self, memory_size: usize, warm_accounts: impl IntoIterator<Item = U256>) {
+        self.used = 0;
+        self.memory_words = (memory_size as u64).div_ceil(32);
+        self.warm_accounts = warm_accounts.into_iter().collect();
+        self.warm_slots.clear();
+        self.original_values.clear();
+    }
+
+    pub fn charge(&mut self, gas: u64) {
+        self.used = self.used.saturating_add(gas);
+    }
+
+    /// Charges the memory expansion cost for expanding memory to
+    /// `memory_size` bytes.
+    pub fn charge_memory(&mut self, memory_size: usize) {
+        let words = (memory_size as u64).div_ceil(32);
+        if words > self.memory_words {
+            let cost = memory_cost(words) - memory_cost(self.memory_words);
+            self.charge(cost);
+            self.memory_words = words;
+        }
+    }
+
+    /// Replaces the metered memory with a memory of `memory_size` bytes, and
+    /// returns the size of the previous one.
+    ///
+    /// Each message call has its own memory, so this is called on entering
+    /// and exiting a nested message call.
+    pub fn switch_memory(&mut self, memory_size: usize) -> usize {
+        let words = (memory_size as u64).div_ceil(32);
+        let prev = std::mem::replace(&mut self.memory_words, words);
+        (prev * 32) as usize
+    }
+
+    /// Marks `addr` as accessed and returns `true` if it was cold.
+    pub fn access_account(&mut self, addr: U256) -> bool {
+        self.warm_accounts.insert(addr)
+    }
+
+    /// Marks the slot `key` of the account at `addr` as accessed.
+    /// `current` is recorded as the original value on the first access.
+    pub fn access_slot(&mut self, addr: U256, key: U256, current: U256) -> SlotAccess {
+        let slot = (addr, key);
+        let original = *self.original_values.entry(slot).or_insert(current);
+        SlotAccess {
+            is_cold: self.warm_slots.insert(slot),
+            original,
+            current,
+        }
+    }
+}
+
+fn memory_cost(words: u64) -> u64 {
+    words
+        .saturating_mul(MEMORY)
+        .saturating_add(words.saturating_mul(words) / 512)
+}
+
+const ZERO: u64 = 0;
+const BASE: u64 = 2;
+const VERY_LOW: u64 = 3;
+const LOW: u64 = 5;
+const MID: u64 = 8;
+const HIGH: u64 = 10;
+const JUMP: u64 = MID;
+const JUMPI: u64 = HIGH;
+const MEMORY: u64 = 3;
+const COPY: u64 = 3;
+const KECCAK256: u64 = 30;
+const KECCAK256_WORD: u64 = 6;
+const EXP: u64 = 10;
+const EXP_BYTE: u64 = 50;
+const LOG: u64 = 375;
+const LOG_TOPIC: u64 = 375;
+const LOG_DATA: u64 = 8;
+const BLOCKHASH: u64 = 20;
+const CREATE: u64 = 32000;
+const INIT_CODE_WORD: u64 = 2;
+const CALL_VALUE: u64 = 9000;
+const SELF_DESTRUCT: u64 = 5000;
+const WARM_ACCESS: u64 = 100;
+const COLD_ACCOUNT_ACCESS: u64 = 2600;
+const COLD_SLOAD: u64 = 2100;
+const SSTORE_SET: u64 = 20000;
+/// The cost of calling an internal function, i.e., pushing the return address
+/// and jumping to the callee and back.
+const INTERNAL_CALL: u64 = VERY_LOW + 2 * JUMP;
+
+/// Returns the cost of accessing an account.
+fn account_access(ctx: &mut dyn GasCtx, addr: Option<U256>) -> u64 {
+    if ctx.evm_version() < EvmVersion::Berlin {
+        return 700;
+    }
+
+    match addr {
+        Some(addr) if !ctx.access_account(addr) => WARM_ACCESS,
+        _ => COLD_ACCOUNT_ACCESS,
+    }
+}
+
+fn words(len: Option<U256>) -> u64 {
+    let len = len.unwrap_or_default();
+    if len > U256::from(u64::MAX) {
+        u64::MAX / 32
+    } else {
+        len.low_u64().div_ceil(32)
+    }
+}
+
+fn copy_cost(ctx: &mut dyn GasCtx, len: ValueId) -> u64 {
+    let len = ctx.lookup_word(len);
+    VERY_LOW + COPY.saturating_mul(words(len))
+}
+
+fn log_cost(ctx: &mut dyn GasCtx, len: ValueId, topics: u64) -> u64 {
+    let len = ctx.lookup_word(len).unwrap_or_default();
+    let len = if len > U256::from(u64::MAX) {
+        u64::MAX
+    } else {
+        len.low_u64()
+    };
+    LOG + LOG_TOPIC * topics + LOG_DATA.saturating_mul(len)
+}
+
+fn call_cost(ctx: &mut dyn GasCtx, addr: ValueId, value: Option<ValueId>) -> u64 {
+    let addr = ctx.lookup_word(addr);
+    let value = value.and_then(|value| ctx.lookup_word(value));
+    let transfer = match value {
+        Some(value) if !value.is_zero() => CALL_VALUE,
+        _ => 0,
+    };
+    account_access(ctx, addr) + transfer
+}
+
+fn create_cost(ctx: &mut dyn GasCtx, len: ValueId, is_create2: bool) -> u64 {
+    let words = words(ctx.lookup_word(len));
+    let mut cost = CREATE;
+    if ctx.evm_version() >= EvmVersion::Shanghai {
+        cost = cost.saturating_add(INIT_CODE_WORD.saturating_mul(words));
+    }
+    if is_create2 {
+        cost = cost.saturating_add(KECCAK256_WORD.saturating_mul(words));
+    }
+    cost
+}
+
+/// Returns the cost of memory access of `ty`, one `mload`/`mstore` per word.
+fn memory_access_cost(ctx: &mut dyn GasCtx, ty: Type) -> u64 {
+    let words = (ctx.size_of(ty) as u64).div_ceil(32).max(1);
+    VERY_LOW * words
+}
+
+macro_rules! impl_static_gas {
+    ($($ty:ty => $cost:expr,)*) => {
+        $(
+            impl GasCost for $ty {
+                fn gas_cost(&self, _ctx: &mut dyn GasCtx) -> u64 {
+                    $cost
+                }
+            }
+        )*
+    };
+}
+
+impl_static_gas! {
+    Neg => VERY_LOW,
+    Add => VERY_LOW,
+    Mul => LOW,
+    Sub => VERY_LOW,
+    Sdiv => LOW,
+    Udiv => LOW,
+    Umod => LOW,
+    Smod => LOW,
+    Shl => VERY_LOW,
+    Shr => VERY_LOW,
+    Sar => VERY_LOW,
+    // `le`, `ge`, `sle` and `sge` need an additional `iszero`.
+    Lt => VERY_LOW,
+    Gt => VERY_LOW,
+    Slt => VERY_LOW,
+    Sgt => VERY_LOW,
+    Le => 2 * VERY_LOW,
+    Ge => 2 * VERY_LOW,
+    Sle => 2 * VERY_LOW,
+    Sge => 2 * VERY_LOW,
+    Eq => VERY_LOW,
+    Ne => 2 * VERY_LOW,
+    IsZero => VERY_LOW,
+    Not => VERY_LOW,
+    And => VERY_LOW,
+    Or => VERY_LOW,
+    Xor => VERY_LOW,
+    Sext => LOW,
+    Zext => VERY_LOW,
+    Trunc => VERY_LOW,
+    Bitcast => ZERO,
+    IntToPtr => ZERO,
+    PtrToInt => ZERO,
+    GetFunctionPtr => VERY_LOW,
+    Alloca => ZERO,
+    InsertValue => ZERO,
+    ExtractValue => ZERO,
+    Jump => JUMP,
+    Br => JUMPI,
+    Phi => ZERO,
+    Return => JUMP,
+    EvmUdiv => LOW,
+    EvmSdiv => LOW,
+    EvmUmod => LOW,
+    EvmSmod => LOW,
+    EvmStop => ZERO,
+    EvmInvalid => ZERO,
+    EvmAddMod => MID,
+    EvmMulMod => MID,
+    EvmByte => VERY_LOW,
+    EvmAddress => BASE,
+    EvmOrigin => BASE,
+    EvmCaller => BASE,
+    EvmCallValue => BASE,
+    EvmCalldataLoad => VERY_LOW,
+    EvmCalldataSize => BASE,
+    EvmCodeSize => BASE,
+    EvmGasPrice => BASE,
+    EvmReturnDataSize => BASE,
+    EvmBlockHash => BLOCKHASH,
+    EvmCoinBase => BASE,
+    EvmTimestamp => BASE,
+    EvmNumber => BASE,
+    EvmPrevRandao => BASE,
+    EvmGasLimit => BASE,
+    EvmChainId => BASE,
+    EvmSelfBalance => LOW,
+    EvmBaseFee => BASE,
+    EvmBlobHash => VERY_LOW,
+    EvmBlobBaseFee => BASE,
+    EvmMstore8 => VERY_LOW,
+    EvmMsize => BASE,
+    EvmGas => BASE,
+    EvmTload => WARM_ACCESS,
+    EvmTstore => WARM_ACCESS,
+    EvmReturn => ZERO,
+    EvmRevert => ZERO,
+    // Loads the free memory pointer, bumps it and stores it back.
+    EvmMalloc => 3 * VERY_LOW,
+    EvmContractSize => VERY_LOW,
+}
+
+impl GasCost for Mload {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        memory_access_cost(ctx, *self.ty())
+    }
+}
+
+impl GasCost for Mstore {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        memory_access_cost(ctx, *self.ty())
+    }
+}
+
+impl GasCost for Gep {
+    fn gas_cost(&self, _ctx: &mut dyn GasCtx) -> u64 {
+        // Each index needs a multiplication and an addition.
+        let indices = self.values().len() as u64 - 1;
+        (LOW + VERY_LOW) * indices
+    }
+}
+
+impl GasCost for BrTable {
+    fn gas_cost(&self, _ctx: &mut dyn GasCtx) -> u64 {
+        // Lowered to a chain of comparisons followed by a jump to the default.
+        let entries = self.table().len() as u64;
+        (VERY_LOW + JUMPI) * entries + JUMP
+    }
+}
+
+impl GasCost for Call {
+    fn gas_cost(&self, _ctx: &mut dyn GasCtx) -> u64 {
+        INTERNAL_CALL
+    }
+}
+
+impl GasCost for CallIndirect {
+    fn gas_cost(&self, _ctx: &mut dyn GasCtx) -> u64 {
+        // The return address is pushed and the callee is jumped to
+        // dynamically, which costs the same as a direct call.
+        INTERNAL_CALL
+    }
+}
+
+impl GasCost for EvmExp {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        let exponent = ctx.lookup_word(*self.exponent()).unwrap_or_default();
+        let bytes = exponent.bits().div_ceil(8) as u64;
+        EXP + EXP_BYTE * bytes
+    }
+}
+
+impl GasCost for EvmKeccak256 {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        let words = words(ctx.lookup_word(*self.len()));
+        KECCAK256.saturating_add(KECCAK256_WORD.saturating_mul(words))
+    }
+}
+
+impl GasCost for EvmBalance {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        let addr = ctx.lookup_word(*self.contract_addr());
+        account_access(ctx, addr)
+    }
+}
+
+impl GasCost for EvmExtCodeSize {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        let addr = ctx.lookup_word(*self.ext_addr());
+        account_access(ctx, addr)
+    }
+}
+
+impl GasCost for EvmExtCodeHash {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        let addr = ctx.lookup_word(*self.ext_addr());
+        account_access(ctx, addr)
+    }
+}
+
+impl GasCost for EvmExtCodeCopy {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        let addr = ctx.lookup_word(*self.ext_addr());
+        let copy = copy_cost(ctx, *self.len()) - VERY_LOW;
+        account_access(ctx, addr) + copy
+    }
+}
+
+impl GasCost for EvmCalldataCopy {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        copy_cost(ctx, *self.len())
+    }
+}
+
+impl GasCost for EvmCodeCopy {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        copy_cost(ctx, *self.len())
+    }
+}
+
+impl GasCost for EvmReturnDataCopy {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        copy_cost(ctx, *self.len())
+    }
+}
+
+impl GasCost for EvmMcopy {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        copy_cost(ctx, *self.len())
+    }
+}
+
+impl GasCost for EvmSload {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        let version = ctx.evm_version();
+        if version < EvmVersion::Berlin {
+            return if version >= EvmVersion::Istanbul {
+                800
+            } else {
+                200
+            };
+        }
+
+        match ctx.lookup_word(*self.key()) {
+            Some(key) if !ctx.access_slot(key).is_cold => WARM_ACCESS,
+            _ => COLD_SLOAD,
+        }
+    }
+}
+
+impl GasCost for EvmSstore {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        let (Some(key), Some(new)) = (ctx.lookup_word(*self.key()), ctx.lookup_word(*self.val()))
+        else {
+            return SSTORE_SET;
+        };
+        let access = ctx.access_slot(key);
+        let version = ctx.evm_version();
+
+        // Before Istanbul, the cost only depends on the current value.
+        if version < EvmVersion::Istanbul {
+            return if access.current.is_zero() && !new.is_zero() {
+                SSTORE_SET
+            } else {
+                5000
+            };
+        }
+
+        let (warm_read, reset, cold) = if version >= EvmVersion::Berlin {
+            (WARM_ACCESS, 5000 - COLD_SLOAD, COLD_SLOAD)
+        } else {
+            (800, 5000, 0)
+        };
+
+        let cost = if access.current == new || access.original != access.current {
+            warm_read
+        } else if access.original.is_zero() {
+            SSTORE_SET
+        } else {
+            reset
+        };
+
+        if access.is_cold {
+            cost + cold
+        } else {
+            cost
+        }
+    }
+}
+
+impl GasCost for EvmLog0 {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        log_cost(ctx, *self.len(), 0)
+    }
+}
+
+impl GasCost for EvmLog1 {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        log_cost(ctx, *self.len(), 1)
+    }
+}
+
+impl GasCost for EvmLog2 {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        log_cost(ctx, *self.len(), 2)
+    }
+}
+
+impl GasCost for EvmLog3 {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        log_cost(ctx, *self.len(), 3)
+    }
+}
+
+impl GasCost for EvmLog4 {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        log_cost(ctx, *self.len(), 4)
+    }
+}
+
+impl GasCost for EvmCreate {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        create_cost(ctx, *self.len(), false)
+    }
+}
+
+impl GasCost for EvmCreate2 {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        create_cost(ctx, *self.len(), true)
+    }
+}
+
+impl GasCost for EvmCall {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        call_cost(ctx, *self.addr(), Some(*self.val()))
+    }
+}
+
+impl GasCost for EvmCallCode {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        call_cost(ctx, *self.addr(), Some(*self.val()))
+    }
+}
+
+impl GasCost for EvmDelegateCall {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        call_cost(ctx, *self.ext_addr(), None)
+    }
+}
+
+impl GasCost for EvmStaticCall {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        call_cost(ctx, *self.ext_addr(), None)
+    }
+}
+
+impl GasCost for EvmSelfDestruct {
+    fn gas_cost(&self, ctx: &mut dyn GasCtx) -> u64 {
+        if ctx.evm_version() < EvmVersion::Berlin {
+            return SELF_DESTRUCT;
+        }
+
+        let beneficiary = ctx.lookup_word(*self.addr());
+        let is_cold = match beneficiary {
+            Some(addr) => ctx.access_account(addr),
+            None => true,
+        };
+        if is_cold {
+            SELF_DESTRUCT + COLD_ACCOUNT_ACCESS
+        } else {
+            SELF_DESTRUCT
+        }
+    }
+}
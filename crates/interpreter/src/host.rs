@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+
+use sha3::{Digest, Keccak256};
+use sonatina_ir::U256;
+
+/// The environment that the [`Machine`](crate::Machine) executes a message
+/// call in.
+///
+/// A host owns everything that lives outside of a single call frame, i.e.,
+/// accounts, storages, block/transaction context and emitted logs.
+pub trait Host {
+    /// Returns the block and transaction context of the current call.
+    fn context(&self) -> &Context;
+
+    fn calldata(&self) -> &[u8];
+
+    /// Returns the output data of the last message call.
+    fn return_data(&self) -> &[u8];
+
+    fn set_return_data(&mut self, data: Vec<u8>);
+
+    /// Returns the storage value of the current account at `key`.
+    fn sload(&mut self, key: U256) -> U256;
+
+    fn sstore(&mut self, key: U256, value: U256);
+
+    /// Returns the transient storage value of the current account at `key`.
+    fn tload(&mut self, key: U256) -> U256;
+
+    fn tstore(&mut self, key: U256, value: U256);
+
+    fn balance(&self, addr: U256) -> U256;
+
+    /// Returns the code of the account at `addr`. An account without code
+    /// returns an empty slice.
+    fn code(&self, addr: U256) -> &[u8];
+
+    /// Returns the hash of the code of the account at `addr`, or zero if the
+    /// account doesn't exist.
+    fn code_hash(&self, addr: U256) -> U256;
+
+    fn block_hash(&self, number: U256) -> U256;
+
+    fn blob_hash(&self, idx: U256) -> U256;
+
+    fn emit_log(&mut self, log: Log);
+
+    /// Transfers the balance of the current account to `beneficiary`.
+    fn self_destruct(&mut self, beneficiary: U256);
+
+    fn set_code(&mut self, addr: U256, code: Vec<u8>);
+
+    /// Moves `value` from the balance of `from` to `to`, and returns `false`
+    /// without changing any balance if `from` can't afford it.
+    fn transfer(&mut self, from: U256, to: U256, value: U256) -> bool;
+
+    fn nonce(&self, addr: U256) -> u64;
+
+    fn increment_nonce(&mut self, addr: U256);
+
+    /// Starts a nested message call in `context`.
+    ///
+    /// The storage, the transient storage and the calldata are switched to
+    /// the ones of the callee, and the return data is cleared. Everything is
+    /// restored by [`Host::exit_call`].
+    fn enter_call(&mut self, context: Context, calldata: Vec<u8>);
+
+    /// Finishes the nested message call started by the last
+    /// [`Host::enter_call`].
+    fn exit_call(&mut self);
+
+    /// Records the current state so that it can be restored by
+    /// [`Host::revert`]. Checkpoints can be nested.
+    fn checkpoint(&mut self);
+
+    /// Discards the last checkpoint and keeps all changes made after it.
+    fn commit(&mut self);
+
+    /// Restores the state recorded by the last checkpoint.
+    fn revert(&mut self);
+}
+
+/// Block and transaction context of a message call.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Context {
+    pub address: U256,
+    pub origin: U256,
+    pub caller: U256,
+    pub call_value: U256,
+    pub gas_price: U256,
+    pub coinbase: U256,
+    pub timestamp: U256,
+    pub number: U256,
+    pub prev_randao: U256,
+    pub gas_limit: U256,
+    pub chain_id: U256,
+    pub base_fee: U256,
+    pub blob_base_fee: U256,
+    pub gas: U256,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Log {
+    pub address: U256,
+    pub topics: Vec<U256>,
+    pub data: Vec<u8>,
+}
+
+/// A [`Host`] that keeps the whole world state in memory.
+///
+/// All fields are public so that tests can populate the state before running
+/// a function and inspect it afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryHost {
+    pub context: Context,
+    pub calldata: Vec<u8>,
+    pub return_data: Vec<u8>,
+    /// The storage of the current account. Slots holding zero are removed
+    /// since they can't be distinguished from the unwritten ones.
+    pub storage: HashMap<U256, U256>,
+    /// The transient storage of the current account.
+    pub transient_storage: HashMap<U256, U256>,
+    /// The storages of the accounts other than the current one.
+    pub storages: HashMap<U256, HashMap<U256, U256>>,
+    /// The transient storages of the accounts other than the current one.
+    pub transient_storages: HashMap<U256, HashMap<U256, U256>>,
+    pub balances: HashMap<U256, U256>,
+    pub codes: HashMap<U256, Vec<u8>>,
+    pub nonces: HashMap<U256, u64>,
+    pub block_hashes: HashMap<U256, U256>,
+    pub blob_hashes: Vec<U256>,
+    /// Logs emitted in the order of emission.
+    pub logs: Vec<Log>,
+    /// `sstore` writes in the order of execution.
+    pub storage_writes: Vec<(U256, U256)>,
+    checkpoints: Vec<Checkpoint>,
+    callers: Vec<Caller>,
+}
+
+#[derive(Debug, Clone)]
+struct Checkpoint {
+    storage: HashMap<U256, U256>,
+    transient_storage: HashMap<U256, U256>,
+    storages: HashMap<U256, HashMap<U256, U256>>,
+    transient_storages: HashMap<U256, HashMap<U256, U256>>,
+    balances: HashMap<U256, U256>,
+    codes: HashMap<U256, Vec<u8>>,
+    nonces: HashMap<U256, u64>,
+    log_len: usize,
+    storage_write_len: usize,
+}
+
+/// The state of the caller that is restored by [`Host::exit_call`].
+#[derive(Debug, Clone)]
+struct Caller {
+    context: Context,
+    calldata: Vec<u8>,
+    return_data: Vec<u8>,
+}
+
+impl InMemoryHost {
+    pub fn new(context: Context) -> Self {
+        Self {
+            context,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_calldata(mut self, calldata: Vec<u8>) -> Self {
+        self.calldata = calldata;
+        self
+    }
+
+    /// Returns the storage of the account at `addr`.
+    pub fn account_storage(&self, addr: U256) -> Option<&HashMap<U256, U256>> {
+        if addr == self.context.address {
+            Some(&self.storage)
+        } else {
+            self.storages.get(&addr)
+        }
+    }
+
+    /// Makes `to` the current account by swapping the storages of `from` and
+    /// `to`.
+    fn switch_account(&mut self, from: U256, to: U256) {
+        if from == to {
+            return;
+        }
+
+        let storage = self.storages.remove(&to).unwrap_or_default();
+        let storage = std::mem::replace(&mut self.storage, storage);
+        if !storage.is_empty() {
+            self.storages.insert(from, storage);
+        }
+
+        let storage = self.transient_storages.remove(&to).unwrap_or_default();
+        let storage = std::mem::replace(&mut self.transient_storage, storage);
+        if !storage.is_empty() {
+            self.transient_storages.insert(from, storage);
+        }
+    }
+}
+
+/// Writes `value` to `storage`, removing the slot if `value` is zero.
+fn store(storage: &mut HashMap<U256, U256>, key: U256, value: U256) {
+    if value.is_zero() {
+        storage.remove(&key);
+    } else {
+        storage.insert(key, value);
+    }
+}
+
+impl Host for InMemoryHost {
+    fn context(&self) -> &Context {
+        &self.context
+    }
+
+    fn calldata(&self) -> &[u8] {
+        &self.calldata
+    }
+
+    fn return_data(&self) -> &[u8] {
+        &self.return_data
+    }
+
+    fn set_return_data(&mut self, data: Vec<u8>) {
+        self.return_data = data;
+    }
+
+    fn sload(&mut self, key: U256) -> U256 {
+        self.storage.get(&key).copied().unwrap_or_default()
+    }
+
+    fn sstore(&mut self, key: U256, value: U256) {
+        store(&mut self.storage, key, value);
+        self.storage_writes.push((key, value));
+    }
+
+    fn tload(&mut self, key: U256) -> U256 {
+        self.transient_storage
+            .get(&key)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn tstore(&mut self, key: U256, value: U256) {
+        store(&mut self.transient_storage, key, value);
+    }
+
+    fn balance(&self, addr: U256) -> U256 {
+        self.balances.get(&addr).copied().unwrap_or_default()
+    }
+
+    fn code(&self, addr: U256) -> &[u8] {
+        self.codes.get(&addr).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    fn code_hash(&self, addr: U256) -> U256 {
+        let exists = self.codes.contains_key(&addr) || self.balances.contains_key(&addr);
+        if !exists {
+            return U256::zero();
+        }
+
+        let hash = Keccak256::digest(self.code(addr));
+        U256::from_big_endian(&hash)
+    }
+
+    fn block_hash(&self, number: U256) -> U256 {
+        self.block_hashes.get(&number).copied().unwrap_or_default()
+    }
+
+    fn blob_hash(&self, idx: U256) -> U256 {
+        if idx >= U256::from(self.blob_hashes.len()) {
+            return U256::zero();
+        }
+        self.blob_hashes[idx.as_usize()]
+    }
+
+    fn emit_log(&mut self, log: Log) {
+        self.logs.push(log);
+    }
+
+    fn self_destruct(&mut self, beneficiary: U256) {
+        let addr = self.context.address;
+        let balance = self.balances.remove(&addr).unwrap_or_default();
+        *self.balances.entry(beneficiary).or_default() += balance;
+    }
+
+    fn set_code(&mut self, addr: U256, code: Vec<u8>) {
+        self.codes.insert(addr, code);
+    }
+
+    fn transfer(&mut self, from: U256, to: U256, value: U256) -> bool {
+        if self.balance(from) < value {
+            return false;
+        }
+        if value.is_zero() || from == to {
+            return true;
+        }
+
+        *self.balances.get_mut(&from).unwrap() -= value;
+        *self.balances.entry(to).or_default() += value;
+        true
+    }
+
+    fn nonce(&self, addr: U256) -> u64 {
+        self.nonces.get(&addr).copied().unwrap_or_default()
+    }
+
+    fn increment_nonce(&mut self, addr: U256) {
+        *self.nonces.entry(addr).or_default() += 1;
+    }
+
+    fn enter_call(&mut self, context: Context, calldata: Vec<u8>) {
+        let caller = Caller {
+            context: std::mem::replace(&mut self.context, context),
+            calldata: std::mem::replace(&mut self.calldata, calldata),
+            return_data: std::mem::take(&mut self.return_data),
+        };
+        self.switch_account(caller.context.address, self.context.address);
+        self.callers.push(caller);
+    }
+
+    fn exit_call(&mut self) {
+        let caller = self.callers.pop().unwrap();
+        self.switch_account(self.context.address, caller.context.address);
+        self.context = caller.context;
+        self.calldata = caller.calldata;
+        self.return_data = caller.return_data;
+    }
+
+    fn checkpoint(&mut self) {
+        self.checkpoints.push(Checkpoint {
+            storage: self.storage.clone(),
+            transient_storage: self.transient_storage.clone(),
+            storages: self.storages.clone(),
+            transient_storages: self.transient_storages.clone(),
+            balances: self.balances.clone(),
+            codes: self.codes.clone(),
+            nonces: self.nonces.clone(),
+            log_len: self.logs.len(),
+            storage_write_len: self.storage_writes.len(),
+        });
+    }
+
+    fn commit(&mut self) {
+        self.checkpoints.pop().unwrap();
+    }
+
+    fn revert(&mut self) {
+        let checkpoint = self.checkpoints.pop().unwrap();
+        self.storage = checkpoint.storage;
+        self.transient_storage = checkpoint.transient_storage;
+        self.storages = checkpoint.storages;
+        self.transient_storages = checkpoint.transient_storages;
+        self.balances = checkpoint.balances;
+        self.codes = checkpoint.codes;
+        self.nonces = checkpoint.nonces;
+        self.logs.truncate(checkpoint.log_len);
+        self.storage_writes.truncate(checkpoint.storage_write_len);
+    }
+}
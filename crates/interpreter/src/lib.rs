@@ -1,25 +1,66 @@
-use cranelift_entity::SecondaryMap;
+use cranelift_entity::{EntityRef, SecondaryMap};
 use sonatina_ir::{
-    interpret::{Action, EvalValue, Interpret, State},
+    interpret::{
+        Action, CallKind, DataSource, EnvQuery, EvalValue, ExtCall, HaltReason, Interpret, State,
+        MEMORY_LIMIT,
+    },
     isa::Endian,
     module::{FuncRef, ModuleCtx, RoFuncStore},
     prelude::*,
     types::CompoundType,
-    BlockId, DataFlowGraph, Function, Immediate, InstId, Module, Type, Value, ValueId, I256,
+    BlockId, DataFlowGraph, Function, Immediate, InstId, Module, Type, Value, ValueId, I256, U256,
 };
 
-pub struct Machine {
+use sonatina_triple::{EvmVersion, OperatingSystem};
+
+pub mod contract;
+pub mod gas;
+pub mod host;
+pub mod memory;
+pub mod trace;
+pub mod ub;
+
+pub use contract::contract_code;
+pub use gas::GasMeter;
+use gas::{GasCost, GasCtx, SlotAccess};
+pub use host::{Context, Host, InMemoryHost, Log};
+pub use memory::{Allocator, Memory};
+pub use trace::{DebugCommand, Debugger, FrameView, Observer, Tracer};
+use ub::{UbCheck, UbCtx};
+pub use ub::{UbKind, UndefUse, UndefinedBehavior};
+
+/// The maximum depth of nested message calls.
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+pub struct Machine<H = InMemoryHost> {
     frames: Vec<Frame>,
     pc: InstId,
     action: Action,
     pub funcs: RoFuncStore,
     pub module_ctx: ModuleCtx,
-    memory: Vec<u8>,
-    free_region: usize,
+    memory: Memory,
+    host: H,
+    gas: Option<GasMeter>,
+    observer: Option<Box<dyn Observer>>,
+    strict: bool,
+    ub: Option<UndefinedBehavior>,
+    /// The code being executed if it's not the code of the current account,
+    /// i.e., in `evm_delegate_call`, `evm_call_code` and contract creation.
+    code: Option<Vec<u8>>,
+    /// `true` if the state can't be modified in the current message call.
+    is_static: bool,
+    /// The depth of nested message calls.
+    call_depth: usize,
 }
 
 impl Machine {
     pub fn new(module: Module) -> Self {
+        Self::with_host(module, InMemoryHost::default())
+    }
+}
+
+impl<H: Host> Machine<H> {
+    pub fn with_host(module: Module, host: H) -> Self {
         Self {
             frames: Vec::new(),
             // Dummy pc
@@ -27,22 +68,269 @@ impl Machine {
             action: Action::Continue,
             funcs: module.func_store.into_read_only(),
             module_ctx: module.ctx,
-            memory: Vec::new(),
-            free_region: 0,
+            memory: Memory::default(),
+            host,
+            gas: None,
+            observer: None,
+            strict: false,
+            ub: None,
+            code: None,
+            is_static: false,
+            call_depth: 0,
+        }
+    }
+
+    pub fn host(&self) -> &H {
+        &self.host
+    }
+
+    pub fn host_mut(&mut self) -> &mut H {
+        &mut self.host
+    }
+
+    /// Enables gas metering with the gas schedule of the EVM version of the
+    /// module's target triple.
+    pub fn enable_gas_metering(&mut self) {
+        let OperatingSystem::Evm(evm_version) = self.module_ctx.triple.operating_system;
+        self.enable_gas_metering_with(evm_version);
+    }
+
+    /// Enables gas metering with the gas schedule of `evm_version`.
+    pub fn enable_gas_metering_with(&mut self, evm_version: EvmVersion) {
+        self.gas = Some(GasMeter::new(evm_version));
+    }
+
+    /// Sets the gas limit of a message call, enabling gas metering if it's
+    /// disabled. The execution halts with [`HaltReason::OutOfGas`] when the
+    /// limit is exceeded.
+    pub fn set_gas_limit(&mut self, limit: u64) {
+        if self.gas.is_none() {
+            self.enable_gas_metering();
+        }
+        self.gas.as_mut().unwrap().set_limit(Some(limit));
+    }
+
+    /// Returns the gas used by the last [`Machine::run`], or `None` if gas
+    /// metering is disabled.
+    pub fn gas_used(&self) -> Option<u64> {
+        self.gas.as_ref().map(GasMeter::used)
+    }
+
+    /// Deploys the contract whose entry function is `func` at `addr`, so that
+    /// the contract is executed when `addr` is called.
+    pub fn deploy(&mut self, addr: U256, func: FuncRef) {
+        self.host.set_code(addr, contract_code(func));
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Sets the allocator used by `alloca` and `evm_malloc`, and clears the
+    /// memory.
+    pub fn set_allocator(&mut self, allocator: Allocator) {
+        self.memory = Memory::new(allocator);
+    }
+
+    /// Enables the strict mode, where undefined behavior stops the execution
+    /// with [`ExecResult::UndefinedBehavior`] instead of silently propagating
+    /// `undef`. See [`ub`] for the detected behavior.
+    pub fn enable_strict_mode(&mut self) {
+        self.strict = true;
+    }
+
+    /// Sets `observer` to be notified of the execution, replacing the current
+    /// one.
+    pub fn set_observer(&mut self, observer: impl Observer) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// Removes the observer and returns it.
+    pub fn take_observer(&mut self) -> Option<Box<dyn Observer>> {
+        self.observer.take()
+    }
+
+    /// Returns the observer if it is of type `O`.
+    pub fn observer<O: Observer>(&self) -> Option<&O> {
+        self.observer.as_deref()?.as_any().downcast_ref()
+    }
+
+    /// Returns the observer if it is of type `O`.
+    pub fn observer_mut<O: Observer>(&mut self) -> Option<&mut O> {
+        self.observer.as_deref_mut()?.as_any_mut().downcast_mut()
+    }
+
+    /// Runs `func_ref` with `args` as a single message call.
+    ///
+    /// If the execution is reverted, all changes made to the host during the
+    /// execution are rolled back.
+    pub fn run(&mut self, func_ref: FuncRef, args: Vec<EvalValue>) -> ExecResult {
+        if let Some(gas) = &mut self.gas {
+            // The current account, the sender and the origin are always warm.
+            let ctx = self.host.context();
+            gas.reset(self.memory.size(), [ctx.address, ctx.caller, ctx.origin]);
         }
+
+        self.host.checkpoint();
+        let result = self.execute(func_ref, args);
+        self.finish(&result);
+        result
     }
 
-    pub fn run(&mut self, func_ref: FuncRef, args: Vec<EvalValue>) -> EvalValue {
+    /// Clears the execution state of the machine.
+    ///
+    /// NOTE: The state of the host is kept as is.
+    pub fn clear_state(&mut self) {
+        self.frames.clear();
+        self.memory.clear();
+        self.ub = None;
+    }
+
+    /// Executes `func_ref` until it returns or the message call halts.
+    fn execute(&mut self, func_ref: FuncRef, args: Vec<EvalValue>) -> ExecResult {
         let func = self.funcs.get(&func_ref).unwrap();
         let frame = Frame::new(func_ref, func, args);
+        let depth = self.frames.len();
         self.frames.push(frame);
         self.action = Action::Continue;
-        self.run_on_func()
+        self.notify(|observer, frame| observer.on_call(frame));
+
+        let value = self.run_on_func();
+
+        // Unwind all frames of the message call, a halt can happen at any depth.
+        self.frames.truncate(depth);
+
+        let action = std::mem::replace(&mut self.action, Action::Continue);
+        match (self.ub.take(), action) {
+            (Some(ub), _) => ExecResult::UndefinedBehavior(ub),
+            (None, Action::Halt(reason)) => ExecResult::Halt(reason),
+            (None, _) => ExecResult::Value(value),
+        }
     }
 
-    pub fn clear_state(&mut self) {
-        self.frames.clear();
-        self.memory.clear();
+    /// Applies `result` of the current message call to the host, and discards
+    /// the checkpoint of the message call.
+    fn finish(&mut self, result: &ExecResult) {
+        match result {
+            _ if result.is_reverted() => {
+                self.host.revert();
+            }
+
+            ExecResult::Halt(HaltReason::SelfDestruct(beneficiary)) => {
+                if let Some(beneficiary) = as_word(beneficiary) {
+                    self.host.self_destruct(beneficiary);
+                }
+                self.host.commit();
+            }
+
+            _ => self.host.commit(),
+        }
+    }
+
+    /// Executes `msg` as a nested message call with its own memory.
+    fn message_call(&mut self, msg: Message) -> ExecResult {
+        let from = self.host.context().address;
+        let to = msg.context.address;
+        self.host.enter_call(msg.context, msg.input);
+        self.host.checkpoint();
+        let transferred = self.host.transfer(from, to, msg.value);
+        debug_assert!(transferred, "the balance must be checked by the caller");
+        if msg.is_create {
+            self.host.increment_nonce(to);
+        }
+
+        let memory = Memory::new(self.memory.allocator());
+        if let Some(gas) = &mut self.gas {
+            gas.switch_memory(memory.size());
+        }
+        let caller_memory = std::mem::replace(&mut self.memory, memory);
+        let caller_pc = self.pc;
+        let caller_action = std::mem::replace(&mut self.action, Action::Continue);
+        let caller_is_static = std::mem::replace(&mut self.is_static, msg.is_static);
+        let caller_code = if msg.is_create || msg.delegated {
+            self.code.replace(msg.code.clone())
+        } else {
+            self.code.take()
+        };
+        self.call_depth += 1;
+
+        let result = if msg.code.is_empty() {
+            ExecResult::Halt(HaltReason::Stop)
+        } else {
+            match contract::entry_func(&msg.code).filter(|func| self.funcs.contains_key(func)) {
+                Some(func) => {
+                    let arg_len = self.funcs.get(&func).unwrap().arg_values.len();
+                    self.execute(func, vec![EvalValue::Undef; arg_len])
+                }
+                None => ExecResult::Halt(HaltReason::Invalid),
+            }
+        };
+
+        // The output of the init code is the code of the created account.
+        if msg.is_create && !result.is_reverted() {
+            self.host.set_code(to, result.output().to_vec());
+        }
+        self.finish(&result);
+        self.host.exit_call();
+
+        self.call_depth -= 1;
+        self.code = caller_code;
+        self.is_static = caller_is_static;
+        self.action = caller_action;
+        self.pc = caller_pc;
+        let memory = std::mem::replace(&mut self.memory, caller_memory);
+        if let Some(gas) = &mut self.gas {
+            gas.switch_memory(memory.size());
+        }
+        // Stop the caller too, undefined behavior is reported by `run`.
+        if let ExecResult::UndefinedBehavior(ub) = &result {
+            self.ub = Some(ub.clone());
+        }
+
+        result
+    }
+
+    /// Returns the code being executed.
+    fn current_code(&self) -> &[u8] {
+        match &self.code {
+            Some(code) => code,
+            None => self.host.code(self.host.context().address),
+        }
+    }
+
+    /// Halts the current message call if the state can't be modified, and
+    /// returns `true` in that case.
+    fn check_write_protection(&mut self) -> bool {
+        if self.is_static {
+            self.action = Action::Halt(HaltReason::WriteProtection);
+        }
+        self.is_static
+    }
+
+    /// Converts `addr` into a memory address. Halts the current message call
+    /// and returns `None` if `size` bytes at the address exceed
+    /// [`MEMORY_LIMIT`].
+    fn memory_addr(&mut self, addr: Immediate, size: usize) -> Option<usize> {
+        let addr = addr.as_i256().to_u256();
+        let end = addr.checked_add(U256::from(size));
+        if end.is_some_and(|end| end <= U256::from(MEMORY_LIMIT)) {
+            Some(addr.as_usize())
+        } else {
+            self.action = Action::Halt(HaltReason::OutOfGas);
+            None
+        }
+    }
+
+    /// Allocates `size` bytes of memory. Halts the current message call if
+    /// the allocated region exceeds [`MEMORY_LIMIT`].
+    fn alloc(&mut self, size: usize) -> EvalValue {
+        match self.memory.alloc(size) {
+            Some(ptr) => EvalValue::Imm(Immediate::I256(I256::from(ptr))),
+            None => {
+                self.action = Action::Halt(HaltReason::OutOfGas);
+                EvalValue::Undef
+            }
+        }
     }
 
     fn top_frame(&self) -> &Frame {
@@ -57,13 +345,34 @@ impl Machine {
         self.funcs.get(&self.top_frame().func).unwrap()
     }
 
+    /// Notifies the observer of an event in the top frame.
+    fn notify(&mut self, f: impl FnOnce(&mut dyn Observer, &FrameView)) {
+        let Some(observer) = self.observer.as_deref_mut() else {
+            return;
+        };
+
+        let frame = self.frames.last().unwrap();
+        let view = FrameView {
+            func_ref: frame.func,
+            func: self.funcs.get(&frame.func).unwrap(),
+            locals: &frame.locals,
+            prev_block: frame.prev_block,
+            depth: self.frames.len() - 1,
+        };
+        f(observer, &view);
+    }
+
     fn run_on_func(&mut self) -> EvalValue {
         let layout = &self.top_func().layout;
         let entry_block = layout.entry_block().unwrap();
         let first_inst = layout.first_inst_of(entry_block).unwrap();
         self.pc = first_inst;
+        self.notify(|observer, frame| observer.on_block(frame, entry_block));
 
         loop {
+            let pc = self.pc;
+            self.notify(|observer, frame| observer.on_inst(frame, pc));
+
             let inst = self.top_func().dfg.inst(self.pc);
             let inst = dyn_clone::clone_box(inst);
             let Some(interpretable): Option<&dyn Interpret> =
@@ -72,9 +381,56 @@ impl Machine {
                 panic!("`Intepret is not yet implemented for `{}`", inst.as_text());
             };
 
+            if self.gas.is_some() {
+                let cost: &dyn GasCost =
+                    InstDowncast::downcast(self.top_func().inst_set(), inst.as_ref()).unwrap();
+                let cost = cost.gas_cost(self);
+                let gas = self.gas.as_mut().unwrap();
+                gas.charge(cost);
+
+                if gas.is_out_of_gas() {
+                    self.action = Action::Halt(HaltReason::OutOfGas);
+                    self.notify(|observer, frame| observer.on_halt(frame, &HaltReason::OutOfGas));
+                    return EvalValue::Undef;
+                }
+            }
+
+            if self.strict {
+                let check: Option<&dyn UbCheck> =
+                    InstDowncast::downcast(self.top_func().inst_set(), inst.as_ref());
+                if let Some(Err(kind)) = check.map(|check| check.check_ub(self)) {
+                    let func = self.top_frame().func;
+                    self.ub = Some(UndefinedBehavior {
+                        func,
+                        inst: pc,
+                        kind,
+                    });
+                    return EvalValue::Undef;
+                }
+            }
+
             let e_val = interpretable.interpret(self);
+            // Undefined behavior is detected in a callee.
+            if self.ub.is_some() {
+                return EvalValue::Undef;
+            }
+            // Memory expanded by the instruction is charged afterwards, which
+            // may exceed the limit even if the instruction halted.
+            if let Some(gas) = &mut self.gas {
+                gas.charge_memory(self.memory.size());
+
+                if gas.is_out_of_gas() {
+                    self.action = Action::Halt(HaltReason::OutOfGas);
+                    self.notify(|observer, frame| observer.on_halt(frame, &HaltReason::OutOfGas));
+                    return EvalValue::Undef;
+                }
+            }
             if let Some(inst_result) = self.top_func().dfg.inst_result(self.pc) {
                 self.top_frame_mut().map_val(inst_result, e_val);
+                self.notify(|observer, frame| {
+                    let e_val = frame.value(inst_result);
+                    observer.on_value(frame, pc, inst_result, &e_val)
+                });
             };
 
             match self.action.clone() {
@@ -86,18 +442,87 @@ impl Machine {
                     let current_block = self.top_func().layout.inst_block(self.pc);
                     self.top_frame_mut().prev_block = Some(current_block);
                     self.pc = self.top_func().layout.first_inst_of(next_block).unwrap();
+                    self.notify(|observer, frame| observer.on_block(frame, next_block));
                 }
 
                 Action::FallThrough => {
                     panic!("fall through detected!")
                 }
 
-                Action::Return(e_val) => return e_val,
+                Action::Return(e_val) => {
+                    self.notify(|observer, frame| observer.on_return(frame, &e_val));
+                    return e_val;
+                }
+
+                Action::Halt(mut reason) => {
+                    if self.is_static && matches!(reason, HaltReason::SelfDestruct(_)) {
+                        reason = HaltReason::WriteProtection;
+                        self.action = Action::Halt(reason.clone());
+                    }
+                    self.notify(|observer, frame| observer.on_halt(frame, &reason));
+                    return EvalValue::Undef;
+                }
             }
         }
     }
 }
 
+/// The result of [`Machine::run`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecResult {
+    /// The function returned with the `return` instruction.
+    Value(EvalValue),
+    /// The message call was terminated by an EVM instruction, e.g.,
+    /// `evm_return` or `evm_revert`.
+    Halt(HaltReason),
+    /// Undefined behavior is detected in the strict mode.
+    UndefinedBehavior(UndefinedBehavior),
+}
+
+impl ExecResult {
+    /// Returns the value returned by the function, or `Undef` if the execution
+    /// was halted.
+    pub fn into_value(self) -> EvalValue {
+        match self {
+            Self::Value(value) => value,
+            Self::Halt(_) | Self::UndefinedBehavior(_) => EvalValue::Undef,
+        }
+    }
+
+    /// Returns the output data of `evm_return` or `evm_revert`.
+    pub fn output(&self) -> &[u8] {
+        match self {
+            Self::Halt(HaltReason::Return(data) | HaltReason::Revert(data)) => data,
+            _ => &[],
+        }
+    }
+
+    /// Returns `true` if all changes made to the host during the execution
+    /// are rolled back.
+    pub fn is_reverted(&self) -> bool {
+        match self {
+            Self::Value(_) => false,
+            Self::Halt(reason) => reason.is_reverted(),
+            Self::UndefinedBehavior(_) => true,
+        }
+    }
+}
+
+/// A nested message call performed by [`Machine::message_call`].
+struct Message {
+    context: Context,
+    input: Vec<u8>,
+    /// The code to execute.
+    code: Vec<u8>,
+    /// The value transferred from the caller to `context.address`.
+    value: U256,
+    is_static: bool,
+    /// `true` if `code` is not the code of `context.address`.
+    delegated: bool,
+    /// `true` if `code` is init code that creates `context.address`.
+    is_create: bool,
+}
+
 pub struct Frame {
     func: FuncRef,
     locals: SecondaryMap<ValueId, EvalValue>,
@@ -127,7 +552,7 @@ impl Frame {
     }
 }
 
-impl State for Machine {
+impl<H: Host> State for Machine<H> {
     fn lookup_val(&mut self, value_id: ValueId) -> EvalValue {
         let value = self.top_func().dfg.value(value_id);
         match value {
@@ -145,11 +570,16 @@ impl State for Machine {
         let func = self.funcs.get(&func_ref).unwrap();
         let new_frame = Frame::new(func_ref, func, args);
         self.frames.push(new_frame);
+        self.notify(|observer, frame| observer.on_call(frame));
 
         let result = self.run_on_func();
 
         self.frames.pop();
         self.pc = ret_addr;
+        // Keep `Halt` so that the caller also stops the execution.
+        if !matches!(self.action, Action::Halt(_)) {
+            self.action = Action::Continue;
+        }
 
         result
     }
@@ -167,14 +597,12 @@ impl State for Machine {
         let Some(addr) = addr.as_imm() else {
             return EvalValue::Undef;
         };
-
-        let addr = addr.as_usize();
         let size = self.module_ctx.size_of_unchecked(ty);
-        if addr + size > self.memory.len() {
+        let Some(addr) = self.memory_addr(addr, size) else {
             return EvalValue::Undef;
-        }
+        };
 
-        // Store a value of aggregate type.
+        // Load a value of aggregate type.
         if !(ty.is_integral() || ty.is_pointer(&self.module_ctx)) {
             let mut fields = Vec::new();
 
@@ -212,14 +640,20 @@ impl State for Machine {
             return EvalValue::Aggregate { fields, ty };
         }
 
-        // Store a value of integer/pointer type.
-        let slice = &self.memory[addr..addr + size];
+        // Load a value of integer/pointer type.
+        let slice = self.memory.read(addr, size);
         let value_i256 = match self.module_ctx.endian() {
             Endian::Be => I256::from_be_bytes(slice),
             Endian::Le => I256::from_le_bytes(slice),
         };
 
-        let imm = Immediate::from_i256(value_i256, ty);
+        // Pointers are represented as integers of the pointer size.
+        let imm_ty = if ty.is_pointer(&self.module_ctx) {
+            self.module_ctx.type_layout.pointer_repl()
+        } else {
+            ty
+        };
+        let imm = Immediate::from_i256(value_i256, imm_ty);
         EvalValue::Imm(imm)
     }
 
@@ -231,11 +665,10 @@ impl State for Machine {
         let Some(addr) = addr.as_imm() else {
             panic!("udnef address in store")
         };
-        let addr = addr.as_usize();
         let size = self.module_ctx.size_of_unchecked(ty);
-        if addr + size > self.memory.len() {
-            self.memory.resize(addr + size, 0);
-        }
+        let Some(addr) = self.memory_addr(addr, size) else {
+            return EvalValue::Undef;
+        };
 
         // Store a value of aggregate type.
         if !(ty.is_integral() || ty.is_pointer(&self.module_ctx)) {
@@ -248,7 +681,7 @@ impl State for Machine {
                     let elem_size = self.module_ctx.size_of_unchecked(elem_ty);
                     for field in &fields {
                         let elem_addr = EvalValue::Imm(Immediate::I256(I256::from(addr)));
-                        self.store(field.clone(), elem_addr, elem_ty);
+                        self.store(elem_addr, field.clone(), elem_ty);
                         addr += elem_size;
                     }
                 }
@@ -257,7 +690,7 @@ impl State for Machine {
                     let mut addr = addr;
                     for (i, field_ty) in s.fields.into_iter().enumerate() {
                         let elem_addr = EvalValue::Imm(Immediate::I256(I256::from(addr)));
-                        self.store(fields[i].clone(), elem_addr, field_ty);
+                        self.store(elem_addr, fields[i].clone(), field_ty);
                         addr += self.module_ctx.size_of_unchecked(field_ty);
                     }
                 }
@@ -279,18 +712,15 @@ impl State for Machine {
             panic!("undef value in store");
         };
 
+        let v = value.as_i256().to_u256();
         match self.module_ctx.endian() {
             Endian::Be => {
-                let v = value.as_i256().to_u256();
                 let bytes = v.to_big_endian();
-                let slice = &bytes[bytes.len() - size..];
-                self.memory[addr..addr + size].copy_from_slice(slice);
+                self.memory.write(addr, &bytes[bytes.len() - size..]);
             }
             Endian::Le => {
-                let v = value.as_i256().to_u256();
                 let bytes = v.to_little_endian();
-                let slice = &bytes[..size];
-                self.memory[addr..addr + size].copy_from_slice(slice);
+                self.memory.write(addr, &bytes[..size]);
             }
         }
 
@@ -298,12 +728,327 @@ impl State for Machine {
     }
 
     fn alloca(&mut self, ty: Type) -> EvalValue {
-        let ptr = self.free_region;
-        self.free_region += self.module_ctx.size_of_unchecked(ty);
-        EvalValue::Imm(Immediate::I256(I256::from(ptr)))
+        self.alloc(self.module_ctx.size_of_unchecked(ty))
+    }
+
+    /// Function pointers are the indices of the functions plus one, so that a
+    /// null pointer never points to a function.
+    fn func_ptr(&mut self, func: FuncRef) -> EvalValue {
+        let ptr_ty = self.module_ctx.type_layout.pointer_repl();
+        let addr = Immediate::from_i256(I256::from(func.index() + 1), ptr_ty);
+        EvalValue::Imm(addr)
+    }
+
+    fn resolve_func_ptr(&mut self, ptr: EvalValue) -> Option<FuncRef> {
+        let ptr = as_word(&ptr)?;
+        if ptr.is_zero() || ptr > U256::from(u32::MAX) {
+            return None;
+        }
+
+        let func = FuncRef::from_u32(ptr.low_u32() - 1);
+        self.funcs.contains_key(&func).then_some(func)
     }
 
     fn dfg(&self) -> &DataFlowGraph {
         &self.top_func().dfg
     }
+
+    fn malloc(&mut self, size: EvalValue) -> EvalValue {
+        let Some(size) = size.as_imm() else {
+            return EvalValue::Undef;
+        };
+
+        // A size that doesn't fit in `usize` never fits in the memory.
+        let size = size.as_i256().to_u256();
+        let size = if size > U256::from(usize::MAX) {
+            usize::MAX
+        } else {
+            size.as_usize()
+        };
+        self.alloc(size)
+    }
+
+    fn msize(&mut self) -> EvalValue {
+        EvalValue::Imm(Immediate::I256(I256::from(self.memory.size())))
+    }
+
+    fn env(&mut self, query: EnvQuery) -> EvalValue {
+        let host = &self.host;
+        let ctx = host.context();
+        let value = match query {
+            EnvQuery::Address => Some(ctx.address),
+            EnvQuery::Origin => Some(ctx.origin),
+            EnvQuery::Caller => Some(ctx.caller),
+            EnvQuery::CallValue => Some(ctx.call_value),
+            EnvQuery::GasPrice => Some(ctx.gas_price),
+            EnvQuery::Timestamp => Some(ctx.timestamp),
+            EnvQuery::Number => Some(ctx.number),
+            EnvQuery::PrevRandao => Some(ctx.prev_randao),
+            EnvQuery::GasLimit => Some(ctx.gas_limit),
+            EnvQuery::ChainId => Some(ctx.chain_id),
+            EnvQuery::BaseFee => Some(ctx.base_fee),
+            EnvQuery::BlobBaseFee => Some(ctx.blob_base_fee),
+            EnvQuery::Gas => Some(ctx.gas),
+            // The coinbase doesn't depend on the operand.
+            EnvQuery::CoinBase(_) => Some(ctx.coinbase),
+            EnvQuery::CalldataSize => Some(host.calldata().len().into()),
+            EnvQuery::CodeSize => Some(self.current_code().len().into()),
+            EnvQuery::ReturnDataSize => Some(host.return_data().len().into()),
+            EnvQuery::SelfBalance => Some(host.balance(ctx.address)),
+            EnvQuery::Balance(addr) => as_word(&addr).map(|addr| host.balance(addr)),
+            EnvQuery::ExtCodeSize(addr) => as_word(&addr).map(|addr| host.code(addr).len().into()),
+            EnvQuery::ExtCodeHash(addr) => as_word(&addr).map(|addr| host.code_hash(addr)),
+            EnvQuery::BlockHash(num) => as_word(&num).map(|num| host.block_hash(num)),
+            EnvQuery::BlobHash(idx) => as_word(&idx).map(|idx| host.blob_hash(idx)),
+        };
+
+        value.map_or(EvalValue::Undef, word)
+    }
+
+    fn env_data(&mut self, src: DataSource, offset: usize, len: usize) -> Vec<u8> {
+        let data = match src {
+            DataSource::Calldata => self.host.calldata(),
+            DataSource::Code => self.current_code(),
+            DataSource::ExtCode(addr) => match as_word(&addr) {
+                Some(addr) => self.host.code(addr),
+                None => &[],
+            },
+            DataSource::ReturnData => self.host.return_data(),
+        };
+
+        let mut bytes = vec![0; len];
+        if offset < data.len() {
+            let end = data.len().min(offset.saturating_add(len));
+            bytes[..end - offset].copy_from_slice(&data[offset..end]);
+        }
+        bytes
+    }
+
+    fn sload(&mut self, key: EvalValue) -> EvalValue {
+        word(self.host.sload(word_or_zero(&key)))
+    }
+
+    fn sstore(&mut self, key: EvalValue, value: EvalValue) {
+        if self.check_write_protection() {
+            return;
+        }
+        self.host.sstore(word_or_zero(&key), word_or_zero(&value));
+    }
+
+    fn tload(&mut self, key: EvalValue) -> EvalValue {
+        word(self.host.tload(word_or_zero(&key)))
+    }
+
+    fn tstore(&mut self, key: EvalValue, value: EvalValue) {
+        if self.check_write_protection() {
+            return;
+        }
+        self.host.tstore(word_or_zero(&key), word_or_zero(&value));
+    }
+
+    fn emit_log(&mut self, data: Vec<u8>, topics: Vec<EvalValue>) {
+        if self.check_write_protection() {
+            return;
+        }
+        let topics = topics.iter().map(word_or_zero).collect();
+
+        let log = Log {
+            address: self.host.context().address,
+            topics,
+            data,
+        };
+        self.host.emit_log(log);
+    }
+
+    fn ext_call(&mut self, call: ExtCall) -> (EvalValue, Vec<u8>) {
+        let Some(addr) = as_word(&call.addr) else {
+            return (EvalValue::Undef, Vec::new());
+        };
+        let value = match call.kind {
+            CallKind::Call | CallKind::CallCode => match as_word(&call.value) {
+                Some(value) => value,
+                None => return (EvalValue::Undef, Vec::new()),
+            },
+            CallKind::DelegateCall | CallKind::StaticCall => U256::zero(),
+        };
+        if call.kind == CallKind::Call && !value.is_zero() && self.check_write_protection() {
+            return (EvalValue::Undef, Vec::new());
+        }
+
+        let failure = word(U256::zero());
+        self.host.set_return_data(Vec::new());
+        let current = self.host.context().clone();
+        if self.call_depth >= MAX_CALL_DEPTH || self.host.balance(current.address) < value {
+            return (failure, Vec::new());
+        }
+
+        let context = match call.kind {
+            CallKind::Call | CallKind::StaticCall => Context {
+                address: addr,
+                caller: current.address,
+                call_value: value,
+                ..current
+            },
+            // The code of `addr` is executed on the current account.
+            CallKind::CallCode => Context {
+                caller: current.address,
+                call_value: value,
+                ..current
+            },
+            CallKind::DelegateCall => current,
+        };
+        let msg = Message {
+            context,
+            input: call.input,
+            code: self.host.code(addr).to_vec(),
+            value,
+            is_static: self.is_static || call.kind == CallKind::StaticCall,
+            delegated: matches!(call.kind, CallKind::CallCode | CallKind::DelegateCall),
+            is_create: false,
+        };
+
+        let result = self.message_call(msg);
+        let output = result.output().to_vec();
+        self.host.set_return_data(output.clone());
+        let success = word(U256::from(!result.is_reverted() as u8));
+        (success, output)
+    }
+
+    fn ext_create(
+        &mut self,
+        value: EvalValue,
+        code: Vec<u8>,
+        salt: Option<EvalValue>,
+    ) -> EvalValue {
+        let Some(value) = as_word(&value) else {
+            return EvalValue::Undef;
+        };
+        let salt = match salt {
+            Some(salt) => match as_word(&salt) {
+                Some(salt) => Some(salt),
+                None => return EvalValue::Undef,
+            },
+            None => None,
+        };
+        if self.check_write_protection() {
+            return EvalValue::Undef;
+        }
+
+        let failure = word(U256::zero());
+        self.host.set_return_data(Vec::new());
+        let current = self.host.context().clone();
+        let creator = current.address;
+        if self.call_depth >= MAX_CALL_DEPTH || self.host.balance(creator) < value {
+            return failure;
+        }
+
+        let nonce = self.host.nonce(creator);
+        self.host.increment_nonce(creator);
+        let addr = match salt {
+            Some(salt) => contract::create2_address(creator, salt, &code),
+            None => contract::create_address(creator, nonce),
+        };
+        // The address is already in use.
+        if !self.host.code(addr).is_empty() || self.host.nonce(addr) != 0 {
+            return failure;
+        }
+
+        let msg = Message {
+            context: Context {
+                address: addr,
+                caller: creator,
+                call_value: value,
+                ..current
+            },
+            input: Vec::new(),
+            code,
+            value,
+            is_static: false,
+            delegated: false,
+            is_create: true,
+        };
+
+        let result = self.message_call(msg);
+        if result.is_reverted() {
+            self.host.set_return_data(result.output().to_vec());
+            failure
+        } else {
+            word(addr)
+        }
+    }
+
+    fn contract_size(&mut self, contract: FuncRef) -> EvalValue {
+        word(contract_code(contract).len().into())
+    }
+}
+
+impl<H: Host> GasCtx for Machine<H> {
+    fn evm_version(&self) -> EvmVersion {
+        self.gas.as_ref().unwrap().evm_version()
+    }
+
+    fn lookup_word(&mut self, value: ValueId) -> Option<U256> {
+        let value = self.lookup_val(value);
+        as_word(&value)
+    }
+
+    fn size_of(&self, ty: Type) -> usize {
+        self.module_ctx.size_of_unchecked(ty)
+    }
+
+    fn access_account(&mut self, addr: U256) -> bool {
+        self.gas.as_mut().unwrap().access_account(addr)
+    }
+
+    fn access_slot(&mut self, key: U256) -> SlotAccess {
+        let addr = self.host.context().address;
+        let current = self.host.sload(key);
+        self.gas.as_mut().unwrap().access_slot(addr, key, current)
+    }
+}
+
+impl<H: Host> UbCtx for Machine<H> {
+    fn is_undef(&mut self, value: ValueId) -> bool {
+        self.lookup_val(value).is_undef()
+    }
+
+    fn lookup_imm(&mut self, value: ValueId) -> Option<Immediate> {
+        self.lookup_val(value).as_imm()
+    }
+
+    fn lookup_addr(&mut self, value: ValueId) -> Option<usize> {
+        // An address that doesn't fit in `usize` is never written.
+        self.lookup_val(value).as_imm().map(|addr| {
+            let addr = addr.as_i256().to_u256();
+            if addr > U256::from(usize::MAX) {
+                usize::MAX
+            } else {
+                addr.as_usize()
+            }
+        })
+    }
+
+    fn is_written(&self, addr: usize, ty: Type) -> bool {
+        let size = self.module_ctx.size_of_unchecked(ty);
+        self.memory.is_written(addr, size)
+    }
+
+    fn lookup_func_ptr(&mut self, value: ValueId) -> Option<FuncRef> {
+        let ptr = self.lookup_val(value);
+        self.resolve_func_ptr(ptr)
+    }
+}
+
+fn word(value: U256) -> EvalValue {
+    EvalValue::Imm(Immediate::I256(I256::from_u256(value)))
+}
+
+fn as_word(value: &EvalValue) -> Option<U256> {
+    value.as_imm().map(|imm| imm.as_i256().to_u256())
+}
+
+/// Converts `value` into a word, reading an undefined value as zero in the same
+/// way as uninitialized memory.
+fn word_or_zero(value: &EvalValue) -> U256 {
+    as_word(value).unwrap_or_default()
 }
@@ -0,0 +1,139 @@
+//! The memory model of the interpreter.
+//!
+//! The memory mirrors the EVM memory: it is byte-addressed, zero-initialized,
+//! and expanded in 32-byte words whenever a byte beyond the current size is
+//! read or written.
+
+use sonatina_ir::{interpret::MEMORY_LIMIT, U256};
+
+/// The address of the free memory pointer in the [`Allocator::FreePointer`]
+/// convention.
+pub const FREE_PTR_ADDR: usize = 0x40;
+
+/// The initial value of the free memory pointer in the
+/// [`Allocator::FreePointer`] convention. Memory below this address is
+/// reserved for scratch space, the free memory pointer and the zero slot.
+pub const FREE_PTR_INIT: usize = 0x80;
+
+/// Decides how `alloca` and `evm_malloc` allocate memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Allocator {
+    /// Allocates memory from address 0 by bumping a pointer that is not
+    /// visible to the program.
+    #[default]
+    Bump,
+
+    /// Follows the convention of Solidity: the free memory pointer is stored
+    /// at [`FREE_PTR_ADDR`], and an allocation reads it, bumps it and writes it
+    /// back. The pointer is initialized to [`FREE_PTR_INIT`] as the contract
+    /// prologue does, so the memory is expanded to 3 words from the beginning.
+    FreePointer,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    data: Vec<u8>,
+    /// `true` for each byte of `data` that has been written.
+    written: Vec<bool>,
+    allocator: Allocator,
+    /// The next free address of [`Allocator::Bump`].
+    bump_ptr: usize,
+}
+
+impl Memory {
+    pub fn new(allocator: Allocator) -> Self {
+        let mut memory = Self {
+            allocator,
+            ..Default::default()
+        };
+        memory.clear();
+        memory
+    }
+
+    pub fn allocator(&self) -> Allocator {
+        self.allocator
+    }
+
+    /// Returns the size of the expanded memory in bytes, which is always a
+    /// multiple of 32.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Reads `len` bytes at `addr`, expanding the memory if necessary.
+    pub fn read(&mut self, addr: usize, len: usize) -> &[u8] {
+        if len == 0 {
+            return &[];
+        }
+
+        self.expand(addr, len);
+        &self.data[addr..addr + len]
+    }
+
+    /// Writes `bytes` at `addr`, expanding the memory if necessary.
+    pub fn write(&mut self, addr: usize, bytes: &[u8]) {
+        let len = bytes.len();
+        self.expand(addr, len);
+        self.data[addr..addr + len].copy_from_slice(bytes);
+        self.written[addr..addr + len].fill(true);
+    }
+
+    /// Returns `true` if all `len` bytes at `addr` have been written.
+    pub fn is_written(&self, addr: usize, len: usize) -> bool {
+        addr.checked_add(len)
+            .and_then(|end| self.written.get(addr..end))
+            .is_some_and(|written| written.iter().all(|&w| w))
+    }
+
+    /// Allocates `size` bytes and returns the address of the allocated region.
+    ///
+    /// Returns `None` without allocating if the region exceeds
+    /// [`MEMORY_LIMIT`].
+    pub fn alloc(&mut self, size: usize) -> Option<usize> {
+        let ptr = match self.allocator {
+            Allocator::Bump => self.bump_ptr,
+            Allocator::FreePointer => {
+                let ptr = U256::from_big_endian(self.read(FREE_PTR_ADDR, 32));
+                if ptr > U256::from(MEMORY_LIMIT) {
+                    return None;
+                }
+                ptr.as_usize()
+            }
+        };
+
+        let end = ptr.checked_add(size).filter(|end| *end <= MEMORY_LIMIT)?;
+        match self.allocator {
+            Allocator::Bump => self.bump_ptr = end,
+            Allocator::FreePointer => self.write_free_ptr(end),
+        }
+        Some(ptr)
+    }
+
+    /// Clears the memory and resets the allocator.
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.written.clear();
+        self.bump_ptr = 0;
+        if self.allocator == Allocator::FreePointer {
+            self.write_free_ptr(FREE_PTR_INIT);
+        }
+    }
+
+    fn write_free_ptr(&mut self, ptr: usize) {
+        self.write(FREE_PTR_ADDR, &U256::from(ptr).to_big_endian());
+    }
+
+    /// Expands the memory in words so that `len` bytes at `addr` are
+    /// accessible. An access of zero bytes doesn't expand the memory.
+    fn expand(&mut self, addr: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+
+        let size = (addr + len).div_ceil(32) * 32;
+        if size > self.data.len() {
+            self.data.resize(size, 0);
+            self.written.resize(size, false);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Hooks to observe the execution of the [`Machine`](crate::Machine).
+
+use std::{any::Any, collections::HashSet, fmt::Write};
+
+use cranelift_entity::SecondaryMap;
+use sonatina_ir::{
+    interpret::{EvalValue, HaltReason},
+    ir_writer::{FuncWriteCtx, InstStatement, IrWrite},
+    module::FuncRef,
+    BlockId, Function, InstId, Value, ValueId,
+};
+
+/// An observer of the execution of the [`Machine`](crate::Machine).
+///
+/// All methods do nothing by default, so an implementor only needs to
+/// implement the events it is interested in.
+pub trait Observer: AsAny {
+    /// Called when a new frame is pushed, i.e., when the machine starts
+    /// running a function. Arguments are already bound in `frame`.
+    fn on_call(&mut self, _frame: &FrameView) {}
+
+    /// Called when the control enters `block`, including the entry block.
+    fn on_block(&mut self, _frame: &FrameView, _block: BlockId) {}
+
+    /// Called before `inst` is executed.
+    fn on_inst(&mut self, _frame: &FrameView, _inst: InstId) {}
+
+    /// Called after `inst` is executed and its result `value` is bound to
+    /// `e_val`.
+    fn on_value(&mut self, _frame: &FrameView, _inst: InstId, _value: ValueId, _e_val: &EvalValue) {
+    }
+
+    /// Called when the function of `frame` returns `ret`.
+    fn on_return(&mut self, _frame: &FrameView, _ret: &EvalValue) {}
+
+    /// Called when `frame` is unwound by a halt of the message call. This is
+    /// called for every frame on the call stack, innermost first.
+    fn on_halt(&mut self, _frame: &FrameView, _reason: &HaltReason) {}
+}
+
+/// Allows downcasting an [`Observer`] to its concrete type.
+pub trait AsAny: Any {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// A read-only view of the frame that is currently executed.
+pub struct FrameView<'a> {
+    pub(crate) func_ref: FuncRef,
+    pub(crate) func: &'a Function,
+    pub(crate) locals: &'a SecondaryMap<ValueId, EvalValue>,
+    pub(crate) prev_block: Option<BlockId>,
+    pub(crate) depth: usize,
+}
+
+impl<'a> FrameView<'a> {
+    pub fn func_ref(&self) -> FuncRef {
+        self.func_ref
+    }
+
+    pub fn func(&self) -> &'a Function {
+        self.func
+    }
+
+    /// Returns the block that the control came from.
+    pub fn prev_block(&self) -> Option<BlockId> {
+        self.prev_block
+    }
+
+    /// Returns the depth of the call stack, the frame of the function passed
+    /// to [`Machine::run`](crate::Machine::run) has depth 0.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the current value of `value`, or `Undef` if it is not yet
+    /// evaluated.
+    pub fn value(&self, value: ValueId) -> EvalValue {
+        match self.func.dfg.value(value) {
+            Value::Immediate { imm, .. } => (*imm).into(),
+            _ => self.locals[value].clone(),
+        }
+    }
+
+    pub fn write_ctx(&self) -> FuncWriteCtx<'a> {
+        FuncWriteCtx::new(self.func, self.func_ref)
+    }
+
+    pub fn func_name(&self) -> String {
+        self.func
+            .ctx()
+            .func_sig(self.func_ref, |sig| sig.name().to_string())
+    }
+}
+
+/// An [`Observer`] that records the execution trace in the text form of the
+/// IR.
+///
+/// e.g.,
+/// ```text
+/// call %f(v0 = 1.i32)
+///   block0:
+///     v1.i32 = add v0 1.i32;  // v1 = 2.i32
+///     return v1;
+///   ret 2.i32
+/// ```
+#[derive(Debug, Default)]
+pub struct Tracer {
+    trace: String,
+    /// `true` if the last line is an instruction that hasn't been annotated
+    /// with its result yet.
+    is_inst_line: bool,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn trace(&self) -> &str {
+        &self.trace
+    }
+
+    pub fn into_trace(self) -> String {
+        self.trace
+    }
+
+    pub fn clear(&mut self) {
+        self.trace.clear();
+        self.is_inst_line = false;
+    }
+
+    fn line(&mut self, frame: &FrameView, level: usize, line: &str) {
+        let indent = " ".repeat((frame.depth * 2 + level) * 2);
+        writeln!(self.trace, "{indent}{line}").unwrap();
+        self.is_inst_line = false;
+    }
+}
+
+impl Observer for Tracer {
+    fn on_call(&mut self, frame: &FrameView) {
+        let ctx = frame.write_ctx();
+        let args: Vec<_> = frame
+            .func
+            .arg_values
+            .iter()
+            .map(|&arg| {
+                let e_val = write_e_val(&frame.value(arg), &ctx);
+                format!("{} = {e_val}", arg.dump_string(&ctx))
+            })
+            .collect();
+        let line = format!("call %{}({})", frame.func_name(), args.join(", "));
+        self.line(frame, 0, &line);
+    }
+
+    fn on_block(&mut self, frame: &FrameView, block: BlockId) {
+        let line = format!("{}:", block.dump_string(&frame.write_ctx()));
+        self.line(frame, 1, &line);
+    }
+
+    fn on_inst(&mut self, frame: &FrameView, inst: InstId) {
+        let line = InstStatement(inst).dump_string(&frame.write_ctx());
+        self.line(frame, 2, &line);
+        self.is_inst_line = true;
+    }
+
+    fn on_value(&mut self, frame: &FrameView, _inst: InstId, value: ValueId, e_val: &EvalValue) {
+        let ctx = frame.write_ctx();
+        let comment = format!(
+            "// {} = {}",
+            value.dump_string(&ctx),
+            write_e_val(e_val, &ctx)
+        );
+
+        // Append the result to the line of the instruction unless the trace of
+        // a callee is in between.
+        if self.is_inst_line {
+            self.trace.pop();
+            writeln!(self.trace, "  {comment}").unwrap();
+            self.is_inst_line = false;
+        } else {
+            self.line(frame, 2, &comment);
+        }
+    }
+
+    fn on_return(&mut self, frame: &FrameView, ret: &EvalValue) {
+        let ret = write_e_val(ret, &frame.write_ctx());
+        self.line(frame, 1, &format!("ret {ret}"));
+    }
+
+    fn on_halt(&mut self, frame: &FrameView, reason: &HaltReason) {
+        let reason = match reason {
+            HaltReason::Stop => "stop".to_string(),
+            HaltReason::Return(data) => format!("return 0x{}", hex(data)),
+            HaltReason::Revert(data) => format!("revert 0x{}", hex(data)),
+            HaltReason::Invalid => "invalid".to_string(),
+            HaltReason::OutOfGas => "out_of_gas".to_string(),
+            HaltReason::WriteProtection => "write_protection".to_string(),
+            HaltReason::SelfDestruct(beneficiary) => {
+                let beneficiary = write_e_val(beneficiary, &frame.write_ctx());
+                format!("self_destruct {beneficiary}")
+            }
+        };
+        self.line(frame, 1, &format!("halt {reason}"));
+    }
+}
+
+/// Writes `e_val` in the same form as immediates in the IR, e.g., `1.i32`.
+fn write_e_val(e_val: &EvalValue, ctx: &FuncWriteCtx) -> String {
+    match e_val {
+        EvalValue::Imm(imm) => format!("{imm}.{}", imm.ty().dump_string(ctx)),
+        EvalValue::Aggregate { fields, .. } => {
+            let fields: Vec<_> = fields.iter().map(|f| write_e_val(f, ctx)).collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        EvalValue::Undef => "undef".to_string(),
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// A command returned by the callback of [`Debugger`] to decide how to resume
+/// the execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugCommand {
+    /// Runs until the next breakpoint.
+    Continue,
+    /// Stops again before the next instruction.
+    Step,
+}
+
+/// An [`Observer`] that stops the execution at breakpoints.
+///
+/// When the execution stops, the callback is called before the instruction is
+/// executed, and the returned [`DebugCommand`] decides where to stop next.
+pub struct Debugger<F> {
+    breakpoints: HashSet<(FuncRef, InstId)>,
+    stepping: bool,
+    callback: F,
+}
+
+impl<F> Debugger<F>
+where
+    F: FnMut(&FrameView, InstId) -> DebugCommand + 'static,
+{
+    pub fn new(callback: F) -> Self {
+        Self {
+            breakpoints: HashSet::new(),
+            stepping: false,
+            callback,
+        }
+    }
+
+    /// Sets a breakpoint at `inst` in `func`.
+    pub fn set_breakpoint(&mut self, func: FuncRef, inst: InstId) {
+        self.breakpoints.insert((func, inst));
+    }
+
+    pub fn remove_breakpoint(&mut self, func: FuncRef, inst: InstId) {
+        self.breakpoints.remove(&(func, inst));
+    }
+
+    /// Stops before the first instruction that is executed.
+    pub fn step(&mut self) {
+        self.stepping = true;
+    }
+}
+
+impl<F> Observer for Debugger<F>
+where
+    F: FnMut(&FrameView, InstId) -> DebugCommand + 'static,
+{
+    fn on_inst(&mut self, frame: &FrameView, inst: InstId) {
+        if self.stepping || self.breakpoints.contains(&(frame.func_ref, inst)) {
+            self.stepping = (self.callback)(frame, inst) == DebugCommand::Step;
+        }
+    }
+}
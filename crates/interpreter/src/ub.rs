@@ -0,0 +1,316 @@
+//! Detection of undefined behavior in the strict mode of the interpreter.
+//!
+//! In the strict mode, the [`Machine`](crate::Machine) checks each instruction
+//! before executing it, and stops the execution at the first instruction that
+//! uses `undef` where a defined value is required, that reads memory that has
+//! never been written, or that branches to no destination.
+
+use std::fmt;
+
+use macros::inst_prop;
+use sonatina_ir::{
+    inst::{arith::*, control_flow::*, data::*, evm::*},
+    module::FuncRef,
+    Immediate, InstId, Type, ValueId,
+};
+
+#[inst_prop]
+pub trait UbCheck {
+    /// Returns an error if executing the instruction in the current state is
+    /// undefined behavior.
+    ///
+    /// This method is called before the instruction is executed.
+    fn check_ub(&self, ctx: &mut dyn UbCtx) -> Result<(), UbKind>;
+
+    type Members = (
+        Br,
+        BrTable,
+        Call,
+        CallIndirect,
+        Sdiv,
+        Udiv,
+        Umod,
+        Smod,
+        EvmUdiv,
+        EvmSdiv,
+        EvmUmod,
+        EvmSmod,
+        EvmAddMod,
+        EvmMulMod,
+        Mload,
+        Mstore,
+        EvmMstore8,
+        EvmSload,
+        EvmSstore,
+        EvmTload,
+        EvmTstore,
+        EvmKeccak256,
+        EvmCalldataCopy,
+        EvmCodeCopy,
+        EvmExtCodeCopy,
+        EvmReturnDataCopy,
+        EvmMcopy,
+        EvmLog0,
+        EvmLog1,
+        EvmLog2,
+        EvmLog3,
+        EvmLog4,
+        EvmCreate,
+        EvmCreate2,
+        EvmCall,
+        EvmCallCode,
+        EvmDelegateCall,
+        EvmStaticCall,
+        EvmReturn,
+        EvmRevert,
+    );
+}
+
+/// Provides the information required to check undefined behavior.
+pub trait UbCtx {
+    /// Returns `true` if the current value of `value` is `undef`.
+    fn is_undef(&mut self, value: ValueId) -> bool;
+
+    /// Returns the current value of `value`, or `None` if it is `undef`.
+    fn lookup_imm(&mut self, value: ValueId) -> Option<Immediate>;
+
+    /// Returns the current value of `value` as a memory address, or `None` if
+    /// it is `undef`.
+    fn lookup_addr(&mut self, value: ValueId) -> Option<usize>;
+
+    /// Returns `true` if all bytes of a value of `ty` at `addr` have been
+    /// written.
+    fn is_written(&self, addr: usize, ty: Type) -> bool;
+
+    /// Returns the function pointed to by the current value of `value`, or
+    /// `None` if it doesn't point to a function.
+    fn lookup_func_ptr(&mut self, value: ValueId) -> Option<FuncRef>;
+}
+
+/// Undefined behavior detected in the strict mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndefinedBehavior {
+    /// The function that contains the offending instruction.
+    pub func: FuncRef,
+    /// The offending instruction, which is not executed.
+    pub inst: InstId,
+    pub kind: UbKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UbKind {
+    /// `value` is `undef` though `usage` requires a defined value.
+    UndefOperand { value: ValueId, usage: UndefUse },
+    /// A value of `ty` is loaded from `addr`, but some of its bytes have never
+    /// been written.
+    UninitRead { addr: usize, ty: Type },
+    /// `value` is called, but it doesn't point to a function.
+    InvalidFuncPtr { value: ValueId },
+    /// `br_table` has neither a destination matching the value of `scrutinee`
+    /// nor a default destination.
+    NoBranchDest { scrutinee: ValueId },
+}
+
+impl fmt::Display for UbKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UndefOperand { value, usage } => {
+                write!(f, "undef value `v{}` is used as {usage}", value.as_u32())
+            }
+            Self::UninitRead { addr, .. } => {
+                write!(f, "uninitialized memory is read at {addr}")
+            }
+            Self::InvalidFuncPtr { value } => {
+                write!(f, "`v{}` doesn't point to a function", value.as_u32())
+            }
+            Self::NoBranchDest { scrutinee } => {
+                write!(f, "no branch destination matches `v{}`", scrutinee.as_u32())
+            }
+        }
+    }
+}
+
+/// The usage of an operand that requires a defined value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndefUse {
+    BranchCond,
+    MemoryAddr,
+    DivOperand,
+    CallArg,
+    Callee,
+    StorageKey,
+    LogTopic,
+    CallAddr,
+    CallValue,
+}
+
+impl fmt::Display for UndefUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let usage = match self {
+            Self::BranchCond => "a branch condition",
+            Self::MemoryAddr => "a memory address",
+            Self::DivOperand => "an operand of a division",
+            Self::CallArg => "a call argument",
+            Self::Callee => "a callee",
+            Self::StorageKey => "a storage key",
+            Self::LogTopic => "a log topic",
+            Self::CallAddr => "an account address",
+            Self::CallValue => "a value transferred by a call",
+        };
+        f.write_str(usage)
+    }
+}
+
+fn check_defined(ctx: &mut dyn UbCtx, value: ValueId, usage: UndefUse) -> Result<(), UbKind> {
+    if ctx.is_undef(value) {
+        Err(UbKind::UndefOperand { value, usage })
+    } else {
+        Ok(())
+    }
+}
+
+impl UbCheck for Br {
+    fn check_ub(&self, ctx: &mut dyn UbCtx) -> Result<(), UbKind> {
+        check_defined(ctx, *self.cond(), UndefUse::BranchCond)
+    }
+}
+
+impl UbCheck for BrTable {
+    fn check_ub(&self, ctx: &mut dyn UbCtx) -> Result<(), UbKind> {
+        let scrutinee = *self.scrutinee();
+        check_defined(ctx, scrutinee, UndefUse::BranchCond)?;
+        if self.default().is_some() {
+            return Ok(());
+        }
+
+        let value = ctx.lookup_imm(scrutinee);
+        if self
+            .table()
+            .iter()
+            .any(|(case, _)| ctx.lookup_imm(*case) == value)
+        {
+            Ok(())
+        } else {
+            Err(UbKind::NoBranchDest { scrutinee })
+        }
+    }
+}
+
+impl UbCheck for Call {
+    fn check_ub(&self, ctx: &mut dyn UbCtx) -> Result<(), UbKind> {
+        self.args()
+            .iter()
+            .try_for_each(|arg| check_defined(ctx, *arg, UndefUse::CallArg))
+    }
+}
+
+impl UbCheck for CallIndirect {
+    fn check_ub(&self, ctx: &mut dyn UbCtx) -> Result<(), UbKind> {
+        let callee = *self.callee();
+        check_defined(ctx, callee, UndefUse::Callee)?;
+        if ctx.lookup_func_ptr(callee).is_none() {
+            return Err(UbKind::InvalidFuncPtr { value: callee });
+        }
+
+        self.args()
+            .iter()
+            .try_for_each(|arg| check_defined(ctx, *arg, UndefUse::CallArg))
+    }
+}
+
+macro_rules! impl_div_check {
+    ($($ty:ty),*) => {
+        $(
+            impl UbCheck for $ty {
+                fn check_ub(&self, ctx: &mut dyn UbCtx) -> Result<(), UbKind> {
+                    check_defined(ctx, *self.lhs(), UndefUse::DivOperand)?;
+                    check_defined(ctx, *self.rhs(), UndefUse::DivOperand)
+                }
+            }
+        )*
+    };
+}
+
+impl_div_check! {Sdiv, Udiv, Umod, Smod, EvmUdiv, EvmSdiv, EvmUmod, EvmSmod}
+
+impl UbCheck for EvmAddMod {
+    fn check_ub(&self, ctx: &mut dyn UbCtx) -> Result<(), UbKind> {
+        check_defined(ctx, *self.modulus(), UndefUse::DivOperand)
+    }
+}
+
+impl UbCheck for EvmMulMod {
+    fn check_ub(&self, ctx: &mut dyn UbCtx) -> Result<(), UbKind> {
+        check_defined(ctx, *self.modulus(), UndefUse::DivOperand)
+    }
+}
+
+impl UbCheck for Mload {
+    fn check_ub(&self, ctx: &mut dyn UbCtx) -> Result<(), UbKind> {
+        let Some(addr) = ctx.lookup_addr(*self.addr()) else {
+            return Err(UbKind::UndefOperand {
+                value: *self.addr(),
+                usage: UndefUse::MemoryAddr,
+            });
+        };
+
+        let ty = *self.ty();
+        if ctx.is_written(addr, ty) {
+            Ok(())
+        } else {
+            Err(UbKind::UninitRead { addr, ty })
+        }
+    }
+}
+
+/// Implements [`UbCheck`] for instructions whose listed operands must be
+/// defined for the given usage.
+///
+/// NOTE: Memory accessed by EVM instructions is zero-initialized, so reading
+/// memory that has never been written is not undefined behavior for them.
+macro_rules! impl_operand_check {
+    ($($ty:ty => [$($operand:ident: $usage:ident),*]),* $(,)?) => {
+        $(
+            impl UbCheck for $ty {
+                fn check_ub(&self, ctx: &mut dyn UbCtx) -> Result<(), UbKind> {
+                    $(check_defined(ctx, *self.$operand(), UndefUse::$usage)?;)*
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_operand_check! {
+    Mstore => [addr: MemoryAddr],
+    EvmMstore8 => [addr: MemoryAddr],
+    EvmSload => [key: StorageKey],
+    EvmSstore => [key: StorageKey],
+    EvmTload => [key: StorageKey],
+    EvmTstore => [key: StorageKey],
+    EvmKeccak256 => [addr: MemoryAddr],
+    EvmCalldataCopy => [dst_addr: MemoryAddr],
+    EvmCodeCopy => [dst_addr: MemoryAddr],
+    EvmExtCodeCopy => [dst_addr: MemoryAddr],
+    EvmReturnDataCopy => [dst_addr: MemoryAddr],
+    EvmMcopy => [dest: MemoryAddr, addr: MemoryAddr],
+    EvmLog0 => [addr: MemoryAddr],
+    EvmLog1 => [addr: MemoryAddr, topic0: LogTopic],
+    EvmLog2 => [addr: MemoryAddr, topic0: LogTopic, topic1: LogTopic],
+    EvmLog3 => [addr: MemoryAddr, topic0: LogTopic, topic1: LogTopic, topic2: LogTopic],
+    EvmLog4 => [
+        addr: MemoryAddr,
+        topic0: LogTopic,
+        topic1: LogTopic,
+        topic2: LogTopic,
+        topic3: LogTopic
+    ],
+    EvmCreate => [val: CallValue, addr: MemoryAddr],
+    EvmCreate2 => [val: CallValue, addr: MemoryAddr],
+    EvmCall => [addr: CallAddr, val: CallValue, arg_addr: MemoryAddr, ret_addr: MemoryAddr],
+    EvmCallCode => [addr: CallAddr, val: CallValue, arg_addr: MemoryAddr, ret_addr: MemoryAddr],
+    EvmDelegateCall => [ext_addr: CallAddr, arg_addr: MemoryAddr, ret_addr: MemoryAddr],
+    EvmStaticCall => [ext_addr: CallAddr, arg_addr: MemoryAddr, ret_addr: MemoryAddr],
+    EvmReturn => [addr: MemoryAddr],
+    EvmRevert => [addr: MemoryAddr],
+}
@@ -2,7 +2,7 @@ use std::io::Write;
 
 use once_cell::sync::Lazy;
 use regex::Regex;
-use sonatina_interpreter::Machine;
+use sonatina_interpreter::{Machine, Tracer};
 use sonatina_ir::{interpret::EvalValue, module::FuncRef, Immediate};
 use sonatina_parser::{
     ast::{Value, ValueKind},
@@ -48,10 +48,12 @@ pub struct TestCase {
 
 impl TestCase {
     pub fn run(self, machine: &mut Machine) -> Result<(), String> {
-        let evaluated = machine.run(
-            self.func,
-            self.args.iter().cloned().map(EvalValue::Imm).collect(),
-        );
+        let evaluated = machine
+            .run(
+                self.func,
+                self.args.iter().cloned().map(EvalValue::Imm).collect(),
+            )
+            .into_value();
         machine.clear_state();
 
         let err_pair = match self.ret {
@@ -74,10 +76,13 @@ impl TestCase {
 
         if let Some((expected, evaluated)) = err_pair {
             let text = &self.text;
+            let trace = self.trace(machine);
             let func = machine.funcs.get(&self.func).unwrap();
 
             func.ctx().func_sig(self.func, |sig| {
-                let msg = format!("{text}\nexpected: {expected}\nevaluated: {evaluated}\n");
+                let msg = format!(
+                    "{text}\nexpected: {expected}\nevaluated: {evaluated}\ntrace:\n{trace}"
+                );
                 Err(format_error(sig.name(), &msg))
             })
         } else {
@@ -85,6 +90,20 @@ impl TestCase {
         }
     }
 
+    /// Runs the case again and returns the execution trace.
+    fn trace(&self, machine: &mut Machine) -> String {
+        machine.set_observer(Tracer::new());
+        machine.run(
+            self.func,
+            self.args.iter().cloned().map(EvalValue::Imm).collect(),
+        );
+        machine.clear_state();
+
+        let trace = machine.observer::<Tracer>().unwrap().trace().to_string();
+        machine.take_observer();
+        trace
+    }
+
     fn parse(module: &ParsedModule, func_ref: FuncRef, comment: &str) -> Result<Self, String> {
         let Some(caps) = PATTERN.captures(comment) else {
             return module.module.ctx.func_sig(func_ref, |sig| {
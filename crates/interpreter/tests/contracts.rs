@@ -0,0 +1,372 @@
+use sonatina_interpreter::{
+    contract::{create2_address, create_address},
+    contract_code, Context, ExecResult, Host, InMemoryHost, Machine,
+};
+use sonatina_ir::{interpret::EvalValue, module::FuncRef, Immediate, I256, U256};
+use sonatina_parser::parse_module;
+
+const SRC: &str = r#"
+target = "evm-ethereum-cancun"
+
+func public %counter() {
+    block0:
+        v0.i256 = evm_sload 0.i256;
+        v1.i256 = add v0 1.i256;
+        evm_sstore 0.i256 v1;
+        v2.*i256 = int_to_ptr 0.i256 *i256;
+        mstore v2 v1 i256;
+        evm_return 0.i256 32.i256;
+}
+
+func public %getter() {
+    block0:
+        v0.i256 = evm_sload 0.i256;
+        v1.*i256 = int_to_ptr 0.i256 *i256;
+        mstore v1 v0 i256;
+        evm_return 0.i256 32.i256;
+}
+
+func public %reverter() {
+    block0:
+        evm_sstore 0.i256 1.i256;
+        evm_mstore8 0.i256 0xab.i256;
+        evm_revert 0.i256 1.i256;
+}
+
+func public %env() {
+    block0:
+        v0.i256 = evm_caller;
+        v1.i256 = evm_call_value;
+        v2.i256 = evm_address;
+        v3.*i256 = int_to_ptr 0.i256 *i256;
+        mstore v3 v0 i256;
+        v4.*i256 = int_to_ptr 32.i256 *i256;
+        mstore v4 v1 i256;
+        v5.*i256 = int_to_ptr 64.i256 *i256;
+        mstore v5 v2 i256;
+        evm_return 0.i256 96.i256;
+}
+
+func public %ctor() {
+    block0:
+        evm_sstore 0.i256 10.i256;
+        v0.i256 = evm_contract_size %counter;
+        v1.i256 = evm_contract_size %ctor;
+        evm_code_copy 0.i256 v1 v0;
+        evm_return 0.i256 v0;
+}
+
+func public %call(v0.i256, v1.i256) -> i256 {
+    block0:
+        v2.i256 = evm_call 100000.i256 v0 v1 0.i256 0.i256 0.i256 32.i256;
+        v3.*i256 = int_to_ptr 0.i256 *i256;
+        v4.i256 = mload v3 i256;
+        v5.i256 = mul v2 1000.i256;
+        v6.i256 = add v4 v5;
+        return v6;
+}
+
+func public %sload_and_call(v0.i256, v1.i256) -> i256 {
+    block0:
+        v2.i256 = evm_sload v1;
+        v3.i256 = evm_call 100000.i256 v0 0.i256 0.i256 0.i256 0.i256 32.i256;
+        return v3;
+}
+
+func public %call_twice(v0.i256) -> i256 {
+    block0:
+        v1.i256 = call %call v0 0.i256;
+        v2.i256 = call %call v0 0.i256;
+        return v2;
+}
+
+func public %static_call(v0.i256) -> i256 {
+    block0:
+        v1.i256 = evm_static_call 100000.i256 v0 0.i256 0.i256 0.i256 32.i256;
+        v2.*i256 = int_to_ptr 0.i256 *i256;
+        v3.i256 = mload v2 i256;
+        v4.i256 = mul v1 1000.i256;
+        v5.i256 = add v3 v4;
+        return v5;
+}
+
+func public %delegate_call(v0.i256) -> i256 {
+    block0:
+        v1.i256 = evm_delegate_call 100000.i256 v0 0.i256 0.i256 0.i256 96.i256;
+        v2.*i256 = int_to_ptr 0.i256 *i256;
+        v3.i256 = mload v2 i256;
+        v4.i256 = mul v1 1000.i256;
+        v5.i256 = add v3 v4;
+        return v5;
+}
+
+func public %call_code(v0.i256) -> i256 {
+    block0:
+        v1.i256 = evm_call_code 100000.i256 v0 0.i256 0.i256 0.i256 0.i256 32.i256;
+        v2.*i256 = int_to_ptr 0.i256 *i256;
+        v3.i256 = mload v2 i256;
+        v4.i256 = mul v1 1000.i256;
+        v5.i256 = add v3 v4;
+        return v5;
+}
+
+func public %return_data(v0.i256) -> i256 {
+    block0:
+        evm_sstore 1.i256 1.i256;
+        v1.i256 = evm_call 100000.i256 v0 0.i256 0.i256 0.i256 0.i256 0.i256;
+        v2.i256 = evm_return_data_size;
+        evm_return_data_copy 0.i256 0.i256 v2;
+        v3.i256 = evm_sload 1.i256;
+        v4.*i8 = int_to_ptr 0.i256 *i8;
+        v5.i8 = mload v4 i8;
+        v6.i256 = zext v5 i256;
+        v7.i256 = mul v2 1000.i256;
+        v8.i256 = add v6 v7;
+        v9.i256 = add v8 v3;
+        v10.i256 = add v9 v1;
+        return v10;
+}
+
+func public %create(v0.i256) -> i256 {
+    block0:
+        v1.i256 = evm_calldata_size;
+        evm_calldata_copy 0.i256 0.i256 v1;
+        v2.i256 = evm_create v0 0.i256 v1;
+        return v2;
+}
+
+func public %create2(v0.i256) -> i256 {
+    block0:
+        v1.i256 = evm_calldata_size;
+        evm_calldata_copy 0.i256 0.i256 v1;
+        v2.i256 = evm_create2 0.i256 0.i256 v1 v0;
+        return v2;
+}
+"#;
+
+const CALLER: u64 = 0xaa;
+const CALLEE: u64 = 0xbb;
+
+fn setup() -> (Machine<InMemoryHost>, impl Fn(&str) -> FuncRef) {
+    let parsed = parse_module(SRC).unwrap();
+    let funcs: Vec<_> = parsed
+        .module
+        .funcs()
+        .into_iter()
+        .map(|func_ref| {
+            let name = parsed
+                .module
+                .ctx
+                .func_sig(func_ref, |sig| sig.name().to_string());
+            (name, func_ref)
+        })
+        .collect();
+    let lookup = move |name: &str| funcs.iter().find(|(n, _)| n == name).unwrap().1;
+
+    let context = Context {
+        address: CALLER.into(),
+        origin: 1.into(),
+        caller: 1.into(),
+        ..Default::default()
+    };
+    let machine = Machine::with_host(parsed.module, InMemoryHost::new(context));
+    (machine, lookup)
+}
+
+fn imm(value: u64) -> EvalValue {
+    EvalValue::Imm(Immediate::I256(I256::from(value)))
+}
+
+fn balance(machine: &Machine, addr: u64) -> U256 {
+    machine.host().balance(addr.into())
+}
+
+fn slot(machine: &Machine, addr: u64, key: u64) -> Option<U256> {
+    let storage = machine.host().account_storage(addr.into())?;
+    storage.get(&key.into()).copied()
+}
+
+#[test]
+fn call() {
+    let (mut machine, func) = setup();
+    machine.deploy(CALLEE.into(), func("counter"));
+
+    let result = machine.run(func("call_twice"), vec![imm(CALLEE)]);
+    // The call succeeds and returns the incremented counter.
+    assert_eq!(result.into_value(), imm(1002));
+
+    // Each account has its own storage.
+    assert_eq!(slot(&machine, CALLEE, 0), Some(2.into()));
+    assert!(machine.host().storage.is_empty());
+}
+
+#[test]
+fn slot_access_per_account() {
+    let gas_used = |key| {
+        let (mut machine, func) = setup();
+        machine.deploy(CALLEE.into(), func("getter"));
+        machine.enable_gas_metering();
+        let result = machine.run(func("sload_and_call"), vec![imm(CALLEE), imm(key)]);
+        assert_eq!(result.into_value(), imm(1));
+        machine.gas_used().unwrap()
+    };
+
+    // Accessing slot 0 of the caller doesn't warm up slot 0 of the callee.
+    assert_eq!(gas_used(0), gas_used(1));
+}
+
+#[test]
+fn call_without_code() {
+    let (mut machine, func) = setup();
+
+    // Calling an account without code succeeds with empty output.
+    let result = machine.run(func("call"), vec![imm(CALLEE), imm(0)]);
+    assert_eq!(result.into_value(), imm(1000));
+}
+
+#[test]
+fn call_context_and_value() {
+    let (mut machine, func) = setup();
+    machine.deploy(CALLEE.into(), func("env"));
+    machine
+        .host_mut()
+        .balances
+        .insert(CALLER.into(), 100.into());
+
+    // The first word of the output is the caller.
+    let result = machine.run(func("call"), vec![imm(CALLEE), imm(30)]);
+    assert_eq!(result.into_value(), imm(1000 + CALLER));
+    assert_eq!(balance(&machine, CALLER), 70.into());
+    assert_eq!(balance(&machine, CALLEE), 30.into());
+
+    // The call fails if the caller can't afford the value.
+    machine.clear_state();
+    let result = machine.run(func("call"), vec![imm(CALLEE), imm(71)]);
+    assert_eq!(result.into_value(), imm(0));
+    assert_eq!(balance(&machine, CALLEE), 30.into());
+}
+
+#[test]
+fn static_call() {
+    let (mut machine, func) = setup();
+    machine.deploy(CALLEE.into(), func("counter"));
+    machine.deploy(0xcc.into(), func("getter"));
+    machine
+        .host_mut()
+        .storages
+        .insert(0xcc.into(), [(0.into(), 42.into())].into());
+
+    let result = machine.run(func("static_call"), vec![imm(0xcc)]);
+    assert_eq!(result.into_value(), imm(1042));
+
+    // `evm_sstore` in a static call fails the call.
+    machine.clear_state();
+    let result = machine.run(func("static_call"), vec![imm(CALLEE)]);
+    assert_eq!(result.into_value(), imm(0));
+    assert_eq!(slot(&machine, CALLEE, 0), None);
+}
+
+#[test]
+fn delegate_call() {
+    let (mut machine, func) = setup();
+    machine.deploy(CALLEE.into(), func("counter"));
+    machine.deploy(0xcc.into(), func("env"));
+
+    // The code of the callee runs on the storage of the caller.
+    let result = machine.run(func("delegate_call"), vec![imm(CALLEE)]);
+    assert_eq!(result.into_value(), imm(1001));
+    assert_eq!(machine.host().storage.get(&0.into()), Some(&1.into()));
+    assert_eq!(slot(&machine, CALLEE, 0), None);
+
+    let result = machine.run(func("call_code"), vec![imm(CALLEE)]);
+    assert_eq!(result.into_value(), imm(1002));
+    assert_eq!(machine.host().storage.get(&0.into()), Some(&2.into()));
+
+    // The caller is inherited from the current message call.
+    let result = machine.run(func("delegate_call"), vec![imm(0xcc)]);
+    assert_eq!(result.into_value(), imm(1001));
+
+    // The caller of `evm_call_code` is the current account.
+    let (mut machine, func) = setup();
+    machine.deploy(0xcc.into(), func("env"));
+    let result = machine.run(func("call_code"), vec![imm(0xcc)]);
+    assert_eq!(result.into_value(), imm(1000 + CALLER));
+}
+
+#[test]
+fn revert_and_return_data() {
+    let (mut machine, func) = setup();
+    machine.deploy(CALLEE.into(), func("reverter"));
+
+    // The call fails with the revert data, but the caller continues.
+    let result = machine.run(func("return_data"), vec![imm(CALLEE)]);
+    assert_eq!(result.into_value(), imm(1000 + 0xab + 1));
+    assert_eq!(slot(&machine, CALLEE, 0), None);
+    assert_eq!(machine.host().storage.get(&1.into()), Some(&1.into()));
+}
+
+#[test]
+fn create() {
+    let (mut machine, func) = setup();
+    let mut init_code = contract_code(func("ctor"));
+    init_code.extend(contract_code(func("counter")));
+    machine.host_mut().calldata = init_code;
+
+    let addr = create_address(CALLER.into(), 0);
+    let result = machine.run(func("create"), vec![imm(0)]).into_value();
+    assert_eq!(
+        result,
+        EvalValue::Imm(Immediate::I256(I256::from_u256(addr)))
+    );
+
+    // The constructor returns the code of the created account.
+    let host = machine.host();
+    assert_eq!(host.code(addr), contract_code(func("counter")));
+    assert_eq!(host.account_storage(addr).unwrap()[&0.into()], 10.into());
+    assert_eq!(host.nonce(CALLER.into()), 1);
+    assert_eq!(host.nonce(addr), 1);
+
+    // The created contract can be called.
+    let result = machine.run(func("call"), vec![result, imm(0)]);
+    assert_eq!(result.into_value(), imm(1011));
+
+    // The next account is created at a different address.
+    let result = machine.run(func("create"), vec![imm(0)]).into_value();
+    let addr = create_address(CALLER.into(), 1);
+    assert_eq!(
+        result,
+        EvalValue::Imm(Immediate::I256(I256::from_u256(addr)))
+    );
+}
+
+#[test]
+fn create2() {
+    let (mut machine, func) = setup();
+    let mut init_code = contract_code(func("ctor"));
+    init_code.extend(contract_code(func("counter")));
+    machine.host_mut().calldata = init_code.clone();
+
+    let addr = create2_address(CALLER.into(), 7.into(), &init_code);
+    let result = machine.run(func("create2"), vec![imm(7)]).into_value();
+    assert_eq!(
+        result,
+        EvalValue::Imm(Immediate::I256(I256::from_u256(addr)))
+    );
+
+    // The address is already in use.
+    let result = machine.run(func("create2"), vec![imm(7)]).into_value();
+    assert_eq!(result, imm(0));
+}
+
+#[test]
+fn create_revert() {
+    let (mut machine, func) = setup();
+    machine.host_mut().calldata = contract_code(func("reverter"));
+
+    let result = machine.run(func("create"), vec![imm(0)]);
+    assert_eq!(result, ExecResult::Value(imm(0)));
+    let addr = create_address(CALLER.into(), 0);
+    assert!(machine.host().code(addr).is_empty());
+    // The revert data is available to the creator.
+    assert_eq!(machine.host().return_data, vec![0xab]);
+}
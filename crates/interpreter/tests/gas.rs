@@ -0,0 +1,259 @@
+use sonatina_codegen::{domtree::DomTree, loop_analysis::LoopTree, optim::licm::LicmSolver};
+use sonatina_interpreter::{ExecResult, Machine};
+use sonatina_ir::{
+    interpret::{EvalValue, HaltReason},
+    module::FuncRef,
+    ControlFlowGraph, Immediate, Module, I256, U256,
+};
+use sonatina_parser::parse_module;
+use sonatina_triple::EvmVersion;
+
+const SRC: &str = r#"
+target = "evm-ethereum-cancun"
+
+func public %sload_twice() -> i256 {
+    block0:
+        v0.i256 = evm_sload 1.i256;
+        v1.i256 = evm_sload 1.i256;
+        v2.i256 = add v0 v1;
+        return v2;
+}
+
+func public %sstore_twice(v0.i256) {
+    block0:
+        evm_sstore 1.i256 v0;
+        evm_sstore 1.i256 2.i256;
+        return;
+}
+
+func public %memory() {
+    block0:
+        evm_mstore8 0.i256 1.i256;
+        evm_mstore8 1023.i256 1.i256;
+        return;
+}
+
+func public %balance(v0.i256) -> i256 {
+    block0:
+        v1.i256 = evm_balance v0;
+        return v1;
+}
+
+func public %licm(v0.i256, v1.i256) -> i256 {
+    block0:
+        jump block1;
+
+    block1:
+        v2.i256 = phi (0.i256 block0) (v4 block1);
+        v3.i256 = mul v0 v1;
+        v4.i256 = add v2 1.i256;
+        v5.i1 = lt v4 10.i256;
+        br v5 block1 block2;
+
+    block2:
+        return v3;
+}
+
+func public %infinite_loop() {
+    block0:
+        jump block0;
+}
+
+func public %return_large_data() {
+    block0:
+        evm_return 0.i256 4096.i256;
+}
+"#;
+
+fn parse() -> (Module, impl Fn(&str) -> FuncRef) {
+    let parsed = parse_module(SRC).unwrap();
+    let funcs: Vec<_> = parsed
+        .module
+        .funcs()
+        .into_iter()
+        .map(|func_ref| {
+            let name = parsed
+                .module
+                .ctx
+                .func_sig(func_ref, |sig| sig.name().to_string());
+            (name, func_ref)
+        })
+        .collect();
+    let lookup = move |name: &str| funcs.iter().find(|(n, _)| n == name).unwrap().1;
+
+    (parsed.module, lookup)
+}
+
+fn imm(value: u64) -> EvalValue {
+    EvalValue::Imm(Immediate::I256(I256::from(value)))
+}
+
+/// Runs `func` and returns the gas used.
+fn gas_of(machine: &mut Machine, func: FuncRef, args: Vec<EvalValue>) -> u64 {
+    machine.run(func, args);
+    machine.gas_used().unwrap()
+}
+
+#[test]
+fn disabled_by_default() {
+    let (module, func) = parse();
+    let mut machine = Machine::new(module);
+
+    machine.run(func("sload_twice"), vec![]);
+    assert_eq!(machine.gas_used(), None);
+}
+
+#[test]
+fn cold_and_warm_sload() {
+    let (module, func) = parse();
+    let mut machine = Machine::new(module);
+
+    // Cold and warm `sload`, `add` and `return`.
+    machine.enable_gas_metering();
+    assert_eq!(
+        gas_of(&mut machine, func("sload_twice"), vec![]),
+        2100 + 100 + 3 + 8
+    );
+
+    // Storage access status is reset for each run.
+    assert_eq!(
+        gas_of(&mut machine, func("sload_twice"), vec![]),
+        2100 + 100 + 3 + 8
+    );
+
+    // Storage access is not distinguished before Berlin.
+    machine.enable_gas_metering_with(EvmVersion::Istanbul);
+    assert_eq!(
+        gas_of(&mut machine, func("sload_twice"), vec![]),
+        800 + 800 + 3 + 8
+    );
+
+    machine.enable_gas_metering_with(EvmVersion::Byzantium);
+    assert_eq!(
+        gas_of(&mut machine, func("sload_twice"), vec![]),
+        200 + 200 + 3 + 8
+    );
+}
+
+#[test]
+fn sstore() {
+    let (module, func) = parse();
+    let mut machine = Machine::new(module);
+    machine.enable_gas_metering();
+
+    // Cold `sstore` that sets a fresh slot, followed by a dirty write.
+    let gas = gas_of(&mut machine, func("sstore_twice"), vec![imm(1)]);
+    assert_eq!(gas, (20000 + 2100) + 100 + 8);
+
+    // The slot now has a non-zero original value, so the first write resets
+    // it. Refunds for restoring the original value are not taken into account.
+    let gas = gas_of(&mut machine, func("sstore_twice"), vec![imm(1)]);
+    assert_eq!(gas, (2900 + 2100) + 100 + 8);
+
+    // Writing the current value only costs a warm read.
+    machine.host_mut().storage.insert(1.into(), 1.into());
+    let gas = gas_of(&mut machine, func("sstore_twice"), vec![imm(1)]);
+    assert_eq!(gas, (100 + 2100) + 2900 + 8);
+
+    // Before Istanbul, the cost only depends on the current value.
+    machine.enable_gas_metering_with(EvmVersion::Byzantium);
+    let gas = gas_of(&mut machine, func("sstore_twice"), vec![imm(0)]);
+    assert_eq!(gas, 5000 + 20000 + 8);
+}
+
+#[test]
+fn memory_expansion() {
+    let (module, func) = parse();
+    let mut machine = Machine::new(module);
+    machine.enable_gas_metering();
+
+    // Expansion to 1 word costs 3, and expansion from 1 word to 32 words
+    // costs `(32 * 3 + 32 * 32 / 512) - 3`.
+    let gas = gas_of(&mut machine, func("memory"), vec![]);
+    assert_eq!(gas, (3 + 3) + (3 + 95) + 8);
+
+    // Memory that is already expanded is not charged again.
+    let gas = gas_of(&mut machine, func("memory"), vec![]);
+    assert_eq!(gas, 3 + 3 + 8);
+
+    machine.clear_state();
+    let gas = gas_of(&mut machine, func("memory"), vec![]);
+    assert_eq!(gas, (3 + 3) + (3 + 95) + 8);
+}
+
+#[test]
+fn account_access() {
+    let (module, func) = parse();
+    let mut machine = Machine::new(module);
+    machine.enable_gas_metering();
+
+    // The current account is always warm.
+    machine.host_mut().context.address = U256::from(0xff);
+    assert_eq!(
+        gas_of(&mut machine, func("balance"), vec![imm(0xff)]),
+        100 + 8
+    );
+    assert_eq!(
+        gas_of(&mut machine, func("balance"), vec![imm(1)]),
+        2600 + 8
+    );
+
+    machine.enable_gas_metering_with(EvmVersion::London);
+    assert_eq!(
+        gas_of(&mut machine, func("balance"), vec![imm(1)]),
+        2600 + 8
+    );
+
+    machine.enable_gas_metering_with(EvmVersion::Istanbul);
+    assert_eq!(gas_of(&mut machine, func("balance"), vec![imm(1)]), 700 + 8);
+}
+
+#[test]
+fn gas_limit() {
+    let (module, func) = parse();
+    let mut machine = Machine::new(module);
+    machine.set_gas_limit(100);
+
+    let result = machine.run(func("infinite_loop"), vec![]);
+    assert_eq!(result, ExecResult::Halt(HaltReason::OutOfGas));
+    assert!(result.is_reverted());
+    // The execution halts at the first `jump` that exceeds the limit.
+    assert_eq!(machine.gas_used(), Some(13 * 8));
+
+    // Memory expanded by an instruction that halts is charged too.
+    let result = machine.run(func("return_large_data"), vec![]);
+    assert_eq!(result, ExecResult::Halt(HaltReason::OutOfGas));
+}
+
+#[test]
+fn compare_before_and_after_licm() {
+    let (module, func) = parse();
+    let func_ref = func("licm");
+    let args = vec![imm(3), imm(4)];
+
+    let mut machine = Machine::new(module);
+    machine.enable_gas_metering();
+    let before = machine.run(func_ref, args.clone());
+    let gas_before = machine.gas_used().unwrap();
+
+    let (module, _) = parse();
+    module.func_store.modify(func_ref, |func| {
+        let mut cfg = ControlFlowGraph::default();
+        let mut domtree = DomTree::default();
+        let mut lpt = LoopTree::default();
+        cfg.compute(func);
+        domtree.compute(&cfg);
+        lpt.compute(&cfg, &domtree);
+        LicmSolver::new().run(func, &mut cfg, &mut lpt);
+    });
+
+    let mut machine = Machine::new(module);
+    machine.enable_gas_metering();
+    let after = machine.run(func_ref, args);
+    let gas_after = machine.gas_used().unwrap();
+
+    assert_eq!(before, after);
+    assert_eq!(before.into_value(), imm(12));
+    // `mul` is executed once instead of ten times.
+    assert!(gas_after < gas_before, "{gas_after} >= {gas_before}");
+}
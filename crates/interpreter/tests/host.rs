@@ -0,0 +1,219 @@
+use sonatina_interpreter::{Context, ExecResult, InMemoryHost, Log, Machine};
+use sonatina_ir::{
+    interpret::{EvalValue, HaltReason},
+    module::FuncRef,
+    Immediate, I256, U256,
+};
+use sonatina_parser::parse_module;
+
+const SRC: &str = r#"
+target = "evm-ethereum-cancun"
+
+func public %context() -> i256 {
+    block0:
+        v0.i256 = evm_caller;
+        v1.i256 = evm_call_value;
+        v2.i256 = evm_timestamp;
+        v3.i256 = evm_chain_id;
+        v4.i256 = add v0 v1;
+        v5.i256 = add v4 v2;
+        v6.i256 = add v5 v3;
+        return v6;
+}
+
+func public %calldata() -> i256 {
+    block0:
+        v0.i256 = evm_calldata_size;
+        v1.i256 = evm_calldata_load 1.i256;
+        v2.i256 = add v0 v1;
+        return v2;
+}
+
+func public %store_and_log(v0.i256, v1.i256) {
+    block0:
+        evm_sstore v0 v1;
+        evm_sstore v1 v0;
+        evm_mstore8 0.i256 0xab.i256;
+        evm_log2 0.i256 1.i256 v0 v1;
+        return;
+}
+
+func public %store_and_revert(v0.i256) {
+    block0:
+        evm_sstore 1.i256 v0;
+        evm_log0 0.i256 0.i256;
+        call %revert_with v0;
+        return;
+}
+
+func private %revert_with(v0.i256) {
+    block0:
+        evm_mstore8 0.i256 v0;
+        evm_revert 0.i256 1.i256;
+}
+
+func public %return_data(v0.i256) -> i256 {
+    block0:
+        evm_sstore 1.i256 v0;
+        evm_mstore8 31.i256 v0;
+        evm_return 30.i256 2.i256;
+}
+
+func public %undef_sstore() -> i256 {
+    block0:
+        evm_sstore undef.i256 7.i256;
+        v0.i256 = evm_sload 0.i256;
+        return v0;
+}
+
+func public %undef_tstore() -> i256 {
+    block0:
+        evm_tstore undef.i256 7.i256;
+        v0.i256 = evm_tload 0.i256;
+        return v0;
+}
+
+func public %undef_topic() {
+    block0:
+        evm_log2 0.i256 0.i256 undef.i256 1.i256;
+        return;
+}
+"#;
+
+fn setup(host: InMemoryHost) -> (Machine<InMemoryHost>, impl Fn(&str) -> FuncRef) {
+    let parsed = parse_module(SRC).unwrap();
+    let funcs: Vec<_> = parsed
+        .module
+        .funcs()
+        .into_iter()
+        .map(|func_ref| {
+            let name = parsed
+                .module
+                .ctx
+                .func_sig(func_ref, |sig| sig.name().to_string());
+            (name, func_ref)
+        })
+        .collect();
+    let lookup = move |name: &str| funcs.iter().find(|(n, _)| n == name).unwrap().1;
+
+    (Machine::with_host(parsed.module, host), lookup)
+}
+
+fn imm(value: u64) -> EvalValue {
+    EvalValue::Imm(Immediate::I256(I256::from(value)))
+}
+
+#[test]
+fn context() {
+    let context = Context {
+        caller: 1.into(),
+        call_value: 20.into(),
+        timestamp: 300.into(),
+        chain_id: 4000.into(),
+        ..Default::default()
+    };
+    let (mut machine, func) = setup(InMemoryHost::new(context));
+
+    let result = machine.run(func("context"), vec![]).into_value();
+    assert_eq!(result, imm(4321));
+}
+
+#[test]
+fn calldata() {
+    let mut calldata = vec![0; 33];
+    calldata[32] = 0x10;
+    let host = InMemoryHost::default().with_calldata(calldata);
+    let (mut machine, func) = setup(host);
+
+    // `calldata[1..33]` is `0x10`, followed by zero padding.
+    let result = machine.run(func("calldata"), vec![]).into_value();
+    assert_eq!(result, imm(33 + 0x10));
+}
+
+#[test]
+fn storage_and_logs() {
+    let context = Context {
+        address: 0xff.into(),
+        ..Default::default()
+    };
+    let (mut machine, func) = setup(InMemoryHost::new(context));
+
+    machine.run(func("store_and_log"), vec![imm(1), imm(2)]);
+
+    let host = machine.host();
+    assert_eq!(
+        host.storage_writes,
+        vec![(1.into(), 2.into()), (2.into(), 1.into())]
+    );
+    assert_eq!(host.storage[&U256::from(1)], 2.into());
+    assert_eq!(
+        host.logs,
+        vec![Log {
+            address: 0xff.into(),
+            topics: vec![1.into(), 2.into()],
+            data: vec![0xab],
+        }]
+    );
+}
+
+#[test]
+fn zero_slot_is_removed() {
+    let (mut machine, func) = setup(InMemoryHost::default());
+
+    machine.run(func("store_and_log"), vec![imm(1), imm(0)]);
+
+    // Writing zero to a slot is observable only as a write, the slot itself
+    // is indistinguishable from an unwritten one.
+    let host = machine.host();
+    assert_eq!(
+        host.storage_writes,
+        vec![(1.into(), 0.into()), (0.into(), 1.into())]
+    );
+    assert_eq!(host.storage, [(0.into(), 1.into())].into_iter().collect());
+}
+
+#[test]
+fn revert() {
+    let (mut machine, func) = setup(InMemoryHost::default());
+
+    // The revert in the callee terminates the whole call.
+    let result = machine.run(func("store_and_revert"), vec![imm(0xcd)]);
+    assert_eq!(result, ExecResult::Halt(HaltReason::Revert(vec![0xcd])));
+    assert!(result.is_reverted());
+
+    // All state changes are rolled back.
+    let host = machine.host();
+    assert!(host.storage.is_empty());
+    assert!(host.storage_writes.is_empty());
+    assert!(host.logs.is_empty());
+}
+
+#[test]
+fn return_data() {
+    let (mut machine, func) = setup(InMemoryHost::default());
+
+    let result = machine.run(func("return_data"), vec![imm(0xcd)]);
+    assert_eq!(result, ExecResult::Halt(HaltReason::Return(vec![0, 0xcd])));
+    assert_eq!(result.output(), &[0, 0xcd]);
+    assert_eq!(machine.host().storage[&U256::from(1)], 0xcd.into());
+}
+
+#[test]
+fn undef_storage_key() {
+    // An undefined key is read as zero like uninitialized memory.
+    let (mut machine, func) = setup(InMemoryHost::default());
+    let result = machine.run(func("undef_sstore"), vec![]);
+    assert_eq!(result, ExecResult::Value(imm(7)));
+    assert_eq!(machine.host().storage_writes, vec![(0.into(), 7.into())]);
+
+    let result = machine.run(func("undef_tstore"), vec![]);
+    assert_eq!(result, ExecResult::Value(imm(7)));
+}
+
+#[test]
+fn undef_log_topic() {
+    let (mut machine, func) = setup(InMemoryHost::default());
+    let result = machine.run(func("undef_topic"), vec![]);
+    assert_eq!(result, ExecResult::Value(EvalValue::Undef));
+    assert_eq!(machine.host().logs[0].topics, vec![0.into(), 1.into()]);
+}
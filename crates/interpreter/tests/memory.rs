@@ -0,0 +1,221 @@
+use sonatina_interpreter::{memory::FREE_PTR_INIT, Allocator, ExecResult, Machine};
+use sonatina_ir::{
+    interpret::{EvalValue, HaltReason},
+    module::FuncRef,
+    Immediate, Module, I256,
+};
+use sonatina_parser::parse_module;
+
+const SRC: &str = r#"
+target = "evm-ethereum-cancun"
+
+func public %msize_after_read() -> i256 {
+    block0:
+        v0.*i256 = int_to_ptr 64.i256 *i256;
+        v1.i256 = mload v0 i256;
+        v2.i256 = evm_msize;
+        v3.i256 = add v1 v2;
+        return v3;
+}
+
+func public %malloc_twice() -> i256 {
+    block0:
+        v0.*i8 = evm_malloc 10.i256;
+        v1.*i8 = evm_malloc 32.i256;
+        v2.i256 = ptr_to_int v1 i256;
+        return v2;
+}
+
+func public %free_ptr() -> i256 {
+    block0:
+        v0.*i8 = evm_malloc 10.i256;
+        v1.*i256 = int_to_ptr 64.i256 *i256;
+        v2.i256 = mload v1 i256;
+        return v2;
+}
+
+func public %mixed() -> i256 {
+    block0:
+        v0.*i256 = alloca i256;
+        mstore v0 0x1122.i256 i256;
+        v1.*i8 = evm_malloc 32.i256;
+        v2.i256 = ptr_to_int v0 i256;
+        v3.i256 = ptr_to_int v1 i256;
+        evm_mcopy v3 v2 32.i256;
+        v4.i256 = add v3 31.i256;
+        evm_mstore8 v4 0x33.i256;
+        v5.*i256 = int_to_ptr v3 *i256;
+        v6.i256 = mload v5 i256;
+        return v6;
+}
+
+func public %huge_keccak() -> i256 {
+    block0:
+        v0.i256 = evm_keccak256 0.i256 0x0100000000.i256;
+        return v0;
+}
+
+func public %overflowing_copy() {
+    block0:
+        evm_calldata_copy -1.i256 0.i256 2.i256;
+        return;
+}
+
+func public %huge_return() {
+    block0:
+        evm_return 0.i256 -1.i256;
+}
+
+func public %empty_return() {
+    block0:
+        evm_return -1.i256 0.i256;
+}
+
+func public %huge_store() {
+    block0:
+        v0.*i256 = int_to_ptr -1.i256 *i256;
+        mstore v0 1.i256 i256;
+        return;
+}
+
+func public %huge_gep() -> i256 {
+    block0:
+        v0.*i256 = alloca i256;
+        v1.*i256 = gep v0 0x0100000000000000000000000000000000.i256;
+        v2.i256 = mload v1 i256;
+        return v2;
+}
+
+func public %huge_malloc() -> i256 {
+    block0:
+        v0.*i8 = evm_malloc -1.i256;
+        v1.i256 = ptr_to_int v0 i256;
+        return v1;
+}
+
+func public %malloc_beyond_limit() -> i256 {
+    block0:
+        v0.*i8 = evm_malloc 16777216.i256;
+        v1.*i8 = evm_malloc 16777217.i256;
+        v2.i256 = ptr_to_int v1 i256;
+        return v2;
+}
+"#;
+
+fn parse() -> (Module, impl Fn(&str) -> FuncRef) {
+    let parsed = parse_module(SRC).unwrap();
+    let funcs: Vec<_> = parsed
+        .module
+        .funcs()
+        .into_iter()
+        .map(|func_ref| {
+            let name = parsed
+                .module
+                .ctx
+                .func_sig(func_ref, |sig| sig.name().to_string());
+            (name, func_ref)
+        })
+        .collect();
+    let lookup = move |name: &str| funcs.iter().find(|(n, _)| n == name).unwrap().1;
+
+    (parsed.module, lookup)
+}
+
+fn imm(value: u64) -> EvalValue {
+    EvalValue::Imm(Immediate::I256(I256::from(value)))
+}
+
+fn run(allocator: Allocator, func: &str) -> (EvalValue, usize) {
+    let (module, lookup) = parse();
+    let mut machine = Machine::new(module);
+    machine.set_allocator(allocator);
+
+    let value = machine.run(lookup(func), vec![]).into_value();
+    (value, machine.memory().size())
+}
+
+#[test]
+fn expansion_on_read() {
+    // Reading memory expands it in words, and the memory is zero-initialized.
+    let (value, size) = run(Allocator::Bump, "msize_after_read");
+    assert_eq!(value, imm(96));
+    assert_eq!(size, 96);
+}
+
+#[test]
+fn bump_allocator() {
+    let (value, size) = run(Allocator::Bump, "malloc_twice");
+    assert_eq!(value, imm(10));
+    // Allocation doesn't expand the memory.
+    assert_eq!(size, 0);
+}
+
+#[test]
+fn free_ptr_allocator() {
+    let init = FREE_PTR_INIT as u64;
+    let (value, _) = run(Allocator::FreePointer, "malloc_twice");
+    assert_eq!(value, imm(init + 10));
+
+    // The free memory pointer is visible to the program.
+    let (value, size) = run(Allocator::FreePointer, "free_ptr");
+    assert_eq!(value, imm(init + 10));
+    assert_eq!(size, 96);
+}
+
+#[test]
+fn mixed_memory_insts() {
+    // `alloca`, `evm_malloc`, `mstore`, `evm_mcopy` and `evm_mstore8` share the
+    // same memory.
+    let expected = imm(0x1133);
+    assert_eq!(run(Allocator::Bump, "mixed").0, expected);
+    assert_eq!(run(Allocator::FreePointer, "mixed").0, expected);
+}
+
+#[test]
+fn clear_state() {
+    let (module, lookup) = parse();
+    let mut machine = Machine::new(module);
+    machine.set_allocator(Allocator::FreePointer);
+
+    let func = lookup("malloc_twice");
+    let first = machine.run(func, vec![]);
+    let second = machine.run(func, vec![]);
+    // Allocated memory lives until the state is cleared.
+    assert_ne!(first, second);
+
+    machine.clear_state();
+    assert_eq!(machine.memory().size(), 96);
+    assert_eq!(machine.run(func, vec![]), first);
+}
+
+#[test]
+fn memory_limit() {
+    let (module, lookup) = parse();
+    let mut machine = Machine::new(module);
+
+    // An access beyond the memory limit halts instead of allocating the range.
+    let out_of_gas = ExecResult::Halt(HaltReason::OutOfGas);
+    for func in [
+        "huge_keccak",
+        "overflowing_copy",
+        "huge_return",
+        "huge_store",
+        "huge_gep",
+        "huge_malloc",
+    ] {
+        assert_eq!(machine.run(lookup(func), vec![]), out_of_gas, "{func}");
+    }
+
+    // Allocations are bounded by the memory limit regardless of the allocator.
+    for allocator in [Allocator::Bump, Allocator::FreePointer] {
+        machine.set_allocator(allocator);
+        let result = machine.run(lookup("malloc_beyond_limit"), vec![]);
+        assert_eq!(result, out_of_gas, "{allocator:?}");
+    }
+
+    // An empty range is valid regardless of the address.
+    assert_eq!(
+        machine.run(lookup("empty_return"), vec![]),
+        ExecResult::Halt(HaltReason::Return(vec![]))
+    );
+}
@@ -0,0 +1,199 @@
+use std::{cell::RefCell, rc::Rc};
+
+use sonatina_interpreter::{DebugCommand, Debugger, Machine, Observer, Tracer};
+use sonatina_ir::{interpret::EvalValue, module::FuncRef, Immediate, InstId, Module};
+use sonatina_parser::parse_module;
+
+const SRC: &str = r#"
+target = "evm-ethereum-cancun"
+
+func public %sum(v0.i32) -> i32 {
+    block0:
+        jump block1;
+
+    block1:
+        v1.i32 = phi (0.i32 block0) (v3 block1);
+        v2.i32 = phi (0.i32 block0) (v4 block1);
+        v3.i32 = add v1 v2;
+        v4.i32 = add v2 1.i32;
+        v5.i1 = lt v4 v0;
+        br v5 block1 block2;
+
+    block2:
+        v6.i32 = call %double v3;
+        return v6;
+}
+
+func private %double(v0.i32) -> i32 {
+    block0:
+        v1.i32 = mul v0 2.i32;
+        return v1;
+}
+
+func public %revert() {
+    block0:
+        call %revert_inner;
+        return;
+}
+
+func private %revert_inner() {
+    block0:
+        evm_mstore8 0.i256 0xab.i256;
+        evm_revert 0.i256 1.i256;
+}
+"#;
+
+fn parse() -> (Module, impl Fn(&str) -> FuncRef) {
+    let parsed = parse_module(SRC).unwrap();
+    let funcs: Vec<_> = parsed
+        .module
+        .funcs()
+        .into_iter()
+        .map(|func_ref| {
+            let name = parsed
+                .module
+                .ctx
+                .func_sig(func_ref, |sig| sig.name().to_string());
+            (name, func_ref)
+        })
+        .collect();
+    let lookup = move |name: &str| funcs.iter().find(|(n, _)| n == name).unwrap().1;
+
+    (parsed.module, lookup)
+}
+
+fn imm(value: i32) -> EvalValue {
+    EvalValue::Imm(Immediate::I32(value))
+}
+
+fn trace(func: &str, args: Vec<EvalValue>) -> String {
+    let (module, lookup) = parse();
+    let mut machine = Machine::new(module);
+    machine.set_observer(Tracer::new());
+    machine.run(lookup(func), args);
+
+    machine.observer::<Tracer>().unwrap().trace().to_string()
+}
+
+#[test]
+fn tracer() {
+    let expected = "\
+call %sum(v0 = 2.i32)
+  block0:
+    jump block1;
+  block1:
+    v1.i32 = phi (0.i32 block0) (v3 block1);  // v1 = 0.i32
+    v2.i32 = phi (0.i32 block0) (v4 block1);  // v2 = 0.i32
+    v3.i32 = add v1 v2;  // v3 = 0.i32
+    v4.i32 = add v2 1.i32;  // v4 = 1.i32
+    v5.i1 = lt v4 v0;  // v5 = 1.i1
+    br v5 block1 block2;
+  block1:
+    v1.i32 = phi (0.i32 block0) (v3 block1);  // v1 = 0.i32
+    v2.i32 = phi (0.i32 block0) (v4 block1);  // v2 = 1.i32
+    v3.i32 = add v1 v2;  // v3 = 1.i32
+    v4.i32 = add v2 1.i32;  // v4 = 2.i32
+    v5.i1 = lt v4 v0;  // v5 = 0.i1
+    br v5 block1 block2;
+  block2:
+    v6.i32 = call %double v3;
+    call %double(v0 = 1.i32)
+      block0:
+        v1.i32 = mul v0 2.i32;  // v1 = 2.i32
+        return v1;
+      ret 2.i32
+    // v6 = 2.i32
+    return v6;
+  ret 2.i32
+";
+    assert_eq!(trace("sum", vec![imm(2)]), expected);
+}
+
+#[test]
+fn tracer_halt() {
+    let expected = "\
+call %revert()
+  block0:
+    call %revert_inner;
+    call %revert_inner()
+      block0:
+        evm_mstore8 0.i256 171.i256;
+        evm_revert 0.i256 1.i256;
+      halt revert 0xab
+  halt revert 0xab
+";
+    assert_eq!(trace("revert", vec![]), expected);
+}
+
+#[test]
+fn debugger() {
+    let (module, lookup) = parse();
+    let sum = lookup("sum");
+    let double = lookup("double");
+
+    // Inspect the operands of `v3 = add v1 v2` and step into the next two
+    // instructions.
+    let stops: Rc<RefCell<Vec<(InstId, String)>>> = Rc::default();
+    let stops_ref = stops.clone();
+    let mut debugger = Debugger::new(move |frame, inst| {
+        let mut args = Vec::new();
+        frame
+            .func()
+            .dfg
+            .inst(inst)
+            .for_each_value(&mut |value| args.push(frame.value(value).to_string()));
+        stops_ref.borrow_mut().push((inst, args.join(" ")));
+
+        if stops_ref.borrow().len() == 1 {
+            DebugCommand::Step
+        } else {
+            DebugCommand::Continue
+        }
+    });
+
+    let (add, mul) = {
+        let find = |func_ref, nth| {
+            module.func_store.view(func_ref, |func| {
+                let block = func.layout.iter_block().nth(nth).unwrap();
+                func.layout.iter_inst(block).find(|inst| {
+                    let name = func.dfg.inst(*inst).as_text();
+                    name == "add" || name == "mul"
+                })
+            })
+        };
+        (find(sum, 1).unwrap(), find(double, 0).unwrap())
+    };
+    debugger.set_breakpoint(sum, add);
+    debugger.set_breakpoint(double, mul);
+
+    let mut machine = Machine::new(module);
+    machine.set_observer(debugger);
+    let result = machine.run(sum, vec![imm(3)]).into_value();
+    assert_eq!(result, imm(6));
+
+    let stops: Vec<_> = stops.borrow().iter().map(|(_, s)| s.clone()).collect();
+    assert_eq!(
+        stops,
+        vec![
+            // `v3 = add v1 v2` in the first iteration, then step.
+            "0 0", // `v4 = add v2 1.i32`.
+            "0 1", // `v3 = add v1 v2` in the second and third iteration.
+            "0 1", "1 2", // `v1 = mul v0 2.i32` in `%double`.
+            "3 2",
+        ]
+    );
+}
+
+#[test]
+fn observer_downcast() {
+    let (module, _) = parse();
+    let mut machine = Machine::new(module);
+    assert!(machine.observer::<Tracer>().is_none());
+
+    machine.set_observer(Tracer::new());
+    assert!(machine.observer_mut::<Tracer>().is_some());
+
+    let observer: Box<dyn Observer> = machine.take_observer().unwrap();
+    assert!((*observer).as_any().is::<Tracer>());
+    assert!(machine.observer::<Tracer>().is_none());
+}
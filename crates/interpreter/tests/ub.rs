@@ -0,0 +1,326 @@
+use sonatina_interpreter::{ExecResult, Machine, UbKind, UndefUse, UndefinedBehavior};
+use sonatina_ir::{interpret::EvalValue, module::FuncRef, Immediate, Module, Type};
+use sonatina_parser::parse_module;
+
+const SRC: &str = r#"
+target = "evm-ethereum-cancun"
+
+func public %br_undef() -> i32 {
+    block0:
+        br undef.i1 block1 block2;
+
+    block1:
+        return 1.i32;
+
+    block2:
+        return 2.i32;
+}
+
+func public %div_undef(v0.i32) -> i32 {
+    block0:
+        v1.i32 = evm_udiv v0 undef.i32;
+        return v1;
+}
+
+func public %mload_undef_addr() -> i32 {
+    block0:
+        v0.i32 = mload undef.*i32 i32;
+        return v0;
+}
+
+func public %call_undef() -> i32 {
+    block0:
+        evm_sstore 0.i256 1.i256;
+        v0.i32 = call %callee 1.i32;
+        return v0;
+}
+
+func private %callee(v0.i32) -> i32 {
+    block0:
+        v1.i32 = call %id undef.i32;
+        return v1;
+}
+
+func private %id(v0.i32) -> i32 {
+    block0:
+        return v0;
+}
+
+func public %uninit_read() -> i32 {
+    block0:
+        v0.*i32 = alloca i32;
+        v1.i32 = mload v0 i32;
+        return v1;
+}
+
+func public %partial_read() -> i64 {
+    block0:
+        v0.*i64 = alloca i64;
+        mstore v0 1.i32 i32;
+        v1.i64 = mload v0 i64;
+        return v1;
+}
+
+func public %init_read() -> i32 {
+    block0:
+        v0.*i32 = alloca i32;
+        mstore v0 1.i32 i32;
+        v1.i32 = mload v0 i32;
+        return v1;
+}
+
+func public %invalid_func_ptr() -> i32 {
+    block0:
+        v0.*(i32) -> i32 = int_to_ptr 1000.i256 *(i32) -> i32;
+        v1.i32 = call_indirect v0 1.i32;
+        return v1;
+}
+
+func public %null_func_ptr() -> i32 {
+    block0:
+        v0.*(i32) -> i32 = int_to_ptr 0.i256 *(i32) -> i32;
+        v1.i32 = call_indirect v0 1.i32;
+        return v1;
+}
+
+func public %evm_memory() -> i256 {
+    block0:
+        v0.i256 = evm_keccak256 0.i256 32.i256;
+        return v0;
+}
+
+func public %sstore_undef_key() {
+    block0:
+        evm_sstore undef.i256 1.i256;
+        return;
+}
+
+func public %tstore_undef_key() {
+    block0:
+        evm_tstore undef.i256 1.i256;
+        return;
+}
+
+func public %log_undef_topic() {
+    block0:
+        evm_log2 0.i256 0.i256 1.i256 undef.i256;
+        return;
+}
+
+func public %call_undef_addr() -> i256 {
+    block0:
+        v0.i256 = evm_call 100.i256 undef.i256 0.i256 0.i256 0.i256 0.i256 0.i256;
+        return v0;
+}
+
+func public %call_undef_value() -> i256 {
+    block0:
+        v0.i256 = evm_call 100.i256 1.i256 undef.i256 0.i256 0.i256 0.i256 0.i256;
+        return v0;
+}
+
+func public %br_table_no_dest(v0.i32) -> i32 {
+    block0:
+        br_table v0 (1.i32 block1) (2.i32 block2);
+
+    block1:
+        return 1.i32;
+
+    block2:
+        return 2.i32;
+}
+"#;
+
+fn parse() -> (Module, impl Fn(&str) -> FuncRef) {
+    let parsed = parse_module(SRC).unwrap();
+    let funcs: Vec<_> = parsed
+        .module
+        .funcs()
+        .into_iter()
+        .map(|func_ref| {
+            let name = parsed
+                .module
+                .ctx
+                .func_sig(func_ref, |sig| sig.name().to_string());
+            (name, func_ref)
+        })
+        .collect();
+    let lookup = move |name: &str| funcs.iter().find(|(n, _)| n == name).unwrap().1;
+
+    (parsed.module, lookup)
+}
+
+fn imm(value: i32) -> EvalValue {
+    EvalValue::Imm(Immediate::I32(value))
+}
+
+/// Runs `func` in the strict mode and returns the detected undefined behavior
+/// with the text of the offending instruction.
+fn run_strict(func: &str, args: Vec<EvalValue>) -> Option<(UndefinedBehavior, String)> {
+    let (module, lookup) = parse();
+    let mut machine = Machine::new(module);
+    machine.enable_strict_mode();
+
+    let ExecResult::UndefinedBehavior(ub) = machine.run(lookup(func), args) else {
+        return None;
+    };
+    let func = machine.funcs.get(&ub.func).unwrap();
+    let inst = func.dfg.inst(ub.inst).as_text().to_string();
+    Some((ub, inst))
+}
+
+fn undef_use(ub: &UndefinedBehavior) -> UndefUse {
+    match ub.kind {
+        UbKind::UndefOperand { usage, .. } => usage,
+        _ => panic!("unexpected undefined behavior: {}", ub.kind),
+    }
+}
+
+#[test]
+fn disabled_by_default() {
+    let (module, func) = parse();
+    let mut machine = Machine::new(module);
+
+    let result = machine.run(func("div_undef"), vec![imm(1)]);
+    assert_eq!(result, ExecResult::Value(EvalValue::Undef));
+}
+
+#[test]
+fn branch_cond() {
+    let (ub, inst) = run_strict("br_undef", vec![]).unwrap();
+    assert_eq!(undef_use(&ub), UndefUse::BranchCond);
+    assert_eq!(inst, "br");
+    assert!(ub
+        .kind
+        .to_string()
+        .ends_with("is used as a branch condition"));
+}
+
+#[test]
+fn div_operand() {
+    let (ub, inst) = run_strict("div_undef", vec![imm(1)]).unwrap();
+    assert_eq!(undef_use(&ub), UndefUse::DivOperand);
+    assert_eq!(inst, "evm_udiv");
+}
+
+#[test]
+fn memory_addr() {
+    let (ub, inst) = run_strict("mload_undef_addr", vec![]).unwrap();
+    assert_eq!(undef_use(&ub), UndefUse::MemoryAddr);
+    assert_eq!(inst, "mload");
+}
+
+#[test]
+fn call_arg() {
+    let (module, lookup) = parse();
+    let mut machine = Machine::new(module);
+    machine.enable_strict_mode();
+
+    let result = machine.run(lookup("call_undef"), vec![]);
+    let ExecResult::UndefinedBehavior(ub) = &result else {
+        panic!("undefined behavior is not detected: {result:?}");
+    };
+
+    // The error points to the instruction in the callee.
+    assert_eq!(ub.func, lookup("callee"));
+    assert_eq!(undef_use(ub), UndefUse::CallArg);
+    // Changes to the host are rolled back.
+    assert!(result.is_reverted());
+    assert!(machine.host().storage.is_empty());
+
+    // The machine can be reused after undefined behavior.
+    let result = machine.run(lookup("init_read"), vec![]);
+    assert_eq!(result, ExecResult::Value(imm(1)));
+}
+
+#[test]
+fn invalid_func_ptr() {
+    let (ub, inst) = run_strict("invalid_func_ptr", vec![]).unwrap();
+    assert!(matches!(ub.kind, UbKind::InvalidFuncPtr { .. }));
+    assert_eq!(inst, "call_indirect");
+
+    // A null pointer never points to a function, even the first one.
+    let (ub, inst) = run_strict("null_func_ptr", vec![]).unwrap();
+    assert!(matches!(ub.kind, UbKind::InvalidFuncPtr { .. }));
+    assert_eq!(inst, "call_indirect");
+}
+
+#[test]
+fn uninit_read() {
+    let (ub, inst) = run_strict("uninit_read", vec![]).unwrap();
+    assert_eq!(
+        ub.kind,
+        UbKind::UninitRead {
+            addr: 0,
+            ty: Type::I32
+        }
+    );
+    assert_eq!(inst, "mload");
+
+    // Reading a value that is only partially written.
+    let (ub, _) = run_strict("partial_read", vec![]).unwrap();
+    assert!(matches!(ub.kind, UbKind::UninitRead { .. }));
+
+    assert!(run_strict("init_read", vec![]).is_none());
+}
+
+#[test]
+fn evm_memory_is_zero_initialized() {
+    assert!(run_strict("evm_memory", vec![]).is_none());
+}
+
+#[test]
+fn storage_key() {
+    for (func, expected) in [
+        ("sstore_undef_key", "evm_sstore"),
+        ("tstore_undef_key", "evm_tstore"),
+    ] {
+        let (ub, inst) = run_strict(func, vec![]).unwrap();
+        assert_eq!(undef_use(&ub), UndefUse::StorageKey);
+        assert_eq!(inst, expected);
+    }
+}
+
+#[test]
+fn log_topic() {
+    let (ub, inst) = run_strict("log_undef_topic", vec![]).unwrap();
+    assert_eq!(undef_use(&ub), UndefUse::LogTopic);
+    assert_eq!(inst, "evm_log2");
+}
+
+#[test]
+fn call_operands() {
+    let (ub, inst) = run_strict("call_undef_addr", vec![]).unwrap();
+    assert_eq!(undef_use(&ub), UndefUse::CallAddr);
+    assert_eq!(inst, "evm_call");
+
+    let (ub, _) = run_strict("call_undef_value", vec![]).unwrap();
+    assert_eq!(undef_use(&ub), UndefUse::CallValue);
+}
+
+#[test]
+fn no_branch_dest() {
+    let (ub, inst) = run_strict("br_table_no_dest", vec![imm(3)]).unwrap();
+    assert!(matches!(ub.kind, UbKind::NoBranchDest { .. }));
+    assert_eq!(inst, "br_table");
+
+    assert!(run_strict("br_table_no_dest", vec![imm(2)]).is_none());
+}
+
+#[test]
+fn offending_inst() {
+    let (module, lookup) = parse();
+    let func_ref = lookup("div_undef");
+    let udiv = module.func_store.view(func_ref, |func| {
+        let block = func.layout.entry_block().unwrap();
+        func.layout.first_inst_of(block).unwrap()
+    });
+
+    let mut machine = Machine::new(module);
+    machine.enable_strict_mode();
+    let result = machine.run(func_ref, vec![imm(1)]);
+    let ExecResult::UndefinedBehavior(ub) = result else {
+        panic!("undefined behavior is not detected");
+    };
+    assert_eq!((ub.func, ub.inst), (func_ref, udiv));
+}
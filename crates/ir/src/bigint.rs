@@ -154,8 +154,9 @@ impl I256 {
     }
 
     pub fn make_negative(abs: U256) -> Self {
+        // Zero is always positive so that it has a unique representation.
         Self {
-            is_negative: true,
+            is_negative: !abs.is_zero(),
             abs,
         }
     }
@@ -62,6 +62,16 @@ pub struct Call {
     args: SmallVec<[ValueId; 8]>,
 }
 
+/// Calls the function pointed to by `callee`, which is a pointer obtained by
+/// `get_function_ptr`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Inst)]
+#[inst(side_effect(super::SideEffect::Write))]
+pub struct CallIndirect {
+    callee: ValueId,
+
+    args: SmallVec<[ValueId; 8]>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Inst)]
 #[inst(side_effect(super::SideEffect::Write))]
 #[inst(terminator)]
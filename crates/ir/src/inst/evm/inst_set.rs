@@ -23,6 +23,7 @@ pub struct EvmInstSet(
     cmp::Sgt,
     cmp::Le,
     cmp::Ge,
+    cmp::Sle,
     cmp::Sge,
     cmp::Eq,
     cmp::Ne,
@@ -32,6 +33,7 @@ pub struct EvmInstSet(
     control_flow::Phi,
     control_flow::BrTable,
     control_flow::Call,
+    control_flow::CallIndirect,
     control_flow::Return,
     data::Mload,
     data::Mstore,
@@ -117,11 +117,12 @@ impl Interpret for Sar {
         let value = state.lookup_val(*self.value());
 
         EvalValue::zip_with_imm(bits, value, |bits, value| {
-            let shifted = value >> bits;
-            if value.is_positive() {
-                shifted
+            // Shifting the complement of a negative value fills the vacated
+            // bits with one, which rounds the result toward negative infinity.
+            if value.is_negative() {
+                !(!value >> bits)
             } else {
-                -shifted
+                value >> bits
             }
         })
     }
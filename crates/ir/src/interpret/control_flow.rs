@@ -77,9 +77,27 @@ impl Interpret for Call {
             .map(|arg| state.lookup_val(*arg))
             .collect();
 
-        let val = state.call_func(func, args);
+        // The action needs to be set before the call so that the callee can
+        // halt the execution.
         state.set_action(Action::Continue);
-        val
+        state.call_func(func, args)
+    }
+}
+
+impl Interpret for CallIndirect {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        let callee = state.lookup_val(*self.callee());
+        let args = self
+            .args()
+            .iter()
+            .map(|arg| state.lookup_val(*arg))
+            .collect();
+
+        state.set_action(Action::Continue);
+        match state.resolve_func_ptr(callee) {
+            Some(func) => state.call_func(func, args),
+            None => EvalValue::Undef,
+        }
     }
 }
 
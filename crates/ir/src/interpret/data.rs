@@ -35,12 +35,16 @@ impl Interpret for Gep {
             "GEP must start with a pointer type"
         );
 
-        let mut offset = 0;
+        // Address arithmetic wraps around in the pointer representation, an
+        // out-of-range address is reported when the memory is accessed.
+        let ptr_ty = state.dfg().ctx.type_layout.pointer_repl();
+        let imm_of = |value: usize| Immediate::from_i256(I256::from(value), ptr_ty);
+
+        let mut offset = imm_of(0);
         for value in self.values()[1..].iter() {
             let Some(idx_value) = state.lookup_val(*value).as_imm() else {
                 return EvalValue::Undef;
             };
-            let idx_value = idx_value.as_usize();
 
             let cmpd = match current_ty {
                 Type::I1
@@ -61,28 +65,30 @@ impl Interpret for Gep {
                 .ctx
                 .with_ty_store(|s| s.resolve_compound(cmpd).clone());
 
+            let idx = Immediate::from_i256(idx_value.as_i256(), ptr_ty);
             match cmpd_data {
                 CompoundType::Array { elem, .. } => {
                     let elem_size = state.dfg().ctx.size_of_unchecked(elem);
-                    offset += elem_size * idx_value;
+                    offset = offset + imm_of(elem_size) * idx;
                     current_ty = elem;
                 }
 
                 CompoundType::Ptr(ty) => {
                     let size = state.dfg().ctx.size_of_unchecked(ty);
-                    offset += size * idx_value;
+                    offset = offset + imm_of(size) * idx;
                     current_ty = ty;
                 }
 
                 CompoundType::Struct(s) => {
+                    let idx_value = idx_value.as_usize();
                     let mut local_offset = 0;
                     for i in 0..idx_value {
                         let field_ty = s.fields[i];
                         let size = state.dfg().ctx.size_of_unchecked(field_ty);
                         let align = state.dfg().ctx.align_of_unchecked(field_ty);
-                        local_offset += align_to(offset + size, align);
+                        local_offset += align_to(size, align);
                     }
-                    offset += local_offset;
+                    offset = offset + imm_of(local_offset);
                     current_ty = s.fields[idx_value];
                 }
 
@@ -94,12 +100,18 @@ impl Interpret for Gep {
             }
         }
 
-        let tl = state.dfg().ctx.type_layout;
-        let res = base_addr + Immediate::from_i256(I256::from(offset), tl.pointer_repl());
+        let res = base_addr + offset;
         EvalValue::Imm(res)
     }
 }
 
+impl Interpret for GetFunctionPtr {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        state.func_ptr(*self.func())
+    }
+}
+
 impl Interpret for Alloca {
     fn interpret(&self, state: &mut dyn State) -> EvalValue {
         state.alloca(*self.ty())
@@ -1,118 +1,673 @@
-use super::{Action, EvalValue, Interpret, State};
-use crate::inst::evm::*;
+use std::ops::Range;
 
-impl Interpret for EvmUdiv {
+use primitive_types::U512;
+use sha3::{Digest, Keccak256};
+
+use super::{
+    Action, CallKind, DataSource, EnvQuery, EvalValue, ExtCall, HaltReason, Interpret, State,
+    MEMORY_LIMIT,
+};
+use crate::{inst::evm::*, Immediate, Type, I256, U256};
+
+macro_rules! impl_binary {
+    ($ty:ty, $lhs:ident, $rhs:ident, $method:ident) => {
+        impl Interpret for $ty {
+            fn interpret(&self, state: &mut dyn State) -> EvalValue {
+                state.set_action(Action::Continue);
+
+                let lhs = state.lookup_val(*self.$lhs());
+                let rhs = state.lookup_val(*self.$rhs());
+
+                EvalValue::zip_with_imm(lhs, rhs, Immediate::$method)
+            }
+        }
+    };
+}
+
+impl_binary!(EvmUdiv, lhs, rhs, evm_udiv);
+impl_binary!(EvmSdiv, lhs, rhs, evm_sdiv);
+impl_binary!(EvmUmod, lhs, rhs, evm_umod);
+impl_binary!(EvmSmod, lhs, rhs, evm_smod);
+impl_binary!(EvmExp, base, exponent, evm_exp);
+impl_binary!(EvmByte, pos, value, evm_byte);
+
+impl Interpret for EvmAddMod {
     fn interpret(&self, state: &mut dyn State) -> EvalValue {
         state.set_action(Action::Continue);
-
         let lhs = state.lookup_val(*self.lhs());
         let rhs = state.lookup_val(*self.rhs());
+        let modulus = state.lookup_val(*self.modulus());
 
-        EvalValue::zip_with_imm(
-            lhs,
-            rhs,
-            |lhs, rhs| {
-                if rhs.is_zero() {
-                    rhs
-                } else {
-                    lhs.udiv(rhs)
-                }
-            },
-        )
+        match (lhs.as_imm(), rhs.as_imm(), modulus.as_imm()) {
+            (Some(lhs), Some(rhs), Some(modulus)) => lhs.evm_add_mod(rhs, modulus).into(),
+            _ => EvalValue::Undef,
+        }
     }
 }
 
-impl Interpret for EvmSdiv {
+impl Interpret for EvmMulMod {
     fn interpret(&self, state: &mut dyn State) -> EvalValue {
         state.set_action(Action::Continue);
-
         let lhs = state.lookup_val(*self.lhs());
         let rhs = state.lookup_val(*self.rhs());
+        let modulus = state.lookup_val(*self.modulus());
 
-        EvalValue::zip_with_imm(
-            lhs,
-            rhs,
-            |lhs, rhs| {
-                if rhs.is_zero() {
-                    rhs
-                } else {
-                    lhs.sdiv(rhs)
-                }
-            },
-        )
+        match (lhs.as_imm(), rhs.as_imm(), modulus.as_imm()) {
+            (Some(lhs), Some(rhs), Some(modulus)) => lhs.evm_mul_mod(rhs, modulus).into(),
+            _ => EvalValue::Undef,
+        }
     }
 }
 
-impl Interpret for EvmUmod {
+/// Arithmetic with the exact semantics of the EVM, which is shared with the
+/// constant folding in the optimizer.
+impl Immediate {
+    /// `self / rhs`, which is zero if `rhs` is zero.
+    pub fn evm_udiv(self, rhs: Self) -> Self {
+        if rhs.is_zero() {
+            rhs
+        } else {
+            self.udiv(rhs)
+        }
+    }
+
+    /// Signed `self / rhs`, which is zero if `rhs` is zero.
+    pub fn evm_sdiv(self, rhs: Self) -> Self {
+        if rhs.is_zero() {
+            rhs
+        } else {
+            self.sdiv(rhs)
+        }
+    }
+
+    /// `self % rhs`, which is zero if `rhs` is zero.
+    pub fn evm_umod(self, rhs: Self) -> Self {
+        if rhs.is_zero() {
+            rhs
+        } else {
+            self.urem(rhs)
+        }
+    }
+
+    /// Signed `self % rhs`, which is zero if `rhs` is zero.
+    pub fn evm_smod(self, rhs: Self) -> Self {
+        if rhs.is_zero() {
+            rhs
+        } else {
+            self.srem(rhs)
+        }
+    }
+
+    /// `(self + rhs) % modulus`, which is zero if `modulus` is zero.
+    pub fn evm_add_mod(self, rhs: Self, modulus: Self) -> Self {
+        // The intermediate sum is not subject to the 2^256 modulo.
+        let ty = self.ty();
+        let m = to_u256(modulus);
+        if m.is_zero() {
+            return Immediate::zero(ty);
+        }
+        let sum = U512::from(to_u256(self)) + U512::from(to_u256(rhs));
+        let res = U256::try_from(sum % U512::from(m)).unwrap();
+        from_u256(res, ty)
+    }
+
+    /// `(self * rhs) % modulus`, which is zero if `modulus` is zero.
+    pub fn evm_mul_mod(self, rhs: Self, modulus: Self) -> Self {
+        // The intermediate product is not subject to the 2^256 modulo.
+        let ty = self.ty();
+        let m = to_u256(modulus);
+        if m.is_zero() {
+            return Immediate::zero(ty);
+        }
+        let prod = to_u256(self).full_mul(to_u256(rhs));
+        let res = U256::try_from(prod % U512::from(m)).unwrap();
+        from_u256(res, ty)
+    }
+
+    /// `self ** exponent` modulo 2^256.
+    pub fn evm_exp(self, exponent: Self) -> Self {
+        let (res, _) = to_u256(self).overflowing_pow(to_u256(exponent));
+        from_u256(res, self.ty())
+    }
+
+    /// The `self`-th byte of `value` counted from the most significant byte,
+    /// which is zero if `self` is 32 or more.
+    pub fn evm_byte(self, value: Self) -> Self {
+        let pos = to_u256(self);
+        let byte = if pos < U256::from(32) {
+            to_u256(value).byte(31 - pos.as_usize())
+        } else {
+            0
+        };
+        from_u256(byte.into(), value.ty())
+    }
+}
+
+impl Interpret for EvmKeccak256 {
     fn interpret(&self, state: &mut dyn State) -> EvalValue {
         state.set_action(Action::Continue);
+        let addr = state.lookup_val(*self.addr());
+        let len = state.lookup_val(*self.len());
 
-        let lhs = state.lookup_val(*self.lhs());
-        let rhs = state.lookup_val(*self.rhs());
+        let Some(data) = read_memory(state, addr, len) else {
+            return EvalValue::Undef;
+        };
 
-        EvalValue::zip_with_imm(
-            lhs,
-            rhs,
-            |lhs, rhs| {
-                if rhs.is_zero() {
-                    rhs
-                } else {
-                    lhs.urem(rhs)
-                }
-            },
-        )
+        let hash = Keccak256::digest(&data);
+        Immediate::I256(I256::from_be_bytes(&hash)).into()
     }
 }
 
-impl Interpret for EvmSmod {
+macro_rules! impl_env_query {
+    ($ty:ty, $query:ident) => {
+        impl Interpret for $ty {
+            fn interpret(&self, state: &mut dyn State) -> EvalValue {
+                state.set_action(Action::Continue);
+                state.env(EnvQuery::$query)
+            }
+        }
+    };
+
+    ($ty:ty, $query:ident, $arg:ident) => {
+        impl Interpret for $ty {
+            fn interpret(&self, state: &mut dyn State) -> EvalValue {
+                state.set_action(Action::Continue);
+                let arg = state.lookup_val(*self.$arg());
+                state.env(EnvQuery::$query(arg))
+            }
+        }
+    };
+}
+
+impl_env_query!(EvmAddress, Address);
+impl_env_query!(EvmBalance, Balance, contract_addr);
+impl_env_query!(EvmOrigin, Origin);
+impl_env_query!(EvmCaller, Caller);
+impl_env_query!(EvmCallValue, CallValue);
+impl_env_query!(EvmCalldataSize, CalldataSize);
+impl_env_query!(EvmCodeSize, CodeSize);
+impl_env_query!(EvmGasPrice, GasPrice);
+impl_env_query!(EvmExtCodeSize, ExtCodeSize, ext_addr);
+impl_env_query!(EvmReturnDataSize, ReturnDataSize);
+impl_env_query!(EvmExtCodeHash, ExtCodeHash, ext_addr);
+impl_env_query!(EvmBlockHash, BlockHash, block_num);
+impl_env_query!(EvmCoinBase, CoinBase, block_num);
+impl_env_query!(EvmTimestamp, Timestamp);
+impl_env_query!(EvmNumber, Number);
+impl_env_query!(EvmPrevRandao, PrevRandao);
+impl_env_query!(EvmGasLimit, GasLimit);
+impl_env_query!(EvmChainId, ChainId);
+impl_env_query!(EvmSelfBalance, SelfBalance);
+impl_env_query!(EvmBaseFee, BaseFee);
+impl_env_query!(EvmBlobHash, BlobHash, idx);
+impl_env_query!(EvmBlobBaseFee, BlobBaseFee);
+impl_env_query!(EvmGas, Gas);
+
+impl Interpret for EvmCalldataLoad {
     fn interpret(&self, state: &mut dyn State) -> EvalValue {
         state.set_action(Action::Continue);
+        let Some(offset) = state.lookup_val(*self.data_offset()).as_imm() else {
+            return EvalValue::Undef;
+        };
 
-        let lhs = state.lookup_val(*self.lhs());
-        let rhs = state.lookup_val(*self.rhs());
+        let data = state.env_data(DataSource::Calldata, to_offset(offset), 32);
+        Immediate::I256(I256::from_be_bytes(&data)).into()
+    }
+}
 
-        EvalValue::zip_with_imm(
-            lhs,
-            rhs,
-            |lhs, rhs| {
-                if rhs.is_zero() {
-                    rhs
-                } else {
-                    lhs.srem(rhs)
-                }
-            },
-        )
+impl Interpret for EvmCalldataCopy {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let dst = state.lookup_val(*self.dst_addr());
+        let offset = state.lookup_val(*self.data_offset());
+        let len = state.lookup_val(*self.len());
+
+        copy_to_memory(state, DataSource::Calldata, dst, offset, len);
+        EvalValue::Undef
     }
 }
 
-impl Interpret for EvmAddMod {
+impl Interpret for EvmCodeCopy {
     fn interpret(&self, state: &mut dyn State) -> EvalValue {
         state.set_action(Action::Continue);
-        let lhs = state.lookup_val(*self.lhs());
-        let rhs = state.lookup_val(*self.rhs());
+        let dst = state.lookup_val(*self.dst_addr());
+        let offset = state.lookup_val(*self.code_offset());
+        let len = state.lookup_val(*self.len());
+
+        copy_to_memory(state, DataSource::Code, dst, offset, len);
+        EvalValue::Undef
+    }
+}
+
+impl Interpret for EvmExtCodeCopy {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let ext_addr = state.lookup_val(*self.ext_addr());
+        let dst = state.lookup_val(*self.dst_addr());
+        let offset = state.lookup_val(*self.code_offset());
+        let len = state.lookup_val(*self.len());
+
+        copy_to_memory(state, DataSource::ExtCode(ext_addr), dst, offset, len);
+        EvalValue::Undef
+    }
+}
+
+impl Interpret for EvmReturnDataCopy {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let dst = state.lookup_val(*self.dst_addr());
+        let offset = state.lookup_val(*self.data_offset());
+        let len = state.lookup_val(*self.len());
+
+        copy_to_memory(state, DataSource::ReturnData, dst, offset, len);
+        EvalValue::Undef
+    }
+}
+
+impl Interpret for EvmMstore8 {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let addr = state.lookup_val(*self.addr());
+        let val = state.lookup_val(*self.val());
+
+        let byte = val.with_imm(|val| val.trunc(Type::I8));
+        state.store(addr, byte, Type::I8)
+    }
+}
+
+impl Interpret for EvmSload {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let key = state.lookup_val(*self.key());
+        state.sload(key)
+    }
+}
+
+impl Interpret for EvmSstore {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let key = state.lookup_val(*self.key());
+        let val = state.lookup_val(*self.val());
+        state.sstore(key, val);
+        EvalValue::Undef
+    }
+}
+
+impl Interpret for EvmTload {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let key = state.lookup_val(*self.key());
+        state.tload(key)
+    }
+}
+
+impl Interpret for EvmTstore {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let key = state.lookup_val(*self.key());
+        let val = state.lookup_val(*self.val());
+        state.tstore(key, val);
+        EvalValue::Undef
+    }
+}
+
+impl Interpret for EvmMsize {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        state.msize()
+    }
+}
+
+impl Interpret for EvmMcopy {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let dest = state.lookup_val(*self.dest());
+        let addr = state.lookup_val(*self.addr());
+        let len = state.lookup_val(*self.len());
+
+        if let Some(data) = read_memory(state, addr, len) {
+            write_memory(state, dest, &data);
+        }
+        EvalValue::Undef
+    }
+}
 
-        EvalValue::zip_with_imm(lhs, rhs, |lhs, rhs| {
-            if rhs.is_zero() {
-                rhs
-            } else {
-                (lhs + rhs).urem(rhs)
+macro_rules! impl_log {
+    ($ty:ty $(, $topic:ident)*) => {
+        impl Interpret for $ty {
+            fn interpret(&self, state: &mut dyn State) -> EvalValue {
+                state.set_action(Action::Continue);
+                let addr = state.lookup_val(*self.addr());
+                let len = state.lookup_val(*self.len());
+                let topics = vec![$(state.lookup_val(*self.$topic())),*];
+
+                if let Some(data) = read_memory(state, addr, len) {
+                    state.emit_log(data, topics);
+                }
+                EvalValue::Undef
             }
-        })
+        }
+    };
+}
+
+impl_log!(EvmLog0);
+impl_log!(EvmLog1, topic0);
+impl_log!(EvmLog2, topic0, topic1);
+impl_log!(EvmLog3, topic0, topic1, topic2);
+impl_log!(EvmLog4, topic0, topic1, topic2, topic3);
+
+impl Interpret for EvmCreate {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let val = state.lookup_val(*self.val());
+        let addr = state.lookup_val(*self.addr());
+        let len = state.lookup_val(*self.len());
+
+        let Some(code) = read_memory(state, addr, len) else {
+            return EvalValue::Undef;
+        };
+        state.ext_create(val, code, None)
     }
 }
 
-impl Interpret for EvmMulMod {
+impl Interpret for EvmCreate2 {
     fn interpret(&self, state: &mut dyn State) -> EvalValue {
         state.set_action(Action::Continue);
-        let lhs = state.lookup_val(*self.lhs());
-        let rhs = state.lookup_val(*self.rhs());
+        let val = state.lookup_val(*self.val());
+        let addr = state.lookup_val(*self.addr());
+        let len = state.lookup_val(*self.len());
+        let salt = state.lookup_val(*self.salt());
+
+        let Some(code) = read_memory(state, addr, len) else {
+            return EvalValue::Undef;
+        };
+        state.ext_create(val, code, Some(salt))
+    }
+}
+
+impl Interpret for EvmCall {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let gas = state.lookup_val(*self.gas());
+        let addr = state.lookup_val(*self.addr());
+        let value = state.lookup_val(*self.val());
+        let arg_addr = state.lookup_val(*self.arg_addr());
+        let arg_len = state.lookup_val(*self.arg_len());
+        let ret_addr = state.lookup_val(*self.ret_addr());
+        let ret_len = state.lookup_val(*self.ret_offset());
 
-        EvalValue::zip_with_imm(lhs, rhs, |lhs, rhs| {
-            if rhs.is_zero() {
-                rhs
-            } else {
-                (lhs * rhs).urem(rhs)
+        let Some(input) = read_memory(state, arg_addr, arg_len) else {
+            return EvalValue::Undef;
+        };
+
+        let call = ExtCall {
+            kind: CallKind::Call,
+            gas,
+            addr,
+            value,
+            input,
+        };
+        perform_call(state, call, ret_addr, ret_len)
+    }
+}
+
+impl Interpret for EvmCallCode {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let gas = state.lookup_val(*self.gas());
+        let addr = state.lookup_val(*self.addr());
+        let value = state.lookup_val(*self.val());
+        let arg_addr = state.lookup_val(*self.arg_addr());
+        let arg_len = state.lookup_val(*self.arg_len());
+        let ret_addr = state.lookup_val(*self.ret_addr());
+        let ret_len = state.lookup_val(*self.ret_offset());
+
+        let Some(input) = read_memory(state, arg_addr, arg_len) else {
+            return EvalValue::Undef;
+        };
+
+        let call = ExtCall {
+            kind: CallKind::CallCode,
+            gas,
+            addr,
+            value,
+            input,
+        };
+        perform_call(state, call, ret_addr, ret_len)
+    }
+}
+
+impl Interpret for EvmDelegateCall {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let gas = state.lookup_val(*self.gas());
+        let addr = state.lookup_val(*self.ext_addr());
+        let arg_addr = state.lookup_val(*self.arg_addr());
+        let arg_len = state.lookup_val(*self.arg_len());
+        let ret_addr = state.lookup_val(*self.ret_addr());
+        let ret_len = state.lookup_val(*self.ret_len());
+
+        let Some(input) = read_memory(state, arg_addr, arg_len) else {
+            return EvalValue::Undef;
+        };
+
+        let call = ExtCall {
+            kind: CallKind::DelegateCall,
+            gas,
+            addr,
+            value: EvalValue::Undef,
+            input,
+        };
+        perform_call(state, call, ret_addr, ret_len)
+    }
+}
+
+impl Interpret for EvmStaticCall {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let gas = state.lookup_val(*self.gas());
+        let addr = state.lookup_val(*self.ext_addr());
+        let arg_addr = state.lookup_val(*self.arg_addr());
+        let arg_len = state.lookup_val(*self.arg_len());
+        let ret_addr = state.lookup_val(*self.ret_addr());
+        let ret_len = state.lookup_val(*self.ret_len());
+
+        let Some(input) = read_memory(state, arg_addr, arg_len) else {
+            return EvalValue::Undef;
+        };
+
+        let call = ExtCall {
+            kind: CallKind::StaticCall,
+            gas,
+            addr,
+            value: EvalValue::Undef,
+            input,
+        };
+        perform_call(state, call, ret_addr, ret_len)
+    }
+}
+
+impl Interpret for EvmStop {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Halt(HaltReason::Stop));
+        EvalValue::Undef
+    }
+}
+
+impl Interpret for EvmInvalid {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Halt(HaltReason::Invalid));
+        EvalValue::Undef
+    }
+}
+
+impl Interpret for EvmReturn {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        let addr = state.lookup_val(*self.addr());
+        let len = state.lookup_val(*self.len());
+
+        // An undefined range is read as empty data, but a range beyond the
+        // memory limit halts with `OutOfGas` in `read_memory`.
+        state.set_action(Action::Halt(HaltReason::Return(Vec::new())));
+        if let Some(data) = read_memory(state, addr, len) {
+            state.set_action(Action::Halt(HaltReason::Return(data)));
+        }
+        EvalValue::Undef
+    }
+}
+
+impl Interpret for EvmRevert {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        let addr = state.lookup_val(*self.addr());
+        let len = state.lookup_val(*self.len());
+
+        state.set_action(Action::Halt(HaltReason::Revert(Vec::new())));
+        if let Some(data) = read_memory(state, addr, len) {
+            state.set_action(Action::Halt(HaltReason::Revert(data)));
+        }
+        EvalValue::Undef
+    }
+}
+
+impl Interpret for EvmSelfDestruct {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        let beneficiary = state.lookup_val(*self.addr());
+        state.set_action(Action::Halt(HaltReason::SelfDestruct(beneficiary)));
+        EvalValue::Undef
+    }
+}
+
+impl Interpret for EvmMalloc {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        let size = state.lookup_val(*self.size());
+        state.malloc(size)
+    }
+}
+
+impl Interpret for EvmContractSize {
+    fn interpret(&self, state: &mut dyn State) -> EvalValue {
+        state.set_action(Action::Continue);
+        state.contract_size(*self.contract())
+    }
+}
+
+/// Performs `call` and copies its output to the return buffer.
+fn perform_call(
+    state: &mut dyn State,
+    call: ExtCall,
+    ret_addr: EvalValue,
+    ret_len: EvalValue,
+) -> EvalValue {
+    let (success, output) = state.ext_call(call);
+
+    // Only the part of the output that fits in the return buffer is copied.
+    if let Some(ret_len) = ret_len.as_imm() {
+        let len = to_offset(ret_len).min(output.len());
+        write_memory(state, ret_addr, &output[..len]);
+    }
+
+    success
+}
+
+/// Copies `len` bytes of `src` starting from `offset` to the memory at `dst`.
+fn copy_to_memory(
+    state: &mut dyn State,
+    src: DataSource,
+    dst: EvalValue,
+    offset: EvalValue,
+    len: EvalValue,
+) {
+    let (Some(dst_imm), Some(offset), Some(len)) = (dst.as_imm(), offset.as_imm(), len.as_imm())
+    else {
+        return;
+    };
+    // Check the destination before asking the state for the data so that a huge
+    // `len` doesn't allocate a huge buffer.
+    if memory_range(dst_imm, len).is_none() {
+        state.set_action(Action::Halt(HaltReason::OutOfGas));
+        return;
+    }
+
+    let data = state.env_data(src, to_offset(offset), to_offset(len));
+    write_memory(state, dst, &data);
+}
+
+/// Reads `len` bytes from the memory at `addr`.
+///
+/// Returns `None` if either `addr` or `len` is undefined, or if the range
+/// exceeds [`MEMORY_LIMIT`]. In the latter case, the execution is halted with
+/// [`HaltReason::OutOfGas`].
+fn read_memory(state: &mut dyn State, addr: EvalValue, len: EvalValue) -> Option<Vec<u8>> {
+    let range = memory_range(addr.as_imm()?, len.as_imm()?);
+    let Some(range) = range else {
+        state.set_action(Action::Halt(HaltReason::OutOfGas));
+        return None;
+    };
+
+    let data = range
+        .map(|addr| {
+            let addr = Immediate::I256(I256::from(addr)).into();
+            match state.load(addr, Type::I8).as_imm() {
+                Some(byte) => byte.as_i256().trunc_to_i8() as u8,
+                // Memory is zero-initialized in the EVM.
+                None => 0,
             }
         })
+        .collect();
+    Some(data)
+}
+
+/// Writes `data` to the memory at `addr`.
+///
+/// Halts the execution with [`HaltReason::OutOfGas`] if the range exceeds
+/// [`MEMORY_LIMIT`].
+fn write_memory(state: &mut dyn State, addr: EvalValue, data: &[u8]) {
+    let Some(addr) = addr.as_imm() else {
+        return;
+    };
+    let len = Immediate::I256(I256::from(data.len()));
+    let Some(range) = memory_range(addr, len) else {
+        state.set_action(Action::Halt(HaltReason::OutOfGas));
+        return;
+    };
+
+    for (byte_addr, byte) in range.zip(data) {
+        let byte_addr = Immediate::I256(I256::from(byte_addr)).into();
+        let byte = Immediate::I8(*byte as i8).into();
+        state.store(byte_addr, byte, Type::I8);
+    }
+}
+
+/// Returns the range of `len` bytes starting from `addr`, or `None` if the
+/// range exceeds [`MEMORY_LIMIT`].
+///
+/// An empty range is always valid regardless of `addr`, like in the EVM.
+fn memory_range(addr: Immediate, len: Immediate) -> Option<Range<usize>> {
+    let len = to_offset(len);
+    if len == 0 {
+        return Some(0..0);
+    }
+
+    let addr = to_offset(addr);
+    let end = addr.checked_add(len)?;
+    (end <= MEMORY_LIMIT).then_some(addr..end)
+}
+
+fn to_u256(imm: Immediate) -> U256 {
+    imm.as_i256().to_u256()
+}
+
+fn from_u256(val: U256, ty: Type) -> Immediate {
+    Immediate::from_i256(I256::from_u256(val), ty)
+}
+
+/// Converts an offset into `usize`, saturating at `usize::MAX`.
+fn to_offset(imm: Immediate) -> usize {
+    let val = to_u256(imm);
+    if val > U256::from(usize::MAX) {
+        usize::MAX
+    } else {
+        val.as_usize()
     }
 }
@@ -16,13 +16,13 @@ mod logic;
 pub trait Interpret {
     fn interpret(&self, state: &mut dyn State) -> EvalValue;
 
-    // TODO: Implement `Interpret` for all inst types and use
-    // `type Members = All`
+    // `type Members = All` can't be used outside of the `inst` module, so all
+    // insts are listed in the same order as `InstSetBase`.
     type Members = (
         inst::arith::Neg,
         inst::arith::Add,
-        inst::arith::Sub,
         inst::arith::Mul,
+        inst::arith::Sub,
         inst::arith::Sdiv,
         inst::arith::Udiv,
         inst::arith::Umod,
@@ -30,16 +30,6 @@ pub trait Interpret {
         inst::arith::Shl,
         inst::arith::Shr,
         inst::arith::Sar,
-        inst::logic::Not,
-        inst::logic::And,
-        inst::logic::Or,
-        inst::logic::Xor,
-        inst::cast::Sext,
-        inst::cast::Zext,
-        inst::cast::Trunc,
-        inst::cast::IntToPtr,
-        inst::cast::PtrToInt,
-        inst::cast::Bitcast,
         inst::cmp::Lt,
         inst::cmp::Gt,
         inst::cmp::Slt,
@@ -51,24 +41,92 @@ pub trait Interpret {
         inst::cmp::Eq,
         inst::cmp::Ne,
         inst::cmp::IsZero,
+        inst::logic::Not,
+        inst::logic::And,
+        inst::logic::Or,
+        inst::logic::Xor,
+        inst::cast::Sext,
+        inst::cast::Zext,
+        inst::cast::Trunc,
+        inst::cast::Bitcast,
+        inst::cast::IntToPtr,
+        inst::cast::PtrToInt,
         inst::data::Mload,
         inst::data::Mstore,
         inst::data::Gep,
+        inst::data::GetFunctionPtr,
         inst::data::Alloca,
         inst::data::InsertValue,
         inst::data::ExtractValue,
+        inst::control_flow::Call,
+        inst::control_flow::CallIndirect,
         inst::control_flow::Jump,
         inst::control_flow::Br,
         inst::control_flow::BrTable,
-        inst::control_flow::Phi,
-        inst::control_flow::Call,
         inst::control_flow::Return,
+        inst::control_flow::Phi,
         inst::evm::EvmUdiv,
         inst::evm::EvmSdiv,
         inst::evm::EvmUmod,
         inst::evm::EvmSmod,
+        inst::evm::EvmStop,
+        inst::evm::EvmInvalid,
         inst::evm::EvmAddMod,
         inst::evm::EvmMulMod,
+        inst::evm::EvmExp,
+        inst::evm::EvmByte,
+        inst::evm::EvmKeccak256,
+        inst::evm::EvmAddress,
+        inst::evm::EvmBalance,
+        inst::evm::EvmOrigin,
+        inst::evm::EvmCaller,
+        inst::evm::EvmCallValue,
+        inst::evm::EvmCalldataLoad,
+        inst::evm::EvmCalldataCopy,
+        inst::evm::EvmCalldataSize,
+        inst::evm::EvmCodeSize,
+        inst::evm::EvmCodeCopy,
+        inst::evm::EvmGasPrice,
+        inst::evm::EvmExtCodeSize,
+        inst::evm::EvmExtCodeCopy,
+        inst::evm::EvmReturnDataSize,
+        inst::evm::EvmReturnDataCopy,
+        inst::evm::EvmExtCodeHash,
+        inst::evm::EvmBlockHash,
+        inst::evm::EvmCoinBase,
+        inst::evm::EvmTimestamp,
+        inst::evm::EvmNumber,
+        inst::evm::EvmPrevRandao,
+        inst::evm::EvmGasLimit,
+        inst::evm::EvmChainId,
+        inst::evm::EvmSelfBalance,
+        inst::evm::EvmBaseFee,
+        inst::evm::EvmBlobHash,
+        inst::evm::EvmBlobBaseFee,
+        inst::evm::EvmMstore8,
+        inst::evm::EvmSload,
+        inst::evm::EvmSstore,
+        inst::evm::EvmMsize,
+        inst::evm::EvmGas,
+        inst::evm::EvmTload,
+        inst::evm::EvmTstore,
+        inst::evm::EvmMcopy,
+        inst::evm::EvmLog0,
+        inst::evm::EvmLog1,
+        inst::evm::EvmLog2,
+        inst::evm::EvmLog3,
+        inst::evm::EvmLog4,
+        inst::evm::EvmCreate,
+        inst::evm::EvmCall,
+        inst::evm::EvmCallCode,
+        inst::evm::EvmReturn,
+        inst::evm::EvmDelegateCall,
+        inst::evm::EvmCreate2,
+        inst::evm::EvmStaticCall,
+        inst::evm::EvmRevert,
+        inst::evm::EvmSelfDestruct,
+        inst::evm::EvmMalloc,
+        inst::evm::EvmContractSize,
     );
 }
 
@@ -106,9 +164,65 @@ pub trait State {
 
     fn alloca(&mut self, ty: Type) -> EvalValue;
 
+    /// Returns the address of the function `func`.
+    ///
+    /// The returned value is opaque; a state only needs to guarantee that the
+    /// same function always maps to the same address.
+    fn func_ptr(&mut self, func: FuncRef) -> EvalValue;
+
+    /// Returns the function whose address is `ptr`, i.e., the inverse of
+    /// [`State::func_ptr`], or `None` if `ptr` doesn't point to a function.
+    fn resolve_func_ptr(&mut self, ptr: EvalValue) -> Option<FuncRef>;
+
     fn dfg(&self) -> &DataFlowGraph;
+
+    /// Allocates `size` bytes of memory that live until the end of the
+    /// execution and returns the address of the allocated region.
+    fn malloc(&mut self, size: EvalValue) -> EvalValue;
+
+    /// Returns the current size of memory in bytes.
+    fn msize(&mut self) -> EvalValue;
+
+    /// Queries the execution environment, e.g., block and transaction
+    /// context.
+    fn env(&mut self, query: EnvQuery) -> EvalValue;
+
+    /// Returns `len` bytes of `src` starting from `offset`.
+    ///
+    /// Bytes beyond the end of the data source must be filled with zeros.
+    fn env_data(&mut self, src: DataSource, offset: usize, len: usize) -> Vec<u8>;
+
+    fn sload(&mut self, key: EvalValue) -> EvalValue;
+
+    fn sstore(&mut self, key: EvalValue, value: EvalValue);
+
+    fn tload(&mut self, key: EvalValue) -> EvalValue;
+
+    fn tstore(&mut self, key: EvalValue, value: EvalValue);
+
+    fn emit_log(&mut self, data: Vec<u8>, topics: Vec<EvalValue>);
+
+    /// Performs a message call to another account and returns the success
+    /// flag together with the output data of the callee.
+    fn ext_call(&mut self, call: ExtCall) -> (EvalValue, Vec<u8>);
+
+    /// Creates a new account with `code` as init code and returns the address
+    /// of the created account. `salt` is `Some` for `create2`.
+    fn ext_create(&mut self, value: EvalValue, code: Vec<u8>, salt: Option<EvalValue>)
+        -> EvalValue;
+
+    /// Returns the size of the contract whose entry function is `contract`.
+    fn contract_size(&mut self, contract: FuncRef) -> EvalValue;
 }
 
+/// The end of the memory range that a state can access, i.e., the maximum
+/// memory size in bytes.
+///
+/// The gas cost of memory expansion grows quadratically with the size, so no
+/// realistic gas limit can pay for an access beyond this. Accessing memory
+/// beyond the limit halts the execution with [`HaltReason::OutOfGas`].
+pub const MEMORY_LIMIT: usize = 1 << 25;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Action {
     Continue,
@@ -119,6 +233,95 @@ pub enum Action {
     /// corresponds to scrutinee.
     FallThrough,
     Return(EvalValue),
+    /// Indicate that the execution of the whole message call is terminated.
+    Halt(HaltReason),
+}
+
+/// The reason why a message call is terminated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HaltReason {
+    /// `evm_stop`.
+    Stop,
+    /// `evm_return` with the output data.
+    Return(Vec<u8>),
+    /// `evm_revert` with the revert data.
+    Revert(Vec<u8>),
+    /// `evm_invalid`.
+    Invalid,
+    /// `evm_self_destruct` with the beneficiary address.
+    SelfDestruct(EvalValue),
+    /// The gas limit of the message call is exceeded.
+    OutOfGas,
+    /// A static call tried to modify the state.
+    WriteProtection,
+}
+
+impl HaltReason {
+    /// Returns `true` if all state changes made by the message call need to be
+    /// reverted.
+    pub fn is_reverted(&self) -> bool {
+        matches!(
+            self,
+            Self::Revert(_) | Self::Invalid | Self::OutOfGas | Self::WriteProtection
+        )
+    }
+}
+
+/// Environment values that can be obtained through [`State::env`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvQuery {
+    Address,
+    Balance(EvalValue),
+    Origin,
+    Caller,
+    CallValue,
+    CalldataSize,
+    CodeSize,
+    GasPrice,
+    ExtCodeSize(EvalValue),
+    ExtCodeHash(EvalValue),
+    ReturnDataSize,
+    BlockHash(EvalValue),
+    CoinBase(EvalValue),
+    Timestamp,
+    Number,
+    PrevRandao,
+    GasLimit,
+    ChainId,
+    SelfBalance,
+    BaseFee,
+    BlobHash(EvalValue),
+    BlobBaseFee,
+    Gas,
+}
+
+/// Byte sequences that can be read through [`State::env_data`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataSource {
+    Calldata,
+    Code,
+    ExtCode(EvalValue),
+    ReturnData,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    Call,
+    CallCode,
+    DelegateCall,
+    StaticCall,
+}
+
+/// A message call request passed to [`State::ext_call`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtCall {
+    pub kind: CallKind,
+    pub gas: EvalValue,
+    pub addr: EvalValue,
+    /// The value to transfer, always `Undef` for `DelegateCall` and
+    /// `StaticCall`.
+    pub value: EvalValue,
+    pub input: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
@@ -7,7 +7,7 @@ use crate::{
     inst::InstId,
     ir_writer::{FuncWriteCtx, IrWrite},
     module::ModuleCtx,
-    GlobalVariableRef, I256,
+    GlobalVariableRef, I256, U256,
 };
 
 /// An opaque reference to [`Value`].
@@ -374,8 +374,16 @@ impl ops::Shl for Immediate {
 impl ops::Shr for Immediate {
     type Output = Self;
 
+    /// Logical shift right, which fills the vacated bits with zero.
     fn shr(self, rhs: Self) -> Self::Output {
-        self.apply_binop(rhs, ops::Shl::shl)
+        let ty = self.ty();
+        let bits = rhs.zext(Type::I256).as_i256().to_u256();
+        if bits >= U256::from(256) {
+            return Self::zero(ty);
+        }
+
+        let res = self.zext(Type::I256).as_i256().to_u256() >> bits;
+        Self::from_i256(I256::from_u256(res), ty)
     }
 }
 
@@ -447,3 +455,30 @@ imm_from_primary!(u64, i64, Immediate::I64);
 imm_from_primary!(i128, i128, Immediate::I128);
 imm_from_primary!(u128, i128, Immediate::I128);
 imm_from_primary!(I256, I256, Immediate::I256);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shr_is_logical() {
+        let shr = |value: i8, bits: i8| Immediate::I8(value) >> Immediate::I8(bits);
+        assert_eq!(shr(-128, 1), Immediate::I8(64));
+        assert_eq!(shr(-1, 7), Immediate::I8(1));
+        assert_eq!(shr(-1, 8), Immediate::I8(0));
+        assert_eq!(shr(64, 2), Immediate::I8(16));
+    }
+
+    #[test]
+    fn shr_by_large_amount() {
+        let value = Immediate::I256(I256::all_one());
+        assert_eq!(
+            value >> Immediate::I256(256.into()),
+            Immediate::zero(Type::I256)
+        );
+        assert_eq!(
+            value >> Immediate::I256(I256::all_one()),
+            Immediate::zero(Type::I256)
+        );
+    }
+}
@@ -122,12 +122,12 @@ impl TraitDefinition {
         let (arms, arms_mut): (Vec<_>, Vec<_>) = self.insts.iter().map(|path| {
             (quote!{
                 s if s == std::any::TypeId::of::<$crate::inst::#path>() => {
-                    <&#path as $crate::prelude::InstDowncast>::map(isb, inst, |inst| inst as &dyn $prop)
+                    <&$crate::inst::#path as $crate::prelude::InstDowncast>::map(isb, inst, |inst| inst as &dyn $prop)
                 }
             },
             quote!{
                 s if s == std::any::TypeId::of::<$crate::inst::#path>() => {
-                    <&mut #path as $crate::prelude::InstDowncastMut>::map_mut(isb, inst, |inst| inst as &mut dyn $prop)
+                    <&mut $crate::inst::#path as $crate::prelude::InstDowncastMut>::map_mut(isb, inst, |inst| inst as &mut dyn $prop)
                 }
             })}).unzip();
 
@@ -8,6 +8,7 @@ super::impl_inst_build! {Br, (cond: ValueId, nz_dest: BlockId, z_dest: BlockId)}
 super::impl_inst_build_common! {BrTable, ArityBound::AtLeast(1), build_br_table}
 super::impl_inst_build_common! {Phi, ArityBound::AtLeast(1), build_phi}
 super::impl_inst_build_common! {Call, ArityBound::AtLeast(1), build_call}
+super::impl_inst_build_common! {CallIndirect, ArityBound::AtLeast(1), build_call_indirect}
 super::impl_inst_build_common! {Return, ArityBound::AtMost(1), build_return}
 
 fn build_br_table(
@@ -19,13 +20,13 @@ fn build_br_table(
     let mut args = args.iter().peekable();
     let scrutinee = super::process_arg!(ctx, fb, args, ValueId);
 
-    let default = (*args.peek().unwrap())
-        .try_into()
+    let default = args
+        .peek()
+        .and_then(|&arg| arg.try_into().ok())
         .map(|block: &ast::BlockId| {
             args.next();
             ctx.block(block)
-        })
-        .ok();
+        });
 
     let mut table = Vec::new();
     for arg in args {
@@ -94,6 +95,34 @@ fn build_call(
     }
 }
 
+fn build_call_indirect(
+    ctx: &mut BuildCtx,
+    fb: &mut FunctionBuilder<ir::func_cursor::InstInserter>,
+    args: &[ast::InstArg],
+    has_inst: &dyn HasInst<CallIndirect>,
+) -> Result<CallIndirect, Box<Error>> {
+    let mut ast_args = args.iter().peekable();
+    let callee = super::process_arg!(ctx, fb, ast_args, ValueId);
+
+    let mut args = SmallVec::new();
+    while let Some(&ast_arg) = ast_args.peek() {
+        let Ok(value) = ast_arg.try_into() else {
+            break;
+        };
+
+        let value = ctx.value(fb, value);
+        args.push(value);
+
+        ast_args.next();
+    }
+
+    if let Some(arg) = ast_args.next() {
+        Err(Box::new(Error::UnexpectedTrailingInstArg(arg.span)))
+    } else {
+        Ok(CallIndirect::new(has_inst, callee, args))
+    }
+}
+
 fn build_return(
     ctx: &mut BuildCtx,
     fb: &mut FunctionBuilder<ir::func_cursor::InstInserter>,
@@ -143,7 +143,7 @@ impl Display for OperatingSystem {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum EvmVersion {
     Frontier,
     Homestead,
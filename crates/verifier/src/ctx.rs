@@ -1,21 +1,23 @@
 //! Verification context
 
-use sonatina_ir::{ControlFlowGraph, Function};
+use sonatina_ir::{module::FuncRef, ControlFlowGraph, Function};
 
 use crate::{error::ErrorData, ErrorStack};
 
 pub struct VerificationCtx<'a> {
+    pub func_ref: FuncRef,
     pub func: &'a Function,
     pub cfg: ControlFlowGraph,
     pub error_stack: ErrorStack,
 }
 
 impl<'a> VerificationCtx<'a> {
-    pub fn new(func: &'a Function) -> Self {
+    pub fn new(func_ref: FuncRef, func: &'a Function) -> Self {
         let mut cfg = ControlFlowGraph::new();
         cfg.compute(func);
 
         Self {
+            func_ref,
             func,
             cfg,
             error_stack: ErrorStack::default(),
@@ -28,6 +28,7 @@ pub enum ErrorKind {
     InstResultWrongType(Type),
     CalleeArgWrongType(Type),
     CalleeResultWrongType(Type),
+    CalleeNotFunctionPointer(ValueId),
     CompoundTypeIsNullReference(Type),
 }
 
@@ -45,7 +46,7 @@ impl ErrorKind {
             ValueIsNullReference(v) => IrSource::Value(v),
             BlockIsNullReference(b) | BranchToEntryBlock(b) => IrSource::Block(b),
             FunctionIsNullReference(f) => IrSource::Callee(f),
-            ValueLeak(v) => IrSource::Value(v),
+            ValueLeak(v) | CalleeNotFunctionPointer(v) => IrSource::Value(v),
             InstArgWrongType(ty)
             | InstResultWrongType(ty)
             | CalleeArgWrongType(ty)
@@ -133,6 +134,10 @@ impl fmt::Display for DisplayErrorKind<'_> {
                 let ty = ty.dump_string(self.ctx.module_ctx());
                 write!(f, "result type inconsistent with callee signature, {ty}")
             }
+            CalleeNotFunctionPointer(value) => {
+                let value = ValueWithTy(value).dump_string(&self.ctx);
+                write!(f, "indirect callee is not a function pointer, {value}")
+            }
         }
     }
 }
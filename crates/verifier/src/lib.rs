@@ -5,4 +5,4 @@ pub mod pass;
 
 pub use ctx::VerificationCtx;
 pub use error_stack::ErrorStack;
-pub use pass::VerificationPass;
+pub use pass::{CallIndirectVerifier, VerificationPass};
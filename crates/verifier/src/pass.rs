@@ -1,7 +1,11 @@
 //! Verification pass
 
+pub mod call_indirect;
+
+pub use call_indirect::CallIndirectVerifier;
+
 use crate::VerificationCtx;
 
 pub trait VerificationPass {
-    fn run(&mut self, ctx: VerificationCtx);
+    fn run(&mut self, ctx: &mut VerificationCtx);
 }
@@ -0,0 +1,184 @@
+//! Verifies that the callee of each `call_indirect` is a function pointer
+//! whose signature agrees with the arguments and the result of the call.
+
+use sonatina_ir::{
+    inst::control_flow::CallIndirect, types::CompoundType, InstDowncast, InstId, Type,
+};
+
+use crate::{
+    error::{ErrorData, ErrorKind, TraceInfoBuilder},
+    VerificationCtx, VerificationPass,
+};
+
+#[derive(Debug, Default)]
+pub struct CallIndirectVerifier {
+    errs: Vec<ErrorData>,
+}
+
+impl CallIndirectVerifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn verify_inst(&mut self, ctx: &VerificationCtx, inst_id: InstId) {
+        let func = ctx.func;
+        let is = func.inst_set();
+        let Some(call) = <&CallIndirect as InstDowncast>::downcast(is, func.dfg.inst(inst_id))
+        else {
+            return;
+        };
+
+        let trace_info = TraceInfoBuilder::new(ctx.func_ref)
+            .block(func.layout.inst_block(inst_id))
+            .inst_id(inst_id);
+
+        let callee = *call.callee();
+        let callee_ty = func.dfg.value_ty(callee);
+        let Some((args, ret_ty)) = func_sig_of(ctx, callee_ty) else {
+            let trace_info = trace_info.value(callee).ty(callee_ty).build();
+            let kind = ErrorKind::CalleeNotFunctionPointer(callee);
+            self.errs.push(ErrorData::new(kind, trace_info));
+            return;
+        };
+
+        let passed = call.args();
+        for (i, &arg) in passed.iter().enumerate() {
+            let arg_ty = func.dfg.value_ty(arg);
+            if args.get(i) != Some(&arg_ty) {
+                let trace_info = trace_info.value(arg).ty(arg_ty).build();
+                let kind = ErrorKind::CalleeArgWrongType(arg_ty);
+                self.errs.push(ErrorData::new(kind, trace_info));
+            }
+        }
+        // Missing arguments are reported with the expected types.
+        for &arg_ty in args.iter().skip(passed.len()) {
+            let trace_info = trace_info.ty(arg_ty).build();
+            let kind = ErrorKind::CalleeArgWrongType(arg_ty);
+            self.errs.push(ErrorData::new(kind, trace_info));
+        }
+
+        if let Some(result) = func.dfg.inst_result(inst_id) {
+            let result_ty = func.dfg.value_ty(result);
+            if result_ty != ret_ty {
+                let trace_info = trace_info.value(result).ty(result_ty).build();
+                let kind = ErrorKind::CalleeResultWrongType(result_ty);
+                self.errs.push(ErrorData::new(kind, trace_info));
+            }
+        }
+    }
+}
+
+impl VerificationPass for CallIndirectVerifier {
+    fn run(&mut self, ctx: &mut VerificationCtx) {
+        self.errs.clear();
+
+        for block in ctx.func.layout.iter_block() {
+            for inst_id in ctx.func.layout.iter_inst(block) {
+                self.verify_inst(ctx, inst_id);
+            }
+        }
+
+        ctx.report_nonfatal(&self.errs);
+    }
+}
+
+/// Returns the argument types and the return type of the function pointed to
+/// by a value of `ty`, or `None` if `ty` isn't a function pointer.
+fn func_sig_of(ctx: &VerificationCtx, ty: Type) -> Option<(Vec<Type>, Type)> {
+    let module_ctx = &ctx.func.dfg.ctx;
+    let CompoundType::Ptr(pointee) = ty.resolve_compound(module_ctx)? else {
+        return None;
+    };
+
+    match pointee.resolve_compound(module_ctx)? {
+        CompoundType::Func { args, ret_ty } => Some((args.to_vec(), ret_ty)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use smallvec::smallvec;
+    use sonatina_ir::{
+        builder::test_util::{test_func_builder, test_module_builder},
+        inst::control_flow::Return,
+        isa::Isa,
+        module::FuncRef,
+        Module,
+    };
+
+    use super::*;
+
+    fn verify(module: &Module, func_ref: FuncRef) -> Vec<ErrorKind> {
+        module.func_store.view(func_ref, |func| {
+            let mut ctx = VerificationCtx::new(func_ref, func);
+            CallIndirectVerifier::new().run(&mut ctx);
+            ctx.error_stack
+                .non_fatal_errors
+                .values()
+                .map(|err| err.kind)
+                .collect()
+        })
+    }
+
+    #[test]
+    fn callee_not_function_pointer() {
+        let mb = test_module_builder();
+        let (evm, mut builder) = test_func_builder(&mb, &[Type::I256], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let callee = builder.args()[0];
+        let ret =
+            builder.insert_inst_with(|| CallIndirect::new(is, callee, smallvec![]), Type::I32);
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(ret)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let errs = verify(&module, module.funcs()[0]);
+        assert_eq!(errs.len(), 1);
+        assert!(matches!(errs[0], ErrorKind::CalleeNotFunctionPointer(v) if v == callee));
+    }
+
+    #[test]
+    fn callee_signature_mismatch() {
+        let mb = test_module_builder();
+        let func_ty = mb.declare_func_type(&[Type::I32, Type::I64], Type::I64);
+        let func_ptr_ty = mb.ptr_type(func_ty);
+        let (evm, mut builder) = test_func_builder(&mb, &[func_ptr_ty, Type::I32], Type::I32);
+        let is = evm.inst_set();
+
+        let b0 = builder.append_block();
+        builder.switch_to_block(b0);
+        let callee = builder.args()[0];
+        let arg = builder.args()[1];
+
+        // Well-typed call.
+        let arg64 = builder.make_imm_value(1i64);
+        builder.insert_inst_with(
+            || CallIndirect::new(is, callee, smallvec![arg, arg64]),
+            Type::I64,
+        );
+        // Wrong type of the second argument and of the result.
+        let ret = builder.insert_inst_with(
+            || CallIndirect::new(is, callee, smallvec![arg, arg]),
+            Type::I32,
+        );
+        builder.insert_inst_no_result_with(|| Return::new(is, Some(ret)));
+
+        builder.seal_all();
+        builder.finish();
+
+        let module = mb.build();
+        let errs = verify(&module, module.funcs()[0]);
+        assert_eq!(errs.len(), 2);
+        assert!(matches!(errs[0], ErrorKind::CalleeArgWrongType(Type::I32)));
+        assert!(matches!(
+            errs[1],
+            ErrorKind::CalleeResultWrongType(Type::I32)
+        ));
+    }
+}